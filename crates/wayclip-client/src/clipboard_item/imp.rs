@@ -3,9 +3,9 @@
 use std::cell::{Cell, RefCell};
 
 use glib::Properties;
-use gtk4::glib;
 use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
+use gtk4::{gdk, glib};
 
 #[derive(Properties, Default)]
 #[properties(wrapper_type = super::ClipboardItem)]
@@ -14,10 +14,22 @@ pub struct ClipboardItem {
     #[property(get, set)]
     pub id: Cell<i64>,
 
-    /// Whether this is an image (true) or text (false).
+    /// Identifier that, unlike the ID, is never reused.
+    #[property(get, set)]
+    pub uuid: RefCell<String>,
+
+    /// SHA-256 of the content, hex encoded.
+    #[property(get, set)]
+    pub hash: RefCell<String>,
+
+    /// Whether this is an image.
     #[property(name = "is-image", get, set)]
     pub is_image: Cell<bool>,
 
+    /// Whether this is text; entries that are neither hold other data.
+    #[property(name = "is-text", get, set)]
+    pub is_text: Cell<bool>,
+
     /// MIME type string.
     #[property(name = "mime-type", get, set)]
     pub mime_type: RefCell<String>,
@@ -37,6 +49,70 @@ pub struct ClipboardItem {
     /// Whether pinned.
     #[property(get, set)]
     pub pinned: Cell<bool>,
+
+    /// Whether this is an animated image.
+    #[property(get, set)]
+    pub animated: Cell<bool>,
+
+    /// Whether only metadata was recorded, without the content.
+    #[property(get, set)]
+    pub redacted: Cell<bool>,
+
+    /// Whether the daemon flagged this image as looking like an earlier one.
+    #[property(get, set)]
+    pub similar: Cell<bool>,
+
+    /// Whether this is what the clipboard holds right now.
+    #[property(get, set)]
+    pub current: Cell<bool>,
+
+    /// Whether this entry is in the archive rather than the live history.
+    #[property(get, set)]
+    pub archived: Cell<bool>,
+
+    /// User-given title, if any.
+    #[property(get, set, nullable)]
+    pub title: RefCell<Option<String>>,
+
+    /// Where the entry came from, as `OriginKind::name`.
+    #[property(get, set)]
+    pub origin: RefCell<String>,
+
+    /// Text marking where the entry came from, unless it was copied here.
+    #[property(name = "origin-label", get, set, nullable)]
+    pub origin_label: RefCell<Option<String>>,
+
+    /// User note, if any.
+    #[property(get, set, nullable)]
+    pub note: RefCell<Option<String>>,
+
+    /// Application the content was copied from, if known.
+    #[property(name = "source-app", get, set, nullable)]
+    pub source_app: RefCell<Option<String>>,
+
+    /// Title of the window the content was copied from, if known.
+    #[property(name = "window-title", get, set, nullable)]
+    pub window_title: RefCell<Option<String>>,
+
+    /// Workspace that window was on, if known.
+    #[property(get, set, nullable)]
+    pub workspace: RefCell<Option<String>>,
+
+    /// Seat the content was copied on, if known.
+    #[property(get, set, nullable)]
+    pub seat: RefCell<Option<String>>,
+
+    /// Selection the entry was captured from, as `Selection::name`.
+    #[property(get, set)]
+    pub selection: RefCell<String>,
+
+    /// Decoded thumbnail for images, set once the row showing the item
+    /// has had it decoded.
+    #[property(get, set, nullable)]
+    pub thumbnail: RefCell<Option<gdk::Texture>>,
+
+    /// Where the thumbnail is decoded from, until it has been.
+    pub thumbnail_source: RefCell<Option<super::ThumbnailSource>>,
 }
 
 #[glib::object_subclass]
@@ -3,7 +3,8 @@
 mod imp;
 
 use glib::Object;
-use gtk4::glib;
+use gtk4::subclass::prelude::*;
+use gtk4::{gdk, glib};
 use wayclip_common::HistoryEntry;
 
 glib::wrapper! {
@@ -11,26 +12,146 @@ glib::wrapper! {
     pub struct ClipboardItem(ObjectSubclass<imp::ClipboardItem>);
 }
 
+/// Where an item's thumbnail is decoded from, once its row is shown.
+#[derive(Debug, Clone)]
+pub enum ThumbnailSource {
+    /// PNG file in the daemon's thumbnail cache.
+    File(String),
+    /// Base64-encoded PNG sent along with the entry.
+    Data(String),
+}
+
+impl ThumbnailSource {
+    /// Decode the thumbnail. Textures can be made off the main thread.
+    pub fn decode(&self) -> Result<gdk::Texture, glib::Error> {
+        match self {
+            Self::File(path) => gdk::Texture::from_filename(path),
+            Self::Data(data) => {
+                let bytes = glib::Bytes::from_owned(glib::base64_decode(data));
+                gdk::Texture::from_bytes(&bytes)
+            }
+        }
+    }
+}
+
 impl ClipboardItem {
     /// Create a new ClipboardItem from a HistoryEntry.
     pub fn from_entry(entry: HistoryEntry) -> Self {
-        Object::builder()
+        // The daemon sends a path to its thumbnail cache when it can
+        let thumbnail = match (entry.thumbnail_path, entry.thumbnail) {
+            (Some(path), _) => Some(ThumbnailSource::File(path)),
+            (None, Some(data)) => Some(ThumbnailSource::Data(data)),
+            (None, None) => None,
+        };
+
+        let item: Self = Object::builder()
             .property("id", entry.id)
+            .property("uuid", &entry.uuid)
+            .property("hash", &entry.hash)
             .property("is-image", entry.content_type.is_image())
+            .property("is-text", entry.content_type.is_text())
             .property("mime-type", &entry.mime_type)
             .property("preview", &entry.preview)
             .property("byte-size", entry.byte_size)
             .property("created-at", entry.created_at)
             .property("pinned", entry.pinned)
-            .build()
+            .property("animated", entry.animated)
+            .property("redacted", entry.redacted)
+            .property("similar", entry.similar_to.is_some())
+            .property("current", entry.is_current)
+            .property("archived", entry.archived)
+            .property("title", entry.title)
+            .property("note", entry.note)
+            .property("origin", entry.origin.kind().name())
+            .property("origin-label", entry.origin.label())
+            .property("source-app", entry.source_app)
+            .property("window-title", entry.window_title)
+            .property("workspace", entry.workspace)
+            .property("seat", entry.seat)
+            .property("selection", entry.selection.name())
+            .build();
+        item.imp().thumbnail_source.replace(thumbnail);
+        item
+    }
+
+    /// Where the thumbnail is still to be decoded from, if it hasn't been.
+    pub fn thumbnail_source(&self) -> Option<ThumbnailSource> {
+        match self.thumbnail() {
+            Some(_) => None,
+            None => self.imp().thumbnail_source.borrow().clone(),
+        }
+    }
+
+    /// Show `thumbnail` once decoded, or give up on one that failed to.
+    pub fn finish_thumbnail(&self, thumbnail: Option<gdk::Texture>) {
+        self.imp().thumbnail_source.take();
+        match thumbnail {
+            Some(thumbnail) => self.set_thumbnail(Some(thumbnail)),
+            None => self.notify_thumbnail(),
+        }
     }
 
     /// Generate an accessible description.
     pub fn accessible_description(&self) -> String {
-        if self.is_image() {
-            format!("Image: {}", self.preview())
+        let text = self.display_text();
+        let mut description = if self.animated() {
+            format!("Animated image: {}", text)
+        } else if self.is_image() {
+            format!("Image: {}", text)
+        } else if self.is_text() {
+            format!("Text: {}", text)
         } else {
-            format!("Text: {}", self.preview())
+            format!("Data: {}", text)
+        };
+
+        if self.similar() {
+            description = format!("{}. Similar to an earlier image", description);
         }
+        if let Some(origin) = self.origin_label() {
+            description = format!("{}. {}", description, origin);
+        }
+        if let Some(note) = self.note() {
+            description = format!("{}. Note: {}", description, note);
+        }
+        if self.pinned() {
+            description = format!("Pinned. {}", description);
+        }
+        if self.current() {
+            description = format!("On the clipboard. {}", description);
+        }
+        if self.archived() {
+            description = format!("Archived. {}", description);
+        }
+        description
+    }
+
+    /// Where and when the content was copied, one fact per line, for the
+    /// row's tooltip.
+    pub fn provenance(&self) -> String {
+        let mut lines = Vec::new();
+        match (self.window_title(), self.source_app()) {
+            (Some(title), Some(app)) => lines.push(format!("Copied from “{}” ({})", title, app)),
+            (Some(title), None) => lines.push(format!("Copied from “{}”", title)),
+            (None, Some(app)) => lines.push(format!("Copied from {}", app)),
+            (None, None) => {}
+        }
+        if let Some(workspace) = self.workspace() {
+            lines.push(format!("Workspace: {}", workspace));
+        }
+        if let Some(seat) = self.seat() {
+            lines.push(format!("Seat: {}", seat));
+        }
+        if let Some(time) = glib::DateTime::from_unix_local(self.created_at())
+            .ok()
+            .and_then(|time| time.format("%c").ok())
+        {
+            lines.push(format!("Copied at {}", time));
+        }
+        lines.join("\n")
+    }
+
+    /// The title if one was set, otherwise the preview.
+    pub fn display_text(&self) -> String {
+        self.title().unwrap_or_else(|| self.preview())
     }
 }
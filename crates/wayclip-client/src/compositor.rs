@@ -0,0 +1,169 @@
+//! Compositor IPC for handing focus back to the window that was focused
+//! before the picker opened, so pasting right after picking an entry goes to
+//! the right application, and for pasting there on the user's behalf.
+//!
+//! Hyprland and Sway are supported; elsewhere nothing is remembered and the
+//! compositor picks the next window itself.
+
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+/// How long to wait on the compositor before giving up; this runs on the UI
+/// thread.
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Sway IPC message types.
+const SWAY_RUN_COMMAND: u32 = 0;
+const SWAY_GET_TREE: u32 = 4;
+
+/// A window of another application that can be focused again later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FocusedWindow {
+    /// Hyprland window address, such as "0x55d1c2a0e7b0".
+    Hyprland(String),
+    /// Sway container ID.
+    Sway(i64),
+}
+
+impl FocusedWindow {
+    /// The window focused right now, if the compositor can tell us.
+    pub fn current() -> Option<Self> {
+        let result = if let Some(socket) = hyprland_socket() {
+            hyprland_request(&socket, "j/activewindow").map(|reply| {
+                let window: Value = serde_json::from_str(&reply).unwrap_or_default();
+                window["address"]
+                    .as_str()
+                    .map(|a| Self::Hyprland(a.to_string()))
+            })
+        } else if let Some(socket) = env::var_os("SWAYSOCK") {
+            sway_request(Path::new(&socket), SWAY_GET_TREE, "")
+                .map(|tree| sway_focused(&tree).map(Self::Sway))
+        } else {
+            return None;
+        };
+
+        match result {
+            Ok(window) => {
+                debug!("Focused window before opening: {:?}", window);
+                window
+            }
+            Err(e) => {
+                warn!("Failed to ask the compositor for the focused window: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Ask the compositor to focus this window again.
+    pub fn focus(&self) {
+        let result = match self {
+            Self::Hyprland(address) => hyprland_socket().map(|socket| {
+                let request = format!("dispatch focuswindow address:{}", address);
+                hyprland_request(&socket, &request).map(drop)
+            }),
+            Self::Sway(id) => env::var_os("SWAYSOCK").map(|socket| {
+                let command = format!("[con_id={}] focus", id);
+                sway_request(Path::new(&socket), SWAY_RUN_COMMAND, &command).map(drop)
+            }),
+        };
+
+        if let Some(Err(e)) = result {
+            warn!("Failed to focus {:?} again: {}", self, e);
+        }
+    }
+}
+
+/// Press Ctrl+V in `window`, or in the focused window when there is none.
+///
+/// Hyprland sends the shortcut to the window itself; elsewhere `wtype` types
+/// it into whatever has focus.
+pub fn paste(window: Option<&FocusedWindow>) {
+    if let (Some(FocusedWindow::Hyprland(address)), Some(socket)) = (window, hyprland_socket()) {
+        let request = format!("dispatch sendshortcut CTRL,V,address:{}", address);
+        match hyprland_request(&socket, &request) {
+            Ok(_) => return,
+            Err(e) => warn!("Failed to paste into {:?}: {}", address, e),
+        }
+    }
+
+    match Command::new("wtype")
+        .args(["-M", "ctrl", "v", "-m", "ctrl"])
+        .status()
+    {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("wtype failed to paste: {}", status),
+        Err(e) => warn!("Failed to run wtype to paste: {}", e),
+    }
+}
+
+/// Hyprland's request socket, when running under Hyprland.
+fn hyprland_socket() -> Option<PathBuf> {
+    let signature = env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+
+    // Older releases kept their sockets in /tmp
+    let runtime_dir = dirs::runtime_dir().map(|dir| dir.join("hypr"));
+    [runtime_dir, Some(PathBuf::from("/tmp/hypr"))]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(&signature).join(".socket.sock"))
+        .find(|path| path.exists())
+}
+
+/// Send one request to Hyprland, which answers and closes the connection.
+fn hyprland_request(socket: &Path, request: &str) -> std::io::Result<String> {
+    let mut stream = connect(socket)?;
+    stream.write_all(request.as_bytes())?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
+
+/// Send one message to Sway and read its reply, both framed by the i3 IPC
+/// header: the magic string, then payload length and type in native order.
+fn sway_request(socket: &Path, kind: u32, payload: &str) -> std::io::Result<Value> {
+    let mut stream = connect(socket)?;
+
+    let mut message = b"i3-ipc".to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&kind.to_ne_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    stream.write_all(&message)?;
+
+    let mut header = [0u8; 14];
+    stream.read_exact(&mut header)?;
+    let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+    let mut reply = vec![0; length as usize];
+    stream.read_exact(&mut reply)?;
+
+    serde_json::from_slice(&reply).map_err(std::io::Error::other)
+}
+
+fn connect(socket: &Path) -> std::io::Result<UnixStream> {
+    let stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// ID of the focused window in a Sway tree, skipping workspaces and outputs
+/// that have focus only because they are empty.
+fn sway_focused(node: &Value) -> Option<i64> {
+    let is_window = matches!(node["type"].as_str(), Some("con" | "floating_con"));
+    if node["focused"].as_bool() == Some(true) && is_window {
+        return node["id"].as_i64();
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_focused)
+}
@@ -0,0 +1,84 @@
+//! Entry contents already fetched from the daemon, so picking the same
+//! entry again doesn't send megabytes over IPC a second time.
+
+use std::collections::HashMap;
+
+/// Bytes of content kept at most; the least recently used goes first.
+const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Content larger than this isn't kept, so one entry can't push out all
+/// the others.
+const MAX_ENTRY_BYTES: usize = MAX_BYTES / 4;
+
+struct Cached {
+    /// Content hash the entry had when fetched. A cached entry is only used
+    /// while the entry still has it, as IDs can be reused.
+    hash: String,
+    mime_type: String,
+    data: Vec<u8>,
+    /// `ContentCache::uses` when last fetched or used.
+    used: u64,
+}
+
+/// Contents by entry ID.
+#[derive(Default)]
+pub struct ContentCache {
+    entries: HashMap<i64, Cached>,
+    bytes: usize,
+    uses: u64,
+}
+
+impl ContentCache {
+    /// The MIME type and content of entry `id`, if cached while it had
+    /// `hash`.
+    pub fn get(&mut self, id: i64, hash: &str) -> Option<(String, Vec<u8>)> {
+        self.uses += 1;
+        let cached = self.entries.get_mut(&id).filter(|c| c.hash == hash)?;
+        cached.used = self.uses;
+        Some((cached.mime_type.clone(), cached.data.clone()))
+    }
+
+    /// Keep the content of entry `id`, which has `hash`.
+    pub fn insert(&mut self, id: i64, hash: &str, mime_type: &str, data: &[u8]) {
+        self.invalidate(id);
+        if data.len() > MAX_ENTRY_BYTES {
+            return;
+        }
+
+        self.uses += 1;
+        self.bytes += data.len();
+        self.entries.insert(
+            id,
+            Cached {
+                hash: hash.to_string(),
+                mime_type: mime_type.to_string(),
+                data: data.to_vec(),
+                used: self.uses,
+            },
+        );
+        while self.bytes > MAX_BYTES {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(&id, _)| id)
+            else {
+                break;
+            };
+            self.invalidate(oldest);
+        }
+    }
+
+    /// Forget entry `id`, as it was deleted or changed.
+    pub fn invalidate(&mut self, id: i64) {
+        if let Some(cached) = self.entries.remove(&id) {
+            self.bytes -= cached.data.len();
+        }
+    }
+
+    /// Forget everything, as when switching to another profile's daemon.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+}
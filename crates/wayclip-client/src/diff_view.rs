@@ -0,0 +1,163 @@
+//! Side-by-side diff of two text entries.
+
+use gtk4::glib::{self, clone};
+use gtk4::prelude::*;
+use similar::{ChangeTag, TextDiff};
+
+/// One side of a compared pair.
+pub struct DiffSide {
+    /// Heading shown above the text.
+    pub title: String,
+    pub text: String,
+}
+
+/// Open a window comparing `old` (left) with `new` (right).
+pub fn show(parent: &impl IsA<gtk4::Window>, old: &DiffSide, new: &DiffSide) {
+    let window = gtk4::Window::builder()
+        .title("Compare Entries")
+        .transient_for(parent)
+        .default_width(900)
+        .default_height(600)
+        .build();
+
+    let left = text_view(&format!("Older entry: {}", old.title));
+    let right = text_view(&format!("Newer entry: {}", new.title));
+
+    let diff = TextDiff::from_lines(&old.text, &new.text);
+    let (mut removed, mut added) = (0, 0);
+    for op in diff.ops() {
+        let mut old_lines = Vec::new();
+        let mut new_lines = Vec::new();
+        for change in diff.iter_changes(op) {
+            match change.tag() {
+                ChangeTag::Equal => {
+                    old_lines.push((change.tag(), change.to_string_lossy()));
+                    new_lines.push((change.tag(), change.to_string_lossy()));
+                }
+                ChangeTag::Delete => old_lines.push((change.tag(), change.to_string_lossy())),
+                ChangeTag::Insert => new_lines.push((change.tag(), change.to_string_lossy())),
+            }
+        }
+        removed += old_lines
+            .iter()
+            .filter(|(tag, _)| *tag == ChangeTag::Delete)
+            .count();
+        added += new_lines
+            .iter()
+            .filter(|(tag, _)| *tag == ChangeTag::Insert)
+            .count();
+
+        // Pad the shorter side so changed lines stay aligned
+        let rows = old_lines.len().max(new_lines.len());
+        for i in 0..rows {
+            append_line(&left, old_lines.get(i));
+            append_line(&right, new_lines.get(i));
+        }
+    }
+
+    let summary = gtk4::Label::new(Some(&if removed == 0 && added == 0 {
+        "The entries are identical".to_string()
+    } else {
+        format!("{} lines removed, {} lines added", removed, added)
+    }));
+    summary.set_xalign(0.0);
+    summary.set_margin_top(8);
+    summary.set_margin_bottom(8);
+    summary.set_margin_start(12);
+    summary.add_css_class("dim-label");
+
+    let panes = gtk4::Paned::new(gtk4::Orientation::Horizontal);
+    panes.set_vexpand(true);
+    panes.set_start_child(Some(&pane(&old.title, &left)));
+    panes.set_end_child(Some(&pane(&new.title, &right)));
+    panes.set_position(450);
+
+    let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+    main_box.append(&panes);
+    main_box.append(&summary);
+
+    let escape = gtk4::EventControllerKey::new();
+    escape.connect_key_pressed(clone!(
+        #[weak]
+        window,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                window.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    window.add_controller(escape);
+
+    window.set_child(Some(&main_box));
+    window.present();
+}
+
+fn text_view(accessible_label: &str) -> gtk4::TextView {
+    let view = gtk4::TextView::new();
+    view.set_editable(false);
+    view.set_monospace(true);
+    view.set_wrap_mode(gtk4::WrapMode::None);
+    view.set_left_margin(6);
+    view.update_property(&[gtk4::accessible::Property::Label(accessible_label)]);
+
+    let buffer = view.buffer();
+    buffer.create_tag(
+        Some("delete"),
+        &[("paragraph-background", &"rgba(224, 27, 36, 0.2)")],
+    );
+    buffer.create_tag(
+        Some("insert"),
+        &[("paragraph-background", &"rgba(46, 194, 126, 0.2)")],
+    );
+    view
+}
+
+fn pane(title: &str, view: &gtk4::TextView) -> gtk4::Box {
+    let heading = gtk4::Label::new(Some(title));
+    heading.set_xalign(0.0);
+    heading.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    heading.set_margin_top(8);
+    heading.set_margin_bottom(8);
+    heading.set_margin_start(12);
+    heading.add_css_class("heading");
+
+    let scrolled = gtk4::ScrolledWindow::builder()
+        .vexpand(true)
+        .hexpand(true)
+        .child(view)
+        .build();
+
+    let pane = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+    pane.append(&heading);
+    pane.append(&scrolled);
+    pane
+}
+
+/// Append one line to a side of the diff, or a blank filler line.
+///
+/// Changed lines are prefixed with `-` or `+` so the diff doesn't rely on
+/// color alone.
+fn append_line(view: &gtk4::TextView, line: Option<&(ChangeTag, std::borrow::Cow<str>)>) {
+    let buffer = view.buffer();
+    let mut end = buffer.end_iter();
+    let Some((tag, text)) = line else {
+        buffer.insert(&mut end, "\n");
+        return;
+    };
+
+    let (marker, tag_name) = match tag {
+        ChangeTag::Equal => ("  ", None),
+        ChangeTag::Delete => ("- ", Some("delete")),
+        ChangeTag::Insert => ("+ ", Some("insert")),
+    };
+    let text = format!("{}{}\n", marker, text.trim_end_matches(['\r', '\n']));
+    match tag_name {
+        Some(name) => buffer.insert_with_tags_by_name(&mut end, &text, &[name]),
+        None => buffer.insert(&mut end, &text),
+    }
+}
@@ -1,14 +1,81 @@
 //! Unix socket IPC client using synchronous I/O.
 
 use anyhow::{anyhow, Result};
-use std::io::{BufRead, BufReader, Write};
+use gtk4::glib;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 use tracing::debug;
-use wayclip_common::{decode_response, encode_request, HistoryEntry, Request, Response};
+use wayclip_common::{
+    decode_response, encode_request, Collection, DaemonEvent, ErrorCode, HistoryEntry, HistorySort,
+    IgnoreRules, OriginKind, Request, Response, Transform,
+};
+
+/// The daemon refused a request because the history is locked, or a
+/// passphrase given to unlock it was wrong.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct Locked(pub String);
+
+/// The daemon didn't answer in time, such as when it is stuck on a busy
+/// database.
+#[derive(Debug, thiserror::Error)]
+#[error("The daemon is not responding")]
+pub struct NotResponding;
+
+/// Nothing answered on the daemon's socket, most likely because the daemon
+/// isn't running.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to connect to daemon at {path:?}: {source}. Is wayclip-daemon running?")]
+pub struct NotRunning {
+    path: PathBuf,
+    source: std::io::Error,
+}
+
+/// How long to wait for the daemon to answer, unless set with `set_timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// Set how long clients wait for the daemon to answer a request, or `None`
+/// to wait as long as it takes. Only the first call counts, and only for
+/// clients connected after it.
+pub fn set_timeout(timeout: Option<Duration>) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+/// Which entries `IpcClient::get_history` asks for.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub search: Option<String>,
+    pub collection: Option<i64>,
+    /// Only pinned entries and entries in any collection.
+    pub snippets: bool,
+    /// Only images that look like this one.
+    pub similar_to: Option<i64>,
+    pub origin: Option<OriginKind>,
+    pub sort: HistorySort,
+}
+
+/// Aborts a request an `IpcClient` is waiting on, from another thread.
+pub struct CancelHandle(UnixStream);
+
+impl CancelHandle {
+    /// Close the connection, so the waiting request fails right away.
+    pub fn cancel(&self) {
+        let _ = self.0.shutdown(Shutdown::Both);
+    }
+}
 
 /// IPC client for communicating with the daemon.
+///
+/// One client can send any number of requests over its connection.
 pub struct IpcClient {
-    stream: UnixStream,
+    reader: BufReader<UnixStream>,
 }
 
 impl IpcClient {
@@ -17,46 +84,141 @@ impl IpcClient {
         let path = wayclip_common::socket_path();
         debug!("Connecting to daemon at {:?}", path);
 
-        let stream = UnixStream::connect(&path).map_err(|e| {
-            anyhow!(
-                "Failed to connect to daemon at {:?}: {}. Is wayclip-daemon running?",
-                path,
-                e
-            )
+        let stream = UnixStream::connect(&path).map_err(|source| NotRunning {
+            path: path.clone(),
+            source,
         })?;
+        let timeout = *TIMEOUT.get_or_init(|| Some(DEFAULT_TIMEOUT));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        Ok(Self {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Connect and subscribe to the daemon's events, to be read with
+    /// `next_event`. Events can be far apart, so reads don't time out.
+    pub fn subscribe() -> Result<Self> {
+        let mut client = Self::connect()?;
+        match client.request(&Request::Subscribe)? {
+            Response::Ok => {}
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+        client.reader.get_ref().set_read_timeout(None)?;
+        Ok(client)
+    }
+
+    /// Wait for the next event on a connection made with `subscribe`.
+    pub fn next_event(&mut self) -> Result<DaemonEvent> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Daemon closed the connection",
+                )
+                .into());
+            }
+            if let Response::Event { event } = decode_response(line.trim().as_bytes())? {
+                return Ok(event);
+            }
+        }
+    }
 
-        Ok(Self { stream })
+    /// Get a handle that can cancel this client's requests.
+    pub fn cancel_handle(&self) -> Result<CancelHandle> {
+        Ok(CancelHandle(self.reader.get_ref().try_clone()?))
     }
 
     /// Send a request and receive a response.
+    ///
+    /// A connection closed by the daemon is reported as an I/O error, a
+    /// locked history as `Locked`, and a daemon that doesn't answer in time
+    /// as `NotResponding`.
     fn request(&mut self, request: &Request) -> Result<Response> {
         let encoded = encode_request(request)?;
-        self.stream.write_all(&encoded)?;
-        self.stream.flush()?;
-
-        let mut reader = BufReader::new(&mut self.stream);
         let mut line = String::new();
-        reader.read_line(&mut line)?;
+        match self.exchange(&encoded, &mut line) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Daemon closed the connection",
+                )
+                .into());
+            }
+            Ok(_) => {}
+            // A late answer would be taken for the next request's, so the
+            // connection is closed and the next request makes a new one
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                let _ = self.reader.get_ref().shutdown(Shutdown::Both);
+                return Err(NotResponding.into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        match decode_response(line.trim().as_bytes())? {
+            Response::Error {
+                code: ErrorCode::Locked,
+                message,
+            } => Err(Locked(message).into()),
+            response => Ok(response),
+        }
+    }
 
-        let response = decode_response(line.trim().as_bytes())?;
-        Ok(response)
+    /// Send an encoded request and read the response line into `line`.
+    fn exchange(&mut self, encoded: &[u8], line: &mut String) -> std::io::Result<usize> {
+        let stream = self.reader.get_mut();
+        stream.write_all(encoded)?;
+        stream.flush()?;
+        self.reader.read_line(line)
     }
 
-    /// Get clipboard history.
+    /// Get clipboard history, along with how many entries match in total.
+    ///
+    /// A search of the whole history also finds archived entries, listed
+    /// after the live ones.
     pub fn get_history(
         &mut self,
         limit: Option<u32>,
         offset: Option<u32>,
-        search: Option<String>,
-    ) -> Result<Vec<HistoryEntry>> {
-        let request = Request::GetHistory {
-            limit,
-            offset,
-            search,
+        query: HistoryQuery,
+    ) -> Result<(Vec<HistoryEntry>, u64)> {
+        let request = match query {
+            HistoryQuery {
+                search: Some(search),
+                collection: None,
+                snippets: false,
+                similar_to: None,
+                origin: None,
+                sort,
+            } => Request::SearchAll {
+                query: search,
+                limit,
+                offset,
+                sort: Some(sort),
+            },
+            query => Request::GetHistory {
+                limit,
+                offset,
+                search: query.search,
+                seat: None,
+                selection: None,
+                collection: query.collection,
+                snippets: query.snippets,
+                similar_to: query.similar_to.map(Into::into),
+                origin: query.origin,
+                sort: Some(query.sort),
+                pinned_first: true,
+                include_archived: false,
+            },
         };
 
         match self.request(&request)? {
-            Response::History { entries, .. } => Ok(entries),
+            Response::History {
+                entries,
+                total_count,
+            } => Ok((entries, total_count)),
             Response::Error { code, message } => {
                 Err(anyhow!("Daemon error ({:?}): {}", code, message))
             }
@@ -64,9 +226,48 @@ impl IpcClient {
         }
     }
 
+    /// Move an archived item back into the history, returning its ID there.
+    pub fn restore_archived(&mut self, id: i64) -> Result<i64> {
+        let request = Request::RestoreArchived { id: id.into() };
+
+        match self.request(&request)? {
+            Response::Entry { entry } => Ok(entry.id),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to restore item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Get several items' content in one request, keyed by ID.
+    ///
+    /// Items that don't exist or didn't fit under the daemon's size cap are
+    /// missing from the result.
+    pub fn get_contents(&mut self, ids: &[i64]) -> Result<HashMap<i64, (String, Vec<u8>)>> {
+        let request = Request::GetContents {
+            ids: ids.iter().map(|&id| id.into()).collect(),
+        };
+
+        match self.request(&request)? {
+            Response::Contents { contents, omitted } => {
+                if !omitted.is_empty() {
+                    debug!("Daemon left out {} items over the size cap", omitted.len());
+                }
+                Ok(contents
+                    .into_iter()
+                    .map(|c| (c.id, (c.mime_type, c.data.0)))
+                    .collect())
+            }
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to get content: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
     /// Copy an item to the clipboard.
     pub fn set_clipboard(&mut self, id: i64) -> Result<()> {
-        let request = Request::SetClipboard { id };
+        let request = Request::SetClipboard { id: id.into() };
 
         match self.request(&request)? {
             Response::Ok => Ok(()),
@@ -77,10 +278,209 @@ impl IpcClient {
         }
     }
 
+    /// List all collections.
+    pub fn list_collections(&mut self) -> Result<Vec<Collection>> {
+        match self.request(&Request::ListCollections)? {
+            Response::Collections { collections } => Ok(collections),
+            Response::Error { code, message } => {
+                Err(anyhow!("Daemon error ({:?}): {}", code, message))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Add an item to a collection.
+    pub fn add_to_collection(&mut self, collection_id: i64, entry_id: i64) -> Result<()> {
+        let request = Request::AddToCollection {
+            collection_id,
+            entry_id: entry_id.into(),
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => Err(anyhow!(
+                "Failed to add to collection: {} ({:?})",
+                message,
+                code
+            )),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Remove an item from a collection.
+    pub fn remove_from_collection(&mut self, collection_id: i64, entry_id: i64) -> Result<()> {
+        let request = Request::RemoveFromCollection {
+            collection_id,
+            entry_id: entry_id.into(),
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => Err(anyhow!(
+                "Failed to remove from collection: {} ({:?})",
+                message,
+                code
+            )),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Pin or unpin an item.
+    pub fn set_pinned(&mut self, id: i64, pinned: bool) -> Result<()> {
+        let request = Request::SetPinned {
+            id: id.into(),
+            pinned,
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to pin item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Set or clear an item's title and note.
+    pub fn set_entry_meta(
+        &mut self,
+        id: i64,
+        title: Option<String>,
+        note: Option<String>,
+    ) -> Result<()> {
+        let request = Request::SetEntryMeta {
+            id: id.into(),
+            title,
+            note,
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => Err(anyhow!(
+                "Failed to save title and note: {} ({:?})",
+                message,
+                code
+            )),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Move a pinned item to a new position among the pinned items.
+    pub fn move_pinned(&mut self, id: i64, position: u32) -> Result<()> {
+        let request = Request::MovePinned {
+            id: id.into(),
+            position,
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to move item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Copy an item to the primary selection (middle-click paste).
+    pub fn set_primary_selection(&mut self, id: i64) -> Result<()> {
+        let request = Request::SetPrimarySelection { id: id.into() };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to copy item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Copy a transformed version of an item to the clipboard.
+    pub fn set_clipboard_as(&mut self, id: i64, transform: Transform) -> Result<()> {
+        let request = Request::SetClipboardAs {
+            id: id.into(),
+            transform,
+        };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to copy item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Add an entry, such as part of another entry's text, and copy it to
+    /// the clipboard.
+    pub fn add_and_copy(&mut self, mime_type: &str, data: &[u8]) -> Result<()> {
+        let request = Request::AddEntry {
+            mime_type: mime_type.to_string(),
+            data: glib::base64_encode(data).to_string(),
+            set_clipboard: true,
+        };
+
+        match self.request(&request)? {
+            Response::Entry { .. } => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to copy item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Write an entry's content to a file, returning the path written, which
+    /// may have gained an extension.
+    pub fn save_to_file(&mut self, id: i64, path: &str) -> Result<String> {
+        let request = Request::SaveToFile {
+            id: id.into(),
+            path: path.to_string(),
+        };
+
+        match self.request(&request)? {
+            Response::Saved { path } => Ok(path),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to save item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Add an entry to the end of the paste queue, returning the entries
+    /// waiting after the one on the clipboard.
+    pub fn queue_paste(&mut self, id: i64) -> Result<Vec<i64>> {
+        let request = Request::QueuePaste {
+            ids: vec![id.into()],
+        };
+
+        match self.request(&request)? {
+            Response::Queue { waiting } => Ok(waiting),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to queue item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Split a text entry into an entry per line, returning the new
+    /// entries' IDs in line order.
+    pub fn split_entry(&mut self, id: i64) -> Result<Vec<i64>> {
+        let request = Request::SplitEntry {
+            id: id.into(),
+            delimiter: None,
+        };
+
+        match self.request(&request)? {
+            Response::Split { ids } => Ok(ids),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to split item: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
     /// Delete an entry.
-    #[allow(dead_code)]
     pub fn delete_entry(&mut self, id: i64) -> Result<()> {
-        let request = Request::DeleteEntry { id };
+        let request = Request::DeleteEntry { id: id.into() };
 
         match self.request(&request)? {
             Response::Ok => Ok(()),
@@ -105,6 +505,58 @@ impl IpcClient {
         }
     }
 
+    /// Get the ignore rules added by clients, on top of the daemon's config.
+    pub fn added_ignore_rules(&mut self) -> Result<IgnoreRules> {
+        match self.request(&Request::GetConfig)? {
+            Response::Config { added_ignore, .. } => Ok(*added_ignore),
+            Response::Error { code, message } => Err(anyhow!(
+                "Failed to get ignore rules: {} ({:?})",
+                message,
+                code
+            )),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Replace the ignore rules added by clients.
+    pub fn set_added_ignore_rules(&mut self, ignore: IgnoreRules) -> Result<()> {
+        let request = Request::SetConfig { ignore };
+
+        match self.request(&request)? {
+            Response::Config { .. } => Ok(()),
+            Response::Error { code, message } => Err(anyhow!(
+                "Failed to set ignore rules: {} ({:?})",
+                message,
+                code
+            )),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Unlock the history.
+    pub fn unlock(&mut self, passphrase: String) -> Result<()> {
+        let request = Request::Unlock { passphrase };
+
+        match self.request(&request)? {
+            Response::Ok => Ok(()),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to unlock: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
+    /// Why the daemon stopped capturing the clipboard, if it has.
+    pub fn capture_error(&mut self) -> Result<Option<String>> {
+        match self.request(&Request::GetStatus)? {
+            Response::Status { capture_error, .. } => Ok(capture_error),
+            Response::Error { code, message } => {
+                Err(anyhow!("Failed to get status: {} ({:?})", message, code))
+            }
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        }
+    }
+
     /// Ping the daemon.
     #[allow(dead_code)]
     pub fn ping(&mut self) -> Result<()> {
@@ -2,4 +2,6 @@
 
 mod client;
 
-pub use client::IpcClient;
+pub use client::{
+    set_timeout, CancelHandle, HistoryQuery, IpcClient, Locked, NotResponding, NotRunning,
+};
@@ -1,15 +1,30 @@
 //! ItemRow implementation.
 
+use std::cell::RefCell;
+
 use gtk4::glib;
 use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
 use gtk4::{Box, Image, Label, Orientation};
 
+use crate::clipboard_item::ClipboardItem;
+
 #[derive(Default)]
 pub struct ItemRow {
     pub icon: Image,
     pub content_label: Label,
     pub timestamp_label: Label,
+    pub animated_badge: Label,
+    pub current_badge: Label,
+    pub origin_badge: Label,
+    pub pin_icon: Image,
+    /// The item currently bound to this row.
+    pub item: RefCell<Option<ClipboardItem>>,
+    /// Bindings from the bound item's properties to the row's widgets.
+    pub bindings: RefCell<Vec<glib::Binding>>,
+    /// Handler updating what the row derives from several of the bound
+    /// item's properties.
+    pub notify_handler: RefCell<Option<glib::SignalHandlerId>>,
 }
 
 #[glib::object_subclass]
@@ -54,6 +69,35 @@ impl ObjectImpl for ItemRow {
         content_box.append(&self.timestamp_label);
 
         obj.append(&content_box);
+
+        // Badge for animated images
+        self.animated_badge.set_label("GIF");
+        self.animated_badge.set_valign(gtk4::Align::Center);
+        self.animated_badge.add_css_class("caption");
+        self.animated_badge.set_visible(false);
+        obj.append(&self.animated_badge);
+
+        // Badge for entries that weren't copied on this machine
+        self.origin_badge.set_valign(gtk4::Align::Center);
+        self.origin_badge.add_css_class("caption");
+        self.origin_badge.add_css_class("dim-label");
+        self.origin_badge.set_visible(false);
+        obj.append(&self.origin_badge);
+
+        // Badge for what the clipboard holds now
+        self.current_badge.set_label("Current");
+        self.current_badge.set_valign(gtk4::Align::Center);
+        self.current_badge.add_css_class("caption");
+        self.current_badge.add_css_class("accent");
+        self.current_badge.set_visible(false);
+        obj.append(&self.current_badge);
+
+        // Marker for pinned items
+        self.pin_icon.set_icon_name(Some("view-pin-symbolic"));
+        self.pin_icon.set_valign(gtk4::Align::Center);
+        self.pin_icon.add_css_class("dim-label");
+        self.pin_icon.set_visible(false);
+        obj.append(&self.pin_icon);
     }
 }
 
@@ -2,12 +2,19 @@
 
 mod imp;
 
+use std::cell::Cell;
+use std::time::Duration;
+
 use glib::Object;
-use gtk4::glib;
+use gtk4::glib::{self, clone};
 use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
 
 use crate::clipboard_item::ClipboardItem;
+use crate::thumbnails;
+
+/// How long a decoded thumbnail takes to fade in over its placeholder.
+const FADE_IN: Duration = Duration::from_millis(200);
 
 glib::wrapper! {
     /// A row widget for displaying a clipboard item.
@@ -28,30 +35,142 @@ impl ItemRow {
         Object::builder().build()
     }
 
-    /// Bind this row to a clipboard item.
+    /// Bind this row to a clipboard item, following changes to it until
+    /// `unbind`.
     pub fn bind(&self, item: &ClipboardItem) {
         let imp = self.imp();
+        self.unbind();
+
+        let bindings = vec![
+            item.bind_property("animated", &imp.animated_badge, "visible")
+                .sync_create()
+                .build(),
+            item.bind_property("current", &imp.current_badge, "visible")
+                .sync_create()
+                .build(),
+            item.bind_property("pinned", &imp.pin_icon, "visible")
+                .sync_create()
+                .build(),
+            item.bind_property("origin-label", &imp.origin_badge, "label")
+                .transform_to(|_, label: Option<String>| Some(label.unwrap_or_default()))
+                .sync_create()
+                .build(),
+            item.bind_property("origin-label", &imp.origin_badge, "visible")
+                .transform_to(|_, label: Option<String>| Some(label.is_some()))
+                .sync_create()
+                .build(),
+        ];
+        imp.bindings.replace(bindings);
+
+        // A recycled row may still show the last item's placeholder. A
+        // thumbnail decoded before shows right away, without one.
+        imp.icon.remove_css_class("thumbnail-loading");
+        thumbnails::request(item);
+        self.update(item);
+        let handler = item.connect_notify_local(
+            None,
+            clone!(
+                #[weak(rename_to = row)]
+                self,
+                move |item, _| row.update(item)
+            ),
+        );
+        imp.notify_handler.replace(Some(handler));
+        imp.item.replace(Some(item.clone()));
+    }
+
+    /// Stop following the bound item, so the row can be recycled for
+    /// another.
+    pub fn unbind(&self) {
+        let imp = self.imp();
+        for binding in imp.bindings.take() {
+            binding.unbind();
+        }
+        let item = imp.item.take();
+        if let Some(item) = &item {
+            thumbnails::cancel(item);
+        }
+        if let (Some(item), Some(handler)) = (item, imp.notify_handler.take()) {
+            item.disconnect(handler);
+        }
+    }
 
-        // Update icon
-        let icon_name = if item.is_image() {
-            "image-x-generic-symbolic"
+    /// Update what the row derives from several of `item`'s properties.
+    fn update(&self, item: &ClipboardItem) {
+        let imp = self.imp();
+
+        // Update icon, preferring the thumbnail when there is one, and
+        // showing a placeholder while it is decoded
+        if let Some(thumbnail) = item.thumbnail() {
+            imp.icon.set_paintable(Some(&thumbnail));
+            if imp.icon.has_css_class("thumbnail-loading") {
+                imp.icon.remove_css_class("thumbnail-loading");
+                fade_in(&imp.icon);
+            }
+        } else if item.thumbnail_source().is_some() {
+            imp.icon.set_icon_name(Some("image-x-generic-symbolic"));
+            imp.icon.add_css_class("thumbnail-loading");
         } else {
-            "text-x-generic-symbolic"
-        };
-        imp.icon.set_icon_name(Some(icon_name));
+            imp.icon.remove_css_class("thumbnail-loading");
+            let icon_name = if item.redacted() {
+                "security-high-symbolic"
+            } else if item.is_image() {
+                "image-x-generic-symbolic"
+            } else if !item.is_text() {
+                "application-x-executable-symbolic"
+            } else {
+                "text-x-generic-symbolic"
+            };
+            imp.icon.set_icon_name(Some(icon_name));
+        }
+        // Archived entries are greyed out below the live ones
+        if item.archived() {
+            self.add_css_class("dim-label");
+        } else {
+            self.remove_css_class("dim-label");
+        }
 
         // Update content label
-        imp.content_label.set_label(&item.preview());
+        imp.content_label.set_label(&item.display_text());
+        let provenance = item.provenance();
+        let tooltip = match item.note() {
+            Some(note) => format!("{}\n\n{}", note, provenance),
+            None => provenance.clone(),
+        };
+        self.set_tooltip_text(Some(&tooltip));
 
         // Update timestamp label
         let timestamp = format_relative_time(item.created_at());
         imp.timestamp_label.set_label(&timestamp);
 
         // Update accessibility
-        self.update_property(&[gtk4::accessible::Property::Label(
-            &item.accessible_description(),
-        )]);
+        self.update_property(&[
+            gtk4::accessible::Property::Label(&item.accessible_description()),
+            gtk4::accessible::Property::Description(&provenance),
+        ]);
     }
+
+    /// The item currently shown in this row.
+    pub fn item(&self) -> Option<ClipboardItem> {
+        self.imp().item.borrow().clone()
+    }
+}
+
+/// Fade `widget` in from transparent over `FADE_IN`.
+fn fade_in(widget: &impl IsA<gtk4::Widget>) {
+    widget.set_opacity(0.0);
+    let start = Cell::new(None);
+    widget.add_tick_callback(move |widget, clock| {
+        let now = clock.frame_time();
+        let started = start.get().unwrap_or(now);
+        start.set(Some(started));
+        let progress = (now - started) as f64 / FADE_IN.as_micros() as f64;
+        widget.set_opacity(progress.min(1.0));
+        match progress < 1.0 {
+            true => glib::ControlFlow::Continue,
+            false => glib::ControlFlow::Break,
+        }
+    });
 }
 
 /// Format a Unix timestamp as relative time (e.g., "2 minutes ago").
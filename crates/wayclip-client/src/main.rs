@@ -1,10 +1,27 @@
 //! Wayclip GTK 4 client - clipboard history browser.
 
 mod clipboard_item;
+mod compositor;
+mod content_cache;
+mod diff_view;
 mod ipc;
 mod item_row;
+mod meta_dialog;
+mod part_dialog;
+mod search_dialog;
+mod share;
+mod shortcuts_window;
+mod startup;
+mod state;
+mod thumbnails;
+mod unlock_dialog;
 mod window;
 
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk4::glib::clone;
 use gtk4::prelude::*;
 use gtk4::{gio, glib};
 use tracing_subscriber::EnvFilter;
@@ -12,6 +29,8 @@ use tracing_subscriber::EnvFilter;
 const APP_ID: &str = "com.wayclip.Client";
 
 fn main() -> glib::ExitCode {
+    let started = Instant::now();
+
     // Initialize logging
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env().add_directive("wayclip=debug".parse().unwrap()))
@@ -24,13 +43,230 @@ fn main() -> glib::ExitCode {
 
     let app = gtk4::Application::builder()
         .application_id(APP_ID)
-        .flags(gio::ApplicationFlags::default())
+        .flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE)
         .build();
 
-    app.connect_activate(|app| {
-        let window = window::WayclipWindow::new(app);
-        window.present();
+    app.add_main_option(
+        "mode",
+        glib::Char::from(b'm'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Window to open: history (default) or snippets",
+        Some("MODE"),
+    );
+    app.add_main_option(
+        "profile",
+        glib::Char::from(b'p'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Profile to show instead of $WAYCLIP_PROFILE",
+        Some("NAME"),
+    );
+    app.add_main_option(
+        "activate",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "What picking an entry does: copy_and_close, copy_and_stay, copy_and_paste or primary \
+         (default from the settings)",
+        Some("ACTION"),
+    );
+    app.add_main_option(
+        "measure-startup",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Print how long it takes until the window is drawn",
+        None,
+    );
+    app.add_main_option(
+        "search",
+        glib::Char::from(b's'),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        "Start with this text in the search field",
+        Some("TEXT"),
+    );
+    app.add_main_option(
+        "timeout",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Int,
+        "Seconds to wait for the daemon to answer, or 0 to wait as long as it takes (default 10)",
+        Some("SECONDS"),
+    );
+    app.add_main_option(
+        "daemonize-ui",
+        glib::Char::from(0),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::None,
+        "Start hidden and keep running after the window closes, so it opens instantly",
+        None,
+    );
+
+    // Options are checked here, in the process that was launched, but acted
+    // on in `command-line`, which runs in the first instance even when they
+    // were forwarded from a second launch
+    app.connect_handle_local_options(move |_, options| {
+        if options.contains("measure-startup") {
+            startup::enable(started);
+        }
+        match options.lookup::<i32>("timeout").ok().flatten() {
+            Some(seconds) if seconds < 0 => {
+                eprintln!("--timeout can't be negative");
+                return 1;
+            }
+            Some(0) => ipc::set_timeout(None),
+            Some(seconds) => ipc::set_timeout(Some(Duration::from_secs(seconds as u64))),
+            None => {}
+        }
+
+        let profile = options.lookup::<String>("profile").ok().flatten();
+        if let Err(e) = wayclip_common::init_profile(profile.as_deref()) {
+            eprintln!("{}", e);
+            return 1;
+        }
+
+        match Launch::from_options(options) {
+            Ok(_) => -1,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        }
     });
 
+    app.connect_startup(|_| load_css());
+
+    // Ctrl+Q quits, even when the window would only be hidden on close
+    let quit = gio::ActionEntry::builder("quit")
+        .activate(|app: &gtk4::Application, _, _| app.quit())
+        .build();
+    app.add_action_entries([quit]);
+    app.set_accels_for_action("app.quit", &["<Control>q"]);
+    app.set_accels_for_action("win.toggle-pin", &["<Control>p"]);
+    app.set_accels_for_action("win.show-help-overlay", &["<Control>question"]);
+
+    let hold = Rc::new(RefCell::new(None));
+    app.connect_command_line(clone!(
+        #[strong]
+        hold,
+        move |app, command_line| {
+            // Already checked by the launched process before forwarding
+            let launch = Launch::from_options(&command_line.options_dict()).unwrap_or_default();
+            show(app, &launch, &hold);
+            0
+        }
+    ));
+    // Activation without a command line, e.g. over D-Bus
+    app.connect_activate(move |app| show(app, &Launch::default(), &hold));
+
     app.run()
 }
+
+/// Add the client's own styles on top of the theme.
+fn load_css() {
+    let Some(display) = gtk4::gdk::Display::default() else {
+        return;
+    };
+    let provider = gtk4::CssProvider::new();
+    provider.load_from_string(include_str!("style.css"));
+    gtk4::style_context_add_provider_for_display(
+        &display,
+        &provider,
+        gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+}
+
+/// What one launch of `wayclip` asked for.
+#[derive(Default)]
+struct Launch {
+    mode: window::Mode,
+    search: Option<String>,
+    profile: Option<String>,
+    /// Overrides the activation set in the settings.
+    activation: Option<window::Activation>,
+    keep_running: bool,
+}
+
+impl Launch {
+    fn from_options(options: &glib::VariantDict) -> Result<Self, String> {
+        let lookup = |name: &str| options.lookup::<String>(name).ok().flatten();
+
+        let mode = match lookup("mode") {
+            Some(name) => window::Mode::from_name(&name)
+                .ok_or_else(|| format!("Unknown mode '{}', expected history or snippets", name))?,
+            None => window::Mode::default(),
+        };
+
+        let activation = match lookup("activate") {
+            Some(name) => Some(window::Activation::from_name(&name).ok_or_else(|| {
+                format!(
+                    "Unknown activation '{}', expected copy_and_close, copy_and_stay, \
+                     copy_and_paste or primary",
+                    name
+                )
+            })?),
+            None => None,
+        };
+
+        Ok(Self {
+            mode,
+            search: lookup("search"),
+            profile: lookup("profile"),
+            activation,
+            keep_running: options.contains("daemonize-ui"),
+        })
+    }
+}
+
+/// Show the window for `launch`. A running instance reuses its window, so
+/// launching `wayclip` again never opens a second one.
+fn show(
+    app: &gtk4::Application,
+    launch: &Launch,
+    hold: &RefCell<Option<gio::ApplicationHoldGuard>>,
+) {
+    startup::mark("application activated");
+
+    let existing = app
+        .windows()
+        .into_iter()
+        .find_map(|w| w.downcast::<window::WayclipWindow>().ok());
+    if let Some(window) = existing {
+        if launch.profile.is_some() && launch.profile != wayclip_common::profile() {
+            window.select_profile(launch.profile.clone());
+        }
+        if !window.is_visible() {
+            window.remember_focus();
+        }
+        window.reopen(launch.mode, launch.search.as_deref());
+        window.set_activation(launch.activation);
+        window.present();
+        return;
+    }
+
+    let window = window::WayclipWindow::new(app, launch.mode);
+    window.set_activation(launch.activation);
+    if let Some(search) = &launch.search {
+        window.set_search(search);
+    }
+    startup::mark("window built");
+
+    if launch.keep_running {
+        // Closing only hides the window, ready for the next launch
+        window.set_hide_on_close(true);
+        hold.replace(Some(app.hold()));
+        return;
+    }
+    window.remember_focus();
+    window.present();
+
+    if startup::enabled() {
+        window.add_tick_callback(|_, _| {
+            startup::mark("first frame");
+            startup::report();
+            glib::ControlFlow::Break
+        });
+    }
+}
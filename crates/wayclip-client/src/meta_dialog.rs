@@ -0,0 +1,108 @@
+//! Dialog for editing an entry's title and note.
+
+use gtk4::glib::{self, clone};
+use gtk4::prelude::*;
+
+use crate::clipboard_item::ClipboardItem;
+
+/// Show the dialog for `item`. `on_save` gets the new title and note, with
+/// empty fields passed as `None`.
+pub fn show(
+    parent: &impl IsA<gtk4::Window>,
+    item: &ClipboardItem,
+    on_save: impl Fn(Option<String>, Option<String>) + 'static,
+) {
+    let dialog = gtk4::Window::builder()
+        .title("Edit Title and Note")
+        .transient_for(parent)
+        .modal(true)
+        .resizable(false)
+        .default_width(360)
+        .build();
+
+    let grid = gtk4::Grid::builder()
+        .row_spacing(8)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+
+    let title = field(&grid, 0, "_Title", item.title());
+    title.set_placeholder_text(Some(&item.preview()));
+    let note = field(&grid, 1, "_Note", item.note());
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 8);
+    buttons.set_halign(gtk4::Align::End);
+    buttons.set_margin_top(4);
+    let cancel = gtk4::Button::with_mnemonic("_Cancel");
+    let save = gtk4::Button::with_mnemonic("_Save");
+    save.add_css_class("suggested-action");
+    buttons.append(&cancel);
+    buttons.append(&save);
+    grid.attach(&buttons, 0, 2, 2, 1);
+
+    cancel.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| dialog.close()
+    ));
+    save.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        title,
+        #[weak]
+        note,
+        move |_| {
+            on_save(text_or_none(&title), text_or_none(&note));
+            dialog.close();
+        }
+    ));
+
+    // Enter in either field saves
+    dialog.set_default_widget(Some(&save));
+    title.set_activates_default(true);
+    note.set_activates_default(true);
+
+    let escape = gtk4::EventControllerKey::new();
+    escape.connect_key_pressed(clone!(
+        #[weak]
+        dialog,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                dialog.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    dialog.add_controller(escape);
+
+    dialog.set_child(Some(&grid));
+    dialog.present();
+}
+
+/// Add a labelled entry to `grid` at `row`.
+fn field(grid: &gtk4::Grid, row: i32, label: &str, text: Option<String>) -> gtk4::Entry {
+    let entry = gtk4::Entry::new();
+    entry.set_hexpand(true);
+    entry.set_text(text.as_deref().unwrap_or_default());
+
+    let label = gtk4::Label::with_mnemonic(label);
+    label.set_xalign(0.0);
+    label.set_mnemonic_widget(Some(&entry));
+
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(&entry, 1, row, 1, 1);
+    entry
+}
+
+fn text_or_none(entry: &gtk4::Entry) -> Option<String> {
+    let text = entry.text().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
@@ -0,0 +1,97 @@
+//! Dialog for copying part of a text entry.
+
+use gtk4::glib::{self, clone};
+use gtk4::prelude::*;
+
+/// Show `text` so part of it can be selected. `on_copy` gets the selected
+/// text.
+pub fn show(parent: &impl IsA<gtk4::Window>, text: &str, on_copy: impl Fn(String) + 'static) {
+    let dialog = gtk4::Window::builder()
+        .title("Copy Part")
+        .transient_for(parent)
+        .modal(true)
+        .default_width(560)
+        .default_height(400)
+        .build();
+
+    // Read-only, but with a cursor so the selection can be made with the
+    // keyboard too
+    let view = gtk4::TextView::new();
+    view.set_editable(false);
+    view.set_cursor_visible(true);
+    view.set_monospace(true);
+    view.set_wrap_mode(gtk4::WrapMode::WordChar);
+    view.set_left_margin(6);
+    view.set_right_margin(6);
+    view.buffer().set_text(text);
+    view.update_property(&[gtk4::accessible::Property::Label(
+        "Entry text; select the part to copy",
+    )]);
+
+    let scrolled = gtk4::ScrolledWindow::builder()
+        .vexpand(true)
+        .hexpand(true)
+        .child(&view)
+        .build();
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 8);
+    buttons.set_halign(gtk4::Align::End);
+    buttons.set_margin_top(8);
+    buttons.set_margin_bottom(12);
+    buttons.set_margin_end(12);
+    let cancel = gtk4::Button::with_mnemonic("_Cancel");
+    let copy = gtk4::Button::with_mnemonic("Copy _Selection");
+    copy.add_css_class("suggested-action");
+    copy.set_sensitive(false);
+    buttons.append(&cancel);
+    buttons.append(&copy);
+
+    view.buffer().connect_has_selection_notify(clone!(
+        #[weak]
+        copy,
+        move |buffer| copy.set_sensitive(buffer.has_selection())
+    ));
+
+    cancel.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| dialog.close()
+    ));
+    copy.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        view,
+        move |_| {
+            let buffer = view.buffer();
+            if let Some((start, end)) = buffer.selection_bounds() {
+                on_copy(buffer.text(&start, &end, false).to_string());
+                dialog.close();
+            }
+        }
+    ));
+    dialog.set_default_widget(Some(&copy));
+
+    let escape = gtk4::EventControllerKey::new();
+    escape.connect_key_pressed(clone!(
+        #[weak]
+        dialog,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                dialog.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    dialog.add_controller(escape);
+
+    let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
+    main_box.append(&scrolled);
+    main_box.append(&buttons);
+    dialog.set_child(Some(&main_box));
+    dialog.present();
+}
@@ -0,0 +1,125 @@
+//! Dialog for saving a search under a name.
+
+use gtk4::glib::{self, clone};
+use gtk4::prelude::*;
+
+/// Show the dialog with `query` filled in. `on_save` gets the name and the
+/// search text, once both are given.
+pub fn show(
+    parent: &impl IsA<gtk4::Window>,
+    query: &str,
+    on_save: impl Fn(String, String) + 'static,
+) {
+    let dialog = gtk4::Window::builder()
+        .title("Save Search")
+        .transient_for(parent)
+        .modal(true)
+        .resizable(false)
+        .default_width(360)
+        .build();
+
+    let grid = gtk4::Grid::builder()
+        .row_spacing(8)
+        .column_spacing(12)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+
+    let name = field(&grid, 0, "_Name", "");
+    name.set_placeholder_text(Some("e.g. Jira tickets"));
+    let search = field(&grid, 1, "_Search", query);
+    search.set_placeholder_text(Some(r"Text, or a regular expression like /\d+/"));
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 8);
+    buttons.set_halign(gtk4::Align::End);
+    buttons.set_margin_top(4);
+    let cancel = gtk4::Button::with_mnemonic("_Cancel");
+    let save = gtk4::Button::with_mnemonic("_Save");
+    save.add_css_class("suggested-action");
+    buttons.append(&cancel);
+    buttons.append(&save);
+    grid.attach(&buttons, 0, 2, 2, 1);
+
+    // Nothing to save without both a name and a search
+    let update_save = clone!(
+        #[weak]
+        name,
+        #[weak]
+        search,
+        #[weak]
+        save,
+        move || {
+            save.set_sensitive(!name.text().trim().is_empty() && !search.text().trim().is_empty());
+        }
+    );
+    update_save();
+    name.connect_changed(clone!(
+        #[strong]
+        update_save,
+        move |_| update_save()
+    ));
+    search.connect_changed(move |_| update_save());
+
+    cancel.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| dialog.close()
+    ));
+    save.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        name,
+        #[weak]
+        search,
+        move |_| {
+            on_save(
+                name.text().trim().to_string(),
+                search.text().trim().to_string(),
+            );
+            dialog.close();
+        }
+    ));
+
+    // Enter in either field saves
+    dialog.set_default_widget(Some(&save));
+    name.set_activates_default(true);
+    search.set_activates_default(true);
+
+    let escape = gtk4::EventControllerKey::new();
+    escape.connect_key_pressed(clone!(
+        #[weak]
+        dialog,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                dialog.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    dialog.add_controller(escape);
+
+    dialog.set_child(Some(&grid));
+    dialog.present();
+}
+
+/// Add a labelled entry to `grid` at `row`.
+fn field(grid: &gtk4::Grid, row: i32, label: &str, text: &str) -> gtk4::Entry {
+    let entry = gtk4::Entry::new();
+    entry.set_hexpand(true);
+    entry.set_text(text);
+
+    let label = gtk4::Label::with_mnemonic(label);
+    label.set_xalign(0.0);
+    label.set_mnemonic_widget(Some(&entry));
+
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(&entry, 1, row, 1, 1);
+    entry
+}
@@ -0,0 +1,54 @@
+//! Files holding entries shared with other applications.
+//!
+//! The desktop portal hands applications a file, so an entry's content is
+//! written out first. The receiving application may read it some time after
+//! the portal returns, so files are only removed once they are an hour old.
+
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use tracing::debug;
+
+/// How long a shared file is kept for the receiving application.
+const KEEP_FOR: Duration = Duration::from_secs(60 * 60);
+
+/// Write an entry's content to a file that can be shared, named after the
+/// entry and with an extension for its type.
+pub fn materialize(id: i64, mime_type: &str, data: &[u8]) -> std::io::Result<PathBuf> {
+    let dir = wayclip_common::share_dir();
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    remove_stale();
+
+    let extension = wayclip_common::extension_for_mime(mime_type);
+    let path = dir.join(format!("clipboard-{}.{}", id, extension));
+    // Clipboard content may be sensitive, keep it private to the user
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)?;
+    file.write_all(data)?;
+    Ok(path)
+}
+
+/// Remove files shared long enough ago that they should have been read.
+fn remove_stale() {
+    let Ok(entries) = std::fs::read_dir(wayclip_common::share_dir()) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > KEEP_FOR);
+        if stale {
+            debug!("Removing shared file {}", entry.path().display());
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Cheatsheet of the client's keyboard shortcuts, opened with Ctrl+?.
+//!
+//! Keep it in step with `WayclipWindow::on_key_pressed`, the accelerators
+//! set in `main` and the table in the README.
+
+const UI: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<interface>
+  <object class="GtkShortcutsWindow" id="shortcuts">
+    <property name="modal">true</property>
+    <child>
+      <object class="GtkShortcutsSection">
+        <property name="section-name">shortcuts</property>
+        <property name="max-height">12</property>
+        <child>
+          <object class="GtkShortcutsGroup">
+            <property name="title">Search</property>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Focus search</property>
+                <property name="accelerator">&lt;Control&gt;f</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Open recent and saved searches</property>
+                <property name="accelerator">&lt;Control&gt;r</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Clear search, leave similar images or an origin, or close</property>
+                <property name="accelerator">Escape</property>
+              </object>
+            </child>
+          </object>
+        </child>
+        <child>
+          <object class="GtkShortcutsGroup">
+            <property name="title">Navigation</property>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Move through the list</property>
+                <property name="accelerator">Up Down</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Move between search and list</property>
+                <property name="accelerator">Tab</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Pick the selected item (copy and close by default)</property>
+                <property name="accelerator">Return</property>
+              </object>
+            </child>
+          </object>
+        </child>
+        <child>
+          <object class="GtkShortcutsGroup">
+            <property name="title">Selected Item</property>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Open actions</property>
+                <property name="accelerator">Menu &lt;Shift&gt;F10</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Pin or unpin</property>
+                <property name="accelerator">&lt;Control&gt;p</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Move a pinned item up or down</property>
+                <property name="accelerator">&lt;Alt&gt;Up &lt;Alt&gt;Down</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Delete, while the list has focus</property>
+                <property name="accelerator">Delete</property>
+              </object>
+            </child>
+          </object>
+        </child>
+        <child>
+          <object class="GtkShortcutsGroup">
+            <property name="title">Transforms</property>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Copy a GIF as a still PNG</property>
+                <property name="accelerator">Menu&amp;s</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Copy HTML as plain text</property>
+                <property name="accelerator">Menu&amp;t</property>
+              </object>
+            </child>
+          </object>
+        </child>
+        <child>
+          <object class="GtkShortcutsGroup">
+            <property name="title">General</property>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Keyboard shortcuts</property>
+                <property name="accelerator">&lt;Control&gt;question</property>
+              </object>
+            </child>
+            <child>
+              <object class="GtkShortcutsShortcut">
+                <property name="title">Quit</property>
+                <property name="accelerator">&lt;Control&gt;q</property>
+              </object>
+            </child>
+          </object>
+        </child>
+      </object>
+    </child>
+  </object>
+</interface>
+"#;
+
+/// Build the cheatsheet, to be set as a window's help overlay.
+pub fn build() -> gtk4::ShortcutsWindow {
+    gtk4::Builder::from_string(UI)
+        .object("shortcuts")
+        .expect("shortcuts window is defined in the UI")
+}
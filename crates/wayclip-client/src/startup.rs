@@ -0,0 +1,55 @@
+//! Startup timing for `--measure-startup`.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+thread_local! {
+    static TIMER: RefCell<Option<Timer>> = const { RefCell::new(None) };
+}
+
+struct Timer {
+    start: Instant,
+    marks: Vec<(&'static str, Duration)>,
+}
+
+/// Start measuring from `start`, normally the moment `main` was entered.
+pub fn enable(start: Instant) {
+    TIMER.with_borrow_mut(|timer| {
+        *timer = Some(Timer {
+            start,
+            marks: Vec::new(),
+        })
+    });
+}
+
+/// Whether startup is being measured.
+pub fn enabled() -> bool {
+    TIMER.with_borrow(|timer| timer.is_some())
+}
+
+/// Record that startup reached `label`. Does nothing unless enabled.
+pub fn mark(label: &'static str) {
+    TIMER.with_borrow_mut(|timer| {
+        if let Some(timer) = timer {
+            timer.marks.push((label, timer.start.elapsed()));
+        }
+    });
+}
+
+/// Print the recorded timings to stderr and stop measuring.
+pub fn report() {
+    let Some(timer) = TIMER.take() else {
+        return;
+    };
+
+    let mut previous = Duration::ZERO;
+    for (label, at) in timer.marks {
+        eprintln!(
+            "{:>8.1} ms (+{:>6.1} ms)  {}",
+            at.as_secs_f64() * 1000.0,
+            (at - previous).as_secs_f64() * 1000.0,
+            label
+        );
+        previous = at;
+    }
+}
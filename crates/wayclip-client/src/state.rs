@@ -0,0 +1,230 @@
+//! What the client remembers between runs: recent and saved searches, the
+//! sort order, what activating an entry does, and how its windows were
+//! left. Kept in GSettings under the `com.wayclip.Client` schema, at a path
+//! of its own for each profile.
+
+use std::collections::BTreeMap;
+use std::sync::Once;
+
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use wayclip_common::HistorySort;
+
+use crate::window::{Activation, Mode};
+
+/// The GSettings schema, with the application's ID.
+const SCHEMA_ID: &str = "com.wayclip.Client";
+
+/// How many recent searches are remembered.
+const MAX_RECENT: usize = 10;
+
+/// A search kept under a name, such as "Jira tickets" for
+/// `/\b[A-Z]+-\d+\b/`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    /// Search text, which is a regular expression when written as
+    /// `/pattern/`.
+    pub query: String,
+}
+
+/// How a window was left, restored when one is opened in its mode again.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WindowState {
+    pub width: i32,
+    pub height: i32,
+    #[serde(default)]
+    pub maximized: bool,
+    /// Collection shown instead of the whole history.
+    #[serde(default)]
+    pub collection: Option<i64>,
+}
+
+/// Everything the client remembers. Deserialized only from the state file
+/// kept before GSettings, which is moved into it.
+#[derive(Debug, Default, Deserialize)]
+pub struct State {
+    /// Searches something was picked from, most recent first.
+    #[serde(default)]
+    pub recent: Vec<String>,
+    /// Saved searches in the order they were saved.
+    #[serde(default)]
+    pub saved: Vec<SavedSearch>,
+    /// Order the history is listed in.
+    #[serde(default)]
+    pub sort: HistorySort,
+    /// What activating an entry does. Newer than the state file.
+    #[serde(skip)]
+    pub activation: Activation,
+    /// Windows by the name of their mode.
+    #[serde(default)]
+    pub windows: BTreeMap<String, WindowState>,
+}
+
+impl State {
+    /// Read the active profile's settings. Without the schema installed
+    /// nothing is remembered.
+    pub fn load() -> Self {
+        match settings() {
+            Some(settings) => Self::read(&settings),
+            None => Self::default(),
+        }
+    }
+
+    fn read(settings: &gio::Settings) -> Self {
+        let saved: Vec<(String, String)> = settings.get("saved-searches");
+        let windows = [Mode::History, Mode::Snippets]
+            .into_iter()
+            .map(|mode| {
+                let key = |name: &str| format!("{}-{}", mode.name(), name);
+                let window = WindowState {
+                    width: settings.int(&key("width")),
+                    height: settings.int(&key("height")),
+                    maximized: settings.boolean(&key("maximized")),
+                    collection: Some(settings.int64(&key("collection"))).filter(|&id| id != 0),
+                };
+                (mode.name().to_string(), window)
+            })
+            .collect();
+
+        Self {
+            recent: settings
+                .strv("recent-searches")
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            saved: saved
+                .into_iter()
+                .map(|(name, query)| SavedSearch { name, query })
+                .collect(),
+            sort: HistorySort::from_name(&settings.string("sort-order")).unwrap_or_default(),
+            activation: Activation::from_name(&settings.string("activate-action"))
+                .unwrap_or_default(),
+            windows,
+        }
+    }
+
+    /// Write everything to `settings` at once.
+    fn write(&self, settings: &gio::Settings) -> Result<(), glib::BoolError> {
+        settings.delay();
+        let recent: Vec<&str> = self.recent.iter().map(String::as_str).collect();
+        settings.set_strv("recent-searches", recent.as_slice())?;
+        let saved: Vec<(String, String)> = self
+            .saved
+            .iter()
+            .map(|saved| (saved.name.clone(), saved.query.clone()))
+            .collect();
+        settings.set("saved-searches", saved)?;
+        settings.set_string("sort-order", self.sort.name())?;
+        settings.set_string("activate-action", self.activation.name())?;
+        for (mode, window) in &self.windows {
+            let key = |name: &str| format!("{}-{}", mode, name);
+            settings.set_int(&key("width"), window.width)?;
+            settings.set_int(&key("height"), window.height)?;
+            settings.set_boolean(&key("maximized"), window.maximized)?;
+            settings.set_int64(&key("collection"), window.collection.unwrap_or(0))?;
+        }
+        settings.apply();
+        Ok(())
+    }
+
+    /// Move `query` to the front of the recent searches.
+    pub fn remember(&mut self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() {
+            return;
+        }
+        self.recent.retain(|recent| recent != query);
+        self.recent.insert(0, query.to_string());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    /// Save `query` as `name`, replacing a saved search of that name.
+    pub fn save_search(&mut self, name: &str, query: &str) {
+        let search = SavedSearch {
+            name: name.to_string(),
+            query: query.to_string(),
+        };
+        match self.saved.iter_mut().find(|saved| saved.name == name) {
+            Some(saved) => *saved = search,
+            None => self.saved.push(search),
+        }
+    }
+
+    /// Forget the saved search called `name`.
+    pub fn forget(&mut self, name: &str) {
+        self.saved.retain(|saved| saved.name != name);
+    }
+
+    /// How the window in `mode` was left, if one was.
+    pub fn window(&self, mode: Mode) -> Option<WindowState> {
+        self.windows.get(mode.name()).copied()
+    }
+
+    /// Remember how the window in `mode` was left.
+    pub fn set_window(&mut self, mode: Mode, window: WindowState) {
+        self.windows.insert(mode.name().to_string(), window);
+    }
+}
+
+/// Apply `change` to the active profile's settings, logging a failure to
+/// write them.
+pub fn update(change: impl FnOnce(&mut State)) {
+    let Some(settings) = settings() else {
+        return;
+    };
+    let mut state = State::read(&settings);
+    change(&mut state);
+    if let Err(e) = state.write(&settings) {
+        error!("Failed to save client settings: {}", e);
+    }
+}
+
+/// The active profile's settings, or `None` if the schema isn't installed.
+/// A state file left from before GSettings is moved into them.
+fn settings() -> Option<gio::Settings> {
+    static MISSING: Once = Once::new();
+
+    let Some(schema) =
+        gio::SettingsSchemaSource::default().and_then(|source| source.lookup(SCHEMA_ID, true))
+    else {
+        MISSING.call_once(|| {
+            warn!(
+                "The {} GSettings schema is not installed; searches and window sizes won't be \
+                 remembered",
+                SCHEMA_ID
+            );
+        });
+        return None;
+    };
+    let path = match wayclip_common::profile() {
+        Some(profile) => format!("/com/wayclip/Client/profiles/{}/", profile),
+        None => "/com/wayclip/Client/".to_string(),
+    };
+    let settings = gio::Settings::new_full(&schema, None::<&gio::SettingsBackend>, Some(&path));
+    migrate_state_file(&settings);
+    Some(settings)
+}
+
+/// Move the state file kept before GSettings, if there is one, into
+/// `settings`.
+fn migrate_state_file(settings: &gio::Settings) {
+    let path = wayclip_common::client_state_path();
+    let Ok(data) = std::fs::read(&path) else {
+        return;
+    };
+    match serde_json::from_slice::<State>(&data) {
+        Ok(state) => {
+            if let Err(e) = state.write(settings) {
+                error!("Failed to move {} into GSettings: {}", path.display(), e);
+                return;
+            }
+            info!("Moved {} into GSettings", path.display());
+        }
+        Err(e) => warn!("Dropping unreadable {}: {}", path.display(), e),
+    }
+    let _ = std::fs::remove_file(&path);
+}
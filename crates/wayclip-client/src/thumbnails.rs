@@ -0,0 +1,127 @@
+//! Decoding thumbnails off the main thread, only for items whose rows are
+//! on screen, so scrolling past many images stays smooth. Decoded
+//! thumbnails are kept for the same entries in later loads of the list.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use gtk4::prelude::*;
+use gtk4::{gdk, gio, glib};
+use tracing::debug;
+
+use crate::clipboard_item::ClipboardItem;
+
+/// Thumbnails decoded at once; more would compete with drawing the list.
+const MAX_RUNNING: usize = 2;
+
+/// Decoded thumbnails kept for later loads of the list.
+const MAX_KEPT: usize = 300;
+
+#[derive(Default)]
+struct Queue {
+    /// Items waiting for a worker, the one shown last first.
+    waiting: RefCell<VecDeque<glib::WeakRef<ClipboardItem>>>,
+    /// Items being decoded.
+    running: RefCell<Vec<ClipboardItem>>,
+    /// Entry ID, content hash and thumbnail of the entries decoded last,
+    /// newest first.
+    kept: RefCell<VecDeque<(i64, String, gdk::Texture)>>,
+}
+
+thread_local! {
+    static QUEUE: Queue = Queue::default();
+}
+
+/// Decode `item`'s thumbnail once a worker is free, unless it has one
+/// already or is on its way to.
+///
+/// The item asked for last goes first, so while scrolling the rows that
+/// just came into view are filled in before those that scrolled past.
+pub fn request(item: &ClipboardItem) {
+    if item.thumbnail_source().is_none() {
+        return;
+    }
+    QUEUE.with(|queue| {
+        let kept = queue
+            .kept
+            .borrow()
+            .iter()
+            .find(|(id, hash, _)| *id == item.id() && *hash == item.hash())
+            .map(|(_, _, thumbnail)| thumbnail.clone());
+        if let Some(thumbnail) = kept {
+            item.finish_thumbnail(Some(thumbnail));
+            return;
+        }
+        if queue.running.borrow().contains(item) {
+            return;
+        }
+        let mut waiting = queue.waiting.borrow_mut();
+        waiting.retain(|weak| weak.upgrade().is_some_and(|waiting| &waiting != item));
+        waiting.push_front(item.downgrade());
+    });
+    run_next();
+}
+
+/// Stop waiting to decode `item`'s thumbnail, as its row scrolled away.
+/// One already being decoded is finished.
+pub fn cancel(item: &ClipboardItem) {
+    QUEUE.with(|queue| {
+        queue
+            .waiting
+            .borrow_mut()
+            .retain(|weak| weak.upgrade().is_some_and(|waiting| &waiting != item));
+    });
+}
+
+/// Drop the kept thumbnail of entry `id`, which was deleted or changed.
+pub fn invalidate(id: i64) {
+    QUEUE.with(|queue| queue.kept.borrow_mut().retain(|(kept, _, _)| *kept != id));
+}
+
+/// Drop all kept thumbnails, as when switching to another profile.
+pub fn clear() {
+    QUEUE.with(|queue| queue.kept.borrow_mut().clear());
+}
+
+/// Start decoding waiting thumbnails while fewer than `MAX_RUNNING` are.
+fn run_next() {
+    QUEUE.with(|queue| {
+        while queue.running.borrow().len() < MAX_RUNNING {
+            let Some(weak) = queue.waiting.borrow_mut().pop_front() else {
+                return;
+            };
+            let Some(item) = weak.upgrade() else {
+                continue;
+            };
+            let Some(source) = item.thumbnail_source() else {
+                continue;
+            };
+
+            queue.running.borrow_mut().push(item.clone());
+            let decoded = gio::spawn_blocking(move || source.decode());
+            glib::spawn_future_local(async move {
+                let thumbnail = match decoded.await {
+                    Ok(Ok(thumbnail)) => Some(thumbnail),
+                    Ok(Err(e)) => {
+                        debug!("Failed to decode thumbnail of {}: {}", item.id(), e);
+                        None
+                    }
+                    Err(_) => None,
+                };
+                QUEUE.with(|queue| {
+                    queue
+                        .running
+                        .borrow_mut()
+                        .retain(|running| running != &item);
+                    if let Some(thumbnail) = &thumbnail {
+                        let mut kept = queue.kept.borrow_mut();
+                        kept.push_front((item.id(), item.hash(), thumbnail.clone()));
+                        kept.truncate(MAX_KEPT);
+                    }
+                });
+                item.finish_thumbnail(thumbnail);
+                run_next();
+            });
+        }
+    });
+}
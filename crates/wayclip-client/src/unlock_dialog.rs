@@ -0,0 +1,106 @@
+//! Prompt for the passphrase of a locked history.
+
+use gtk4::glib::{self, clone};
+use gtk4::prelude::*;
+
+/// Show the prompt. `on_unlock` gets the passphrase and returns an error
+/// message to show if it didn't work, keeping the prompt open to try again.
+pub fn show(
+    parent: &impl IsA<gtk4::Window>,
+    on_unlock: impl Fn(String) -> Result<(), String> + 'static,
+) {
+    let dialog = gtk4::Window::builder()
+        .title("Unlock History")
+        .transient_for(parent)
+        .modal(true)
+        .resizable(false)
+        .default_width(320)
+        .build();
+
+    let content = gtk4::Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(8)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+
+    let label =
+        gtk4::Label::with_mnemonic("The clipboard history is locked. Enter its _passphrase:");
+    label.set_xalign(0.0);
+    label.set_wrap(true);
+    content.append(&label);
+
+    let entry = gtk4::PasswordEntry::new();
+    entry.set_show_peek_icon(true);
+    label.set_mnemonic_widget(Some(&entry));
+    content.append(&entry);
+
+    // Read out by screen readers when it changes
+    let message = gtk4::Label::builder()
+        .accessible_role(gtk4::AccessibleRole::Alert)
+        .xalign(0.0)
+        .css_classes(["error"])
+        .visible(false)
+        .build();
+    content.append(&message);
+
+    let buttons = gtk4::Box::new(gtk4::Orientation::Horizontal, 8);
+    buttons.set_halign(gtk4::Align::End);
+    buttons.set_margin_top(4);
+    let cancel = gtk4::Button::with_mnemonic("_Cancel");
+    let unlock = gtk4::Button::with_mnemonic("_Unlock");
+    unlock.add_css_class("suggested-action");
+    buttons.append(&cancel);
+    buttons.append(&unlock);
+    content.append(&buttons);
+
+    cancel.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| dialog.close()
+    ));
+    unlock.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        #[weak]
+        entry,
+        #[weak]
+        message,
+        move |_| match on_unlock(entry.text().to_string()) {
+            Ok(()) => dialog.close(),
+            Err(e) => {
+                message.set_label(&e);
+                message.set_visible(true);
+                entry.set_text("");
+                entry.grab_focus();
+            }
+        }
+    ));
+
+    // Enter unlocks
+    dialog.set_default_widget(Some(&unlock));
+    entry.set_activates_default(true);
+
+    let escape = gtk4::EventControllerKey::new();
+    escape.connect_key_pressed(clone!(
+        #[weak]
+        dialog,
+        #[upgrade_or]
+        glib::Propagation::Proceed,
+        move |_, key, _, _| {
+            if key == gtk4::gdk::Key::Escape {
+                dialog.close();
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    ));
+    dialog.add_controller(escape);
+
+    dialog.set_child(Some(&content));
+    dialog.present();
+    entry.grab_focus();
+}
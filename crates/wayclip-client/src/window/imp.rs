@@ -1,37 +1,119 @@
 //! WayclipWindow implementation.
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use gtk4::gio::ListStore;
 use gtk4::glib;
+use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
-use gtk4::{CustomFilter, FilterListModel, Label, ListView, SearchEntry, SingleSelection};
+use gtk4::{
+    Button, DropDown, Image, Label, ListBox, ListView, MenuButton, PopoverMenu, ScrolledWindow,
+    SearchEntry, SingleSelection, Stack,
+};
+use wayclip_common::{Collection, HistorySort, OriginKind};
 
+use super::{Activation, Mode};
 use crate::clipboard_item::ClipboardItem;
+use crate::compositor::FocusedWindow;
+use crate::content_cache::ContentCache;
+use crate::ipc::{CancelHandle, IpcClient};
 
 pub struct WayclipWindow {
+    pub mode: Cell<Mode>,
     pub search_entry: SearchEntry,
+    /// Recent and saved searches, listed afresh each time it opens.
+    pub searches_button: MenuButton,
+    pub sort_button: MenuButton,
+    pub profile_switcher: DropDown,
+    /// Named profiles listed in the switcher, after "Default".
+    pub profiles: RefCell<Vec<String>>,
     pub list_view: ListView,
+    /// The list, or a page saying why there is nothing in it.
+    pub view_stack: Stack,
+    pub placeholder_icon: Image,
+    pub placeholder_title: Label,
+    pub placeholder_description: Label,
+    /// Shown on the placeholder page when the daemon isn't running.
+    pub start_daemon_button: Button,
+    /// Why the daemon stopped capturing the clipboard, as of the last load.
+    pub capture_error: RefCell<Option<String>>,
     pub status_label: Label,
+    pub context_menu: PopoverMenu,
+    pub sidebar: ListBox,
+    pub sidebar_scroll: ScrolledWindow,
+    pub collections: RefCell<Vec<Collection>>,
+    pub current_collection: Cell<Option<i64>>,
+    /// Image whose look-alikes are shown instead of the whole history.
+    pub similar_to: Cell<Option<i64>>,
+    /// Origin whose entries are shown instead of the whole history.
+    pub origin: Cell<Option<OriginKind>>,
+    /// Order of the entries after the pinned ones.
+    pub sort: Cell<HistorySort>,
+    /// What activating an entry does.
+    pub activation: Cell<Activation>,
+    /// Entry picked with "Select for Compare".
+    pub compare_mark: Cell<Option<i64>>,
+    /// Connection to the daemon, opened on first use.
+    pub ipc: RefCell<Option<IpcClient>>,
+    /// Bumped on every reload so a stale deferred load can tell it is stale.
+    pub load_generation: Cell<u32>,
+    /// Search still waiting on the daemon, abandoned when the text changes.
+    pub search_cancel: RefCell<Option<CancelHandle>>,
+    /// Connection the last finished search used, kept for the next one.
+    pub search_ipc: RefCell<Option<IpcClient>>,
+    /// Closes the connection events are followed on, so it is made again.
+    pub events_cancel: RefCell<Option<CancelHandle>>,
+    /// Contents fetched for comparing, sharing and the like.
+    pub contents: RefCell<ContentCache>,
     pub model: ListStore,
-    pub filter: RefCell<Option<CustomFilter>>,
-    pub filter_model: RefCell<Option<FilterListModel>>,
+    /// Entries the daemon has for the current view, loaded or not.
+    pub total_count: Cell<u64>,
     pub selection_model: RefCell<Option<SingleSelection>>,
+    /// Window that had focus before this one opened, focused again once an
+    /// entry is picked.
+    pub return_focus: RefCell<Option<FocusedWindow>>,
 }
 
 impl Default for WayclipWindow {
     fn default() -> Self {
         Self {
+            mode: Cell::new(Mode::default()),
             search_entry: SearchEntry::new(),
+            searches_button: MenuButton::new(),
+            sort_button: MenuButton::new(),
+            profile_switcher: DropDown::from_strings(&[]),
+            profiles: RefCell::new(Vec::new()),
             list_view: ListView::new(
                 None::<SingleSelection>,
                 None::<gtk4::SignalListItemFactory>,
             ),
+            view_stack: Stack::new(),
+            placeholder_icon: Image::new(),
+            placeholder_title: Label::new(None),
+            placeholder_description: Label::new(None),
+            start_daemon_button: Button::with_mnemonic("_Start Daemon"),
+            capture_error: RefCell::new(None),
             status_label: Label::new(None),
+            context_menu: PopoverMenu::from_model(None::<&gtk4::gio::MenuModel>),
+            sidebar: ListBox::new(),
+            sidebar_scroll: ScrolledWindow::new(),
+            collections: RefCell::new(Vec::new()),
+            current_collection: Cell::new(None),
+            similar_to: Cell::new(None),
+            origin: Cell::new(None),
+            sort: Cell::new(HistorySort::default()),
+            activation: Cell::new(Activation::default()),
+            compare_mark: Cell::new(None),
+            ipc: RefCell::new(None),
+            load_generation: Cell::new(0),
+            search_cancel: RefCell::new(None),
+            search_ipc: RefCell::new(None),
+            events_cancel: RefCell::new(None),
+            contents: RefCell::new(ContentCache::default()),
             model: ListStore::new::<ClipboardItem>(),
-            filter: RefCell::new(None),
-            filter_model: RefCell::new(None),
+            total_count: Cell::new(0),
             selection_model: RefCell::new(None),
+            return_focus: RefCell::new(None),
         }
     }
 }
@@ -43,7 +125,11 @@ impl ObjectSubclass for WayclipWindow {
     type ParentType = gtk4::ApplicationWindow;
 }
 
-impl ObjectImpl for WayclipWindow {}
+impl ObjectImpl for WayclipWindow {
+    fn dispose(&self) {
+        self.context_menu.unparent();
+    }
+}
 impl WidgetImpl for WayclipWindow {}
 impl WindowImpl for WayclipWindow {}
 impl ApplicationWindowImpl for WayclipWindow {}
@@ -2,15 +2,112 @@
 
 mod imp;
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use glib::Object;
 use gtk4::glib::{self, clone};
 use gtk4::prelude::*;
 use gtk4::subclass::prelude::*;
-use gtk4::Application;
+use gtk4::{gdk, gio, Application};
 use tracing::{debug, error, info};
+use wayclip_common::{DaemonEvent, HistorySort, OriginKind, Transform};
 
 use crate::clipboard_item::ClipboardItem;
-use crate::ipc::IpcClient;
+use crate::compositor::FocusedWindow;
+use crate::ipc::{HistoryQuery, IpcClient, Locked, NotResponding, NotRunning};
+use crate::item_row::ItemRow;
+use crate::startup;
+use crate::state::{self, State, WindowState};
+use crate::thumbnails;
+
+/// Entries fetched before the window is first drawn; the rest follow once it
+/// is on screen.
+const FIRST_PAGE: u32 = 20;
+
+/// Entries shown in the list.
+const HISTORY_LIMIT: u32 = 100;
+
+/// How long to wait before loading the history again when the daemon didn't
+/// answer.
+const RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long the window focused again gets to take focus before the entry is
+/// pasted into it.
+const PASTE_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+
+/// How often to check whether a daemon started from the window answers,
+/// and how many times before giving up.
+const DAEMON_START_POLL: std::time::Duration = std::time::Duration::from_millis(200);
+const DAEMON_START_ATTEMPTS: u32 = 25;
+
+/// What the window shows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The full clipboard history.
+    #[default]
+    History,
+    /// A compact picker with only pinned entries and entries in collections.
+    Snippets,
+}
+
+impl Mode {
+    /// Parse a mode from its command-line name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "history" => Some(Self::History),
+            "snippets" => Some(Self::Snippets),
+            _ => None,
+        }
+    }
+
+    /// The mode's command-line name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::History => "history",
+            Self::Snippets => "snippets",
+        }
+    }
+}
+
+/// What happens when an entry is activated with Enter or a click.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    /// Copy it to the clipboard and close the window.
+    #[default]
+    CopyAndClose,
+    /// Copy it to the clipboard and leave the window open to pick more.
+    CopyAndStay,
+    /// Copy it, close the window and paste it into the window focused
+    /// before.
+    CopyAndPaste,
+    /// Put it in the primary selection, for middle-click paste, and close
+    /// the window.
+    Primary,
+}
+
+impl Activation {
+    /// Parse an activation from its setting and command-line name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "copy_and_close" => Some(Self::CopyAndClose),
+            "copy_and_stay" => Some(Self::CopyAndStay),
+            "copy_and_paste" => Some(Self::CopyAndPaste),
+            "primary" => Some(Self::Primary),
+            _ => None,
+        }
+    }
+
+    /// The activation's setting and command-line name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CopyAndClose => "copy_and_close",
+            Self::CopyAndStay => "copy_and_stay",
+            Self::CopyAndPaste => "copy_and_paste",
+            Self::Primary => "primary",
+        }
+    }
+}
 
 glib::wrapper! {
     /// The main wayclip window.
@@ -22,23 +119,154 @@ glib::wrapper! {
 }
 
 impl WayclipWindow {
-    /// Create a new window.
-    pub fn new(app: &Application) -> Self {
+    /// Create a new window, the size it was last left at in `mode`.
+    pub fn new(app: &Application, mode: Mode) -> Self {
+        let state = State::load();
+        let saved = state.window(mode);
+        let (width, height) = match (saved, mode) {
+            (Some(saved), _) => (saved.width, saved.height),
+            (None, Mode::History) => (450, 500),
+            (None, Mode::Snippets) => (360, 420),
+        };
         let window: Self = Object::builder()
             .property("application", app)
-            .property("title", "Wayclip")
-            .property("default-width", 450)
-            .property("default-height", 500)
+            .property("default-width", width)
+            .property("default-height", height)
+            .property("maximized", saved.is_some_and(|saved| saved.maximized))
             .build();
 
+        window.imp().mode.set(mode);
+        window
+            .imp()
+            .current_collection
+            .set(saved.and_then(|saved| saved.collection));
+        window.imp().sort.set(state.sort);
         window.setup_widgets();
+        window.setup_actions();
         window.setup_callbacks();
         window.setup_shortcuts();
         window.load_history();
+        window.follow_events();
 
         window
     }
 
+    /// Get the window ready to be shown for another launch of `wayclip`.
+    ///
+    /// A hidden window, or one switching to another mode, starts over with an
+    /// empty search and a fresh history. `search` replaces the search text.
+    pub fn reopen(&self, mode: Mode, search: Option<&str>) {
+        let imp = self.imp();
+
+        let mode_changed = mode != imp.mode.get();
+        if mode_changed {
+            self.save_state();
+            imp.mode.set(mode);
+            imp.current_collection.set(
+                State::load()
+                    .window(mode)
+                    .and_then(|saved| saved.collection),
+            );
+            self.apply_mode();
+        }
+        if self.is_visible() && !mode_changed {
+            if let Some(search) = search {
+                imp.search_entry.set_text(search);
+            }
+            return;
+        }
+
+        imp.context_menu.popdown();
+        imp.similar_to.set(None);
+        imp.origin.set(None);
+        imp.search_entry.set_text(search.unwrap_or(""));
+        self.load_history();
+
+        if let Some(selection_model) = imp.selection_model.borrow().as_ref() {
+            selection_model.set_selected(0);
+        }
+        if imp.model.n_items() > 0 {
+            imp.list_view
+                .scroll_to(0, gtk4::ListScrollFlags::NONE, None);
+        }
+    }
+
+    /// Remember the window's size and collection for its mode.
+    fn save_state(&self) {
+        let (width, height) = self.default_size();
+        let window = WindowState {
+            width,
+            height,
+            maximized: self.is_maximized(),
+            collection: self.imp().current_collection.get(),
+        };
+        let mode = self.imp().mode.get();
+        state::update(|state| state.set_window(mode, window));
+    }
+
+    /// Remember which window has focus, before this one is shown and takes
+    /// it, so picking an entry can hand focus back.
+    pub fn remember_focus(&self) {
+        self.imp().return_focus.replace(FocusedWindow::current());
+    }
+
+    /// Close after an entry was picked, focusing the window that had focus
+    /// before, so the entry can be pasted there straight away.
+    fn finish(&self) {
+        self.close();
+        if let Some(window) = self.imp().return_focus.take() {
+            window.focus();
+        }
+    }
+
+    /// Close like `finish`, then paste the entry into the window focused
+    /// again once it has had a moment to take focus.
+    fn finish_and_paste(&self) {
+        let target = self.imp().return_focus.borrow().clone();
+        // Without a window left the application would quit before pasting
+        let hold = self.application().map(|app| app.hold());
+        self.finish();
+        glib::timeout_add_local_once(PASTE_DELAY, move || {
+            crate::compositor::paste(target.as_ref());
+            drop(hold);
+        });
+    }
+
+    /// Use `activation` for entries activated until the next launch, or the
+    /// one set in the settings when `None`.
+    pub fn set_activation(&self, activation: Option<Activation>) {
+        let activation = activation.unwrap_or_else(|| State::load().activation);
+        self.imp().activation.set(activation);
+    }
+
+    /// Start with `text` in the search field.
+    pub fn set_search(&self, text: &str) {
+        self.imp().search_entry.set_text(text);
+    }
+
+    /// Update the title, search field and list for the current mode.
+    fn apply_mode(&self) {
+        let imp = self.imp();
+
+        let placeholder = match imp.mode.get() {
+            Mode::History => "Search clipboard history...",
+            Mode::Snippets => "Search snippets...",
+        };
+        imp.search_entry.set_placeholder_text(Some(placeholder));
+
+        // Accessibility for search
+        imp.search_entry
+            .update_property(&[gtk4::accessible::Property::Label(
+                placeholder.trim_end_matches('.'),
+            )]);
+
+        // The snippet picker inserts on a single click
+        imp.list_view
+            .set_single_click_activate(imp.mode.get() == Mode::Snippets);
+
+        self.update_title();
+    }
+
     fn setup_widgets(&self) {
         let imp = self.imp();
 
@@ -46,8 +274,6 @@ impl WayclipWindow {
         let main_box = gtk4::Box::new(gtk4::Orientation::Vertical, 0);
 
         // Search entry
-        imp.search_entry
-            .set_placeholder_text(Some("Search clipboard history..."));
         imp.search_entry.set_hexpand(true);
         imp.search_entry.set_margin_top(12);
         imp.search_entry.set_margin_bottom(12);
@@ -55,48 +281,76 @@ impl WayclipWindow {
         imp.search_entry.set_margin_end(12);
         imp.search_entry.set_search_delay(150);
 
-        // Accessibility for search
-        imp.search_entry.update_property(&[
-            gtk4::accessible::Property::Label("Search clipboard history"),
-        ]);
+        imp.searches_button
+            .set_icon_name("document-open-recent-symbolic");
+        imp.searches_button
+            .set_tooltip_text(Some("Recent and Saved Searches"));
+        imp.searches_button
+            .update_property(&[gtk4::accessible::Property::Label(
+                "Recent and Saved Searches",
+            )]);
+        imp.searches_button.set_valign(gtk4::Align::Center);
+        imp.searches_button.set_margin_end(12);
+        imp.searches_button.set_create_popup_func(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |button| button.set_menu_model(Some(&window.searches_menu()))
+        ));
 
-        main_box.append(&imp.search_entry);
+        let sort_menu = gio::Menu::new();
+        for (label, sort) in [
+            ("_Recent", HistorySort::Recent),
+            ("_Most Used", HistorySort::MostUsed),
+            ("_Largest", HistorySort::Largest),
+            ("_Oldest", HistorySort::Oldest),
+        ] {
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(Some("win.sort"), Some(&sort.name().to_variant()));
+            sort_menu.append_item(&item);
+        }
+        imp.sort_button
+            .set_icon_name("view-sort-descending-symbolic");
+        imp.sort_button.set_tooltip_text(Some("Sort By"));
+        imp.sort_button
+            .update_property(&[gtk4::accessible::Property::Label("Sort By")]);
+        imp.sort_button.set_valign(gtk4::Align::Center);
+        imp.sort_button.set_margin_end(12);
+        imp.sort_button.set_menu_model(Some(&sort_menu));
 
-        // Create filter
-        let filter = gtk4::CustomFilter::new(clone!(
-            #[weak(rename_to = search_entry)]
-            imp.search_entry,
-            #[upgrade_or]
-            false,
-            move |obj| {
-                let item = obj.downcast_ref::<ClipboardItem>().unwrap();
-                let search_text = search_entry.text().to_lowercase();
-                if search_text.is_empty() {
-                    return true;
-                }
-                item.preview().to_lowercase().contains(&search_text)
-            }
-        ));
+        // Profile switcher, only shown once there are named profiles
+        imp.profile_switcher.set_valign(gtk4::Align::Center);
+        imp.profile_switcher.set_margin_end(12);
+        imp.profile_switcher
+            .update_property(&[gtk4::accessible::Property::Label("Profile")]);
+        self.load_profiles();
 
-        imp.filter.replace(Some(filter.clone()));
+        let header_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+        header_box.append(&imp.search_entry);
+        header_box.append(&imp.searches_button);
+        header_box.append(&imp.sort_button);
+        header_box.append(&imp.profile_switcher);
+        main_box.append(&header_box);
 
-        let filter_model = gtk4::FilterListModel::new(Some(imp.model.clone()), Some(filter));
-        let selection_model = gtk4::SingleSelection::new(Some(filter_model.clone()));
+        // The daemon does the searching, so the list shows the model as is
+        let selection_model = gtk4::SingleSelection::new(Some(imp.model.clone()));
         selection_model.set_autoselect(true);
         selection_model.set_can_unselect(false);
 
-        imp.filter_model.replace(Some(filter_model));
-        imp.selection_model
-            .replace(Some(selection_model.clone()));
+        imp.selection_model.replace(Some(selection_model.clone()));
 
         // Factory
         let factory = gtk4::SignalListItemFactory::new();
 
-        factory.connect_setup(|_, list_item| {
-            let list_item = list_item.downcast_ref::<gtk4::ListItem>().unwrap();
-            let row = crate::item_row::ItemRow::new();
-            list_item.set_child(Some(&row));
-        });
+        factory.connect_setup(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, list_item| {
+                let list_item = list_item.downcast_ref::<gtk4::ListItem>().unwrap();
+                let row = crate::item_row::ItemRow::new();
+                window.setup_pinned_drag(&row);
+                list_item.set_child(Some(&row));
+            }
+        ));
 
         factory.connect_bind(|_, list_item| {
             let list_item = list_item.downcast_ref::<gtk4::ListItem>().unwrap();
@@ -108,11 +362,24 @@ impl WayclipWindow {
             row.bind(&item);
         });
 
+        // Recycled rows stop following their old item before showing another
+        factory.connect_unbind(|_, list_item| {
+            let list_item = list_item.downcast_ref::<gtk4::ListItem>().unwrap();
+            if let Some(row) = list_item.child().and_downcast::<crate::item_row::ItemRow>() {
+                row.unbind();
+            }
+        });
+
+        factory.connect_teardown(|_, list_item| {
+            let list_item = list_item.downcast_ref::<gtk4::ListItem>().unwrap();
+            list_item.set_child(None::<&gtk4::Widget>);
+        });
+
         // ListView
         imp.list_view.set_model(Some(&selection_model));
         imp.list_view.set_factory(Some(&factory));
-        imp.list_view.set_single_click_activate(false);
         imp.list_view.add_css_class("navigation-sidebar");
+        self.apply_mode();
 
         // Scrolled window
         let scrolled = gtk4::ScrolledWindow::builder()
@@ -122,7 +389,72 @@ impl WayclipWindow {
             .child(&imp.list_view)
             .build();
 
-        main_box.append(&scrolled);
+        // Collections sidebar, only shown once there are collections
+        imp.sidebar.set_selection_mode(gtk4::SelectionMode::Single);
+        imp.sidebar.add_css_class("navigation-sidebar");
+        imp.sidebar
+            .update_property(&[gtk4::accessible::Property::Label("Collections")]);
+        imp.sidebar_scroll
+            .set_policy(gtk4::PolicyType::Never, gtk4::PolicyType::Automatic);
+        imp.sidebar_scroll.set_width_request(150);
+        imp.sidebar_scroll.set_child(Some(&imp.sidebar));
+        imp.sidebar_scroll.set_visible(false);
+
+        // Page shown instead of the list when there is nothing to list,
+        // saying why
+        imp.placeholder_icon.set_pixel_size(64);
+        imp.placeholder_icon.add_css_class("dim-label");
+        imp.placeholder_title.add_css_class("title-2");
+        imp.placeholder_title.set_wrap(true);
+        imp.placeholder_title
+            .set_justify(gtk4::Justification::Center);
+        imp.placeholder_description.set_wrap(true);
+        imp.placeholder_description
+            .set_justify(gtk4::Justification::Center);
+        imp.start_daemon_button.set_halign(gtk4::Align::Center);
+        imp.start_daemon_button.add_css_class("suggested-action");
+        imp.start_daemon_button.connect_clicked(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| window.start_daemon()
+        ));
+
+        let placeholder = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+        placeholder.set_valign(gtk4::Align::Center);
+        placeholder.set_margin_start(24);
+        placeholder.set_margin_end(24);
+        placeholder.append(&imp.placeholder_icon);
+        placeholder.append(&imp.placeholder_title);
+        placeholder.append(&imp.placeholder_description);
+        placeholder.append(&imp.start_daemon_button);
+
+        imp.view_stack.add_named(&scrolled, Some("list"));
+        imp.view_stack.add_named(&placeholder, Some("placeholder"));
+        imp.view_stack
+            .set_transition_type(gtk4::StackTransitionType::Crossfade);
+        imp.view_stack.set_hexpand(true);
+
+        let content_box = gtk4::Box::new(gtk4::Orientation::Horizontal, 0);
+        content_box.append(&imp.sidebar_scroll);
+        content_box.append(&imp.view_stack);
+
+        main_box.append(&content_box);
+
+        // Context menu for the selected item, filled in when opened
+        imp.context_menu.set_parent(&imp.list_view);
+        imp.context_menu.set_has_arrow(false);
+
+        // Tell screen readers what the search and sidebar filter, and read
+        // the item count along with the list
+        let list: &gtk4::Accessible = imp.list_view.upcast_ref();
+        imp.search_entry
+            .update_relation(&[gtk4::accessible::Relation::Controls(&[list])]);
+        imp.sidebar
+            .update_relation(&[gtk4::accessible::Relation::Controls(&[list])]);
+        imp.list_view
+            .update_relation(&[gtk4::accessible::Relation::DescribedBy(&[imp
+                .status_label
+                .upcast_ref()])]);
 
         // Status bar with item count
         imp.status_label.set_xalign(0.0);
@@ -135,171 +467,2020 @@ impl WayclipWindow {
         self.set_child(Some(&main_box));
     }
 
-    fn setup_callbacks(&self) {
-        let imp = self.imp();
-
-        // Search changed
-        imp.search_entry.connect_search_changed(clone!(
+    fn setup_actions(&self) {
+        // Copy the selected item through a transform, e.g. "win.copy-as::still_png"
+        let copy_as = gio::SimpleAction::new("copy-as", Some(glib::VariantTy::STRING));
+        copy_as.connect_activate(clone!(
             #[weak(rename_to = window)]
             self,
-            move |_| {
-                window.on_search_changed();
+            move |_, parameter| {
+                let Some(transform) = parameter
+                    .and_then(|p| p.str())
+                    .and_then(Transform::from_name)
+                else {
+                    return;
+                };
+                window.copy_selected_as(transform);
             }
         ));
+        self.add_action(&copy_as);
 
-        // List item activated
-        imp.list_view.connect_activate(clone!(
+        let copy_primary = gio::SimpleAction::new("copy-primary", None);
+        copy_primary.connect_activate(clone!(
             #[weak(rename_to = window)]
             self,
-            move |_, position| {
-                window.on_item_activated(position);
+            move |_, _| {
+                window.copy_selected_to_primary();
             }
         ));
-    }
-
-    fn setup_shortcuts(&self) {
-        let controller = gtk4::EventControllerKey::new();
+        self.add_action(&copy_primary);
 
-        controller.connect_key_pressed(clone!(
+        let add_to_collection =
+            gio::SimpleAction::new("add-to-collection", Some(glib::VariantTy::INT64));
+        add_to_collection.connect_activate(clone!(
             #[weak(rename_to = window)]
             self,
-            #[upgrade_or]
-            glib::Propagation::Proceed,
-            move |_, key, _, modifier| window.on_key_pressed(key, modifier)
+            move |_, parameter| {
+                if let Some(collection_id) = parameter.and_then(|p| p.get::<i64>()) {
+                    window.add_selected_to_collection(collection_id);
+                }
+            }
         ));
+        self.add_action(&add_to_collection);
 
-        self.add_controller(controller);
-    }
-
-    fn on_search_changed(&self) {
-        let imp = self.imp();
-        if let Some(filter) = imp.filter.borrow().as_ref() {
-            filter.changed(gtk4::FilterChange::Different);
-        }
-        self.update_status();
-    }
-
-    fn on_item_activated(&self, position: u32) {
-        let imp = self.imp();
+        let remove_from_collection = gio::SimpleAction::new("remove-from-collection", None);
+        remove_from_collection.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.remove_selected_from_collection();
+            }
+        ));
+        self.add_action(&remove_from_collection);
 
-        let Some(selection_model) = imp.selection_model.borrow().clone() else {
-            return;
-        };
+        let copy_part = gio::SimpleAction::new("copy-part", None);
+        copy_part.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.copy_part_of_selected();
+            }
+        ));
+        self.add_action(&copy_part);
 
-        let Some(item) = selection_model
-            .item(position)
-            .and_downcast::<ClipboardItem>()
-        else {
-            return;
-        };
+        let split_lines = gio::SimpleAction::new("split-lines", None);
+        split_lines.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.split_selected();
+            }
+        ));
+        self.add_action(&split_lines);
 
-        info!(
-            "Activating item: {} (id={})",
-            item.preview(),
-            item.id()
-        );
+        let ignore_app = gio::SimpleAction::new("ignore-app", None);
+        ignore_app.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.ignore_selected_app();
+            }
+        ));
+        self.add_action(&ignore_app);
 
-        // Copy to clipboard via daemon (synchronous, quick operation)
-        let item_id = item.id();
-        match self.copy_item_to_clipboard(item_id) {
-            Ok(()) => {
-                info!("Successfully copied item {} to clipboard", item_id);
-                self.close();
+        let save_as = gio::SimpleAction::new("save-as", None);
+        save_as.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.save_selected();
             }
-            Err(e) => {
-                error!("Failed to copy item: {}", e);
+        ));
+        self.add_action(&save_as);
+
+        let share = gio::SimpleAction::new("share", None);
+        share.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.share_selected();
             }
-        }
-    }
+        ));
+        self.add_action(&share);
 
-    fn on_key_pressed(
-        &self,
-        key: gtk4::gdk::Key,
-        modifier: gtk4::gdk::ModifierType,
-    ) -> glib::Propagation {
-        use gtk4::gdk::Key;
+        let queue_paste = gio::SimpleAction::new("queue-paste", None);
+        queue_paste.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.queue_selected();
+            }
+        ));
+        self.add_action(&queue_paste);
 
-        let imp = self.imp();
+        let edit_meta = gio::SimpleAction::new("edit-meta", None);
+        edit_meta.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.edit_selected_meta();
+            }
+        ));
+        self.add_action(&edit_meta);
 
-        match key {
-            // Escape: Clear search or close
-            Key::Escape => {
-                if !imp.search_entry.text().is_empty() {
-                    imp.search_entry.set_text("");
-                    glib::Propagation::Stop
-                } else {
-                    self.close();
-                    glib::Propagation::Stop
+        let select_for_compare = gio::SimpleAction::new("select-for-compare", None);
+        select_for_compare.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                if let Some(item) = window.selected_item() {
+                    window.imp().compare_mark.set(Some(item.id()));
                 }
             }
-            // Ctrl+F: Focus search
-            Key::f if modifier.contains(gtk4::gdk::ModifierType::CONTROL_MASK) => {
-                imp.search_entry.grab_focus();
-                glib::Propagation::Stop
+        ));
+        self.add_action(&select_for_compare);
+
+        let show_similar = gio::SimpleAction::new("show-similar", None);
+        show_similar.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                if let Some(item) = window.selected_item() {
+                    window.imp().similar_to.set(Some(item.id()));
+                    window.load_history();
+                }
             }
-            // Down arrow from search: Move to list
-            Key::Down if imp.search_entry.has_focus() => {
-                imp.list_view.grab_focus();
-                glib::Propagation::Stop
+        ));
+        self.add_action(&show_similar);
+
+        let show_same_origin = gio::SimpleAction::new("show-same-origin", None);
+        show_same_origin.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                if let Some(item) = window.selected_item() {
+                    window
+                        .imp()
+                        .origin
+                        .set(OriginKind::from_name(&item.origin()));
+                    window.load_history();
+                }
             }
-            _ => glib::Propagation::Proceed,
-        }
-    }
+        ));
+        self.add_action(&show_same_origin);
 
-    fn load_history(&self) {
-        let imp = self.imp();
-        imp.status_label.set_label("Loading...");
+        let compare = gio::SimpleAction::new("compare-with-selected", None);
+        compare.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.compare_with_marked();
+            }
+        ));
+        self.add_action(&compare);
 
-        match self.fetch_history() {
-            Ok(()) => {
-                self.update_status();
-                imp.search_entry.grab_focus();
+        let toggle_pin = gio::SimpleAction::new("toggle-pin", None);
+        toggle_pin.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.toggle_selected_pinned();
             }
-            Err(e) => {
-                error!("Failed to load history: {}", e);
-                imp.status_label.set_label(&format!("Error: {}", e));
+        ));
+        self.add_action(&toggle_pin);
+
+        let restore = gio::SimpleAction::new("restore", None);
+        restore.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.restore_selected();
             }
-        }
-    }
+        ));
+        self.add_action(&restore);
 
-    fn fetch_history(&self) -> anyhow::Result<()> {
-        let imp = self.imp();
+        let delete = gio::SimpleAction::new("delete", None);
+        delete.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                window.delete_selected();
+            }
+        ));
+        self.add_action(&delete);
 
-        let mut client = IpcClient::connect()?;
-        let entries = client.get_history(Some(100), None, None)?;
+        let move_pinned = gio::SimpleAction::new("move-pinned", Some(glib::VariantTy::INT32));
+        move_pinned.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, parameter| {
+                if let Some(offset) = parameter.and_then(|p| p.get::<i32>()) {
+                    window.move_selected_pinned(offset);
+                }
+            }
+        ));
+        self.add_action(&move_pinned);
 
-        imp.model.remove_all();
-        for entry in entries {
-            let item = ClipboardItem::from_entry(entry);
-            imp.model.append(&item);
-        }
+        // Searches from the searches menu, e.g. "win.search::/\d+/"
+        let search = gio::SimpleAction::new("search", Some(glib::VariantTy::STRING));
+        search.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, parameter| {
+                if let Some(query) = parameter.and_then(|p| p.str()) {
+                    let entry = &window.imp().search_entry;
+                    entry.set_text(query);
+                    entry.grab_focus();
+                    entry.set_position(-1);
+                }
+            }
+        ));
+        self.add_action(&search);
 
-        debug!("Loaded {} entries", imp.model.n_items());
+        let save_search = gio::SimpleAction::new("save-search", None);
+        save_search.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, _| {
+                let query = window.imp().search_entry.text();
+                crate::search_dialog::show(&window, &query, |name, query| {
+                    state::update(|state| state.save_search(&name, &query));
+                });
+            }
+        ));
+        self.add_action(&save_search);
+
+        let forget_search = gio::SimpleAction::new("forget-search", Some(glib::VariantTy::STRING));
+        forget_search.connect_activate(|_, parameter| {
+            if let Some(name) = parameter.and_then(|p| p.str()) {
+                state::update(|state| state.forget(name));
+            }
+        });
+        self.add_action(&forget_search);
+
+        let clear_recent = gio::SimpleAction::new("clear-recent-searches", None);
+        clear_recent.connect_activate(|_, _| {
+            state::update(|state| state.recent.clear());
+        });
+        self.add_action(&clear_recent);
+
+        // Radio items in the sort menu, e.g. "win.sort::most_used"
+        let sort = gio::SimpleAction::new_stateful(
+            "sort",
+            Some(glib::VariantTy::STRING),
+            &self.imp().sort.get().name().to_variant(),
+        );
+        sort.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |action, parameter| {
+                let Some(sort) = parameter
+                    .and_then(|p| p.str())
+                    .and_then(HistorySort::from_name)
+                else {
+                    return;
+                };
+                action.set_state(&sort.name().to_variant());
+                window.imp().sort.set(sort);
+                state::update(|state| state.sort = sort);
+                window.load_history();
+            }
+        ));
+        self.add_action(&sort);
+    }
+
+    /// The searches menu: saved searches, recent ones, and saving and
+    /// forgetting them.
+    fn searches_menu(&self) -> gio::Menu {
+        let searches = State::load();
+        let menu = gio::Menu::new();
+
+        // Underscores in labels would otherwise be taken for mnemonics
+        let search_item = |label: &str, action: &str, target: &str| {
+            let item = gio::MenuItem::new(Some(&label.replace('_', "__")), None);
+            item.set_action_and_target_value(Some(action), Some(&target.to_variant()));
+            item
+        };
+
+        if !searches.saved.is_empty() {
+            let saved = gio::Menu::new();
+            for search in &searches.saved {
+                saved.append_item(&search_item(&search.name, "win.search", &search.query));
+            }
+            menu.append_section(Some("Saved Searches"), &saved);
+        }
+        if !searches.recent.is_empty() {
+            let recent = gio::Menu::new();
+            for query in &searches.recent {
+                recent.append_item(&search_item(query, "win.search", query));
+            }
+            menu.append_section(Some("Recent Searches"), &recent);
+        }
+
+        let manage = gio::Menu::new();
+        manage.append(Some("_Save Search…"), Some("win.save-search"));
+        if !searches.saved.is_empty() {
+            let forget = gio::Menu::new();
+            for search in &searches.saved {
+                forget.append_item(&search_item(
+                    &search.name,
+                    "win.forget-search",
+                    &search.name,
+                ));
+            }
+            manage.append_submenu(Some("_Forget Saved Search"), &forget);
+        }
+        if !searches.recent.is_empty() {
+            manage.append(
+                Some("_Clear Recent Searches"),
+                Some("win.clear-recent-searches"),
+            );
+        }
+        menu.append_section(None, &manage);
+        menu
+    }
+
+    /// Remember the search text, if any, as a recent search.
+    fn remember_search(&self) {
+        let query = self.imp().search_entry.text();
+        if !query.trim().is_empty() {
+            state::update(|state| state.remember(&query));
+        }
+    }
+
+    /// Let pinned rows be dragged onto other pinned rows to reorder them.
+    fn setup_pinned_drag(&self, row: &ItemRow) {
+        let drag_source = gtk4::DragSource::new();
+        drag_source.set_actions(gdk::DragAction::MOVE);
+        drag_source.connect_prepare(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            row,
+            #[upgrade_or]
+            None,
+            move |_, _, _| {
+                let item = row
+                    .item()
+                    .filter(|item| window.pinned_position(item).is_some())?;
+                Some(gdk::ContentProvider::for_value(&item.id().to_value()))
+            }
+        ));
+        row.add_controller(drag_source);
+
+        let drop_target = gtk4::DropTarget::new(i64::static_type(), gdk::DragAction::MOVE);
+        drop_target.connect_drop(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[weak]
+            row,
+            #[upgrade_or]
+            false,
+            move |_, value, _, _| {
+                let (Ok(id), Some(target)) = (value.get::<i64>(), row.item()) else {
+                    return false;
+                };
+                match window.pinned_position(&target) {
+                    Some(position) if id != target.id() => {
+                        window.move_pinned(id, position);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        ));
+        row.add_controller(drop_target);
+    }
+
+    fn setup_callbacks(&self) {
+        let imp = self.imp();
+
+        // Search changed
+        imp.search_entry.connect_search_changed(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| {
+                window.on_search_changed();
+            }
+        ));
+
+        // Closing only hides the window; remember how it was left
+        self.connect_close_request(|window| {
+            window.save_state();
+            glib::Propagation::Proceed
+        });
+
+        // Enter in the search: Keep it among the recent searches
+        imp.search_entry.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_| {
+                window.remember_search();
+            }
+        ));
+
+        // List item activated
+        imp.list_view.connect_activate(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, position| {
+                window.on_item_activated(position);
+            }
+        ));
+
+        // Switching profiles
+        imp.profile_switcher.connect_selected_notify(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |switcher| {
+                let selected = switcher.selected();
+                if selected == gtk4::INVALID_LIST_POSITION {
+                    return;
+                }
+                // Item 0 is the default profile, the rest follow the profile list
+                let profile = (selected as usize)
+                    .checked_sub(1)
+                    .and_then(|i| window.imp().profiles.borrow().get(i).cloned());
+                if profile != wayclip_common::profile() {
+                    window.switch_profile(profile);
+                }
+            }
+        ));
+
+        // Switching collections in the sidebar
+        imp.sidebar.connect_row_selected(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |_, row| {
+                let Some(row) = row else {
+                    return;
+                };
+                // Row 0 is the full history, the rest follow the collection list
+                let collection = usize::try_from(row.index() - 1)
+                    .ok()
+                    .and_then(|i| window.imp().collections.borrow().get(i).map(|c| c.id));
+                if collection != window.imp().current_collection.get() {
+                    window.imp().current_collection.set(collection);
+                    window.imp().similar_to.set(None);
+                    window.imp().origin.set(None);
+                    window.load_history();
+                }
+            }
+        ));
+
+        // Right click: select the row under the pointer and open its menu
+        let right_click = gtk4::GestureClick::new();
+        right_click.set_button(gdk::BUTTON_SECONDARY);
+        right_click.connect_pressed(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move |gesture, _, x, y| {
+                if window.select_row_at(x, y) {
+                    gesture.set_state(gtk4::EventSequenceState::Claimed);
+                    window.show_context_menu(Some((x, y)));
+                }
+            }
+        ));
+        imp.list_view.add_controller(right_click);
+    }
+
+    fn setup_shortcuts(&self) {
+        let controller = gtk4::EventControllerKey::new();
+
+        controller.connect_key_pressed(clone!(
+            #[weak(rename_to = window)]
+            self,
+            #[upgrade_or]
+            glib::Propagation::Proceed,
+            move |_, key, _, modifier| window.on_key_pressed(key, modifier)
+        ));
+
+        self.add_controller(controller);
+
+        // Adds win.show-help-overlay, bound to Ctrl+? in main
+        self.set_help_overlay(Some(&crate::shortcuts_window::build()));
+    }
+
+    /// Ask the daemon for entries matching the new search text.
+    ///
+    /// The search entry already waits for typing to pause, and a search
+    /// still running when the text changes again is abandoned.
+    fn on_search_changed(&self) {
+        if let Some(cancel) = self.imp().search_cancel.take() {
+            cancel.cancel();
+        }
+        if self.imp().search_entry.text().is_empty() {
+            self.load_history();
+        } else {
+            self.search_history();
+        }
+    }
+
+    /// Search on a worker thread with its own connection, so typing stays
+    /// responsive while the daemon searches a large history.
+    ///
+    /// The connection is kept for the next search unless the search is
+    /// abandoned, which closes it.
+    fn search_history(&self) {
+        let imp = self.imp();
+
+        let generation = imp.load_generation.get().wrapping_add(1);
+        imp.load_generation.set(generation);
+
+        let reused = imp.search_ipc.take();
+        let retry = reused.is_some();
+        let client = reused
+            .map_or_else(IpcClient::connect, Ok)
+            .and_then(|client| {
+                let cancel = client.cancel_handle()?;
+                Ok((client, cancel))
+            });
+        let (mut client, cancel) = match client {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to search history: {}", e);
+                self.show_load_error(&e);
+                return;
+            }
+        };
+        imp.search_cancel.replace(Some(cancel));
+        imp.status_label.set_label("Searching...");
+
+        let history_query = self.history_query(Some(imp.search_entry.text().to_string()));
+        let query = gio::spawn_blocking(move || {
+            let result = client.get_history(Some(HISTORY_LIMIT), None, history_query);
+            (client, result)
+        });
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = window)]
+            self,
+            async move {
+                let result = query.await;
+                let imp = window.imp();
+                // A newer search or reload has taken over
+                if imp.load_generation.get() != generation {
+                    return;
+                }
+                imp.search_cancel.take();
+
+                match result {
+                    Ok((client, Ok((entries, total)))) => {
+                        imp.search_ipc.replace(Some(client));
+                        window.show_entries(0, entries, total);
+                        window.update_status();
+                    }
+                    // The daemon closed the kept connection since, such as
+                    // by restarting
+                    Ok((_, Err(e))) if retry && e.downcast_ref::<std::io::Error>().is_some() => {
+                        debug!("Reconnecting to the daemon: {}", e);
+                        window.search_history();
+                    }
+                    Ok((_, Err(e))) => {
+                        error!("Failed to search history: {}", e);
+                        window.show_load_error(&e);
+                        window.prompt_unlock_if_locked(&e);
+                    }
+                    Err(_) => error!("Search thread panicked"),
+                }
+            }
+        ));
+    }
+
+    fn on_item_activated(&self, position: u32) {
+        let imp = self.imp();
+
+        let Some(selection_model) = imp.selection_model.borrow().clone() else {
+            return;
+        };
+
+        let Some(item) = selection_model
+            .item(position)
+            .and_downcast::<ClipboardItem>()
+        else {
+            return;
+        };
+
+        if item.redacted() {
+            imp.status_label
+                .set_label("This entry's content was not stored");
+            return;
+        }
+
+        info!(
+            "Activating item: {} (id={})",
+            item.preview(),
+            item.id()
+        );
+        self.remember_search();
+
+        // Nothing to copy when the clipboard already holds it
+        let activation = imp.activation.get();
+        if item.current() && activation != Activation::Primary {
+            self.after_activation(activation);
+            return;
+        }
+
+        // An archived entry is restored to the history and copied in one go
+        let mut item_id = item.id();
+        if item.archived() {
+            match self.with_client(|client| client.restore_archived(item_id)) {
+                Ok(id) => item_id = id,
+                Err(e) => {
+                    error!("Failed to restore item: {}", e);
+                    return;
+                }
+            }
+        }
+
+        // Copy to clipboard via daemon (synchronous, quick operation)
+        let result = match activation {
+            Activation::Primary => self.with_client(|client| client.set_primary_selection(item_id)),
+            _ => self.copy_item_to_clipboard(item_id),
+        };
+        match result {
+            Ok(()) => {
+                info!(
+                    "Successfully copied item {} ({})",
+                    item_id,
+                    activation.name()
+                );
+                self.after_activation(activation);
+            }
+            Err(e) => {
+                error!("Failed to copy item: {}", e);
+                self.prompt_unlock_if_locked(&e);
+            }
+        }
+    }
+
+    /// Close, paste or stay open once an activated entry was copied.
+    fn after_activation(&self, activation: Activation) {
+        match activation {
+            Activation::CopyAndClose | Activation::Primary => self.finish(),
+            Activation::CopyAndPaste => self.finish_and_paste(),
+            Activation::CopyAndStay => {
+                self.imp().status_label.set_label("Copied to the clipboard");
+            }
+        }
+    }
+
+    /// Select the list row at the given list view coordinates.
+    fn select_row_at(&self, x: f64, y: f64) -> bool {
+        let imp = self.imp();
+
+        let Some(item) = imp
+            .list_view
+            .pick(x, y, gtk4::PickFlags::DEFAULT)
+            .and_then(|widget| widget.ancestor(ItemRow::static_type()))
+            .and_downcast::<ItemRow>()
+            .and_then(|row| row.item())
+        else {
+            return false;
+        };
+
+        self.select_item(item.id())
+    }
+
+    /// Select the list row showing the entry with this ID.
+    fn select_item(&self, id: i64) -> bool {
+        let Some(selection_model) = self.imp().selection_model.borrow().clone() else {
+            return false;
+        };
+
+        let position = (0..selection_model.n_items()).find(|&i| {
+            selection_model
+                .item(i)
+                .and_downcast::<ClipboardItem>()
+                .is_some_and(|other| other.id() == id)
+        });
+
+        match position {
+            Some(position) => {
+                selection_model.set_selected(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Position of an item among the pinned items at the top of the full
+    /// history, or `None` if it isn't pinned or a collection is shown.
+    fn pinned_position(&self, item: &ClipboardItem) -> Option<u32> {
+        let imp = self.imp();
+        if !item.pinned() || imp.current_collection.get().is_some() {
+            return None;
+        }
+
+        // The history is asked for with pinned entries first, in their
+        // manual order
+        (0..imp.model.n_items())
+            .map_while(|i| {
+                imp.model
+                    .item(i)
+                    .and_downcast::<ClipboardItem>()
+                    .filter(|other| other.pinned())
+            })
+            .position(|other| other.id() == item.id())
+            .map(|position| position as u32)
+    }
+
+    fn pinned_count(&self) -> u32 {
+        let imp = self.imp();
+        (0..imp.model.n_items())
+            .map_while(|i| imp.model.item(i).and_downcast::<ClipboardItem>())
+            .take_while(|item| item.pinned())
+            .count() as u32
+    }
+
+    fn selected_item(&self) -> Option<ClipboardItem> {
+        self.imp()
+            .selection_model
+            .borrow()
+            .as_ref()?
+            .selected_item()
+            .and_downcast::<ClipboardItem>()
+    }
+
+    /// Open the actions menu for the selected item.
+    ///
+    /// `at` is a position in list view coordinates; without it the menu
+    /// points at the focused row.
+    fn show_context_menu(&self, at: Option<(f64, f64)>) {
+        let imp = self.imp();
+
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let menu = gio::Menu::new();
+        // Archived entries can't be changed until they are back in the history
+        if item.archived() {
+            menu.append(Some("_Restore to History"), Some("win.restore"));
+        } else {
+            self.append_entry_actions(&menu, &item);
+        }
+
+        let rect = match at {
+            Some((x, y)) => gdk::Rectangle::new(x as i32, y as i32, 1, 1),
+            None => imp
+                .list_view
+                .focus_child()
+                .and_then(|row| row.compute_bounds(&imp.list_view))
+                .map(|b| {
+                    gdk::Rectangle::new(
+                        b.x() as i32,
+                        b.y() as i32,
+                        b.width() as i32,
+                        b.height() as i32,
+                    )
+                })
+                .unwrap_or_else(|| gdk::Rectangle::new(0, 0, 1, 1)),
+        };
+
+        imp.context_menu
+            .update_property(&[gtk4::accessible::Property::Label(&format!(
+                "Actions for {}",
+                item.display_text()
+            ))]);
+        imp.context_menu.set_menu_model(Some(&menu));
+        imp.context_menu.set_pointing_to(Some(&rect));
+        imp.context_menu.popup();
+    }
+
+    /// Add the actions for a live history entry to `menu`.
+    fn append_entry_actions(&self, menu: &gio::Menu, item: &ClipboardItem) {
+        let imp = self.imp();
+
+        menu.append(Some("Copy to P_rimary Selection"), Some("win.copy-primary"));
+        menu.append(
+            Some(if item.pinned() { "Un_pin" } else { "_Pin" }),
+            Some("win.toggle-pin"),
+        );
+        // HTML would show its markup
+        if item.is_text() && item.mime_type() != "text/html" {
+            menu.append(Some("Copy P_art…"), Some("win.copy-part"));
+            menu.append(Some("_Split into Lines"), Some("win.split-lines"));
+        }
+        menu.append(Some("Add to Paste _Queue"), Some("win.queue-paste"));
+        if !item.redacted() {
+            menu.append(Some("Sa_ve As…"), Some("win.save-as"));
+            menu.append(Some("S_hare…"), Some("win.share"));
+        }
+        menu.append(Some("_Edit Title and Note…"), Some("win.edit-meta"));
+        menu.append(Some("_Delete"), Some("win.delete"));
+        for transform in Transform::ALL {
+            if transform.applies_to(&item.mime_type()) {
+                let menu_item = gio::MenuItem::new(Some(transform.label()), None);
+                menu_item.set_action_and_target_value(
+                    Some("win.copy-as"),
+                    Some(&transform.name().to_variant()),
+                );
+                menu.append_item(&menu_item);
+            }
+        }
+
+        if item.is_image() && imp.similar_to.get() != Some(item.id()) {
+            let section = gio::Menu::new();
+            section.append(Some("Show Si_milar Images"), Some("win.show-similar"));
+            menu.append_section(None, &section);
+        }
+
+        if item.origin_label().is_some() && imp.origin.get().is_none() {
+            let section = gio::Menu::new();
+            section.append(Some("Show Same _Origin"), Some("win.show-same-origin"));
+            menu.append_section(None, &section);
+        }
+
+        if item.source_app().is_some() {
+            let section = gio::Menu::new();
+            section.append(Some("_Never Record from This App"), Some("win.ignore-app"));
+            menu.append_section(None, &section);
+        }
+
+        if item.is_text() {
+            let section = gio::Menu::new();
+            section.append(Some("Select for _Compare"), Some("win.select-for-compare"));
+            if imp
+                .compare_mark
+                .get()
+                .is_some_and(|marked| marked != item.id())
+            {
+                section.append(
+                    Some("Compare _with Selected"),
+                    Some("win.compare-with-selected"),
+                );
+            }
+            menu.append_section(None, &section);
+        }
+
+        let collections = imp.collections.borrow();
+        let current = imp.current_collection.get();
+        let targets: Vec<_> = collections
+            .iter()
+            .filter(|c| Some(c.id) != current)
+            .collect();
+        if !targets.is_empty() || current.is_some() {
+            let section = gio::Menu::new();
+            if !targets.is_empty() {
+                let submenu = gio::Menu::new();
+                for collection in targets {
+                    // A lone underscore would turn the next letter into a mnemonic
+                    let label = collection.name.replace('_', "__");
+                    let menu_item = gio::MenuItem::new(Some(&label), None);
+                    menu_item.set_action_and_target_value(
+                        Some("win.add-to-collection"),
+                        Some(&collection.id.to_variant()),
+                    );
+                    submenu.append_item(&menu_item);
+                }
+                section.append_submenu(Some("_Add to Collection"), &submenu);
+            }
+            if current.is_some() {
+                section.append(
+                    Some("Remove from This C_ollection"),
+                    Some("win.remove-from-collection"),
+                );
+            }
+            menu.append_section(None, &section);
+        }
+        drop(collections);
+
+        if let Some(position) = self.pinned_position(item) {
+            let section = gio::Menu::new();
+            if position > 0 {
+                let menu_item = gio::MenuItem::new(Some("Move _Up"), None);
+                menu_item
+                    .set_action_and_target_value(Some("win.move-pinned"), Some(&(-1).to_variant()));
+                section.append_item(&menu_item);
+            }
+            if position + 1 < self.pinned_count() {
+                let menu_item = gio::MenuItem::new(Some("Move Dow_n"), None);
+                menu_item
+                    .set_action_and_target_value(Some("win.move-pinned"), Some(&1.to_variant()));
+                section.append_item(&menu_item);
+            }
+            if section.n_items() > 0 {
+                menu.append_section(None, &section);
+            }
+        }
+    }
+
+    fn copy_selected_as(&self, transform: Transform) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        info!("Copying item {} as {}", item.id(), transform.name());
+
+        let result = self.with_client(|client| client.set_clipboard_as(item.id(), transform));
+        match result {
+            Ok(()) => self.finish(),
+            Err(e) => {
+                error!("Failed to copy item: {}", e);
+                self.prompt_unlock_if_locked(&e);
+            }
+        }
+    }
+
+    fn add_selected_to_collection(&self, collection_id: i64) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let result = self.with_client(|client| client.add_to_collection(collection_id, item.id()));
+        match result {
+            Ok(()) => self.load_collections(),
+            Err(e) => error!("Failed to add item to collection: {}", e),
+        }
+    }
+
+    fn remove_selected_from_collection(&self) {
+        let (Some(item), Some(collection_id)) =
+            (self.selected_item(), self.imp().current_collection.get())
+        else {
+            return;
+        };
+
+        let result =
+            self.with_client(|client| client.remove_from_collection(collection_id, item.id()));
+        match result {
+            Ok(()) => self.load_history(),
+            Err(e) => error!("Failed to remove item from collection: {}", e),
+        }
+    }
+
+    fn item_by_id(&self, id: i64) -> Option<ClipboardItem> {
+        let model = &self.imp().model;
+        (0..model.n_items())
+            .filter_map(|i| model.item(i).and_downcast::<ClipboardItem>())
+            .find(|item| item.id() == id)
+    }
+
+    /// Diff the selected entry against the one picked with "Select for Compare".
+    fn compare_with_marked(&self) {
+        let (Some(item), Some(marked)) = (self.selected_item(), self.imp().compare_mark.get())
+        else {
+            return;
+        };
+
+        let result = self.contents(&[marked, item.id()]);
+        let mut contents = match result {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("Failed to load entries to compare: {}", e);
+                self.prompt_unlock_if_locked(&e);
+                return;
+            }
+        };
+        let (Some((marked_mime, marked_data)), Some((mime, data))) =
+            (contents.remove(&marked), contents.remove(&item.id()))
+        else {
+            error!("Entries to compare are missing or too large");
+            return;
+        };
+        if !marked_mime.starts_with("text/") || !mime.starts_with("text/") {
+            error!("Only text entries can be compared");
+            return;
+        }
+
+        let marked_item = self.item_by_id(marked);
+        let marked_side = crate::diff_view::DiffSide {
+            title: marked_item
+                .as_ref()
+                .map(|i| i.display_text())
+                .unwrap_or_else(|| format!("Entry {}", marked)),
+            text: String::from_utf8_lossy(&marked_data).into_owned(),
+        };
+        let side = crate::diff_view::DiffSide {
+            title: item.display_text(),
+            text: String::from_utf8_lossy(&data).into_owned(),
+        };
+
+        // Older entry on the left
+        let marked_is_newer = marked_item.is_some_and(|i| i.created_at() > item.created_at());
+        if marked_is_newer {
+            crate::diff_view::show(self, &side, &marked_side);
+        } else {
+            crate::diff_view::show(self, &marked_side, &side);
+        }
+    }
+
+    /// Let part of the selected text entry be picked and copied as a new
+    /// entry.
+    fn copy_part_of_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let result = self.contents(&[item.id()]);
+        let data = match result.map(|mut contents| contents.remove(&item.id())) {
+            Ok(Some((_, data))) => data,
+            Ok(None) => {
+                error!("Entry {} is missing or too large", item.id());
+                return;
+            }
+            Err(e) => {
+                error!("Failed to load entry: {}", e);
+                self.prompt_unlock_if_locked(&e);
+                return;
+            }
+        };
+
+        crate::part_dialog::show(
+            self,
+            &String::from_utf8_lossy(&data),
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |part| {
+                    let result = window
+                        .with_client(|client| client.add_and_copy("text/plain", part.as_bytes()));
+                    match result {
+                        Ok(()) => window.finish(),
+                        Err(e) => {
+                            error!("Failed to copy part: {}", e);
+                            window.prompt_unlock_if_locked(&e);
+                        }
+                    }
+                }
+            ),
+        );
+    }
+
+    /// Split the selected text entry into an entry per line, selecting the
+    /// first.
+    fn split_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        match self.with_client(|client| client.split_entry(item.id())) {
+            Ok(ids) => {
+                if let Some(&first) = ids.first() {
+                    self.reload_keeping_selection(first);
+                }
+            }
+            Err(e) => {
+                error!("Failed to split item: {}", e);
+                self.imp().status_label.set_label(&format!("Error: {}", e));
+                self.prompt_unlock_if_locked(&e);
+            }
+        }
+    }
+
+    /// Have the daemon stop recording copies from the application the
+    /// selected entry was copied from. Entries already recorded are kept.
+    fn ignore_selected_app(&self) {
+        let Some(app) = self.selected_item().and_then(|item| item.source_app()) else {
+            return;
+        };
+
+        let pattern = format!("^{}$", glib::Regex::escape_string(&app));
+        let result = self.with_client(|client| {
+            let mut rules = client.added_ignore_rules()?;
+            if !rules.apps.contains(&pattern) {
+                rules.apps.push(pattern.clone());
+                client.set_added_ignore_rules(rules)?;
+            }
+            Ok(())
+        });
+        let status = match result {
+            Ok(()) => format!("Copies from {} will no longer be recorded", app),
+            Err(e) => {
+                error!("Failed to ignore {}: {}", app, e);
+                self.prompt_unlock_if_locked(&e);
+                format!("Error: {}", e)
+            }
+        };
+        self.imp().status_label.set_label(&status);
+    }
+
+    /// Ask where to save the selected entry's content, then have the daemon
+    /// write it there.
+    fn save_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let extension = wayclip_common::extension_for_mime(&item.mime_type());
+        let dialog = gtk4::FileDialog::builder()
+            .title("Save Entry")
+            .initial_name(format!("clipboard-{}.{}", item.id(), extension))
+            .modal(true)
+            .build();
+
+        let id = item.id();
+        dialog.save(
+            Some(self),
+            None::<&gio::Cancellable>,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |result| {
+                    // Cancelling the dialog is an error too
+                    let Some(path) = result.ok().and_then(|file| file.path()) else {
+                        return;
+                    };
+                    let path = path.to_string_lossy();
+                    let status = match window.with_client(|client| client.save_to_file(id, &path)) {
+                        Ok(saved) => format!("Saved to {}", saved),
+                        Err(e) => {
+                            error!("Failed to save item: {}", e);
+                            window.prompt_unlock_if_locked(&e);
+                            format!("Error: {}", e)
+                        }
+                    };
+                    window.imp().status_label.set_label(&status);
+                }
+            ),
+        );
+    }
+
+    /// Offer the selected entry to another application, chosen through the
+    /// desktop portal.
+    fn share_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let result = self.contents(&[item.id()]);
+        let (mime_type, data) = match result.map(|mut contents| contents.remove(&item.id())) {
+            Ok(Some(content)) => content,
+            Ok(None) => {
+                error!("Entry {} is missing or too large", item.id());
+                return;
+            }
+            Err(e) => {
+                error!("Failed to load entry: {}", e);
+                self.prompt_unlock_if_locked(&e);
+                return;
+            }
+        };
+
+        let path = match crate::share::materialize(item.id(), &mime_type, &data) {
+            Ok(path) => path,
+            Err(e) => {
+                error!("Failed to write entry for sharing: {}", e);
+                self.imp().status_label.set_label(&format!("Error: {}", e));
+                return;
+            }
+        };
+
+        let launcher = gtk4::FileLauncher::new(Some(&gio::File::for_path(&path)));
+        launcher.set_always_ask(true);
+        launcher.launch(
+            Some(self),
+            None::<&gio::Cancellable>,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |result| match result {
+                    Ok(()) => window.close(),
+                    Err(e) if e.matches(gtk4::DialogError::Dismissed) => {}
+                    Err(e) => {
+                        error!("Failed to share item: {}", e);
+                        window
+                            .imp()
+                            .status_label
+                            .set_label(&format!("Error: {}", e));
+                    }
+                }
+            ),
+        );
+    }
+
+    /// Add the selected entry to the paste queue. The window stays open so
+    /// more entries can be queued after it.
+    fn queue_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let status = match self.with_client(|client| client.queue_paste(item.id())) {
+            Ok(waiting) if waiting.is_empty() => {
+                "Copied; entries queued next follow once it is pasted".to_string()
+            }
+            Ok(waiting) => format!("{} waiting in the paste queue", waiting.len()),
+            Err(e) => {
+                error!("Failed to queue item: {}", e);
+                self.prompt_unlock_if_locked(&e);
+                format!("Error: {}", e)
+            }
+        };
+        self.imp().status_label.set_label(&status);
+    }
+
+    fn edit_selected_meta(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let id = item.id();
+        crate::meta_dialog::show(
+            self,
+            &item,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                move |title, note| {
+                    let result = window.with_client(|client| {
+                        client.set_entry_meta(id, title.clone(), note.clone())
+                    });
+                    match result {
+                        Ok(()) => window.reload_keeping_selection(id),
+                        Err(e) => error!("Failed to save title and note: {}", e),
+                    }
+                }
+            ),
+        );
+    }
+
+    fn toggle_selected_pinned(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        let result = self.with_client(|client| client.set_pinned(item.id(), !item.pinned()));
+        match result {
+            Ok(()) => self.reload_keeping_selection(item.id()),
+            Err(e) => error!("Failed to pin item: {}", e),
+        }
+    }
+
+    /// Delete the selected item, selecting the one that takes its place.
+    fn delete_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+        let position = self
+            .imp()
+            .selection_model
+            .borrow()
+            .as_ref()
+            .map_or(0, |model| model.selected());
+
+        info!("Deleting item {}", item.id());
+
+        match self.with_client(|client| client.delete_entry(item.id())) {
+            Ok(()) => {
+                let imp = self.imp();
+                if self.reload_in_place() && imp.model.n_items() > 0 {
+                    imp.list_view.scroll_to(
+                        position.min(imp.model.n_items() - 1),
+                        gtk4::ListScrollFlags::FOCUS | gtk4::ListScrollFlags::SELECT,
+                        None,
+                    );
+                }
+            }
+            Err(e) => error!("Failed to delete item: {}", e),
+        }
+    }
+
+    /// Move the selected archived item back into the history.
+    fn restore_selected(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        info!("Restoring item {}", item.id());
+
+        match self.with_client(|client| client.restore_archived(item.id())) {
+            Ok(id) => {
+                if self.reload_in_place() {
+                    self.select_item(id);
+                }
+                self.imp().status_label.set_label("Restored to the history");
+            }
+            Err(e) => {
+                error!("Failed to restore item: {}", e);
+                self.prompt_unlock_if_locked(&e);
+            }
+        }
+    }
+
+    /// Move the selected pinned item up (negative) or down (positive).
+    fn move_selected_pinned(&self, offset: i32) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+        let Some(position) = self.pinned_position(&item) else {
+            return;
+        };
+
+        let Some(target) = position.checked_add_signed(offset) else {
+            return;
+        };
+        if target < self.pinned_count() {
+            self.move_pinned(item.id(), target);
+        }
+    }
+
+    fn move_pinned(&self, id: i64, position: u32) {
+        let result = self.with_client(|client| client.move_pinned(id, position));
+        match result {
+            Ok(()) => self.reload_keeping_selection(id),
+            Err(e) => error!("Failed to move item: {}", e),
+        }
+    }
+
+    /// Reload the history without leaving the list, keeping `id` selected.
+    fn reload_keeping_selection(&self, id: i64) {
+        if self.reload_in_place() {
+            self.select_item(id);
+            self.imp().list_view.grab_focus();
+        }
+    }
+
+    /// Reload the list after changing one of its items. Returns whether it
+    /// loaded.
+    fn reload_in_place(&self) -> bool {
+        let imp = self.imp();
+        // Loads everything at once, so a deferred page must not be appended
+        imp.load_generation
+            .set(imp.load_generation.get().wrapping_add(1));
+        match self.fetch_history(0, HISTORY_LIMIT) {
+            Ok(()) => {
+                self.update_status();
+                true
+            }
+            Err(e) => {
+                error!("Failed to load history: {}", e);
+                self.show_load_error(&e);
+                false
+            }
+        }
+    }
+
+    fn copy_selected_to_primary(&self) {
+        let Some(item) = self.selected_item() else {
+            return;
+        };
+
+        info!("Copying item {} to primary selection", item.id());
+
+        let result = self.with_client(|client| client.set_primary_selection(item.id()));
+        match result {
+            Ok(()) => self.finish(),
+            Err(e) => {
+                error!("Failed to copy item: {}", e);
+                self.prompt_unlock_if_locked(&e);
+            }
+        }
+    }
+
+    fn on_key_pressed(
+        &self,
+        key: gtk4::gdk::Key,
+        modifier: gtk4::gdk::ModifierType,
+    ) -> glib::Propagation {
+        use gtk4::gdk::Key;
+
+        let imp = self.imp();
+
+        match key {
+            // Escape: Clear search, leave similar images or an origin, or close
+            Key::Escape => {
+                if !imp.search_entry.text().is_empty() {
+                    imp.search_entry.set_text("");
+                    glib::Propagation::Stop
+                } else if imp.similar_to.take().is_some() || imp.origin.take().is_some() {
+                    self.load_history();
+                    glib::Propagation::Stop
+                } else {
+                    self.close();
+                    glib::Propagation::Stop
+                }
+            }
+            // Ctrl+F: Focus search
+            Key::f if modifier.contains(gtk4::gdk::ModifierType::CONTROL_MASK) => {
+                imp.search_entry.grab_focus();
+                glib::Propagation::Stop
+            }
+            // Ctrl+R: Open recent and saved searches
+            Key::r if modifier.contains(gtk4::gdk::ModifierType::CONTROL_MASK) => {
+                imp.searches_button.popup();
+                glib::Propagation::Stop
+            }
+            // Menu or Shift+F10: Open actions for the selected item
+            Key::Menu => {
+                self.show_context_menu(None);
+                glib::Propagation::Stop
+            }
+            Key::F10 if modifier.contains(gtk4::gdk::ModifierType::SHIFT_MASK) => {
+                self.show_context_menu(None);
+                glib::Propagation::Stop
+            }
+            // Alt+Up/Down: Reorder the selected pinned item
+            Key::Up if modifier.contains(gtk4::gdk::ModifierType::ALT_MASK) => {
+                self.move_selected_pinned(-1);
+                glib::Propagation::Stop
+            }
+            Key::Down if modifier.contains(gtk4::gdk::ModifierType::ALT_MASK) => {
+                self.move_selected_pinned(1);
+                glib::Propagation::Stop
+            }
+            // Delete: Delete the selected item, unless editing the search
+            Key::Delete if imp.list_view.focus_child().is_some() => {
+                self.delete_selected();
+                glib::Propagation::Stop
+            }
+            // Down arrow from search: Move to list
+            Key::Down if imp.search_entry.has_focus() => {
+                imp.list_view.grab_focus();
+                glib::Propagation::Stop
+            }
+            _ => glib::Propagation::Proceed,
+        }
+    }
+
+    /// Load the first page of history right away, and the rest of it and
+    /// the collections sidebar once the window has been drawn.
+    fn load_history(&self) {
+        let imp = self.imp();
+        imp.status_label.set_label("Loading...");
+
+        let generation = imp.load_generation.get().wrapping_add(1);
+        imp.load_generation.set(generation);
+
+        match self.fetch_history(0, FIRST_PAGE) {
+            Ok(()) => {
+                startup::mark("first page loaded");
+                self.update_status();
+                imp.search_entry.grab_focus();
+            }
+            Err(e) => {
+                error!("Failed to load history: {}", e);
+                self.show_load_error(&e);
+                self.prompt_unlock_if_locked(&e);
+                self.retry_if_not_responding(&e, generation);
+                return;
+            }
+        }
+
+        // Idle callbacks run after pending redraws
+        glib::idle_add_local_once(clone!(
+            #[weak(rename_to = window)]
+            self,
+            move || {
+                let imp = window.imp();
+                if imp.load_generation.get() != generation {
+                    return;
+                }
+
+                // A collection deleted elsewhere falls back to the full history
+                let collection = imp.current_collection.get();
+                window.load_collections();
+                if imp.current_collection.get() != collection {
+                    window.load_history();
+                    return;
+                }
+
+                match window.with_client(|client| client.capture_error()) {
+                    Ok(capture_error) => {
+                        imp.capture_error.replace(capture_error);
+                    }
+                    Err(e) => debug!("Failed to get the daemon's status: {}", e),
+                }
+
+                if imp.model.n_items() == FIRST_PAGE {
+                    if let Err(e) = window.fetch_history(FIRST_PAGE, HISTORY_LIMIT - FIRST_PAGE) {
+                        error!("Failed to load history: {}", e);
+                    }
+                }
+                window.update_status();
+            }
+        ));
+    }
+
+    /// What to ask the daemon for: entries matching `search` in the current
+    /// mode, collection and filters.
+    fn history_query(&self, search: Option<String>) -> HistoryQuery {
+        let imp = self.imp();
+        HistoryQuery {
+            search,
+            collection: imp.current_collection.get(),
+            snippets: imp.mode.get() == Mode::Snippets,
+            similar_to: imp.similar_to.get(),
+            origin: imp.origin.get(),
+            sort: imp.sort.get(),
+        }
+    }
+
+    /// Fetch `limit` entries matching the search text, starting at `offset`.
+    fn fetch_history(&self, offset: u32, limit: u32) -> anyhow::Result<()> {
+        let imp = self.imp();
+
+        let search = Some(imp.search_entry.text().to_string()).filter(|s| !s.is_empty());
+        let query = self.history_query(search);
+        let (entries, total) = self
+            .with_client(|client| client.get_history(Some(limit), Some(offset), query.clone()))?;
+
+        self.show_entries(offset, entries, total);
         Ok(())
     }
 
+    /// Put `entries` in the list at `offset`, replacing the list when
+    /// `offset` is 0 and appending to it otherwise.
+    fn show_entries(&self, offset: u32, entries: Vec<wayclip_common::HistoryEntry>, total: u64) {
+        let imp = self.imp();
+
+        let items: Vec<ClipboardItem> =
+            entries.into_iter().map(ClipboardItem::from_entry).collect();
+        // One splice emits a single change instead of one per item
+        let replaced = if offset == 0 { imp.model.n_items() } else { 0 };
+        imp.model
+            .splice(offset.min(imp.model.n_items()), replaced, &items);
+        imp.total_count.set(total);
+
+        debug!("Loaded {} entries", imp.model.n_items());
+    }
+
+    /// Load the history again in a while if `error` is because the daemon
+    /// didn't answer, unless it is reloaded some other way first.
+    fn retry_if_not_responding(&self, error: &anyhow::Error, generation: u32) {
+        if error.downcast_ref::<NotResponding>().is_none() {
+            return;
+        }
+        self.imp().status_label.set_label(&format!(
+            "The daemon is not responding; trying again in {} seconds",
+            RETRY_DELAY.as_secs()
+        ));
+
+        glib::timeout_add_local_once(
+            RETRY_DELAY,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                move || {
+                    if window.imp().load_generation.get() == generation {
+                        window.load_history();
+                    }
+                }
+            ),
+        );
+    }
+
+    /// Ask for the passphrase if `error` is because the history is locked,
+    /// and reload the history once it is unlocked.
+    fn prompt_unlock_if_locked(&self, error: &anyhow::Error) {
+        if error.downcast_ref::<Locked>().is_none() {
+            return;
+        }
+        self.imp().status_label.set_label("History is locked");
+
+        crate::unlock_dialog::show(
+            self,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                #[upgrade_or]
+                Ok(()),
+                move |passphrase| {
+                    window
+                        .with_client(|client| client.unlock(passphrase.clone()))
+                        .map_err(|e| e.to_string())?;
+                    window.load_history();
+                    Ok(())
+                }
+            ),
+        );
+    }
+
+    /// The MIME type and content of the entries `ids`, keyed by ID, from
+    /// the cache where it has them. Entries that don't exist or are over the
+    /// daemon's size cap are missing.
+    fn contents(&self, ids: &[i64]) -> anyhow::Result<HashMap<i64, (String, Vec<u8>)>> {
+        let imp = self.imp();
+
+        let hashes: HashMap<i64, String> = ids
+            .iter()
+            .filter_map(|&id| Some((id, self.item_by_id(id)?.hash())))
+            .collect();
+        let mut contents = HashMap::new();
+        let mut missing = Vec::new();
+        for &id in ids {
+            let cached = hashes
+                .get(&id)
+                .and_then(|hash| imp.contents.borrow_mut().get(id, hash));
+            match cached {
+                Some(content) => {
+                    contents.insert(id, content);
+                }
+                None => missing.push(id),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(contents);
+        }
+
+        let fetched = self.with_client(|client| client.get_contents(&missing))?;
+        let mut cache = imp.contents.borrow_mut();
+        for (id, (mime_type, data)) in fetched {
+            if let Some(hash) = hashes.get(&id) {
+                cache.insert(id, hash, &mime_type, &data);
+            }
+            contents.insert(id, (mime_type, data));
+        }
+        Ok(contents)
+    }
+
+    /// Follow the daemon's events on a thread of its own, connecting again
+    /// when the connection drops, as when the daemon restarts or another
+    /// profile is picked.
+    fn follow_events(&self) {
+        let window = Arc::new(glib::SendWeakRef::from(self.downgrade()));
+        std::thread::spawn(move || loop {
+            let subscribed =
+                IpcClient::subscribe().and_then(|client| Ok((client.cancel_handle()?, client)));
+            match subscribed {
+                Ok((cancel, mut client)) => {
+                    on_main_thread(&window, move |window| {
+                        window.imp().events_cancel.replace(Some(cancel));
+                    });
+                    loop {
+                        match client.next_event() {
+                            Ok(event) => {
+                                on_main_thread(&window, move |window| window.on_daemon_event(event))
+                            }
+                            Err(e) => {
+                                debug!("Stopped following the daemon's events: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => debug!("Not following the daemon's events: {}", e),
+            }
+            std::thread::sleep(RETRY_DELAY);
+        });
+    }
+
+    /// Forget what is cached about entries that changed.
+    fn on_daemon_event(&self, event: DaemonEvent) {
+        match event {
+            DaemonEvent::EntryDeleted { id } | DaemonEvent::EntryUpdated { id } => {
+                self.imp().contents.borrow_mut().invalidate(id);
+                thumbnails::invalidate(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Run `request` on the window's daemon connection, connecting on first
+    /// use. A connection the daemon has closed since is replaced once.
+    fn with_client<T>(
+        &self,
+        mut request: impl FnMut(&mut IpcClient) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut ipc = self.imp().ipc.borrow_mut();
+
+        if let Some(client) = ipc.as_mut() {
+            match request(client) {
+                Err(e) if e.downcast_ref::<std::io::Error>().is_some() => {
+                    debug!("Reconnecting to the daemon: {}", e);
+                    *ipc = None;
+                }
+                result => return result,
+            }
+        }
+
+        let client = ipc.insert(IpcClient::connect()?);
+        let result = request(client);
+        if result
+            .as_ref()
+            .is_err_and(|e| e.downcast_ref::<std::io::Error>().is_some())
+        {
+            *ipc = None;
+        }
+        result
+    }
+
+    /// Fill the profile switcher with the default profile and every named one.
+    fn load_profiles(&self) {
+        let imp = self.imp();
+
+        let current = wayclip_common::profile();
+        let mut profiles = wayclip_common::list_profiles();
+        if let Some(current) = &current {
+            // A profile picked with --profile may not have any files yet
+            if !profiles.contains(current) {
+                profiles.push(current.clone());
+                profiles.sort();
+            }
+        }
+
+        let mut labels = vec!["Default"];
+        labels.extend(profiles.iter().map(String::as_str));
+        imp.profile_switcher
+            .set_model(Some(&gtk4::StringList::new(&labels)));
+
+        let selected = current
+            .and_then(|name| profiles.iter().position(|p| *p == name))
+            .map_or(0, |i| i + 1);
+        imp.profile_switcher.set_selected(selected as u32);
+        imp.profile_switcher.set_visible(!profiles.is_empty());
+        imp.profiles.replace(profiles);
+    }
+
+    /// Switch profiles for a launch with `--profile`, updating the switcher.
+    pub fn select_profile(&self, profile: Option<String>) {
+        self.switch_profile(profile);
+        self.load_profiles();
+    }
+
+    /// Show another profile's history.
+    fn switch_profile(&self, profile: Option<String>) {
+        let imp = self.imp();
+
+        if let Err(e) = wayclip_common::set_profile(profile.as_deref()) {
+            error!("{}", e);
+            return;
+        }
+        info!(
+            "Switched to profile {}",
+            profile.as_deref().unwrap_or("default")
+        );
+
+        // Collections and compare marks belong to the old profile's database
+        imp.ipc.replace(None);
+        imp.search_ipc.replace(None);
+        imp.current_collection.set(None);
+        imp.similar_to.set(None);
+        imp.origin.set(None);
+        imp.compare_mark.set(None);
+        imp.contents.borrow_mut().clear();
+        thumbnails::clear();
+        // Follow the new profile's daemon instead
+        if let Some(cancel) = imp.events_cancel.take() {
+            cancel.cancel();
+        }
+        self.update_title();
+        self.load_history();
+    }
+
+    /// Title the window after its mode and the active profile.
+    fn update_title(&self) {
+        let title = match self.imp().mode.get() {
+            Mode::History => "Wayclip",
+            Mode::Snippets => "Wayclip Snippets",
+        };
+        match wayclip_common::profile() {
+            Some(profile) => self.set_title(Some(&format!("{} ({})", title, profile))),
+            None => self.set_title(Some(title)),
+        }
+    }
+
+    /// Refresh the collections sidebar, keeping the current collection selected.
+    fn load_collections(&self) {
+        let imp = self.imp();
+
+        let collections = match self.with_client(|client| client.list_collections()) {
+            Ok(collections) => collections,
+            Err(e) => {
+                error!("Failed to load collections: {}", e);
+                return;
+            }
+        };
+
+        // A collection deleted elsewhere falls back to the full history
+        let current = imp
+            .current_collection
+            .get()
+            .filter(|id| collections.iter().any(|c| c.id == *id));
+        imp.current_collection.set(current);
+
+        imp.sidebar.remove_all();
+        imp.sidebar.append(&sidebar_label("All History"));
+        for collection in &collections {
+            imp.sidebar.append(&sidebar_label(&format!(
+                "{} ({})",
+                collection.name, collection.entry_count
+            )));
+        }
+
+        let selected = current
+            .and_then(|id| collections.iter().position(|c| c.id == id))
+            .map_or(0, |i| i + 1);
+        imp.sidebar_scroll.set_visible(!collections.is_empty());
+        imp.collections.replace(collections);
+        imp.sidebar
+            .select_row(imp.sidebar.row_at_index(selected as i32).as_ref());
+    }
+
     fn copy_item_to_clipboard(&self, id: i64) -> anyhow::Result<()> {
-        let mut client = IpcClient::connect()?;
-        client.set_clipboard(id)
+        self.with_client(|client| client.set_clipboard(id))
     }
 
     fn update_status(&self) {
         let imp = self.imp();
 
-        let total = imp.model.n_items();
-        let visible = imp
-            .filter_model
-            .borrow()
-            .as_ref()
-            .map(|m| m.n_items())
-            .unwrap_or(total);
+        let shown = imp.model.n_items();
+        let total = imp.total_count.get();
 
-        let label = if imp.search_entry.text().is_empty() {
-            format!("{} items", total)
+        let noun = match imp.mode.get() {
+            Mode::History => "items",
+            Mode::Snippets => "snippets",
+        };
+        let mut label = if imp.similar_to.get().is_some() {
+            format!("{} similar images", total)
+        } else if let Some(origin) = imp.origin.get() {
+            format!("{} {} {}", total, origin_adjective(origin), noun)
+        } else if imp.search_entry.text().is_empty() {
+            format!("{} {}", shown, noun)
+        } else if total > u64::from(shown) {
+            format!("{} of {} matching {}", shown, total, noun)
         } else {
-            format!("{} of {} items", visible, total)
+            format!("{} matching {}", total, noun)
         };
+        if imp.mode.get() == Mode::History && imp.capture_error.borrow().is_some() {
+            label.push_str(" (clipboard capture is off)");
+        }
 
         imp.status_label.set_label(&label);
+        self.update_view();
+    }
+
+    /// Show the list, or a page saying why it is empty.
+    fn update_view(&self) {
+        let imp = self.imp();
+
+        if imp.model.n_items() > 0 {
+            imp.view_stack.set_visible_child_name("list");
+            return;
+        }
+        let filtered = !imp.search_entry.text().is_empty()
+            || imp.similar_to.get().is_some()
+            || imp.origin.get().is_some();
+        let capture_error = imp.capture_error.borrow().clone();
+        let placeholder = if filtered {
+            Placeholder::NoResults
+        } else if imp.current_collection.get().is_some() {
+            Placeholder::EmptyCollection
+        } else if let (Some(reason), Mode::History) = (capture_error, imp.mode.get()) {
+            Placeholder::CaptureOff(reason)
+        } else {
+            Placeholder::Empty(imp.mode.get())
+        };
+        self.show_placeholder(placeholder);
+    }
+
+    /// Show the page for a history that failed to load.
+    fn show_load_error(&self, error: &anyhow::Error) {
+        let placeholder = match error.downcast_ref::<NotRunning>() {
+            Some(_) => Placeholder::NotRunning,
+            None => Placeholder::Error(error.to_string()),
+        };
+        self.imp().status_label.set_label("");
+        self.show_placeholder(placeholder);
+    }
+
+    fn show_placeholder(&self, placeholder: Placeholder) {
+        let imp = self.imp();
+
+        let (icon, title, description) = match &placeholder {
+            Placeholder::Empty(Mode::History) => (
+                "edit-paste-symbolic",
+                "No History Yet",
+                "Text and images show up here as they are copied.".to_string(),
+            ),
+            Placeholder::Empty(Mode::Snippets) => (
+                "starred-symbolic",
+                "No Snippets Yet",
+                "Pin entries in the history, or add them to a collection, to keep them here."
+                    .to_string(),
+            ),
+            Placeholder::EmptyCollection => (
+                "folder-symbolic",
+                "Empty Collection",
+                "Add entries to this collection from their menu in the history.".to_string(),
+            ),
+            Placeholder::NoResults => (
+                "edit-find-symbolic",
+                "No Results",
+                "Nothing matches. Try another search, or press Escape to clear it.".to_string(),
+            ),
+            Placeholder::NotRunning => (
+                "network-offline-symbolic",
+                "Daemon Not Running",
+                "wayclip-daemon keeps the clipboard history. Start it here, or from the \
+                 compositor's autostart to have it always running."
+                    .to_string(),
+            ),
+            Placeholder::CaptureOff(reason) => (
+                "dialog-warning-symbolic",
+                "Clipboard Capture Is Off",
+                format!(
+                    "The daemon stopped watching the clipboard: {}. \
+                     Run wayclip-daemon --doctor for help.",
+                    reason
+                ),
+            ),
+            Placeholder::Error(message) => (
+                "dialog-error-symbolic",
+                "Can't Load the History",
+                message.clone(),
+            ),
+        };
+        imp.placeholder_icon.set_icon_name(Some(icon));
+        imp.placeholder_title.set_label(title);
+        imp.placeholder_description.set_label(&description);
+        imp.start_daemon_button
+            .set_visible(matches!(placeholder, Placeholder::NotRunning));
+        imp.view_stack.set_visible_child_name("placeholder");
+    }
+
+    /// Start the daemon for the active profile, and load the history once
+    /// it answers.
+    fn start_daemon(&self) {
+        let imp = self.imp();
+
+        let mut argv = vec![std::ffi::OsString::from("wayclip-daemon")];
+        if let Some(profile) = wayclip_common::profile() {
+            argv.push("--profile".into());
+            argv.push(profile.into());
+        }
+        let argv: Vec<&std::ffi::OsStr> = argv.iter().map(|arg| arg.as_os_str()).collect();
+        if let Err(e) = gio::Subprocess::newv(&argv, gio::SubprocessFlags::NONE) {
+            error!("Failed to start the daemon: {}", e);
+            self.show_placeholder(Placeholder::Error(format!(
+                "Failed to start wayclip-daemon: {}",
+                e
+            )));
+            return;
+        }
+        imp.start_daemon_button.set_sensitive(false);
+        imp.status_label.set_label("Starting the daemon...");
+
+        let attempts = std::cell::Cell::new(0);
+        glib::timeout_add_local(
+            DAEMON_START_POLL,
+            clone!(
+                #[weak(rename_to = window)]
+                self,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    attempts.set(attempts.get() + 1);
+                    if IpcClient::connect().is_err() && attempts.get() < DAEMON_START_ATTEMPTS {
+                        return glib::ControlFlow::Continue;
+                    }
+                    window.imp().start_daemon_button.set_sensitive(true);
+                    window.load_history();
+                    glib::ControlFlow::Break
+                }
+            ),
+        );
+    }
+}
+
+/// Why the list is empty, as shown on the page in its place.
+enum Placeholder {
+    /// Nothing has been copied, or kept as a snippet, yet.
+    Empty(Mode),
+    /// The collection shown has no entries.
+    EmptyCollection,
+    /// Nothing matches the search or filter.
+    NoResults,
+    /// The daemon's socket doesn't answer.
+    NotRunning,
+    /// The daemon stopped capturing the clipboard, for this reason.
+    CaptureOff(String),
+    /// The history failed to load some other way.
+    Error(String),
+}
+
+/// Run `f` with the window on the main thread, if it still exists.
+fn on_main_thread(
+    window: &Arc<glib::SendWeakRef<WayclipWindow>>,
+    f: impl FnOnce(&WayclipWindow) + Send + 'static,
+) {
+    let window = Arc::clone(window);
+    glib::MainContext::default().invoke(move || {
+        if let Some(window) = window.upgrade() {
+            f(&window);
+        }
+    });
+}
+
+/// How `update_status` describes entries of an origin, as in "12 synced items".
+fn origin_adjective(origin: OriginKind) -> &'static str {
+    match origin {
+        OriginKind::Local => "local",
+        OriginKind::Primary => "primary selection",
+        OriginKind::Synced => "synced",
+        OriginKind::Api => "client-added",
+        OriginKind::Import => "imported",
     }
 }
+
+fn sidebar_label(text: &str) -> gtk4::Label {
+    let label = gtk4::Label::new(Some(text));
+    label.set_xalign(0.0);
+    label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+    label.set_margin_top(6);
+    label.set_margin_bottom(6);
+    label.set_margin_start(6);
+    label.set_margin_end(6);
+    label
+}
@@ -1,17 +1,176 @@
 //! XDG path utilities for wayclip.
+//!
+//! Every path is namespaced by the active profile, so separate histories
+//! (e.g. "work" and "personal") never share a socket, database or config.
+//! The default profile uses the plain `wayclip` directories; a named profile
+//! uses `wayclip/profiles/<name>` inside each of them.
+//!
+//! `WAYCLIP_SOCKET`, `WAYCLIP_DB` and `WAYCLIP_CONFIG` replace individual
+//! paths outright, whatever the profile, for sandboxed installs and tests.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+/// Environment variable selecting the profile.
+pub const PROFILE_ENV: &str = "WAYCLIP_PROFILE";
+
+/// Environment variable overriding [`socket_path`].
+pub const SOCKET_ENV: &str = "WAYCLIP_SOCKET";
+
+/// Environment variable overriding [`database_path`].
+pub const DB_ENV: &str = "WAYCLIP_DB";
+
+/// Environment variable overriding [`config_path`].
+pub const CONFIG_ENV: &str = "WAYCLIP_CONFIG";
+
+static PROFILE: OnceLock<RwLock<Option<String>>> = OnceLock::new();
+
+/// A profile name that can't be used as a directory name.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid profile name '{0}': use letters, digits, '-' and '_'")]
+pub struct InvalidProfile(pub String);
+
+fn profile_lock() -> &'static RwLock<Option<String>> {
+    PROFILE.get_or_init(|| {
+        let name = std::env::var(PROFILE_ENV)
+            .ok()
+            .filter(|name| is_valid_profile(name));
+        RwLock::new(name)
+    })
+}
+
+/// Whether `name` can be used as a profile name.
+pub fn is_valid_profile(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Select the profile at startup: `flag` (from `--profile`) if given,
+/// otherwise `$WAYCLIP_PROFILE`. An empty name selects the default profile.
+pub fn init_profile(flag: Option<&str>) -> Result<(), InvalidProfile> {
+    let name = match flag {
+        Some(name) => Some(name.to_string()),
+        None => std::env::var(PROFILE_ENV).ok(),
+    };
+    set_profile(name.as_deref().filter(|name| !name.is_empty()))
+}
+
+/// Switch to another profile, or back to the default one with `None`.
+pub fn set_profile(name: Option<&str>) -> Result<(), InvalidProfile> {
+    if let Some(name) = name {
+        if !is_valid_profile(name) {
+            return Err(InvalidProfile(name.to_string()));
+        }
+    }
+    *profile_lock().write().unwrap() = name.map(str::to_string);
+    Ok(())
+}
+
+/// The active profile, or `None` for the default one.
+pub fn profile() -> Option<String> {
+    profile_lock().read().unwrap().clone()
+}
+
+/// Named profiles that have a database or config file, sorted by name.
+pub fn list_profiles() -> Vec<String> {
+    let mut names: Vec<String> = [data_home(), config_home()]
+        .iter()
+        .filter_map(|base| std::fs::read_dir(base.join("wayclip").join("profiles")).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_valid_profile(name))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The Flatpak app ID when running inside a Flatpak sandbox.
+///
+/// Data and config directories need no special handling there, since Flatpak
+/// already points the XDG variables into the app's private directory.
+pub fn flatpak_id() -> Option<String> {
+    std::env::var("FLATPAK_ID").ok().filter(|id| !id.is_empty())
+}
+
+/// A path set in the environment variable `var`, ignoring empty values.
+fn env_path(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The `wayclip` directory inside `base` for `profile`.
+fn profile_dir(base: &Path, profile: Option<&str>) -> PathBuf {
+    let dir = base.join("wayclip");
+    match profile {
+        Some(name) => dir.join("profiles").join(name),
+        None => dir,
+    }
+}
+
+/// The `wayclip` directory inside `base` for the active profile.
+fn wayclip_dir(base: &Path) -> PathBuf {
+    profile_dir(base, profile().as_deref())
+}
+
+fn data_home() -> PathBuf {
+    dirs::data_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(".local/share")
+    })
+}
+
+fn cache_home() -> PathBuf {
+    dirs::cache_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(".cache")
+    })
+}
+
+fn state_home() -> PathBuf {
+    dirs::state_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(".local/state")
+    })
+}
+
+fn config_home() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join(".config")
+    })
+}
 
 /// Get the socket path for IPC communication.
 ///
-/// Returns `$XDG_RUNTIME_DIR/wayclip/wayclip.sock` or falls back to
-/// `/tmp/wayclip-$UID/wayclip.sock`.
+/// Returns `$WAYCLIP_SOCKET` if set, otherwise
+/// `$XDG_RUNTIME_DIR/wayclip/wayclip.sock` or falls back to
+/// `/tmp/wayclip-$UID/wayclip.sock`. Inside a Flatpak sandbox the socket
+/// lives in the app's own runtime directory, `$XDG_RUNTIME_DIR/app/<id>`,
+/// which is shared by every instance of the app and visible from the host.
 pub fn socket_path() -> PathBuf {
-    if let Some(runtime_dir) = dirs::runtime_dir() {
-        runtime_dir.join("wayclip").join("wayclip.sock")
+    if let Some(path) = env_path(SOCKET_ENV) {
+        path
+    } else if let Some(runtime_dir) = dirs::runtime_dir() {
+        match flatpak_id() {
+            Some(id) => wayclip_dir(&runtime_dir.join("app").join(id)).join("wayclip.sock"),
+            None => wayclip_dir(&runtime_dir).join("wayclip.sock"),
+        }
     } else {
         let uid = unsafe { libc::getuid() };
-        PathBuf::from(format!("/tmp/wayclip-{}/wayclip.sock", uid))
+        let dir = PathBuf::from(format!("/tmp/wayclip-{}", uid));
+        match profile() {
+            Some(name) => dir.join("profiles").join(name).join("wayclip.sock"),
+            None => dir.join("wayclip.sock"),
+        }
     }
 }
 
@@ -20,17 +179,28 @@ pub fn socket_dir() -> PathBuf {
     socket_path().parent().unwrap().to_path_buf()
 }
 
+/// Get the directory where the client keeps the copies of entries it shares
+/// with other applications.
+///
+/// Returns a `share` directory next to the socket, so in the runtime
+/// directory when there is one, which is emptied at logout.
+pub fn share_dir() -> PathBuf {
+    socket_dir().join("share")
+}
+
+/// Get the path of the file holding the HTTP gateway's token.
+///
+/// Returns `http-token` next to the socket, readable only by the user.
+pub fn http_token_path() -> PathBuf {
+    socket_dir().join("http-token")
+}
+
 /// Get the database path.
 ///
-/// Returns `$XDG_DATA_HOME/wayclip/history.db` or falls back to
-/// `~/.local/share/wayclip/history.db`.
+/// Returns `$WAYCLIP_DB` if set, otherwise `$XDG_DATA_HOME/wayclip/history.db`
+/// or falls back to `~/.local/share/wayclip/history.db`.
 pub fn database_path() -> PathBuf {
-    let data_dir = dirs::data_dir().unwrap_or_else(|| {
-        dirs::home_dir()
-            .unwrap_or_else(|| PathBuf::from("/tmp"))
-            .join(".local/share")
-    });
-    data_dir.join("wayclip").join("history.db")
+    env_path(DB_ENV).unwrap_or_else(|| wayclip_dir(&data_home()).join("history.db"))
 }
 
 /// Get the directory containing the database.
@@ -38,17 +208,33 @@ pub fn database_dir() -> PathBuf {
     database_path().parent().unwrap().to_path_buf()
 }
 
+/// Get the directory where captures are queued while the database is unavailable.
+///
+/// Returns the `spill` directory next to the database.
+pub fn spill_dir() -> PathBuf {
+    database_dir().join("spill")
+}
+
+/// Get the directory where image thumbnails are cached as `<hash>.png`.
+///
+/// Returns `$XDG_CACHE_HOME/wayclip/thumbs`, or a `thumbs` directory next to
+/// the database when `$WAYCLIP_DB` moves it, so separate databases never
+/// share (and prune) each other's thumbnails.
+pub fn thumbnail_dir() -> PathBuf {
+    if env_path(DB_ENV).is_some() {
+        database_dir().join("thumbs")
+    } else {
+        wayclip_dir(&cache_home()).join("thumbs")
+    }
+}
+
 /// Get the configuration file path.
 ///
-/// Returns `$XDG_CONFIG_HOME/wayclip/config.toml` or falls back to
+/// Returns `$WAYCLIP_CONFIG` if set, otherwise
+/// `$XDG_CONFIG_HOME/wayclip/config.toml` or falls back to
 /// `~/.config/wayclip/config.toml`.
 pub fn config_path() -> PathBuf {
-    let config_dir = dirs::config_dir().unwrap_or_else(|| {
-        dirs::home_dir()
-            .unwrap_or_else(|| PathBuf::from("/tmp"))
-            .join(".config")
-    });
-    config_dir.join("wayclip").join("config.toml")
+    env_path(CONFIG_ENV).unwrap_or_else(|| wayclip_dir(&config_home()).join("config.toml"))
 }
 
 /// Get the directory containing the config file.
@@ -56,6 +242,23 @@ pub fn config_dir() -> PathBuf {
     config_path().parent().unwrap().to_path_buf()
 }
 
+/// Get the daemon log file path.
+///
+/// Returns `$XDG_STATE_HOME/wayclip/daemon.log` or falls back to
+/// `~/.local/state/wayclip/daemon.log`.
+pub fn log_path() -> PathBuf {
+    wayclip_dir(&state_home()).join("daemon.log")
+}
+
+/// Get the path of the file where the client remembered searches and window
+/// sizes before keeping them in GSettings; the client moves it there.
+///
+/// Returns `$XDG_STATE_HOME/wayclip/client-state.json` or falls back to
+/// `~/.local/state/wayclip/client-state.json`.
+pub fn client_state_path() -> PathBuf {
+    wayclip_dir(&state_home()).join("client-state.json")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,5 +275,27 @@ mod tests {
         let config = config_path();
         assert!(config.to_str().unwrap().contains("wayclip"));
         assert!(config.to_str().unwrap().ends_with("config.toml"));
+
+        let thumbs = thumbnail_dir();
+        assert!(thumbs.to_str().unwrap().ends_with("thumbs"));
+
+        let log = log_path();
+        assert!(log.to_str().unwrap().ends_with("wayclip/daemon.log"));
+    }
+
+    #[test]
+    fn test_profiles_are_namespaced() {
+        let base = Path::new("/data");
+        assert_eq!(profile_dir(base, None), Path::new("/data/wayclip"));
+        assert_eq!(
+            profile_dir(base, Some("work")),
+            Path::new("/data/wayclip/profiles/work")
+        );
+
+        assert!(is_valid_profile("work"));
+        assert!(is_valid_profile("personal_2"));
+        assert!(!is_valid_profile(""));
+        assert!(!is_valid_profile("../work"));
+        assert!(!is_valid_profile("a/b"));
     }
 }
@@ -2,7 +2,14 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::types::HistoryEntry;
+use crate::types::{
+    AuditRecord, CacheStats, Collection, CollectionExport, ContentData, EntryContent, EntryId,
+    HistoryEntry, HistorySort, IgnoreRules, MimeTypeCount, OriginKind, PruneCriteria, Report,
+    SearchBackend, Selection, SyncDevice, Transform,
+};
+
+/// Maximum total content size, before encoding, returned by one `GetContents`.
+pub const MAX_CONTENTS_BYTES: u64 = 32 * 1024 * 1024;
 
 /// Request from client to daemon.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,45 +23,400 @@ pub enum Request {
         /// Number of entries to skip.
         #[serde(skip_serializing_if = "Option::is_none")]
         offset: Option<u32>,
-        /// Search filter (case-insensitive substring match).
+        /// Search filter (case-insensitive substring match, or a regular
+        /// expression written as `/pattern/`).
         #[serde(skip_serializing_if = "Option::is_none")]
         search: Option<String>,
+        /// Only return entries copied on this seat.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seat: Option<String>,
+        /// Only return entries captured from this selection.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        selection: Option<Selection>,
+        /// Only return entries in this collection, in collection order.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        collection: Option<i64>,
+        /// Only return snippets: pinned entries and entries in any collection.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        snippets: bool,
+        /// Only return images that look like this image entry, itself included.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        similar_to: Option<EntryId>,
+        /// Only return entries of this origin, such as ones synced from
+        /// devices.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        origin: Option<OriginKind>,
+        /// Order to list entries in, most recent first if not given.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sort: Option<HistorySort>,
+        /// List pinned entries before the others, in their manual order,
+        /// unless listing a collection.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pinned_first: bool,
+        /// Also list archived entries, after all of the live ones.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        include_archived: bool,
+    },
+
+    /// Search live and archived entries at once, answered with `History`.
+    /// Archived entries come after the live ones and are marked `archived`.
+    SearchAll {
+        /// Search text, matched like `GetHistory`'s.
+        query: String,
+        /// Maximum number of entries to return.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+        /// Number of entries to skip.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<u32>,
+        /// Order to list entries in, like `GetHistory`'s.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sort: Option<HistorySort>,
+    },
+
+    /// Move an archived entry back into the history as if it had just been
+    /// copied, answered with the restored `Entry`.
+    RestoreArchived {
+        /// Entry ID.
+        id: EntryId,
+    },
+
+    /// Get one entry's metadata.
+    GetEntry {
+        /// Entry ID.
+        id: EntryId,
     },
 
     /// Get the raw content of an entry.
     GetContent {
         /// Entry ID.
-        id: i64,
+        id: EntryId,
+    },
+
+    /// Get the raw content of several entries in one round trip.
+    ///
+    /// Entries that would push the response past `MAX_CONTENTS_BYTES` are
+    /// left out and listed in `Response::Contents::omitted`.
+    GetContents {
+        /// Entry IDs, in the order the contents should be returned.
+        ids: Vec<EntryId>,
     },
 
     /// Copy an entry back to the clipboard.
     SetClipboard {
         /// Entry ID to copy.
-        id: i64,
+        id: EntryId,
+    },
+
+    /// Get the raw content of the entry with this content hash.
+    ///
+    /// Unlike IDs, hashes survive export and import, so scripts can keep
+    /// them. The response is `Response::Content`, which carries the ID.
+    GetByHash {
+        /// SHA-256 of the content, hex encoded, as in `HistoryEntry::hash`.
+        hash: String,
+    },
+
+    /// Copy the entry with this content hash back to the clipboard.
+    SetClipboardByHash {
+        /// SHA-256 of the content, hex encoded.
+        hash: String,
+    },
+
+    /// Copy an entry to the primary selection (middle-click paste).
+    SetPrimarySelection {
+        /// Entry ID to copy.
+        id: EntryId,
+    },
+
+    /// Replace the clipboard with the entry copied before or after the one
+    /// put there by the last cycle, starting from the newest, for cycling
+    /// through recent copies from a hotkey. The response is
+    /// `Response::Entry` with the entry now on the clipboard.
+    CycleClipboard {
+        /// Which way to go.
+        #[serde(default)]
+        direction: CycleDirection,
+    },
+
+    /// Copy a transformed version of an entry to the clipboard.
+    SetClipboardAs {
+        /// Entry ID to copy.
+        id: EntryId,
+        /// Transform to apply before copying.
+        transform: Transform,
+    },
+
+    /// Write an entry's content to a file. Without an extension in `path`,
+    /// one matching the entry's MIME type is added. The response is
+    /// `Response::Saved`.
+    SaveToFile {
+        /// Entry ID to save.
+        id: EntryId,
+        /// Absolute path of the file, replaced if it exists.
+        path: String,
+    },
+
+    /// Split a text entry into a new entry per line, or per `delimiter`, for
+    /// pasting a list one item at a time. Pieces are trimmed, blank ones are
+    /// skipped, and the first piece ends up newest. The response is
+    /// `Response::Split`.
+    SplitEntry {
+        /// Entry ID to split.
+        id: EntryId,
+        /// Text to split at instead of line breaks.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        delimiter: Option<String>,
     },
 
+    /// Add an entry as if it had just been copied, such as part of another
+    /// entry's text. Content already in the history moves that entry to the
+    /// top instead. The response is `Response::Entry`.
+    AddEntry {
+        /// MIME type of the content.
+        mime_type: String,
+        /// Content data (base64 encoded).
+        data: String,
+        /// Also copy the entry to the clipboard.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        set_clipboard: bool,
+    },
+
+    /// Add entries to the paste queue. The first is put on the clipboard
+    /// right away, and each following one as soon as the one before it has
+    /// been pasted. An empty list only reports the queue. The response is
+    /// `Response::Queue`.
+    QueuePaste {
+        /// Entry IDs, in the order they should be pasted.
+        #[serde(default)]
+        ids: Vec<EntryId>,
+    },
+
+    /// Skip to the next entry in the paste queue without waiting for a paste.
+    /// The response is `Response::Queue`.
+    PopQueue,
+
+    /// Empty the paste queue, leaving the clipboard as it is.
+    ClearQueue,
+
     /// Delete an entry from history.
     DeleteEntry {
         /// Entry ID to delete.
-        id: i64,
+        id: EntryId,
     },
 
     /// Clear all history (except pinned entries).
     ClearHistory,
 
+    /// Delete the entries matching `criteria` in one go. Pinned entries and
+    /// collection members are kept, as with `ClearHistory`.
+    Prune {
+        /// Which entries to delete.
+        criteria: PruneCriteria,
+        /// Only count the matching entries, deleting nothing.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        dry_run: bool,
+    },
+
+    /// Merge entries holding the same content, such as text that differs
+    /// only in line endings or trailing whitespace, kept from before such
+    /// copies were recognized. The most recently used entry of each group
+    /// takes over the pins, notes, collections and use counts of the rest.
+    DedupExisting {
+        /// Only count the duplicates, changing nothing.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        dry_run: bool,
+    },
+
     /// Pin or unpin an entry.
     SetPinned {
         /// Entry ID.
-        id: i64,
+        id: EntryId,
         /// Whether to pin (true) or unpin (false).
         pinned: bool,
     },
 
+    /// Set an entry's title and note. `None` or an empty string clears a field.
+    SetEntryMeta {
+        /// Entry ID.
+        id: EntryId,
+        /// Title shown instead of the preview.
+        #[serde(default)]
+        title: Option<String>,
+        /// Free-form note, included in search.
+        #[serde(default)]
+        note: Option<String>,
+    },
+
+    /// Move a pinned entry within the pinned list.
+    MovePinned {
+        /// Entry ID.
+        id: EntryId,
+        /// New zero-based position among pinned entries.
+        position: u32,
+    },
+
+    /// List all collections.
+    ListCollections,
+
+    /// Create an empty collection.
+    CreateCollection {
+        /// Name for the new collection.
+        name: String,
+    },
+
+    /// Rename a collection.
+    RenameCollection {
+        /// Collection ID.
+        id: i64,
+        /// New name.
+        name: String,
+    },
+
+    /// Delete a collection, keeping its entries in the history.
+    DeleteCollection {
+        /// Collection ID.
+        id: i64,
+    },
+
+    /// Append an entry to a collection.
+    AddToCollection {
+        /// Collection ID.
+        collection_id: i64,
+        /// Entry ID to add.
+        entry_id: EntryId,
+    },
+
+    /// Remove an entry from a collection.
+    RemoveFromCollection {
+        /// Collection ID.
+        collection_id: i64,
+        /// Entry ID to remove.
+        entry_id: EntryId,
+    },
+
+    /// Export a collection with its contents.
+    ExportCollection {
+        /// Collection ID.
+        id: i64,
+    },
+
+    /// Import an exported collection, merging into one with the same name.
+    ImportCollection {
+        /// The exported collection.
+        export: CollectionExport,
+    },
+
     /// Get daemon status.
     GetStatus,
 
+    /// Get runtime statistics, such as content cache hits.
+    GetStats,
+
+    /// Get the most recent reads of entry content, newest first.
+    GetAuditLog {
+        /// Maximum number of records to return.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
+
+    /// Summarize what was copied recently. The response is
+    /// `Response::Report`.
+    GetReport {
+        /// Length of the period summarized, ending now.
+        period_secs: u64,
+        /// Maximum number of applications and entries in each list.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
+
+    /// Lock the history: content is refused until `Unlock`. Needs a
+    /// passphrase set with `SetPassphrase`.
+    Lock,
+
+    /// Unlock the history.
+    Unlock {
+        /// The passphrase set with `SetPassphrase`.
+        passphrase: String,
+    },
+
+    /// Set, change or remove the passphrase that unlocks the history.
+    SetPassphrase {
+        /// The passphrase currently set, if there is one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        current: Option<String>,
+        /// The new passphrase, or `None` to remove it and unlock.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new: Option<String>,
+    },
+
+    /// Salvage readable entries from a corrupt database file.
+    RecoverDatabase {
+        /// Path of the corrupt file; defaults to the most recent one moved aside.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+
+    /// Re-hash all stored content and report entries that don't match.
+    VerifyIntegrity,
+
+    /// Start pairing a companion device for LAN sync. The response holds
+    /// the URI to show it, as a QR code; it works once, for a few minutes.
+    StartPairing,
+
+    /// List paired companion devices.
+    ListDevices,
+
+    /// Change what a paired device may do. Whatever isn't given is kept.
+    SetDevicePermissions {
+        /// Device ID.
+        id: i64,
+        /// Whether the device may add entries.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        can_push: Option<bool>,
+        /// Whether the device may list entries and read their content.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        can_pull: Option<bool>,
+    },
+
+    /// Unpair a device; it has to pair again to connect.
+    RemoveDevice {
+        /// Device ID.
+        id: i64,
+    },
+
+    /// Get the ignore rules in effect. The response is `Response::Config`.
+    GetConfig,
+
+    /// Replace the ignore rules added by clients, which apply on top of the
+    /// config file's and are kept with the history. The response is
+    /// `Response::Config`.
+    SetConfig {
+        /// All the rules to add; rules left out are removed.
+        ignore: IgnoreRules,
+    },
+
+    /// Change the daemon's log filter at runtime.
+    SetLogLevel {
+        /// `RUST_LOG`-style filter directive, e.g. `wayclip=debug`.
+        directive: String,
+    },
+
+    /// Receive `Response::Event` messages on this connection as things
+    /// happen. Answered with `Ok`; requests can still be sent afterwards.
+    Subscribe,
+
     /// Ping to check if daemon is alive.
     Ping,
+
+    /// A request of a type this version doesn't know, such as from a newer
+    /// client. Only ever decoded, and answered with
+    /// `ErrorCode::InvalidRequest`.
+    #[serde(skip)]
+    Unknown {
+        /// The request's `type`.
+        name: String,
+    },
 }
 
 /// Response from daemon to client.
@@ -69,6 +431,12 @@ pub enum Response {
         total_count: u64,
     },
 
+    /// One entry's metadata.
+    Entry {
+        /// The entry, boxed so it doesn't grow every other response.
+        entry: Box<HistoryEntry>,
+    },
+
     /// Raw content data.
     Content {
         /// Entry ID.
@@ -76,7 +444,16 @@ pub enum Response {
         /// MIME type of the content.
         mime_type: String,
         /// Content data (base64 encoded).
-        data: String,
+        data: ContentData,
+    },
+
+    /// Raw content of several entries.
+    Contents {
+        /// Contents in request order. IDs that don't exist are skipped.
+        contents: Vec<EntryContent>,
+        /// IDs left out to stay under the size cap.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        omitted: Vec<i64>,
     },
 
     /// Generic success response.
@@ -98,10 +475,210 @@ pub enum Response {
         entry_count: u64,
         /// Database size in bytes.
         database_size_bytes: u64,
+        /// Active log filter directive.
+        #[serde(default)]
+        log_level: String,
+        /// Whether the history is locked.
+        #[serde(default)]
+        locked: bool,
+        /// Problems that need the user's attention.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        warnings: Vec<String>,
+        /// How search text is matched.
+        #[serde(default)]
+        search_backend: SearchBackend,
+        /// Why the daemon stopped capturing the clipboard, if it has.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        capture_error: Option<String>,
+    },
+
+    /// List of collections.
+    Collections {
+        /// Collections in display order.
+        collections: Vec<Collection>,
+    },
+
+    /// A single collection (after creating or importing it).
+    Collection {
+        /// The collection.
+        collection: Collection,
+    },
+
+    /// An exported collection.
+    CollectionExport {
+        /// The collection and its contents.
+        export: CollectionExport,
+    },
+
+    /// Runtime statistics.
+    Stats {
+        /// Content cache statistics.
+        cache: CacheStats,
+        /// Captures dropped because they arrived faster than the daemon
+        /// could store them.
+        #[serde(default)]
+        dropped_captures: u64,
+        /// MIME types offered with selections since the daemon started,
+        /// most often offered first.
+        #[serde(default)]
+        mime_types: Vec<MimeTypeCount>,
+    },
+
+    /// Result of `SaveToFile`.
+    Saved {
+        /// The file written, including any extension that was added.
+        path: String,
+    },
+
+    /// Result of `SplitEntry`.
+    Split {
+        /// The entries for the pieces, in the order they appeared.
+        ids: Vec<i64>,
+    },
+
+    /// Paste queue state.
+    Queue {
+        /// Entries still waiting after the one on the clipboard, next first.
+        waiting: Vec<i64>,
+    },
+
+    /// Result of `Prune`.
+    Pruned {
+        /// Entries deleted, or that would be for a dry run.
+        count: u64,
+    },
+
+    /// Result of `DedupExisting`.
+    Deduplicated {
+        /// Number of entries checked.
+        checked: u64,
+        /// Entries that duplicates were merged into.
+        merged: u64,
+        /// Duplicates removed, or that would be for a dry run.
+        removed: u64,
+    },
+
+    /// Recorded content reads.
+    AuditLog {
+        /// Whether the daemon is currently recording reads.
+        enabled: bool,
+        /// Records, newest first.
+        records: Vec<AuditRecord>,
+    },
+
+    /// Summary of a recent period.
+    Report {
+        /// The summary.
+        report: Report,
+    },
+
+    /// Result of verifying stored content against its hashes.
+    Integrity {
+        /// Number of entries checked.
+        checked: u64,
+        /// IDs of entries whose content no longer matches its hash.
+        mismatched: Vec<i64>,
+        /// IDs of entries whose content could not be read.
+        unreadable: Vec<i64>,
+    },
+
+    /// Result of `StartPairing`.
+    Pairing {
+        /// `wayclip://pair` URI with the address to connect to, the
+        /// certificate fingerprint to expect and the one-time code.
+        uri: String,
+        /// Unix timestamp after which the code no longer works.
+        expires_at: i64,
+    },
+
+    /// Paired companion devices.
+    Devices {
+        /// Devices in pairing order.
+        devices: Vec<SyncDevice>,
+    },
+
+    /// Result of salvaging entries from a corrupt database.
+    Recovered {
+        /// Entries copied into the current database.
+        recovered: u64,
+        /// Entries that were already present.
+        skipped: u64,
+        /// Entries that could not be read.
+        failed: u64,
+    },
+
+    /// The ignore rules in effect; a capture matching any of either set is
+    /// dropped. Both are boxed so they don't grow every other response.
+    Config {
+        /// Rules from the config file.
+        ignore: Box<IgnoreRules>,
+        /// Rules added by clients with `Request::SetConfig`.
+        #[serde(default)]
+        added_ignore: Box<IgnoreRules>,
+    },
+
+    /// Something happened in the daemon; sent to subscribed connections.
+    Event {
+        /// What happened.
+        event: DaemonEvent,
     },
 
     /// Pong response to ping.
     Pong,
+
+    /// A response of a type this version doesn't know, such as from a newer
+    /// daemon. Only ever decoded.
+    #[serde(skip)]
+    Unknown {
+        /// The response's `type`.
+        name: String,
+    },
+}
+
+/// Events pushed to connections that sent `Request::Subscribe`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// An entry over the configured size threshold was captured, which is
+    /// often an accidental select-all.
+    LargeCapture {
+        /// Entry ID.
+        id: i64,
+        /// Size of the content in bytes.
+        byte_size: u64,
+        /// MIME type of the content.
+        mime_type: String,
+    },
+
+    /// An entry was stored, either new or an earlier copy moved back to the
+    /// top.
+    EntryAdded {
+        /// Entry ID.
+        id: i64,
+    },
+
+    /// An entry was deleted on request. Entries removed by cleanup,
+    /// pruning or clearing the history are not announced one by one.
+    EntryDeleted {
+        /// Entry ID.
+        id: i64,
+    },
+
+    /// An entry's pin, position, title or note changed.
+    EntryUpdated {
+        /// Entry ID.
+        id: i64,
+    },
+
+    /// The history was locked or unlocked.
+    LockChanged {
+        /// Whether the history is now locked.
+        locked: bool,
+    },
+
+    /// An event this version doesn't know, from a newer daemon.
+    #[serde(other)]
+    Unknown,
 }
 
 /// Error codes for error responses.
@@ -118,6 +695,24 @@ pub enum ErrorCode {
     InvalidRequest,
     /// Internal error.
     InternalError,
+    /// The history is locked, or the passphrase given to unlock it is wrong.
+    Locked,
+    /// An error code this version doesn't know, from a newer daemon.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Direction for `Request::CycleClipboard`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CycleDirection {
+    /// The entry copied before the current one, wrapping around to the
+    /// newest after the oldest.
+    #[default]
+    Previous,
+    /// The entry copied after the current one, wrapping around to the oldest
+    /// after the newest.
+    Next,
 }
 
 impl Response {
@@ -130,7 +725,7 @@ impl Response {
     }
 
     /// Create a not found error response.
-    pub fn not_found(id: i64) -> Self {
+    pub fn not_found(id: impl std::fmt::Display) -> Self {
         Self::error(ErrorCode::NotFound, format!("Entry {} not found", id))
     }
 
@@ -154,19 +749,76 @@ pub fn encode_response(response: &Response) -> Result<Vec<u8>, serde_json::Error
     Ok(json)
 }
 
-/// Decode a request from JSON bytes.
+/// Decode a request from JSON bytes. A request of a type this version
+/// doesn't know decodes as `Request::Unknown`, so it can be answered rather
+/// than dropped; unknown fields are ignored.
 pub fn decode_request(data: &[u8]) -> Result<Request, serde_json::Error> {
-    serde_json::from_slice(data)
+    serde_json::from_slice(data).or_else(|e| match unknown_type::<Request>(data) {
+        Some(name) => Ok(Request::Unknown { name }),
+        None => Err(e),
+    })
 }
 
-/// Decode a response from JSON bytes.
+/// Decode a response from JSON bytes. A response of a type this version
+/// doesn't know decodes as `Response::Unknown`; unknown fields are ignored.
 pub fn decode_response(data: &[u8]) -> Result<Response, serde_json::Error> {
-    serde_json::from_slice(data)
+    serde_json::from_slice(data).or_else(|e| match unknown_type::<Response>(data) {
+        Some(name) => Ok(Response::Unknown { name }),
+        None => Err(e),
+    })
+}
+
+/// The `type` of a frame that failed to decode as `T`, if it is one `T`
+/// doesn't have rather than a known type with bad fields.
+fn unknown_type<T: serde::de::DeserializeOwned>(data: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Frame {
+        #[serde(rename = "type")]
+        name: String,
+    }
+
+    /// Error recording whether serde gave up on the type itself.
+    #[derive(Debug)]
+    struct Probe {
+        unknown_type: bool,
+    }
+
+    impl std::fmt::Display for Probe {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("probe")
+        }
+    }
+
+    impl std::error::Error for Probe {}
+
+    impl serde::de::Error for Probe {
+        fn custom<M: std::fmt::Display>(_: M) -> Self {
+            Self {
+                unknown_type: false,
+            }
+        }
+
+        fn unknown_variant(_: &str, _: &'static [&'static str]) -> Self {
+            Self { unknown_type: true }
+        }
+    }
+
+    // Only the type is given, so fields can't be what fails
+    let Frame { name } = serde_json::from_slice(data).ok()?;
+    let frame = serde::de::value::MapDeserializer::<_, Probe>::new(std::iter::once((
+        "type",
+        name.as_str(),
+    )));
+    match T::deserialize(frame) {
+        Err(Probe { unknown_type: true }) => Some(name),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_request_serialization() {
@@ -174,6 +826,15 @@ mod tests {
             limit: Some(10),
             offset: None,
             search: Some("test".to_string()),
+            seat: None,
+            selection: Some(Selection::Primary),
+            collection: None,
+            snippets: false,
+            similar_to: None,
+            origin: Some(OriginKind::Synced),
+            sort: Some(HistorySort::MostUsed),
+            pinned_first: true,
+            include_archived: false,
         };
 
         let encoded = encode_request(&request).unwrap();
@@ -184,15 +845,100 @@ mod tests {
                 limit,
                 offset,
                 search,
+                seat,
+                selection,
+                origin,
+                sort,
+                pinned_first,
+                ..
             } => {
                 assert_eq!(limit, Some(10));
                 assert_eq!(offset, None);
                 assert_eq!(search, Some("test".to_string()));
+                assert_eq!(seat, None);
+                assert_eq!(selection, Some(Selection::Primary));
+                assert_eq!(origin, Some(OriginKind::Synced));
+                assert_eq!(sort, Some(HistorySort::MostUsed));
+                assert!(pinned_first);
             }
             _ => panic!("Wrong request type"),
         }
     }
 
+    #[test]
+    fn test_entry_id_is_a_number_or_a_string() {
+        let request: Request = decode_request(br#"{"type":"get_content","id":42}"#).unwrap();
+        assert!(matches!(
+            request,
+            Request::GetContent {
+                id: EntryId::Row(42)
+            }
+        ));
+
+        let uuid = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let json = format!(r#"{{"type":"set_clipboard","id":"{uuid}"}}"#);
+        match decode_request(json.as_bytes()).unwrap() {
+            Request::SetClipboard { id } => assert_eq!(id, EntryId::Uuid(uuid.to_string())),
+            other => panic!("Wrong request type: {:?}", other),
+        }
+
+        assert_eq!("42".parse::<EntryId>().unwrap(), EntryId::Row(42));
+        assert_eq!(uuid.parse::<EntryId>().unwrap().to_string(), uuid);
+    }
+
+    #[test]
+    fn test_content_is_base64() {
+        let response = Response::Content {
+            id: 7,
+            mime_type: "application/octet-stream".to_string(),
+            data: vec![0, 1, 2, 254, 255].into(),
+        };
+
+        let encoded = encode_response(&response).unwrap();
+        assert!(String::from_utf8_lossy(&encoded).contains(r#""data":"AAEC/v8=""#));
+        match decode_response(&encoded).unwrap() {
+            Response::Content { data, .. } => assert_eq!(data.0, [0, 1, 2, 254, 255]),
+            other => panic!("Wrong response type: {:?}", other),
+        }
+
+        let invalid = br#"{"type":"content","id":7,"mime_type":"text/plain","data":"not base64!"}"#;
+        assert!(decode_response(invalid).is_err());
+    }
+
+    #[test]
+    fn test_unknown_types_are_decoded() {
+        let newer = br#"{"type":"summon_entry","id":3,"flourish":true}"#;
+        match decode_request(newer).unwrap() {
+            Request::Unknown { name } => assert_eq!(name, "summon_entry"),
+            other => panic!("Wrong request type: {:?}", other),
+        }
+        match decode_response(br#"{"type":"summoned","id":3}"#).unwrap() {
+            Response::Unknown { name } => assert_eq!(name, "summoned"),
+            other => panic!("Wrong response type: {:?}", other),
+        }
+
+        // Known types still have their fields checked
+        let error = decode_request(br#"{"type":"get_content"}"#).unwrap_err();
+        assert!(error.to_string().contains("missing field `id`"));
+        assert!(decode_request(br#"{"id":3}"#).is_err());
+
+        let event = br#"{"type":"event","event":{"kind":"fireworks","colour":"red"}}"#;
+        assert!(matches!(
+            decode_response(event).unwrap(),
+            Response::Event {
+                event: DaemonEvent::Unknown
+            }
+        ));
+        let error = br#"{"type":"error","code":"too_sparkly","message":"Nope"}"#;
+        assert!(matches!(
+            decode_response(error).unwrap(),
+            Response::Error {
+                code: ErrorCode::Unknown,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_response_serialization() {
         let response = Response::error(ErrorCode::NotFound, "Entry 42 not found");
@@ -208,4 +954,610 @@ mod tests {
             _ => panic!("Wrong response type"),
         }
     }
+
+    /// Strategies for generating protocol values. Every variant of a
+    /// request, response and event is generated, with all of its fields.
+    mod arbitrary {
+        use super::*;
+        use crate::types::{AppCount, ContentType, ExportedEntry, Origin, ReportEntry};
+
+        fn entry_id() -> impl Strategy<Value = EntryId> {
+            prop_oneof![
+                any::<i64>().prop_map(EntryId::Row),
+                "[0-9a-f-]{36}".prop_map(EntryId::Uuid),
+            ]
+        }
+
+        fn selection() -> impl Strategy<Value = Selection> {
+            prop_oneof![Just(Selection::Clipboard), Just(Selection::Primary)]
+        }
+
+        fn content_type() -> impl Strategy<Value = ContentType> {
+            prop_oneof![
+                Just(ContentType::Text),
+                Just(ContentType::Image),
+                Just(ContentType::Other)
+            ]
+        }
+
+        fn origin() -> impl Strategy<Value = Origin> {
+            prop_oneof![
+                Just(Origin::Local),
+                Just(Origin::Primary),
+                (any::<i64>(), ".*").prop_map(|(device, name)| Origin::Synced { device, name }),
+                Just(Origin::Api),
+                Just(Origin::Import),
+            ]
+        }
+
+        fn origin_kind() -> impl Strategy<Value = OriginKind> {
+            origin().prop_map(|origin| origin.kind())
+        }
+
+        fn history_sort() -> impl Strategy<Value = HistorySort> {
+            prop_oneof![
+                Just(HistorySort::Recent),
+                Just(HistorySort::MostUsed),
+                Just(HistorySort::Largest),
+                Just(HistorySort::Oldest),
+            ]
+        }
+
+        fn error_code() -> impl Strategy<Value = ErrorCode> {
+            prop_oneof![
+                Just(ErrorCode::NotFound),
+                Just(ErrorCode::DatabaseError),
+                Just(ErrorCode::ClipboardError),
+                Just(ErrorCode::InvalidRequest),
+                Just(ErrorCode::InternalError),
+                Just(ErrorCode::Locked),
+            ]
+        }
+
+        fn ignore_rules() -> impl Strategy<Value = IgnoreRules> {
+            (
+                any::<Vec<String>>(),
+                any::<Vec<String>>(),
+                any::<Vec<String>>(),
+            )
+                .prop_map(|(mime, apps, content)| IgnoreRules {
+                    mime,
+                    apps,
+                    content,
+                })
+        }
+
+        fn collection() -> impl Strategy<Value = Collection> {
+            (any::<i64>(), ".*", any::<u64>()).prop_map(|(id, name, entry_count)| Collection {
+                id,
+                name,
+                entry_count,
+            })
+        }
+
+        fn collection_export() -> impl Strategy<Value = CollectionExport> {
+            let entry = (".*", ".*", any::<Option<String>>(), any::<Option<String>>()).prop_map(
+                |(mime_type, data, title, note)| ExportedEntry {
+                    mime_type,
+                    data,
+                    title,
+                    note,
+                },
+            );
+            (".*", prop::collection::vec(entry, 0..4))
+                .prop_map(|(name, entries)| CollectionExport { name, entries })
+        }
+
+        fn report() -> impl Strategy<Value = Report> {
+            let app = (".*", any::<u64>()).prop_map(|(app, count)| AppCount { app, count });
+            let entry = (any::<i64>(), ".*", any::<u64>(), any::<u64>()).prop_map(
+                |(id, preview, byte_size, use_count)| ReportEntry {
+                    id,
+                    preview,
+                    byte_size,
+                    use_count,
+                },
+            );
+            (
+                any::<[i64; 2]>(),
+                any::<[u64; 2]>(),
+                prop::collection::vec(app, 0..4),
+                prop::collection::vec(entry.clone(), 0..4),
+                prop::collection::vec(entry, 0..4),
+            )
+                .prop_map(
+                    |(
+                        [since, until],
+                        [captured, captured_bytes],
+                        top_apps,
+                        biggest,
+                        most_reused,
+                    )| {
+                        Report {
+                            since,
+                            until,
+                            captured,
+                            captured_bytes,
+                            top_apps,
+                            biggest,
+                            most_reused,
+                        }
+                    },
+                )
+        }
+
+        fn daemon_event() -> impl Strategy<Value = DaemonEvent> {
+            prop_oneof![
+                (any::<i64>(), any::<u64>(), ".*").prop_map(|(id, byte_size, mime_type)| {
+                    DaemonEvent::LargeCapture {
+                        id,
+                        byte_size,
+                        mime_type,
+                    }
+                }),
+                any::<i64>().prop_map(|id| DaemonEvent::EntryAdded { id }),
+                any::<i64>().prop_map(|id| DaemonEvent::EntryDeleted { id }),
+                any::<i64>().prop_map(|id| DaemonEvent::EntryUpdated { id }),
+                any::<bool>().prop_map(|locked| DaemonEvent::LockChanged { locked }),
+            ]
+        }
+
+        fn audit_record() -> impl Strategy<Value = AuditRecord> {
+            (
+                any::<i64>(),
+                any::<Option<i32>>(),
+                any::<u32>(),
+                any::<Option<String>>(),
+                ".*",
+                any::<i64>(),
+            )
+                .prop_map(|(timestamp, pid, uid, process, request, entry_id)| {
+                    AuditRecord {
+                        timestamp,
+                        pid,
+                        uid,
+                        process,
+                        request,
+                        entry_id,
+                    }
+                })
+        }
+
+        fn sync_device() -> impl Strategy<Value = SyncDevice> {
+            (
+                any::<i64>(),
+                ".*",
+                any::<[bool; 2]>(),
+                any::<i64>(),
+                any::<Option<i64>>(),
+            )
+                .prop_map(
+                    |(id, name, [can_push, can_pull], paired_at, last_seen_at)| SyncDevice {
+                        id,
+                        name,
+                        can_push,
+                        can_pull,
+                        paired_at,
+                        last_seen_at,
+                    },
+                )
+        }
+
+        fn history_entry() -> impl Strategy<Value = HistoryEntry> {
+            (
+                (any::<i64>(), ".*", content_type(), ".*", ".*", any::<u64>()),
+                (
+                    any::<i64>(),
+                    any::<bool>(),
+                    any::<bool>(),
+                    any::<Option<String>>(),
+                ),
+                (
+                    any::<Option<String>>(),
+                    selection(),
+                    any::<Option<String>>(),
+                ),
+                (
+                    any::<bool>(),
+                    "[0-9a-f]{64}",
+                    any::<Option<i64>>(),
+                    origin(),
+                ),
+                (
+                    any::<Option<String>>(),
+                    any::<Option<String>>(),
+                    any::<Option<String>>(),
+                    any::<Option<String>>(),
+                    any::<Option<String>>(),
+                    any::<bool>(),
+                    any::<bool>(),
+                ),
+            )
+                .prop_map(|(a, b, c, d, e)| HistoryEntry {
+                    id: a.0,
+                    uuid: a.1,
+                    content_type: a.2,
+                    mime_type: a.3,
+                    preview: a.4,
+                    byte_size: a.5,
+                    created_at: b.0,
+                    pinned: b.1,
+                    animated: b.2,
+                    thumbnail: b.3,
+                    thumbnail_path: e.0,
+                    seat: c.0,
+                    selection: c.1,
+                    title: c.2,
+                    note: e.1,
+                    redacted: d.0,
+                    hash: d.1,
+                    similar_to: d.2,
+                    origin: d.3,
+                    source_app: e.2,
+                    window_title: e.3,
+                    workspace: e.4,
+                    is_current: e.5,
+                    archived: e.6,
+                })
+        }
+
+        pub fn request() -> impl Strategy<Value = Request> {
+            prop_oneof![
+                (
+                    (
+                        any::<Option<u32>>(),
+                        any::<Option<u32>>(),
+                        any::<Option<String>>()
+                    ),
+                    (
+                        any::<Option<String>>(),
+                        prop::option::of(selection()),
+                        any::<Option<i64>>()
+                    ),
+                    (any::<bool>(), prop::option::of(entry_id())),
+                    (
+                        prop::option::of(origin_kind()),
+                        prop::option::of(history_sort()),
+                        any::<bool>(),
+                        any::<bool>()
+                    ),
+                )
+                    .prop_map(|(a, b, c, d)| Request::GetHistory {
+                        limit: a.0,
+                        offset: a.1,
+                        search: a.2,
+                        seat: b.0,
+                        selection: b.1,
+                        collection: b.2,
+                        snippets: c.0,
+                        similar_to: c.1,
+                        origin: d.0,
+                        sort: d.1,
+                        pinned_first: d.2,
+                        include_archived: d.3,
+                    }),
+                (
+                    any::<String>(),
+                    any::<Option<u32>>(),
+                    any::<Option<u32>>(),
+                    prop::option::of(history_sort())
+                )
+                    .prop_map(|(query, limit, offset, sort)| Request::SearchAll {
+                        query,
+                        limit,
+                        offset,
+                        sort,
+                    }),
+                entry_id().prop_map(|id| Request::RestoreArchived { id }),
+                entry_id().prop_map(|id| Request::GetContent { id }),
+                prop::collection::vec(entry_id(), 0..8)
+                    .prop_map(|ids| Request::GetContents { ids }),
+                prop_oneof![Just(CycleDirection::Previous), Just(CycleDirection::Next)]
+                    .prop_map(|direction| Request::CycleClipboard { direction }),
+                (
+                    entry_id(),
+                    prop_oneof![Just(Transform::StillPng), Just(Transform::PlainText)]
+                )
+                    .prop_map(|(id, transform)| Request::SetClipboardAs { id, transform }),
+                (".*", ".*", any::<bool>()).prop_map(|(mime_type, data, set_clipboard)| {
+                    Request::AddEntry {
+                        mime_type,
+                        data,
+                        set_clipboard,
+                    }
+                }),
+                (
+                    any::<[Option<u64>; 2]>(),
+                    prop::option::of(content_type()),
+                    any::<Option<String>>(),
+                    any::<bool>()
+                )
+                    .prop_map(
+                        |([older_than_secs, larger_than], content_type, source_app, dry_run)| {
+                            Request::Prune {
+                                criteria: PruneCriteria {
+                                    older_than_secs,
+                                    larger_than,
+                                    content_type,
+                                    source_app,
+                                },
+                                dry_run,
+                            }
+                        }
+                    ),
+                any::<bool>().prop_map(|dry_run| Request::DedupExisting { dry_run }),
+                (entry_id(), any::<Option<String>>(), any::<Option<String>>())
+                    .prop_map(|(id, title, note)| Request::SetEntryMeta { id, title, note }),
+                (any::<i64>(), entry_id()).prop_map(|(collection_id, entry_id)| {
+                    Request::AddToCollection {
+                        collection_id,
+                        entry_id,
+                    }
+                }),
+                (any::<Option<String>>(), any::<Option<String>>())
+                    .prop_map(|(current, new)| Request::SetPassphrase { current, new }),
+                (any::<i64>(), any::<Option<bool>>(), any::<Option<bool>>()).prop_map(
+                    |(id, can_push, can_pull)| Request::SetDevicePermissions {
+                        id,
+                        can_push,
+                        can_pull,
+                    }
+                ),
+                entry_id().prop_map(|id| Request::GetEntry { id }),
+                entry_id().prop_map(|id| Request::SetClipboard { id }),
+                entry_id().prop_map(|id| Request::SetPrimarySelection { id }),
+                entry_id().prop_map(|id| Request::DeleteEntry { id }),
+                "[0-9a-f]{64}".prop_map(|hash| Request::GetByHash { hash }),
+                "[0-9a-f]{64}".prop_map(|hash| Request::SetClipboardByHash { hash }),
+                (entry_id(), ".*").prop_map(|(id, path)| Request::SaveToFile { id, path }),
+                (entry_id(), any::<Option<String>>())
+                    .prop_map(|(id, delimiter)| Request::SplitEntry { id, delimiter }),
+                prop::collection::vec(entry_id(), 0..8).prop_map(|ids| Request::QueuePaste { ids }),
+                (entry_id(), any::<bool>())
+                    .prop_map(|(id, pinned)| Request::SetPinned { id, pinned }),
+                (entry_id(), any::<u32>())
+                    .prop_map(|(id, position)| Request::MovePinned { id, position }),
+                ".*".prop_map(|name| Request::CreateCollection { name }),
+                (any::<i64>(), ".*").prop_map(|(id, name)| Request::RenameCollection { id, name }),
+                any::<i64>().prop_map(|id| Request::DeleteCollection { id }),
+                (any::<i64>(), entry_id()).prop_map(|(collection_id, entry_id)| {
+                    Request::RemoveFromCollection {
+                        collection_id,
+                        entry_id,
+                    }
+                }),
+                any::<i64>().prop_map(|id| Request::ExportCollection { id }),
+                collection_export().prop_map(|export| Request::ImportCollection { export }),
+                any::<Option<u32>>().prop_map(|limit| Request::GetAuditLog { limit }),
+                (any::<u64>(), any::<Option<u32>>())
+                    .prop_map(|(period_secs, limit)| Request::GetReport { period_secs, limit }),
+                ".*".prop_map(|passphrase| Request::Unlock { passphrase }),
+                any::<Option<String>>().prop_map(|path| Request::RecoverDatabase { path }),
+                any::<i64>().prop_map(|id| Request::RemoveDevice { id }),
+                ignore_rules().prop_map(|ignore| Request::SetConfig { ignore }),
+                ".*".prop_map(|directive| Request::SetLogLevel { directive }),
+                prop::sample::select(vec![
+                    Request::PopQueue,
+                    Request::ClearQueue,
+                    Request::ClearHistory,
+                    Request::ListCollections,
+                    Request::GetStatus,
+                    Request::GetStats,
+                    Request::Lock,
+                    Request::VerifyIntegrity,
+                    Request::StartPairing,
+                    Request::ListDevices,
+                    Request::GetConfig,
+                    Request::Subscribe,
+                    Request::Ping,
+                ]),
+            ]
+        }
+
+        pub fn response() -> impl Strategy<Value = Response> {
+            prop_oneof![
+                (prop::collection::vec(history_entry(), 0..4), any::<u64>()).prop_map(
+                    |(entries, total_count)| Response::History {
+                        entries,
+                        total_count,
+                    }
+                ),
+                history_entry().prop_map(|entry| Response::Entry {
+                    entry: Box::new(entry)
+                }),
+                (any::<i64>(), ".*", any::<Vec<u8>>()).prop_map(|(id, mime_type, data)| {
+                    Response::Content {
+                        id,
+                        mime_type,
+                        data: data.into(),
+                    }
+                }),
+                (
+                    prop::collection::vec((any::<i64>(), any::<Vec<u8>>()), 0..4),
+                    any::<Vec<i64>>()
+                )
+                    .prop_map(|(contents, omitted)| Response::Contents {
+                        contents: contents
+                            .into_iter()
+                            .map(|(id, data)| EntryContent {
+                                id,
+                                mime_type: "text/plain".to_string(),
+                                data: data.into(),
+                            })
+                            .collect(),
+                        omitted,
+                    }),
+                (error_code(), ".*").prop_map(|(code, message)| Response::Error { code, message }),
+                (
+                    (".*", any::<u64>(), any::<u64>()),
+                    (
+                        ".*",
+                        any::<bool>(),
+                        any::<Vec<String>>(),
+                        any::<Option<String>>()
+                    ),
+                    prop_oneof![
+                        Just(SearchBackend::FullText),
+                        Just(SearchBackend::Trigram),
+                        Just(SearchBackend::Substring)
+                    ]
+                )
+                    .prop_map(|(a, b, search_backend)| Response::Status {
+                        version: a.0,
+                        entry_count: a.1,
+                        database_size_bytes: a.2,
+                        log_level: b.0,
+                        locked: b.1,
+                        warnings: b.2,
+                        search_backend,
+                        capture_error: b.3,
+                    }),
+                (
+                    any::<[u64; 5]>(),
+                    any::<u64>(),
+                    prop::collection::vec((".*", any::<u64>(), any::<u64>()), 0..4)
+                )
+                    .prop_map(|(cache, dropped_captures, mime_types)| {
+                        Response::Stats {
+                            cache: CacheStats {
+                                hits: cache[0],
+                                misses: cache[1],
+                                entries: cache[2],
+                                bytes: cache[3],
+                                capacity_bytes: cache[4],
+                            },
+                            dropped_captures,
+                            mime_types: mime_types
+                                .into_iter()
+                                .map(|(mime_type, offered, selected)| MimeTypeCount {
+                                    mime_type,
+                                    offered,
+                                    selected,
+                                })
+                                .collect(),
+                        }
+                    }),
+                (any::<u64>(), any::<Vec<i64>>(), any::<Vec<i64>>()).prop_map(
+                    |(checked, mismatched, unreadable)| Response::Integrity {
+                        checked,
+                        mismatched,
+                        unreadable,
+                    }
+                ),
+                any::<[u64; 3]>().prop_map(|[checked, merged, removed]| {
+                    Response::Deduplicated {
+                        checked,
+                        merged,
+                        removed,
+                    }
+                }),
+                daemon_event().prop_map(|event| Response::Event { event }),
+                prop::collection::vec(collection(), 0..4)
+                    .prop_map(|collections| Response::Collections { collections }),
+                collection().prop_map(|collection| Response::Collection { collection }),
+                collection_export().prop_map(|export| Response::CollectionExport { export }),
+                ".*".prop_map(|path| Response::Saved { path }),
+                any::<Vec<i64>>().prop_map(|ids| Response::Split { ids }),
+                any::<Vec<i64>>().prop_map(|waiting| Response::Queue { waiting }),
+                any::<u64>().prop_map(|count| Response::Pruned { count }),
+                (any::<bool>(), prop::collection::vec(audit_record(), 0..4))
+                    .prop_map(|(enabled, records)| Response::AuditLog { enabled, records }),
+                report().prop_map(|report| Response::Report { report }),
+                (".*", any::<i64>())
+                    .prop_map(|(uri, expires_at)| Response::Pairing { uri, expires_at }),
+                prop::collection::vec(sync_device(), 0..4)
+                    .prop_map(|devices| Response::Devices { devices }),
+                any::<[u64; 3]>().prop_map(|[recovered, skipped, failed]| {
+                    Response::Recovered {
+                        recovered,
+                        skipped,
+                        failed,
+                    }
+                }),
+                (ignore_rules(), ignore_rules()).prop_map(|(ignore, added_ignore)| {
+                    Response::Config {
+                        ignore: Box::new(ignore),
+                        added_ignore: Box::new(added_ignore),
+                    }
+                }),
+                Just(Response::Ok),
+                Just(Response::Pong),
+            ]
+        }
+    }
+
+    /// Decoding and re-encoding gives back the same JSON, so nothing was
+    /// lost in between. The protocol types have no `PartialEq` to compare
+    /// them directly.
+    fn reencoded<T: Serialize + for<'de> Deserialize<'de>>(frame: &[u8]) -> serde_json::Value {
+        let decoded: T = serde_json::from_slice(frame).unwrap();
+        serde_json::to_value(decoded).unwrap()
+    }
+
+    /// `frame` with a field this version doesn't know about added, as a
+    /// newer peer might send.
+    fn with_unknown_field(frame: &[u8], value: serde_json::Value) -> Vec<u8> {
+        let mut json: serde_json::Value = serde_json::from_slice(frame).unwrap();
+        json.as_object_mut()
+            .unwrap()
+            .insert("added_in_a_later_version".to_string(), value);
+        serde_json::to_vec(&json).unwrap()
+    }
+
+    fn json_value() -> impl Strategy<Value = serde_json::Value> {
+        prop_oneof![
+            Just(serde_json::Value::Null),
+            any::<bool>().prop_map(Into::into),
+            any::<i64>().prop_map(Into::into),
+            ".*".prop_map(Into::into),
+            prop::collection::vec(any::<u32>(), 0..4).prop_map(Into::into),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_requests_round_trip(
+            request in arbitrary::request(),
+            extra in json_value(),
+        ) {
+            let frame = encode_request(&request).unwrap();
+            prop_assert_eq!(frame.last(), Some(&b'\n'));
+            let original = serde_json::to_value(&request).unwrap();
+            prop_assert_eq!(&reencoded::<Request>(&frame), &original);
+            let newer = with_unknown_field(&frame, extra);
+            prop_assert_eq!(&reencoded::<Request>(&newer), &original);
+        }
+
+        #[test]
+        fn test_responses_round_trip(
+            response in arbitrary::response(),
+            extra in json_value(),
+        ) {
+            let frame = encode_response(&response).unwrap();
+            prop_assert_eq!(frame.last(), Some(&b'\n'));
+            let original = serde_json::to_value(&response).unwrap();
+            prop_assert_eq!(&reencoded::<Response>(&frame), &original);
+            let newer = with_unknown_field(&frame, extra);
+            prop_assert_eq!(&reencoded::<Response>(&newer), &original);
+        }
+
+        #[test]
+        fn test_truncated_frames_are_rejected(
+            response in arbitrary::response(),
+            cut in any::<prop::sample::Index>(),
+        ) {
+            // Cut before the closing brace, so the object is never complete
+            let frame = encode_response(&response).unwrap();
+            let cut = cut.index(frame.len() - 2);
+            prop_assert!(decode_response(&frame[..cut]).is_err());
+        }
+
+        #[test]
+        fn test_garbage_is_rejected_without_panicking(
+            frame in prop::collection::vec(any::<u8>(), 0..256),
+        ) {
+            let _ = decode_request(&frame);
+            let _ = decode_response(&frame);
+        }
+    }
 }
@@ -1,6 +1,11 @@
 //! Core types shared between daemon and client.
 
-use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use base64::display::Base64Display;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 /// The type of clipboard content.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -8,15 +13,21 @@ use serde::{Deserialize, Serialize};
 pub enum ContentType {
     Text,
     Image,
+    /// Anything else, such as an application's own format, shown as a
+    /// hexdump.
+    Other,
 }
 
 impl ContentType {
     /// Determine content type from MIME type string.
     pub fn from_mime(mime: &str) -> Self {
-        if mime.starts_with("image/") {
+        let essence = mime_essence(mime);
+        if essence.starts_with("image/") {
             ContentType::Image
-        } else {
+        } else if is_text_mime(&essence) {
             ContentType::Text
+        } else {
+            ContentType::Other
         }
     }
 
@@ -31,11 +42,257 @@ impl ContentType {
     }
 }
 
+/// Whether content with MIME type `essence`, as from `mime_essence`, is text.
+fn is_text_mime(essence: &str) -> bool {
+    let subtype = essence.strip_prefix("application/");
+    essence.starts_with("text/")
+        // X11 targets
+        || matches!(essence, "utf8_string" | "string" | "text" | "compound_text")
+        || matches!(subtype, Some("json" | "xml" | "javascript" | "x-sh"))
+        || subtype.is_some_and(|subtype| subtype.ends_with("+json") || subtype.ends_with("+xml"))
+}
+
+/// A Wayland selection that content can be read from or written to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// The regular clipboard (Ctrl+C / Ctrl+V).
+    #[default]
+    Clipboard,
+    /// The primary selection (select / middle-click paste).
+    Primary,
+}
+
+impl Selection {
+    /// Stable identifier used in the database and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Clipboard => "clipboard",
+            Self::Primary => "primary",
+        }
+    }
+
+    /// Look up a selection by its `name()`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clipboard" => Some(Self::Clipboard),
+            "primary" => Some(Self::Primary),
+            _ => None,
+        }
+    }
+}
+
+/// Where a history entry came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Origin {
+    /// Copied to the clipboard on this machine.
+    #[default]
+    Local,
+    /// Selected on this machine, captured from the primary selection.
+    Primary,
+    /// Pushed by a device paired for LAN sync.
+    Synced {
+        /// Device ID, as in `SyncDevice::id`; 0 if it wasn't known.
+        device: i64,
+        /// Name of the device when the entry was pushed.
+        name: String,
+    },
+    /// Added by a client request, such as copying part of an entry or one
+    /// through the HTTP gateway.
+    Api,
+    /// Imported from an exported collection.
+    Import,
+}
+
+impl Origin {
+    /// Where content captured from `selection` on this machine came from.
+    pub fn captured(selection: Selection) -> Self {
+        match selection {
+            Selection::Clipboard => Self::Local,
+            Selection::Primary => Self::Primary,
+        }
+    }
+
+    /// Which kind of origin this is.
+    pub fn kind(&self) -> OriginKind {
+        match self {
+            Self::Local => OriginKind::Local,
+            Self::Primary => OriginKind::Primary,
+            Self::Synced { .. } => OriginKind::Synced,
+            Self::Api => OriginKind::Api,
+            Self::Import => OriginKind::Import,
+        }
+    }
+
+    /// Whether the entry was copied to the clipboard on this machine.
+    pub fn is_local(&self) -> bool {
+        *self == Self::Local
+    }
+
+    /// Short text for showing the origin next to an entry, or `None` for
+    /// local copies, which need no marking.
+    pub fn label(&self) -> Option<String> {
+        match self {
+            Self::Local => None,
+            Self::Primary => Some("Primary selection".to_string()),
+            Self::Synced { name, .. } => Some(format!("From {}", name)),
+            Self::Api => Some("Added by a client".to_string()),
+            Self::Import => Some("Imported".to_string()),
+        }
+    }
+}
+
+/// How the daemon matches search text against entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchBackend {
+    /// SQLite's FTS5 full-text index, matching words and word prefixes.
+    #[default]
+    FullText,
+    /// An FTS5 index of three-character sequences, matching text anywhere
+    /// inside words, as in languages written without spaces.
+    Trigram,
+    /// Substring matching with LIKE, when SQLite has no FTS5.
+    Substring,
+}
+
+impl SearchBackend {
+    /// Stable identifier, as shown by `wayclip-ctl status`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FullText => "full_text",
+            Self::Trigram => "trigram",
+            Self::Substring => "substring",
+        }
+    }
+}
+
+/// The kind of an `Origin`, without its details, for filtering the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OriginKind {
+    Local,
+    Primary,
+    Synced,
+    Api,
+    Import,
+}
+
+impl OriginKind {
+    /// Stable identifier used in the database and on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Primary => "primary",
+            Self::Synced => "synced",
+            Self::Api => "api",
+            Self::Import => "import",
+        }
+    }
+
+    /// Look up an origin kind by its `name()`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "local" => Some(Self::Local),
+            "primary" => Some(Self::Primary),
+            "synced" => Some(Self::Synced),
+            "api" => Some(Self::Api),
+            "import" => Some(Self::Import),
+            _ => None,
+        }
+    }
+}
+
+/// Order of the history listing. A collection keeps its own order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySort {
+    /// Most recently copied first.
+    #[default]
+    Recent,
+    /// Most often pasted first.
+    MostUsed,
+    /// Largest content first.
+    Largest,
+    /// Least recently copied first.
+    Oldest,
+}
+
+impl HistorySort {
+    /// Stable identifier, as used in client settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Recent => "recent",
+            Self::MostUsed => "most_used",
+            Self::Largest => "largest",
+            Self::Oldest => "oldest",
+        }
+    }
+
+    /// Look up a sort order by its `name()`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "recent" => Some(Self::Recent),
+            "most_used" => Some(Self::MostUsed),
+            "largest" => Some(Self::Largest),
+            "oldest" => Some(Self::Oldest),
+            _ => None,
+        }
+    }
+}
+
+/// A reference to a history entry in a request: its row ID, or its UUID.
+///
+/// Row IDs change when entries are exported and imported or recovered,
+/// so external references should keep the UUID. Either serializes as a
+/// plain number or string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EntryId {
+    /// Row ID, as in `HistoryEntry::id`.
+    Row(i64),
+    /// UUID, as in `HistoryEntry::uuid`.
+    Uuid(String),
+}
+
+impl From<i64> for EntryId {
+    fn from(id: i64) -> Self {
+        Self::Row(id)
+    }
+}
+
+impl std::str::FromStr for EntryId {
+    type Err = std::convert::Infallible;
+
+    /// Parse a command-line argument: a number is a row ID, anything else
+    /// a UUID.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(match text.parse() {
+            Ok(id) => Self::Row(id),
+            Err(_) => Self::Uuid(text.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for EntryId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Row(id) => write!(f, "{}", id),
+            Self::Uuid(uuid) => f.write_str(uuid),
+        }
+    }
+}
+
 /// A clipboard history entry (metadata only, no content data).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     /// Unique identifier for this entry.
     pub id: i64,
+    /// Identifier that, unlike `id`, is never reused and survives recovery,
+    /// for references from outside the daemon.
+    #[serde(default)]
+    pub uuid: String,
     /// Type of content (text or image).
     pub content_type: ContentType,
     /// MIME type of the content.
@@ -48,23 +305,363 @@ pub struct HistoryEntry {
     pub created_at: i64,
     /// Whether this entry is pinned (won't be auto-deleted).
     pub pinned: bool,
+    /// Whether this is an animated image (e.g. a multi-frame GIF).
+    #[serde(default)]
+    pub animated: bool,
     /// Optional thumbnail for images (small PNG, base64 encoded).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub thumbnail: Option<String>,
+    /// PNG file holding the thumbnail, sent instead of `thumbnail` when the
+    /// daemon has it in its on-disk cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_path: Option<String>,
+    /// Name of the seat the entry was copied on, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seat: Option<String>,
+    /// Which selection the entry was captured from.
+    #[serde(default)]
+    pub selection: Selection,
+    /// User-given title, shown instead of the preview.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Free-form user note.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Only metadata and a hash were recorded; the content was not stored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub redacted: bool,
+    /// SHA-256 of the content, hex encoded. Unlike `id`, it stays the same
+    /// when an entry is exported and imported again.
+    #[serde(default)]
+    pub hash: String,
+    /// Earlier image this one looked like when it was captured, if the
+    /// daemon flags similar images.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similar_to: Option<i64>,
+    /// Where the entry came from.
+    #[serde(default, skip_serializing_if = "Origin::is_local")]
+    pub origin: Origin,
+    /// Application the content was copied from, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+    /// Title of the window the content was copied from, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_title: Option<String>,
+    /// Workspace that window was on, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    /// Whether this is what the clipboard holds right now, so copying it
+    /// again would change nothing.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_current: bool,
+    /// Whether this entry is in the archive rather than the live history.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
 }
 
 impl HistoryEntry {
+    /// Text to show for this entry: its title if it has one, else the preview.
+    pub fn display_text(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.preview)
+    }
+
     /// Generate an accessible description for screen readers.
     pub fn accessible_description(&self) -> String {
+        let text = self.display_text();
         match self.content_type {
-            ContentType::Text => format!("Text: {}", self.preview),
-            ContentType::Image => format!("Image: {}", self.preview),
+            ContentType::Text => format!("Text: {}", text),
+            ContentType::Image if self.animated => format!("Animated image: {}", text),
+            ContentType::Image => format!("Image: {}", text),
+            ContentType::Other => format!("Data: {}", text),
+        }
+    }
+}
+
+/// Raw content of one entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntryContent {
+    /// Entry ID.
+    pub id: i64,
+    /// MIME type of the content.
+    pub mime_type: String,
+    /// Content data (base64 encoded).
+    pub data: ContentData,
+}
+
+/// Content bytes, sent as a base64 string.
+///
+/// The base64 is written straight into the serializer's output and decoded
+/// straight from the input, so a large entry is never also held as a base64
+/// `String` on either end.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ContentData(pub Vec<u8>);
+
+impl From<Vec<u8>> for ContentData {
+    fn from(data: Vec<u8>) -> Self {
+        Self(data)
+    }
+}
+
+impl fmt::Debug for ContentData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ContentData({} bytes)", self.0.len())
+    }
+}
+
+impl Serialize for ContentData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Base64Display::new(&self.0, &STANDARD))
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = ContentData;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("base64 encoded content")
+            }
+
+            fn visit_str<E: de::Error>(self, data: &str) -> Result<ContentData, E> {
+                STANDARD.decode(data).map(ContentData).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Hit statistics for the daemon's in-memory content cache.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Content reads answered from the cache.
+    pub hits: u64,
+    /// Content reads that went to the database.
+    pub misses: u64,
+    /// Entries currently cached.
+    pub entries: u64,
+    /// Bytes of content currently cached.
+    pub bytes: u64,
+    /// Maximum bytes of content the cache holds.
+    pub capacity_bytes: u64,
+}
+
+/// How often a MIME type was offered with a new selection, and how often it
+/// was the one read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MimeTypeCount {
+    /// The MIME type, as offered.
+    pub mime_type: String,
+    /// Selections offering it.
+    pub offered: u64,
+    /// Selections read as it.
+    pub selected: u64,
+}
+
+/// Summary of what was copied over a period up to now, from
+/// `Request::GetReport`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    /// Unix timestamp the period starts at.
+    pub since: i64,
+    /// Unix timestamp the period ends at.
+    pub until: i64,
+    /// Entries first copied in the period that are still in the history.
+    pub captured: u64,
+    /// Total size of those entries.
+    pub captured_bytes: u64,
+    /// Applications those entries were copied from, most first.
+    pub top_apps: Vec<AppCount>,
+    /// Largest of those entries, largest first.
+    pub biggest: Vec<ReportEntry>,
+    /// Entries used in the period that have been copied back more than
+    /// once, most often first.
+    pub most_reused: Vec<ReportEntry>,
+}
+
+/// How many entries were copied from one application.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppCount {
+    /// The application's name.
+    pub app: String,
+    /// Entries copied from it.
+    pub count: u64,
+}
+
+/// An entry listed in a `Report`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportEntry {
+    /// Row ID of the entry.
+    pub id: i64,
+    /// Text preview.
+    pub preview: String,
+    /// Size of the content in bytes.
+    pub byte_size: u64,
+    /// How many times the entry has been copied, counting the first.
+    pub use_count: u64,
+}
+
+/// One recorded read of an entry's content, from the daemon's audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Unix timestamp of the access.
+    pub timestamp: i64,
+    /// Process ID of the connected client, if the kernel reported one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    /// User ID of the connected client.
+    pub uid: u32,
+    /// Name of the client process at the time of the access, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    /// Request that read the content, e.g. "GetContent".
+    pub request: String,
+    /// Entry whose content was read.
+    pub entry_id: i64,
+}
+
+/// A companion device paired for LAN sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncDevice {
+    /// Unique identifier for this device.
+    pub id: i64,
+    /// Name the device gave when it paired.
+    pub name: String,
+    /// Whether the device may add entries.
+    pub can_push: bool,
+    /// Whether the device may list entries and read their content.
+    pub can_pull: bool,
+    /// Unix timestamp of the pairing.
+    pub paired_at: i64,
+    /// Unix timestamp of the device's last connection, if it has connected
+    /// since pairing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen_at: Option<i64>,
+}
+
+/// Patterns (regexes) for captures that are never recorded. A capture
+/// matching any one of them is dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoreRules {
+    /// Matched against the MIME type.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mime: Vec<String>,
+    /// Matched against the application the content was copied from, when it
+    /// is known.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apps: Vec<String>,
+    /// Matched against text content.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<String>,
+}
+
+/// Which entries `Request::Prune` deletes. Every condition given must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneCriteria {
+    /// Only entries copied more than this many seconds ago.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub older_than_secs: Option<u64>,
+    /// Only entries larger than this many bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub larger_than: Option<u64>,
+    /// Only entries of this type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<ContentType>,
+    /// Only entries copied from an application whose name contains this,
+    /// ignoring case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_app: Option<String>,
+}
+
+/// A named, ordered group of entries (e.g. "Work addresses" or "SQL snippets").
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Collection {
+    /// Unique identifier for this collection.
+    pub id: i64,
+    /// Display name, unique among collections.
+    pub name: String,
+    /// Number of entries in the collection.
+    pub entry_count: u64,
+}
+
+/// A collection and its contents in a portable form for export/import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionExport {
+    /// Collection name.
+    pub name: String,
+    /// Entries in collection order.
+    pub entries: Vec<ExportedEntry>,
+}
+
+/// One entry of an exported collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedEntry {
+    /// MIME type of the content.
+    pub mime_type: String,
+    /// Content, base64 encoded.
+    pub data: String,
+    /// Title given to the entry, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Note attached to the entry, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// A conversion applied to an entry's content when copying it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Copy the first frame of a GIF as a still PNG.
+    StillPng,
+    /// Copy HTML as plain text with the markup stripped.
+    PlainText,
+}
+
+impl Transform {
+    /// All known transforms, in menu order.
+    pub const ALL: &'static [Transform] = &[Transform::StillPng, Transform::PlainText];
+
+    /// Stable identifier, matching the serialized form.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transform::StillPng => "still_png",
+            Transform::PlainText => "plain_text",
+        }
+    }
+
+    /// Look up a transform by its identifier.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|t| t.name() == name)
+    }
+
+    /// Human-readable label for menus, with an underscore before the
+    /// mnemonic letter.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Transform::StillPng => "Copy as _Still PNG",
+            Transform::PlainText => "Copy as Plain _Text",
+        }
+    }
+
+    /// Check whether this transform can be applied to content of the given MIME type.
+    pub fn applies_to(&self, mime_type: &str) -> bool {
+        match self {
+            Transform::StillPng => mime_essence(mime_type) == "image/gif",
+            Transform::PlainText => mime_essence(mime_type) == "text/html",
         }
     }
 }
 
 /// MIME type priority for text content.
+///
+/// HTML is preferred so rich copies keep their markup; the daemon stores a
+/// stripped plain-text variant alongside it.
 pub const TEXT_MIME_PRIORITY: &[&str] = &[
+    "text/html",
     "text/plain;charset=utf-8",
     "text/plain",
     "UTF8_STRING",
@@ -73,7 +670,10 @@ pub const TEXT_MIME_PRIORITY: &[&str] = &[
 ];
 
 /// MIME type priority for image content.
+///
+/// SVG comes first because the daemon stores a rasterized PNG alongside it.
 pub const IMAGE_MIME_PRIORITY: &[&str] = &[
+    "image/svg+xml",
     "image/png",
     "image/jpeg",
     "image/webp",
@@ -82,22 +682,189 @@ pub const IMAGE_MIME_PRIORITY: &[&str] = &[
     "image/tiff",
 ];
 
-/// Select the best MIME type from a list of offered types.
-pub fn select_best_mime_type(offered: &[String]) -> Option<&str> {
-    // First try image types
-    for priority in IMAGE_MIME_PRIORITY {
-        if offered.iter().any(|m| m == *priority) {
-            return Some(priority);
+/// The MIME types read from a new selection, most preferred first: images,
+/// then text.
+pub fn default_mime_priority() -> Vec<String> {
+    IMAGE_MIME_PRIORITY
+        .iter()
+        .chain(TEXT_MIME_PRIORITY)
+        .map(|m| m.to_string())
+        .collect()
+}
+
+/// Select the best MIME type from a list of offered types, going by the
+/// patterns in `priority` and falling back to the first type offered that
+/// `fallback` accepts.
+///
+/// The type returned is one of those offered, as it was offered.
+pub fn select_best_mime_type<'a>(
+    offered: &'a [String],
+    priority: &[String],
+    fallback: impl Fn(&str) -> bool,
+) -> Option<&'a str> {
+    priority
+        .iter()
+        .find_map(|pattern| offered.iter().find(|m| mime_matches(pattern, m)))
+        .or_else(|| offered.iter().find(|m| fallback(m)))
+        .map(|s| s.as_str())
+}
+
+/// The type and subtype of a MIME type, lowercased and without parameters:
+/// `text/plain` for `Text/Plain; charset=UTF-8`.
+pub fn mime_essence(mime_type: &str) -> String {
+    let essence = mime_type.split(';').next().unwrap_or_default();
+    essence.trim().to_ascii_lowercase()
+}
+
+/// Whether the MIME type `offered` matches `pattern`.
+///
+/// Types and subtypes are compared ignoring case, and either may be `*` in
+/// the pattern, as in `text/*` or `*/*`, or end with one to match a prefix,
+/// as in `application/x-libreoffice-*`. Parameters in the pattern have to
+/// be in the offer too, with values compared ignoring case, but the offer
+/// may have more: `text/plain;charset=utf-8` matches
+/// `text/plain; charset=UTF-8; format=flowed`.
+pub fn mime_matches(pattern: &str, offered: &str) -> bool {
+    let (pattern_essence, offered_essence) = (mime_essence(pattern), mime_essence(offered));
+    let essence_matches = match (
+        pattern_essence.split_once('/'),
+        offered_essence.split_once('/'),
+    ) {
+        (Some((type_, subtype)), Some((offered_type, offered_subtype))) => {
+            glob_matches(type_, offered_type) && glob_matches(subtype, offered_subtype)
         }
+        // X11 target names like UTF8_STRING have no subtype
+        _ => glob_matches(&pattern_essence, &offered_essence),
+    };
+    if !essence_matches {
+        return false;
     }
 
-    // Then try text types
-    for priority in TEXT_MIME_PRIORITY {
-        if offered.iter().any(|m| m == *priority) {
-            return Some(priority);
-        }
+    let offered_params: Vec<(String, String)> = mime_params(offered).collect();
+    mime_params(pattern).all(|param| {
+        offered_params
+            .iter()
+            .any(|offered| offered.0 == param.0 && offered.1.eq_ignore_ascii_case(&param.1))
+    })
+}
+
+/// Whether `text` is `pattern`, or starts with it up to a trailing `*`.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => text.starts_with(prefix),
+        None => pattern == text,
     }
+}
+
+/// The parameters of a MIME type, with lowercased names and unquoted values.
+fn mime_params(mime_type: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    mime_type.split(';').skip(1).filter_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        let value = value.trim().trim_matches('"');
+        Some((name.trim().to_ascii_lowercase(), value.to_string()))
+    })
+}
+
+/// File extension, without the dot, for content of the given MIME type.
+pub fn extension_for_mime(mime_type: &str) -> &'static str {
+    match mime_essence(mime_type).as_str() {
+        "text/html" => "html",
+        "image/svg+xml" => "svg",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "image/bmp" => "bmp",
+        "image/tiff" => "tiff",
+        "application/json" => "json",
+        "utf8_string" | "string" | "text" => "txt",
+        other if other.starts_with("text/") => "txt",
+        _ => "bin",
+    }
+}
 
-    // Fall back to first offered type
-    offered.first().map(|s| s.as_str())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_matches() {
+        assert!(mime_matches("text/plain", "text/plain"));
+        assert!(mime_matches("text/plain", "Text/Plain; charset=UTF-8"));
+        assert!(mime_matches(
+            "text/plain;charset=utf-8",
+            "text/plain;charset=UTF-8"
+        ));
+        assert!(mime_matches("image/png", "image/png;foo=bar"));
+        assert!(mime_matches("text/*", "text/x-moz-url"));
+        assert!(mime_matches("*/*", "application/octet-stream"));
+        assert!(mime_matches("utf8_string", "UTF8_STRING"));
+        assert!(mime_matches(
+            "application/x-libreoffice-*",
+            "application/x-libreoffice-internal-id-123"
+        ));
+
+        assert!(!mime_matches("text/plain;charset=utf-8", "text/plain"));
+        assert!(!mime_matches("text/plain", "text/plain-ish"));
+        assert!(!mime_matches("text/*", "image/png"));
+        assert!(!mime_matches("STRING", "UTF8_STRING"));
+    }
+
+    #[test]
+    fn test_select_best_mime_type() {
+        let offered = |types: &[&str]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let priority = default_mime_priority();
+        let any = |_: &str| true;
+
+        // The offered spelling is what gets requested
+        let text = offered(&["TEXT", "text/plain;charset=UTF-8"]);
+        assert_eq!(
+            select_best_mime_type(&text, &priority, any),
+            Some("text/plain;charset=UTF-8")
+        );
+        let image = offered(&["text/uri-list", "image/png;foo=bar"]);
+        assert_eq!(
+            select_best_mime_type(&image, &priority, any),
+            Some("image/png;foo=bar")
+        );
+
+        let priority = offered(&["text/*"]);
+        let other = offered(&[
+            "application/x-thing",
+            "application/x-stuff",
+            "text/x-moz-url",
+        ]);
+        assert_eq!(
+            select_best_mime_type(&other, &priority, any),
+            Some("text/x-moz-url")
+        );
+        assert_eq!(
+            select_best_mime_type(&other[..2], &priority, any),
+            Some("application/x-thing")
+        );
+        let not_thing = |m: &str| m != "application/x-thing";
+        assert_eq!(
+            select_best_mime_type(&other[..2], &priority, not_thing),
+            Some("application/x-stuff")
+        );
+        assert_eq!(
+            select_best_mime_type(&other[..1], &priority, not_thing),
+            None
+        );
+    }
+
+    #[test]
+    fn test_content_type_from_mime() {
+        assert_eq!(ContentType::from_mime("image/PNG"), ContentType::Image);
+        assert_eq!(ContentType::from_mime("text/html"), ContentType::Text);
+        assert_eq!(ContentType::from_mime("UTF8_STRING"), ContentType::Text);
+        assert_eq!(
+            ContentType::from_mime("application/ld+json"),
+            ContentType::Text
+        );
+        assert_eq!(
+            ContentType::from_mime("application/x-libreoffice-internal-id-1"),
+            ContentType::Other
+        );
+    }
 }
@@ -0,0 +1,53 @@
+//! Minimal synchronous IPC client for one-shot commands.
+
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use wayclip_common::{decode_response, encode_request, Request, Response};
+
+/// Connection to the daemon's IPC socket.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    /// Connect to the daemon.
+    pub fn connect() -> Result<Self> {
+        let path = wayclip_common::socket_path();
+        let stream = UnixStream::connect(&path).map_err(|e| {
+            anyhow!(
+                "Failed to connect to daemon at {:?}: {}. Is wayclip-daemon running?",
+                path,
+                e
+            )
+        })?;
+
+        Ok(Self {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Send a request and wait for its response.
+    ///
+    /// Error responses from the daemon are turned into `Err`.
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        let stream = self.reader.get_mut();
+        stream.write_all(&encode_request(request)?)?;
+        stream.flush()?;
+
+        match self.next_response()? {
+            Response::Error { code, message } => Err(anyhow!("{} ({:?})", message, code)),
+            response => Ok(response),
+        }
+    }
+
+    /// Read the next message from the daemon, such as a subscribed event.
+    pub fn next_response(&mut self) -> Result<Response> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Daemon closed the connection"));
+        }
+
+        Ok(decode_response(line.trim().as_bytes())?)
+    }
+}
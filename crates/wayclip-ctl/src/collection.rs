@@ -0,0 +1,116 @@
+//! `wayclip-ctl collection` subcommands.
+
+use crate::client::Client;
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use std::path::PathBuf;
+use wayclip_common::{CollectionExport, EntryId, Request, Response};
+
+#[derive(Subcommand)]
+pub enum CollectionCommand {
+    /// List collections.
+    List,
+    /// Create an empty collection.
+    Create { name: String },
+    /// Rename a collection.
+    Rename { name: String, new_name: String },
+    /// Delete a collection; its entries stay in the history.
+    Delete { name: String },
+    /// Add a history entry, by ID or UUID, to a collection.
+    Add { name: String, entry_id: EntryId },
+    /// Remove an entry, by ID or UUID, from a collection.
+    Remove { name: String, entry_id: EntryId },
+    /// Export a collection and its contents as JSON.
+    Export {
+        name: String,
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import a collection exported with `export`.
+    Import { file: PathBuf },
+}
+
+pub fn run(client: &mut Client, command: CollectionCommand) -> Result<()> {
+    match command {
+        CollectionCommand::List => match client.request(&Request::ListCollections)? {
+            Response::Collections { collections } => {
+                for collection in collections {
+                    println!("{}\t{} entries", collection.name, collection.entry_count);
+                }
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        CollectionCommand::Create { name } => {
+            client.request(&Request::CreateCollection { name })?;
+        }
+
+        CollectionCommand::Rename { name, new_name } => {
+            let id = find(client, &name)?;
+            client.request(&Request::RenameCollection { id, name: new_name })?;
+        }
+
+        CollectionCommand::Delete { name } => {
+            let id = find(client, &name)?;
+            client.request(&Request::DeleteCollection { id })?;
+        }
+
+        CollectionCommand::Add { name, entry_id } => {
+            let collection_id = find(client, &name)?;
+            client.request(&Request::AddToCollection {
+                collection_id,
+                entry_id,
+            })?;
+        }
+
+        CollectionCommand::Remove { name, entry_id } => {
+            let collection_id = find(client, &name)?;
+            client.request(&Request::RemoveFromCollection {
+                collection_id,
+                entry_id,
+            })?;
+        }
+
+        CollectionCommand::Export { name, output } => {
+            let id = find(client, &name)?;
+            let export = match client.request(&Request::ExportCollection { id })? {
+                Response::CollectionExport { export } => export,
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            };
+
+            let json = serde_json::to_string_pretty(&export)?;
+            match output {
+                Some(path) => std::fs::write(&path, json)?,
+                None => println!("{}", json),
+            }
+        }
+
+        CollectionCommand::Import { file } => {
+            let export: CollectionExport = serde_json::from_slice(&std::fs::read(&file)?)
+                .map_err(|e| anyhow!("{} is not a collection export: {}", file.display(), e))?;
+
+            match client.request(&Request::ImportCollection { export })? {
+                Response::Collection { collection } => println!(
+                    "Imported into '{}' ({} entries)",
+                    collection.name, collection.entry_count
+                ),
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up a collection's ID by name.
+fn find(client: &mut Client, name: &str) -> Result<i64> {
+    match client.request(&Request::ListCollections)? {
+        Response::Collections { collections } => collections
+            .into_iter()
+            .find(|c| c.name == name)
+            .map(|c| c.id)
+            .ok_or_else(|| anyhow!("No collection named '{}'", name)),
+        other => Err(anyhow!("Unexpected response: {:?}", other)),
+    }
+}
@@ -0,0 +1,92 @@
+//! `wayclip-ctl devices` subcommands, for companion devices synced over the
+//! local network.
+
+use crate::client::Client;
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use wayclip_common::{Request, Response};
+
+#[derive(Subcommand)]
+pub enum DevicesCommand {
+    /// List paired devices and what they may do.
+    List,
+    /// Show a QR code for a companion app to scan and pair with. New
+    /// devices may push entries but not pull them.
+    Pair,
+    /// Change what a paired device may do.
+    Allow {
+        /// Device ID, from `list`.
+        id: i64,
+        /// Whether the device may add entries.
+        #[arg(long, value_name = "BOOL")]
+        push: Option<bool>,
+        /// Whether the device may list entries and read their content.
+        #[arg(long, value_name = "BOOL")]
+        pull: Option<bool>,
+    },
+    /// Unpair a device.
+    Remove {
+        /// Device ID, from `list`.
+        id: i64,
+    },
+}
+
+pub fn run(client: &mut Client, command: DevicesCommand) -> Result<()> {
+    match command {
+        DevicesCommand::List => match client.request(&Request::ListDevices)? {
+            Response::Devices { devices } => {
+                for device in devices {
+                    let permissions = match (device.can_push, device.can_pull) {
+                        (true, true) => "push, pull",
+                        (true, false) => "push",
+                        (false, true) => "pull",
+                        (false, false) => "none",
+                    };
+                    let last_seen = device
+                        .last_seen_at
+                        .map_or("never".to_string(), |at| at.to_string());
+                    println!(
+                        "{}\t{}\t{}\tlast seen {}",
+                        device.id, device.name, permissions, last_seen
+                    );
+                }
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        DevicesCommand::Pair => match client.request(&Request::StartPairing)? {
+            Response::Pairing { uri, .. } => {
+                let code = QrCode::new(uri.as_bytes())?;
+                // Light modules drawn dark, for terminals with a dark background
+                let qr = code
+                    .render::<Dense1x2>()
+                    .dark_color(Dense1x2::Light)
+                    .light_color(Dense1x2::Dark)
+                    .build();
+                println!("{}", qr);
+                println!("{}", uri);
+                println!("Scan this with the companion app within 5 minutes; the code works once.");
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        DevicesCommand::Allow { id, push, pull } => {
+            if push.is_none() && pull.is_none() {
+                return Err(anyhow!("Give --push or --pull"));
+            }
+            client.request(&Request::SetDevicePermissions {
+                id,
+                can_push: push,
+                can_pull: pull,
+            })?;
+        }
+
+        DevicesCommand::Remove { id } => {
+            client.request(&Request::RemoveDevice { id })?;
+        }
+    }
+
+    Ok(())
+}
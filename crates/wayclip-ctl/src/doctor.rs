@@ -0,0 +1,42 @@
+//! `wayclip-ctl doctor`, the daemon's own checks plus whether it answers.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::PathBuf;
+use std::process::Command;
+use wayclip_common::{Request, Response};
+
+use crate::client::Client;
+
+/// Run `wayclip-daemon --doctor` for `profile`, then check that a running
+/// daemon answers. Returns whether everything passed.
+pub fn run(profile: Option<&str>) -> Result<bool> {
+    let mut command = Command::new(daemon_path());
+    command.arg("--doctor");
+    if let Some(profile) = profile {
+        command.args(["--profile", profile]);
+    }
+    let status = command
+        .status()
+        .context("Failed to run wayclip-daemon --doctor. Is wayclip-daemon installed?")?;
+
+    let answered =
+        Client::connect().and_then(|mut client| match client.request(&Request::Ping)? {
+            Response::Pong => Ok(()),
+            other => Err(anyhow!("Unexpected response: {:?}", other)),
+        });
+    match &answered {
+        Ok(()) => println!("[ ok ] Daemon: Running and answering"),
+        Err(e) => println!("[FAIL] Daemon: {:#}", e),
+    }
+
+    Ok(status.success() && answered.is_ok())
+}
+
+/// The daemon installed beside this tool, or the one on PATH.
+fn daemon_path() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("wayclip-daemon")))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from("wayclip-daemon"))
+}
@@ -0,0 +1,639 @@
+//! Wayclip command-line control tool.
+
+mod client;
+mod collection;
+mod devices;
+mod doctor;
+mod report;
+mod waybar;
+
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use client::Client;
+use std::io::Read;
+use std::path::PathBuf;
+use wayclip_common::{
+    ContentType, CycleDirection, DaemonEvent, EntryId, PruneCriteria, Request, Response,
+};
+
+/// Control the wayclip clipboard history daemon.
+#[derive(Parser)]
+#[command(name = "wayclip-ctl", version)]
+struct Cli {
+    /// Talk to the daemon for this profile instead of $WAYCLIP_PROFILE.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show daemon status and any warnings.
+    Status {
+        /// Print JSON for a Waybar custom module instead, updated as the
+        /// history changes.
+        #[arg(long)]
+        waybar: bool,
+    },
+    /// Check the compositor, configuration, database and socket the daemon
+    /// needs, and whether it is running, with advice for each problem.
+    Doctor,
+    /// Show runtime statistics, such as content cache hits.
+    Stats,
+    /// Summarize what was copied recently: how much, from which
+    /// applications, the biggest entries and those copied back most.
+    Report {
+        /// Cover the last week, the default.
+        #[arg(long, conflicts_with = "since")]
+        week: bool,
+        /// Cover this far back instead, e.g. 30d or 12h.
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        since: Option<u64>,
+        /// List at most this many applications and entries in each part.
+        #[arg(long, value_name = "N")]
+        top: Option<u32>,
+        /// Print JSON instead.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show which processes read which entries' content, newest first.
+    Audit {
+        /// Show at most this many records.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        limit: u32,
+    },
+    /// Lock the history so entries can't be read until it is unlocked.
+    Lock,
+    /// Unlock the history, prompting for its passphrase.
+    Unlock,
+    /// Set or change the passphrase that unlocks the history.
+    Passphrase {
+        /// Remove the passphrase instead; the history can't be locked without one.
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Delete the entries matching every condition given. Pinned entries
+    /// and collection members are kept.
+    Prune {
+        /// Only entries copied longer ago than this, e.g. 30d, 12h or 90m.
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<u64>,
+        /// Only entries larger than this, e.g. 1M, 500K or 2048.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+        larger_than: Option<u64>,
+        /// Only entries of this type: text, image or other.
+        #[arg(long = "type", value_name = "TYPE", value_parser = parse_content_type)]
+        content_type: Option<ContentType>,
+        /// Only entries copied from an application whose name contains this.
+        #[arg(long, value_name = "NAME")]
+        app: Option<String>,
+        /// Show how many entries would be deleted, without deleting them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Merge entries holding the same text apart from line endings or
+    /// trailing whitespace, keeping their pins, notes and collections.
+    Dedup {
+        /// Show how many duplicates would be merged, without merging them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move an archived entry back into the history as if it had just been
+    /// copied.
+    Restore {
+        /// Entry ID or UUID.
+        id: EntryId,
+    },
+    /// Give an entry a title, note or tags. Whatever isn't given is kept.
+    Annotate {
+        /// Entry ID or UUID.
+        id: EntryId,
+        /// Title shown instead of the preview; "" removes it.
+        #[arg(long)]
+        title: Option<String>,
+        /// Note, included in search; "" removes it and "-" reads it from stdin.
+        #[arg(long)]
+        note: Option<String>,
+        /// Add "#TAG" to the note, so searching for TAG finds the entry. May
+        /// be repeated.
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+    },
+    /// Split a text entry into an entry per line, for pasting a list one
+    /// item at a time. The first line ends up newest.
+    Split {
+        /// Entry ID or UUID.
+        id: EntryId,
+        /// Split at this text instead of at line breaks, e.g. ",".
+        #[arg(short, long)]
+        delimiter: Option<String>,
+    },
+    /// Write an entry's content to a file. An extension matching its type is
+    /// added if the path has none.
+    Save {
+        /// Entry ID or UUID.
+        id: EntryId,
+        /// File to write, replaced if it exists.
+        path: PathBuf,
+    },
+    /// Put the entry copied before the one on the clipboard there instead,
+    /// for cycling through recent copies from a hotkey.
+    Cycle {
+        /// Go the other way, to the entry copied after.
+        #[arg(long)]
+        next: bool,
+    },
+    /// Queue entries to paste one after another: the first goes on the
+    /// clipboard now, and each next one once the one before it is pasted.
+    /// Without arguments, shows what is still waiting.
+    Queue {
+        /// Entry IDs or UUIDs, in the order to paste them.
+        ids: Vec<EntryId>,
+        /// Skip to the next queued entry without pasting.
+        #[arg(long, conflicts_with_all = ["ids", "clear"])]
+        next: bool,
+        /// Empty the queue.
+        #[arg(long, conflicts_with = "ids")]
+        clear: bool,
+    },
+    /// Re-hash stored content and report damaged entries.
+    Verify,
+    /// Salvage entries from a corrupt database that was moved aside.
+    Recover {
+        /// Corrupt database file (defaults to the most recent backup).
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
+    /// Print daemon events, such as unusually large copies, as they happen.
+    Watch,
+    /// Manage named collections of entries.
+    #[command(subcommand)]
+    Collection(collection::CollectionCommand),
+    /// Manage companion devices that sync over the local network.
+    #[command(subcommand)]
+    Devices(devices::DevicesCommand),
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    wayclip_common::init_profile(cli.profile.as_deref())?;
+    if let Command::Status { waybar: true } = cli.command {
+        // Keeps running, and reconnecting, while the daemon comes and goes
+        return waybar::run();
+    }
+    if let Command::Doctor = cli.command {
+        // Works without a daemon to talk to, since that is often the problem
+        if !doctor::run(cli.profile.as_deref())? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let mut client = Client::connect()?;
+
+    match cli.command {
+        Command::Status { .. } => match client.request(&Request::GetStatus)? {
+            Response::Status {
+                version,
+                entry_count,
+                database_size_bytes,
+                log_level,
+                locked,
+                warnings,
+                search_backend,
+                capture_error,
+            } => {
+                println!("Daemon version: {}", version);
+                println!("Entries:        {}", entry_count);
+                println!("Database size:  {} bytes", database_size_bytes);
+                println!("Log level:      {}", log_level);
+                println!("Search:         {}", search_backend.name());
+                if locked {
+                    println!("Locked:         yes");
+                }
+                if let Some(error) = capture_error {
+                    println!("Capture:        stopped ({})", error);
+                }
+                for warning in warnings {
+                    println!("Warning: {}", warning);
+                }
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        Command::Doctor => unreachable!("doctor runs before connecting"),
+
+        Command::Stats => match client.request(&Request::GetStats)? {
+            Response::Stats {
+                cache,
+                dropped_captures,
+                mime_types,
+            } => {
+                let lookups = cache.hits + cache.misses;
+                let hit_rate = match lookups {
+                    0 => 0.0,
+                    _ => cache.hits as f64 * 100.0 / lookups as f64,
+                };
+                println!(
+                    "Cache hits:       {} of {} ({:.0}%)",
+                    cache.hits, lookups, hit_rate
+                );
+                println!("Cached entries:   {}", cache.entries);
+                println!(
+                    "Cache size:       {} of {}",
+                    format_size(cache.bytes),
+                    format_size(cache.capacity_bytes)
+                );
+                println!("Dropped captures: {}", dropped_captures);
+
+                if !mime_types.is_empty() {
+                    let width = mime_types
+                        .iter()
+                        .map(|count| count.mime_type.chars().count())
+                        .fold("MIME type".len(), usize::max);
+                    println!();
+                    println!(
+                        "{:width$}  {:>7}  {:>8}",
+                        "MIME type", "Offered", "Selected"
+                    );
+                    for count in &mime_types {
+                        println!(
+                            "{:width$}  {:>7}  {:>8}",
+                            count.mime_type, count.offered, count.selected
+                        );
+                    }
+                }
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        Command::Report {
+            week: _,
+            since,
+            top,
+            json,
+        } => {
+            let period_secs = since.unwrap_or(7 * 24 * 60 * 60);
+            let request = Request::GetReport {
+                period_secs,
+                limit: top,
+            };
+            match client.request(&request)? {
+                Response::Report { report } if json => report::print_json(&report)?,
+                Response::Report { report } => {
+                    let period = match since {
+                        Some(_) => format_age(period_secs),
+                        None => "week".to_string(),
+                    };
+                    report::print(&report, &period);
+                }
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Audit { limit } => {
+            match client.request(&Request::GetAuditLog { limit: Some(limit) })? {
+                Response::AuditLog { enabled, records } => {
+                    if !enabled {
+                        eprintln!("Audit logging is off; enable it with audit_log = true");
+                    }
+                    for record in records {
+                        let pid = record.pid.map_or("?".to_string(), |pid| pid.to_string());
+                        println!(
+                            "{}\tpid {}\tuid {}\t{}\t{}\tentry {}",
+                            record.timestamp,
+                            pid,
+                            record.uid,
+                            record.process.as_deref().unwrap_or("?"),
+                            record.request,
+                            record.entry_id
+                        );
+                    }
+                }
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Lock => {
+            client.request(&Request::Lock)?;
+        }
+
+        Command::Unlock => {
+            let passphrase = rpassword::prompt_password("Passphrase: ")?;
+            client.request(&Request::Unlock { passphrase })?;
+        }
+
+        Command::Passphrase { remove } => {
+            let new = if remove {
+                None
+            } else {
+                let new = rpassword::prompt_password("New passphrase: ")?;
+                if rpassword::prompt_password("Repeat new passphrase: ")? != new {
+                    return Err(anyhow!("Passphrases don't match"));
+                }
+                Some(new)
+            };
+
+            // The current passphrase is only asked for if one is set
+            let request = Request::SetPassphrase {
+                current: None,
+                new: new.clone(),
+            };
+            if client.request(&request).is_err() {
+                let current = rpassword::prompt_password("Current passphrase: ")?;
+                client.request(&Request::SetPassphrase {
+                    current: Some(current),
+                    new,
+                })?;
+            }
+        }
+
+        Command::Prune {
+            older_than,
+            larger_than,
+            content_type,
+            app,
+            dry_run,
+        } => {
+            let criteria = PruneCriteria {
+                older_than_secs: older_than,
+                larger_than,
+                content_type,
+                source_app: app,
+            };
+            if criteria == PruneCriteria::default() {
+                return Err(anyhow!(
+                    "Give at least one of --older-than, --larger-than, --type or --app"
+                ));
+            }
+
+            match client.request(&Request::Prune { criteria, dry_run })? {
+                Response::Pruned { count } if dry_run => println!("Would delete {} entries", count),
+                Response::Pruned { count } => println!("Deleted {} entries", count),
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Dedup { dry_run } => match client.request(&Request::DedupExisting { dry_run })? {
+            Response::Deduplicated {
+                merged, removed, ..
+            } if dry_run => println!("Would merge {} duplicates into {} entries", removed, merged),
+            Response::Deduplicated {
+                merged, removed, ..
+            } => println!("Merged {} duplicates into {} entries", removed, merged),
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        Command::Restore { id } => match client.request(&Request::RestoreArchived { id })? {
+            Response::Entry { entry } => println!("Restored entry {}", entry.id),
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        Command::Annotate {
+            id,
+            title,
+            note,
+            tags,
+        } => {
+            if title.is_none() && note.is_none() && tags.is_empty() {
+                return Err(anyhow!("Give at least one of --title, --note or --tag"));
+            }
+
+            // SetEntryMeta replaces both fields, so start from the current ones
+            let entry = match client.request(&Request::GetEntry { id: id.clone() })? {
+                Response::Entry { entry } => entry,
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            };
+            let title = title.or(entry.title);
+            let mut note = match note.as_deref() {
+                Some("-") => {
+                    let mut note = String::new();
+                    std::io::stdin().read_to_string(&mut note)?;
+                    note.trim_end().to_string()
+                }
+                Some(note) => note.to_string(),
+                None => entry.note.unwrap_or_default(),
+            };
+            for tag in tags {
+                let tag = format!("#{}", tag.trim_start_matches('#'));
+                if !note.split_whitespace().any(|word| word == tag) {
+                    if !note.is_empty() {
+                        note.push(' ');
+                    }
+                    note.push_str(&tag);
+                }
+            }
+
+            client.request(&Request::SetEntryMeta {
+                id,
+                title,
+                note: Some(note),
+            })?;
+        }
+
+        Command::Split { id, delimiter } => {
+            match client.request(&Request::SplitEntry { id, delimiter })? {
+                Response::Split { ids } => println!("Split into {} entries", ids.len()),
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Save { id, path } => {
+            // The daemon doesn't share our working directory
+            let path = std::path::absolute(&path)?.to_string_lossy().into_owned();
+            match client.request(&Request::SaveToFile { id, path })? {
+                Response::Saved { path } => println!("Saved to {}", path),
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Cycle { next } => {
+            let direction = match next {
+                true => CycleDirection::Next,
+                false => CycleDirection::Previous,
+            };
+            match client.request(&Request::CycleClipboard { direction })? {
+                Response::Entry { entry } => println!("{}: {}", entry.id, entry.display_text()),
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Queue { ids, next, clear } => {
+            let request = if clear {
+                Request::ClearQueue
+            } else if next {
+                Request::PopQueue
+            } else {
+                Request::QueuePaste { ids }
+            };
+            match client.request(&request)? {
+                Response::Ok => println!("Paste queue cleared"),
+                Response::Queue { waiting } if waiting.is_empty() => {
+                    println!("Nothing else waiting in the paste queue")
+                }
+                Response::Queue { waiting } => {
+                    let ids: Vec<String> = waiting.iter().map(i64::to_string).collect();
+                    println!("{} waiting: {}", waiting.len(), ids.join(", "));
+                }
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Verify => match client.request(&Request::VerifyIntegrity)? {
+            Response::Integrity {
+                checked,
+                mismatched,
+                unreadable,
+            } => {
+                println!("Checked {} entries", checked);
+                for id in &mismatched {
+                    println!("Entry {}: content does not match its hash", id);
+                }
+                for id in &unreadable {
+                    println!("Entry {}: content could not be read", id);
+                }
+                let damaged = mismatched.len() + unreadable.len();
+                if damaged > 0 {
+                    return Err(anyhow!("{} damaged entries found", damaged));
+                }
+            }
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        },
+
+        Command::Recover { from } => {
+            let path = from
+                .map(|p| std::path::absolute(&p).map(|p| p.to_string_lossy().into_owned()))
+                .transpose()?;
+            match client.request(&Request::RecoverDatabase { path })? {
+                Response::Recovered {
+                    recovered,
+                    skipped,
+                    failed,
+                } => {
+                    println!("Recovered {} entries", recovered);
+                    if skipped > 0 {
+                        println!("Skipped {} entries already in history", skipped);
+                    }
+                    if failed > 0 {
+                        println!("Could not read {} damaged entries", failed);
+                    }
+                }
+                other => return Err(anyhow!("Unexpected response: {:?}", other)),
+            }
+        }
+
+        Command::Watch => {
+            client.request(&Request::Subscribe)?;
+            loop {
+                if let Response::Event {
+                    event:
+                        DaemonEvent::LargeCapture {
+                            id,
+                            byte_size,
+                            mime_type,
+                        },
+                } = client.next_response()?
+                {
+                    println!(
+                        "You just copied {} to the clipboard ({}, entry {})",
+                        format_size(byte_size),
+                        mime_type,
+                        id
+                    );
+                }
+            }
+        }
+
+        Command::Collection(command) => collection::run(&mut client, command)?,
+        Command::Devices(command) => devices::run(&mut client, command)?,
+    }
+
+    Ok(())
+}
+
+/// Parse an age such as "30d" into seconds. Units are s (the default), m,
+/// h, d and w.
+fn parse_age(text: &str) -> Result<u64> {
+    let (number, unit) = split_unit(text)?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(anyhow!("Unknown unit '{}'; use s, m, h, d or w", unit)),
+    };
+    Ok(number.saturating_mul(scale))
+}
+
+/// Format seconds as the largest whole unit `parse_age` takes, e.g. "30
+/// days".
+fn format_age(secs: u64) -> String {
+    let units = [
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+    let (count, unit) = units
+        .into_iter()
+        .find(|(scale, _)| secs >= *scale && secs % scale == 0)
+        .map_or((secs, "second"), |(scale, unit)| (secs / scale, unit));
+    match count {
+        1 => unit.to_string(),
+        _ => format!("{} {}s", count, unit),
+    }
+}
+
+/// Parse a size such as "1M" into bytes. A bare number is in bytes; K, M
+/// and G are powers of 1024, as in `format_size`.
+fn parse_size(text: &str) -> Result<u64> {
+    let (number, unit) = split_unit(text)?;
+    let scale = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow!("Unknown unit '{}'; use K, M or G", unit)),
+    };
+    Ok(number.saturating_mul(scale))
+}
+
+/// Split "30d" into 30 and "d".
+fn split_unit(text: &str) -> Result<(u64, &str)> {
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(digits);
+    let number = number
+        .parse()
+        .map_err(|_| anyhow!("'{}' doesn't start with a number", text))?;
+    Ok((number, unit.trim()))
+}
+
+fn parse_content_type(text: &str) -> Result<ContentType> {
+    match text {
+        "text" => Ok(ContentType::Text),
+        "image" => Ok(ContentType::Image),
+        "other" => Ok(ContentType::Other),
+        _ => Err(anyhow!("Expected text, image or other")),
+    }
+}
+
+/// Format a byte count for people, e.g. "8.0 MB".
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
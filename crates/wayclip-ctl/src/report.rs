@@ -0,0 +1,64 @@
+//! `wayclip-ctl report`, a digest of what was copied recently.
+
+use anyhow::Result;
+use wayclip_common::{Report, ReportEntry};
+
+use crate::format_size;
+
+/// Longest preview shown for an entry, in characters.
+const PREVIEW_CHARS: usize = 60;
+
+/// Print `report` for people to read, under a heading naming `period`.
+pub fn print(report: &Report, period: &str) {
+    println!("Clipboard report for the last {}", period);
+    println!();
+    println!(
+        "Entries captured: {} ({})",
+        report.captured,
+        format_size(report.captured_bytes)
+    );
+
+    if !report.top_apps.is_empty() {
+        println!();
+        println!("Top applications:");
+        let width = report
+            .top_apps
+            .iter()
+            .map(|app| app.app.chars().count())
+            .max()
+            .unwrap_or(0);
+        for app in &report.top_apps {
+            println!("  {:width$}  {}", app.app, app.count, width = width);
+        }
+    }
+
+    if !report.biggest.is_empty() {
+        println!();
+        println!("Biggest entries:");
+        for entry in &report.biggest {
+            print_entry(entry, &format_size(entry.byte_size));
+        }
+    }
+
+    if !report.most_reused.is_empty() {
+        println!();
+        println!("Most reused:");
+        for entry in &report.most_reused {
+            print_entry(entry, &format!("{} copies", entry.use_count));
+        }
+    }
+}
+
+/// Print `report` as JSON.
+pub fn print_json(report: &Report) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
+
+fn print_entry(entry: &ReportEntry, detail: &str) {
+    let mut preview: String = entry.preview.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < entry.preview.len() {
+        preview.push('…');
+    }
+    println!("  {:>6}  {:>10}  {}", entry.id, detail, preview);
+}
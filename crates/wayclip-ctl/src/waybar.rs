@@ -0,0 +1,111 @@
+//! `wayclip-ctl status --waybar`: status for a Waybar (or eww) custom module.
+//!
+//! Prints one JSON object per line, in the format Waybar reads with
+//! `"return-type": "json"`, and a fresh one whenever the daemon announces a
+//! change.
+
+use crate::client::Client;
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::time::Duration;
+use wayclip_common::{HistoryEntry, Request, Response};
+
+/// Longest tooltip preview, in characters.
+const TOOLTIP_CHARS: usize = 100;
+
+/// How long to wait before reconnecting to a daemon that went away.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Follow the daemon's status until stdout is closed, reconnecting whenever
+/// the daemon restarts.
+pub fn run() -> Result<()> {
+    let mut out = std::io::stdout().lock();
+    loop {
+        if let Err(e) = follow(&mut out) {
+            // Fails in turn once the bar has closed our stdout
+            print(
+                &mut out,
+                "",
+                "stopped",
+                &format!("wayclip-daemon is not running ({})", e),
+            )?;
+            std::thread::sleep(RECONNECT_DELAY);
+        }
+    }
+}
+
+/// Print the status, then again after every event, until the connection
+/// fails.
+fn follow(out: &mut impl Write) -> Result<()> {
+    let mut events = Client::connect()?;
+    events.request(&Request::Subscribe)?;
+
+    // Replies on the subscribed connection would be mixed in with events
+    let mut client = Client::connect()?;
+    loop {
+        let (count, locked) = match client.request(&Request::GetStatus)? {
+            Response::Status {
+                entry_count,
+                locked,
+                ..
+            } => (entry_count, locked),
+            other => return Err(anyhow!("Unexpected response: {:?}", other)),
+        };
+
+        let request = Request::GetHistory {
+            limit: Some(1),
+            offset: None,
+            search: None,
+            seat: None,
+            selection: None,
+            collection: None,
+            snippets: false,
+            similar_to: None,
+            origin: None,
+            sort: None,
+            pinned_first: false,
+            include_archived: false,
+        };
+        let tooltip = match client.request(&request) {
+            Ok(Response::History { entries, .. }) => match entries.first() {
+                Some(entry) => tooltip(entry),
+                None => "History is empty".to_string(),
+            },
+            Ok(other) => return Err(anyhow!("Unexpected response: {:?}", other)),
+            // A locked history may refuse to list its entries
+            Err(_) if locked => "History is locked".to_string(),
+            Err(e) => return Err(e),
+        };
+
+        let class = if locked { "locked" } else { "unlocked" };
+        print(out, &count.to_string(), class, &tooltip)?;
+
+        events.next_response()?;
+    }
+}
+
+/// The newest entry's title or preview, shortened and escaped for Pango
+/// markup.
+fn tooltip(entry: &HistoryEntry) -> String {
+    let text = entry.title.as_deref().unwrap_or(&entry.preview);
+    let mut short: String = text.chars().take(TOOLTIP_CHARS).collect();
+    if short.len() < text.len() {
+        short.push('…');
+    }
+    short
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn print(out: &mut impl Write, text: &str, class: &str, tooltip: &str) -> Result<()> {
+    let module = serde_json::json!({
+        "text": text,
+        "alt": class,
+        "class": class,
+        "tooltip": tooltip,
+    });
+    writeln!(out, "{}", module)?;
+    out.flush()?;
+    Ok(())
+}
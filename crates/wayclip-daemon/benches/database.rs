@@ -0,0 +1,156 @@
+//! Database operations at realistic history sizes. Run with
+//! `cargo bench -p wayclip-daemon --bench database`.
+
+// The daemon is a single binary, so the modules storage needs are built in
+// here too. Much of them goes unused, as do their tests' imports when this
+// is checked along with the tests.
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/database/mod.rs"]
+mod database;
+#[path = "../src/hash.rs"]
+mod hash;
+#[path = "../src/ipc/mod.rs"]
+mod ipc;
+#[path = "../src/thumbnail.rs"]
+mod thumbnail;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use wayclip_common::{ContentType, Origin, Selection};
+
+use database::{Database, EntryLimits, HistoryFilter, NewEntry, Storage};
+use hash::content_hash;
+
+/// History sizes to measure at.
+const SIZES: [u32; 2] = [10_000, 100_000];
+
+/// Words the generated entries are made of, so searches have something to
+/// find at every size.
+const WORDS: [&str; 8] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel",
+];
+
+/// Text of the `n`th generated entry: a few words, a URL now and then, and
+/// a number making it unique.
+fn text(n: u32) -> String {
+    let words = (0..6)
+        .map(|i| WORDS[(n as usize * 7 + i * 3) % WORDS.len()])
+        .collect::<Vec<_>>()
+        .join(" ");
+    match n % 5 {
+        0 => format!("https://example.com/{n}/{words}"),
+        _ => format!("{words} {n}"),
+    }
+}
+
+fn insert(db: &Database, text: &str) -> i64 {
+    let hash = content_hash(text.as_bytes());
+    db.insert_entry(&NewEntry {
+        hash: &hash,
+        content_type: ContentType::Text,
+        mime_type: "text/plain",
+        preview: text,
+        content: text.as_bytes(),
+        thumbnail: None,
+        animated: false,
+        image_hash: None,
+        similar_to: None,
+        variants: &[],
+        seat: None,
+        selection: Selection::Clipboard,
+        source_app: None,
+        window_title: None,
+        workspace: None,
+        origin: &Origin::Local,
+        redacted: None,
+    })
+    .unwrap()
+}
+
+/// A database on disk, as the daemon uses, holding `size` entries.
+fn populated(dir: &tempfile::TempDir, size: u32) -> Database {
+    let db = Database::open_at(dir.path().join(format!("history-{size}.db"))).unwrap();
+    db.migrate().unwrap();
+    for n in 0..size {
+        insert(&db, &text(n));
+    }
+    db
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    for size in SIZES {
+        let db = populated(&dir, size);
+        let mut next = size;
+        let mut group = c.benchmark_group(format!("{size} entries"));
+
+        group.bench_function("insert_entry", |b| {
+            b.iter_batched(
+                || {
+                    next += 1;
+                    text(next)
+                },
+                |text| insert(&db, &text),
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function("get_history", |b| {
+            b.iter(|| {
+                db.get_history(Some(100), None, &HistoryFilter::default())
+                    .unwrap()
+            })
+        });
+
+        for search in ["delta", "example", "delta golf"] {
+            group.bench_with_input(
+                BenchmarkId::new("get_history search", search),
+                search,
+                |b, search| {
+                    let filter = HistoryFilter {
+                        search: Some(search),
+                        ..Default::default()
+                    };
+                    b.iter(|| db.get_history(Some(100), None, &filter).unwrap())
+                },
+            );
+        }
+
+        // Both a hit and a miss, as most captures are new content
+        let existing = content_hash(text(size / 2).as_bytes());
+        let missing = content_hash(b"not in the history");
+        group.bench_function("find_by_hash", |b| {
+            b.iter(|| {
+                db.find_by_hash(&existing).unwrap();
+                db.find_by_hash(&missing).unwrap()
+            })
+        });
+
+        // Each round adds one entry over the limit for cleanup to remove
+        let limits = EntryLimits {
+            total: db.count_entries().unwrap() as u32,
+            text: None,
+            image: None,
+            count_kept: false,
+        };
+        group.bench_function("cleanup", |b| {
+            b.iter_batched(
+                || {
+                    next += 1;
+                    insert(&db, &text(next))
+                },
+                |_| db.cleanup(&limits).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = benchmarks
+}
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+//! Request latency with many clients at once. Run with
+//! `cargo bench -p wayclip-daemon --bench ipc_load`.
+
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use std::time::Instant;
+
+use support::{run_load, Daemon, Load};
+
+fn main() {
+    let daemon = Daemon::start(100_000);
+    for clients in [8, 32, 64] {
+        let load = Load {
+            writers: clients / 8,
+            captures: 200,
+            readers: clients - clients / 8,
+            queries: 50,
+        };
+        let started = Instant::now();
+        let report = run_load(&daemon, &load);
+        let elapsed = started.elapsed();
+        println!(
+            "{:>2} clients: {:>6} requests in {:>8.2?} (p50 {:.2?}, p99 {:.2?}, max {:.2?}), {} entries",
+            clients,
+            report.latencies.len(),
+            elapsed,
+            report.percentile(0.5),
+            report.percentile(0.99),
+            report.percentile(1.0),
+            report.total_count,
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Policies for requests that don't come from a local process: what the
+//! HTTP gateway, synced devices and browser extensions may do.
+
+use wayclip_common::{ErrorCode, HistoryEntry, Request, Response};
+
+use crate::config::{SourceAccess, SourcePolicy, SourcesConfig};
+use crate::ipc::Source;
+
+/// The policy for requests from `source`, or `None` for local ones.
+fn policy<'a>(config: &'a SourcesConfig, source: &Source) -> Option<&'a SourcePolicy> {
+    match source {
+        Source::Local => None,
+        Source::Browser => Some(&config.browser),
+        Source::Http => Some(&config.http),
+        Source::Sync { .. } => Some(&config.sync),
+    }
+}
+
+/// The response refusing `request` from `source`, if its policy doesn't
+/// allow it.
+pub fn refuse(config: &SourcesConfig, source: &Source, request: &Request) -> Option<Response> {
+    let policy = policy(config, source)?;
+    if local_only(request) {
+        return Some(Response::error(
+            ErrorCode::InvalidRequest,
+            format!(
+                "Only local clients may make this request, not {}",
+                label(source)
+            ),
+        ));
+    }
+    let allowed = match policy.access {
+        SourceAccess::Full => true,
+        SourceAccess::NoContent => !reads_content(request),
+        SourceAccess::PushOnly => matches!(request, Request::AddEntry { .. } | Request::Ping),
+    };
+    if !allowed {
+        return Some(Response::error(
+            ErrorCode::InvalidRequest,
+            format!("{} isn't allowed to make this request", label(source)),
+        ));
+    }
+
+    match request {
+        Request::AddEntry { data, .. } if policy.max_entry_size > 0 => {
+            // Exact for valid base64; anything else is refused when decoded
+            let size = (data.trim_end_matches('=').len() * 3 / 4) as u64;
+            (size > policy.max_entry_size).then(|| {
+                Response::error(
+                    ErrorCode::InvalidRequest,
+                    format!(
+                        "Entry of {} bytes is larger than {} may add ({} bytes)",
+                        size,
+                        label(source),
+                        policy.max_entry_size
+                    ),
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Blank what `response` would tell `source` of entries' content, if its
+/// policy doesn't let it read content: previews are often the whole entry.
+pub fn redact(config: &SourcesConfig, source: &Source, response: &mut Response) {
+    if !policy(config, source).is_some_and(|policy| policy.access == SourceAccess::NoContent) {
+        return;
+    }
+    match response {
+        Response::History { entries, .. } => entries.iter_mut().for_each(blank),
+        Response::Entry { entry } => blank(entry),
+        Response::Report { report } => {
+            for entry in report.biggest.iter_mut().chain(&mut report.most_reused) {
+                entry.preview.clear();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn blank(entry: &mut HistoryEntry) {
+    entry.preview.clear();
+    entry.title = None;
+    entry.note = None;
+    entry.window_title = None;
+    entry.thumbnail = None;
+    entry.thumbnail_path = None;
+}
+
+/// Name of a source as shown to the user.
+fn label(source: &Source) -> &str {
+    match source {
+        Source::Local => "A local client",
+        Source::Browser => "A browser extension",
+        Source::Http => "HTTP",
+        Source::Sync { name, .. } => name,
+    }
+}
+
+/// Whether `request` hands entry content to whoever made it.
+fn reads_content(request: &Request) -> bool {
+    matches!(
+        request,
+        Request::GetContent { .. }
+            | Request::GetContents { .. }
+            | Request::GetByHash { .. }
+            | Request::ExportCollection { .. }
+    )
+}
+
+/// Whether `request` writes files, locks or unlocks the history, pairs
+/// devices or changes the daemon's settings. Whatever their policy, remote
+/// clients may not: with `SaveToFile` alone an extension could write a
+/// shell profile or an autostart entry.
+fn local_only(request: &Request) -> bool {
+    matches!(
+        request,
+        Request::SaveToFile { .. }
+            | Request::RecoverDatabase { .. }
+            | Request::Lock
+            | Request::Unlock { .. }
+            | Request::SetPassphrase { .. }
+            | Request::StartPairing
+            | Request::ListDevices
+            | Request::SetDevicePermissions { .. }
+            | Request::RemoveDevice { .. }
+            | Request::GetConfig
+            | Request::SetConfig { .. }
+            | Request::SetLogLevel { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayclip_common::EntryId;
+
+    #[test]
+    fn test_source_policies() {
+        let config: SourcesConfig = toml::from_str(
+            r#"
+            http = { access = "no_content" }
+            sync = { access = "push_only", max_entry_size = 4 }
+            browser = { access = "push_only" }
+            "#,
+        )
+        .unwrap();
+        let phone = Source::Sync {
+            device: 1,
+            name: "Phone".to_string(),
+        };
+        let read = Request::GetContent {
+            id: EntryId::Row(1),
+        };
+        let list = Request::ListCollections;
+        let push = |data: &str| Request::AddEntry {
+            mime_type: "text/plain".to_string(),
+            data: data.to_string(),
+            set_clipboard: false,
+        };
+
+        assert!(refuse(&config, &Source::Local, &read).is_none());
+        assert!(refuse(&config, &Source::Http, &read).is_some());
+        assert!(refuse(&config, &Source::Http, &list).is_none());
+        assert!(refuse(&config, &phone, &list).is_some());
+        assert!(refuse(&config, &phone, &push("aGVsbA==")).is_none());
+        assert!(refuse(&config, &phone, &push("aGVsbG8=")).is_some());
+        assert!(refuse(&config, &Source::Browser, &list).is_some());
+        assert!(refuse(&config, &Source::Browser, &push("aGVsbG8=")).is_none());
+
+        // Full access still doesn't reach files or settings
+        let save = Request::SaveToFile {
+            id: EntryId::Row(1),
+            path: "/home/user/.bashrc".to_string(),
+        };
+        let config = SourcesConfig::default();
+        assert!(refuse(&config, &Source::Local, &save).is_none());
+        assert!(refuse(&config, &Source::Http, &save).is_some());
+        assert!(refuse(&config, &Source::Http, &Request::StartPairing).is_some());
+        assert!(refuse(&config, &Source::Http, &read).is_none());
+
+        // Browser extensions may only list entries unless allowed more
+        assert!(refuse(&config, &Source::Browser, &read).is_some());
+        assert!(refuse(&config, &Source::Browser, &list).is_none());
+    }
+
+    #[test]
+    fn test_redact_hides_previews() {
+        let config: SourcesConfig = toml::from_str(r#"http = { access = "no_content" }"#).unwrap();
+        let entry: HistoryEntry = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "content_type": "text",
+            "mime_type": "text/plain",
+            "preview": "hunter2",
+            "byte_size": 7,
+            "created_at": 0,
+            "pinned": false,
+            "title": "Password",
+        }))
+        .unwrap();
+        let history = || Response::History {
+            entries: vec![entry.clone()],
+            total_count: 1,
+        };
+
+        let mut response = history();
+        redact(&config, &Source::Local, &mut response);
+        let Response::History { entries, .. } = &response else {
+            unreachable!()
+        };
+        assert_eq!(entries[0].preview, "hunter2");
+
+        let mut response = history();
+        redact(&config, &Source::Http, &mut response);
+        let Response::History { entries, .. } = &response else {
+            unreachable!()
+        };
+        assert_eq!(entries[0].preview, "");
+        assert_eq!(entries[0].title, None);
+        assert_eq!(entries[0].byte_size, 7);
+
+        let secret = wayclip_common::ReportEntry {
+            id: 1,
+            preview: "hunter2".to_string(),
+            byte_size: 7,
+            use_count: 1,
+        };
+        let report = wayclip_common::Report {
+            biggest: vec![secret.clone()],
+            most_reused: vec![secret],
+            ..Default::default()
+        };
+        let mut response = Response::Report { report };
+        redact(&config, &Source::Http, &mut response);
+        let Response::Report { report } = &response else {
+            unreachable!()
+        };
+        assert_eq!(report.biggest[0].preview, "");
+        assert_eq!(report.most_reused[0].preview, "");
+        assert_eq!(report.biggest[0].byte_size, 7);
+    }
+}
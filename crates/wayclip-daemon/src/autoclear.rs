@@ -0,0 +1,100 @@
+//! Clearing sensitive captures from the clipboard after a timeout.
+
+use std::time::{Duration, Instant};
+use wayclip_common::Selection;
+
+/// A scheduled clear of one selection.
+#[derive(Debug)]
+pub struct PendingClear {
+    pub selection: Selection,
+    /// History entry to delete when the timeout fires.
+    pub delete_entry: Option<i64>,
+    /// Whether the selection still holds the sensitive content. Once
+    /// something else is copied there is nothing left to clear.
+    pub still_current: bool,
+    hash: String,
+    due: Instant,
+}
+
+/// Timers for clearing sensitive captures, at most one per selection.
+#[derive(Debug, Default)]
+pub struct AutoClear {
+    pending: Vec<PendingClear>,
+}
+
+impl AutoClear {
+    /// Clear `selection` after `delay` unless something else is copied first.
+    pub fn schedule(
+        &mut self,
+        selection: Selection,
+        hash: &str,
+        delete_entry: Option<i64>,
+        delay: Duration,
+    ) {
+        // A newer sensitive copy replaces the clipboard contents, but the
+        // older entry is still due for deletion
+        for pending in self.pending.iter_mut().filter(|p| p.selection == selection) {
+            pending.still_current = false;
+        }
+
+        self.pending.push(PendingClear {
+            selection,
+            delete_entry,
+            still_current: true,
+            hash: hash.to_string(),
+            due: Instant::now() + delay,
+        });
+    }
+
+    /// Note a new capture of content with `hash`, so a replaced selection
+    /// isn't cleared later.
+    pub fn captured(&mut self, selection: Selection, hash: &str) {
+        for pending in self
+            .pending
+            .iter_mut()
+            .filter(|p| p.selection == selection && p.still_current)
+        {
+            pending.still_current = hash == pending.hash;
+        }
+    }
+
+    /// When the next clear is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.iter().map(|p| p.due).min()
+    }
+
+    /// Remove and return the clears that are due.
+    pub fn take_due(&mut self) -> Vec<PendingClear> {
+        let now = Instant::now();
+        let (due, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| p.due <= now);
+        self.pending = pending;
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::content_hash;
+
+    #[test]
+    fn test_new_copy_cancels_clear() {
+        let mut autoclear = AutoClear::default();
+        let hunter2 = content_hash(b"hunter2");
+        autoclear.schedule(Selection::Clipboard, &hunter2, Some(1), Duration::ZERO);
+        autoclear.schedule(Selection::Primary, &hunter2, None, Duration::ZERO);
+
+        // The monitor sees our own capture first, then something else
+        autoclear.captured(Selection::Clipboard, &hunter2);
+        autoclear.captured(Selection::Clipboard, &content_hash(b"lunch?"));
+
+        let due = autoclear.take_due();
+        assert_eq!(due.len(), 2);
+        assert!(!due[0].still_current);
+        assert_eq!(due[0].delete_entry, Some(1));
+        assert!(due[1].still_current);
+        assert!(autoclear.next_due().is_none());
+    }
+}
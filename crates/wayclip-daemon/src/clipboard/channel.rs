@@ -0,0 +1,110 @@
+//! Channel carrying captures from the monitor to the main loop.
+//!
+//! Reader threads never wait on the main loop: when it falls behind, such as
+//! while the database is stalled, the oldest waiting capture is dropped to
+//! make room, since the newest is what the clipboard holds.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::Notify;
+
+use super::ClipboardEvent;
+
+/// Captures dropped because the channel was full, since the daemon started.
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+struct Shared {
+    events: Mutex<VecDeque<ClipboardEvent>>,
+    capacity: usize,
+    ready: Notify,
+}
+
+/// Sending half, cloned into each reader thread.
+#[derive(Clone)]
+pub struct CaptureSender(Arc<Shared>);
+
+/// Receiving half, polled by the main loop.
+pub struct CaptureReceiver(Arc<Shared>);
+
+/// Create a channel holding at most `capacity` captures.
+pub fn channel(capacity: usize) -> (CaptureSender, CaptureReceiver) {
+    let shared = Arc::new(Shared {
+        events: Mutex::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        ready: Notify::new(),
+    });
+    (CaptureSender(shared.clone()), CaptureReceiver(shared))
+}
+
+impl CaptureSender {
+    /// Queue a capture without waiting, dropping the oldest one if the
+    /// channel is full.
+    pub fn send(&self, event: ClipboardEvent) {
+        let mut events = self.0.events.lock().unwrap();
+        if events.len() >= self.0.capacity {
+            events.pop_front();
+            let dropped = DROPPED.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::warn!(
+                "Captures are arriving faster than they can be stored, dropped the oldest ({} so far)",
+                dropped
+            );
+        }
+        events.push_back(event);
+        drop(events);
+        self.0.ready.notify_one();
+    }
+}
+
+impl CaptureReceiver {
+    /// Wait for the next capture.
+    pub async fn recv(&mut self) -> ClipboardEvent {
+        loop {
+            if let Some(event) = self.0.events.lock().unwrap().pop_front() {
+                return event;
+            }
+            self.0.ready.notified().await;
+        }
+    }
+}
+
+/// Captures dropped because the main loop fell behind, since the daemon
+/// started.
+pub fn dropped_captures() -> u64 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayclip_common::Selection;
+
+    fn capture(text: &str) -> ClipboardEvent {
+        ClipboardEvent {
+            content: text.as_bytes().to_vec(),
+            hash: crate::hash::content_hash(text.as_bytes()),
+            mime_type: "text/plain".to_string(),
+            seat: None,
+            selection: Selection::Clipboard,
+            source_app: None,
+            window_title: None,
+            workspace: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_drops_oldest() {
+        let (tx, mut rx) = channel(2);
+        let before = dropped_captures();
+        for text in ["a", "b", "c"] {
+            tx.send(capture(text));
+        }
+        assert!(dropped_captures() > before);
+
+        assert_eq!(rx.recv().await.content, b"b");
+        assert_eq!(rx.recv().await.content, b"c");
+        tx.send(capture("d"));
+        assert_eq!(rx.recv().await.content, b"d");
+    }
+}
@@ -0,0 +1,79 @@
+//! Counts of the MIME types offered with each new selection and of the one
+//! read, so the MIME priority can be tuned to what applications really offer.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use wayclip_common::MimeTypeCount;
+
+/// Most distinct MIME types counted; applications can offer any string, and
+/// types first seen after this many are not counted.
+const MAX_MIME_TYPES: usize = 256;
+
+/// Offered and selected counts by MIME type, since the daemon started.
+static COUNTS: Mutex<BTreeMap<String, (u64, u64)>> = Mutex::new(BTreeMap::new());
+
+/// Count a selection offering `offered`, read as `selected` if any.
+pub fn record_offer(offered: &[String], selected: Option<&str>) {
+    let mut counts = COUNTS.lock().unwrap();
+    for (i, mime_type) in offered.iter().enumerate() {
+        // Some applications list a type more than once
+        if offered[..i].contains(mime_type) {
+            continue;
+        }
+        if counts.len() >= MAX_MIME_TYPES && !counts.contains_key(mime_type) {
+            continue;
+        }
+        let count = counts.entry(mime_type.clone()).or_default();
+        count.0 += 1;
+        if selected == Some(mime_type.as_str()) {
+            count.1 += 1;
+        }
+    }
+}
+
+/// MIME types counted so far, most often offered first.
+pub fn mime_type_counts() -> Vec<MimeTypeCount> {
+    let mut counts: Vec<MimeTypeCount> = COUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(mime_type, &(offered, selected))| MimeTypeCount {
+            mime_type: mime_type.clone(),
+            offered,
+            selected,
+        })
+        .collect();
+    // Ties stay in name order
+    counts.sort_by_key(|count| std::cmp::Reverse(count.offered));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_offer() {
+        // Other tests share the counts, so these types are only used here
+        let offered = |types: &[&str]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        record_offer(
+            &offered(&["x-test/rich", "x-test/plain", "x-test/plain"]),
+            Some("x-test/rich"),
+        );
+        record_offer(&offered(&["x-test/plain"]), Some("x-test/plain"));
+
+        let counts: Vec<_> = mime_type_counts()
+            .into_iter()
+            .filter(|count| count.mime_type.starts_with("x-test/"))
+            .map(|count| (count.mime_type, count.offered, count.selected))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("x-test/plain".to_string(), 2, 1),
+                ("x-test/rich".to_string(), 1, 1)
+            ]
+        );
+    }
+}
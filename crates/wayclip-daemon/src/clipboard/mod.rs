@@ -1,23 +1,89 @@
 //! Clipboard monitoring and operations.
 
+mod channel;
+mod mime_stats;
 mod monitor;
+mod source;
+mod toplevel;
 
+pub use channel::*;
+pub use mime_stats::mime_type_counts;
 pub use monitor::*;
+pub use source::*;
 
 use anyhow::{anyhow, Result};
 use std::io::Write;
 use std::process::{Command, Stdio};
+use wayclip_common::Selection;
+
+/// Offered alongside entries put on the clipboard by the paste queue, so the
+/// monitor can tell they are ours and leave them be: reading them would
+/// count as a paste.
+pub const QUEUE_MARKER_MIME: &str = "application/x-wayclip-queued";
+
+/// Which MIME type new selections are read as.
+#[derive(Debug, Clone, Default)]
+pub struct MimeSelection {
+    /// Patterns of the types to read, most preferred first.
+    pub priority: Vec<String>,
+    /// Patterns of the types that may be read when none of `priority` is
+    /// offered.
+    pub fallback_allow: Vec<String>,
+    /// Patterns of the types never read as a fallback.
+    pub fallback_deny: Vec<String>,
+}
+
+impl MimeSelection {
+    /// The type to read a selection offering `offered` as, if any.
+    pub fn select<'a>(&self, offered: &'a [String]) -> Option<&'a str> {
+        let matches_any = |patterns: &[String], mime_type: &str| {
+            patterns
+                .iter()
+                .any(|pattern| wayclip_common::mime_matches(pattern, mime_type))
+        };
+        wayclip_common::select_best_mime_type(offered, &self.priority, |mime_type| {
+            matches_any(&self.fallback_allow, mime_type)
+                && !matches_any(&self.fallback_deny, mime_type)
+        })
+    }
+}
 
 /// Event emitted when clipboard content changes.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct ClipboardEvent {
     /// The clipboard content.
     pub content: Vec<u8>,
+    /// `content_hash` of the content, worked out while it was read.
+    pub hash: String,
     /// MIME type of the content.
     pub mime_type: String,
-    /// Source application (if available).
-    #[allow(dead_code)]
+    /// Name of the seat the content was copied on, if known.
+    pub seat: Option<String>,
+    /// Which selection the content was copied to.
+    pub selection: Selection,
+    /// App ID of the window focused when the content was copied, if known.
     pub source_app: Option<String>,
+    /// Title of that window, if known.
+    pub window_title: Option<String>,
+    /// Name of the workspace that window was on, if known.
+    pub workspace: Option<String>,
+}
+
+/// Error for a compositor that doesn't offer wlr-data-control to us.
+///
+/// Compositors that implement the Wayland security-context protocol hide
+/// privileged globals like data-control from sandboxed clients, so inside
+/// Flatpak this usually means the daemon has to run on the host instead.
+fn unsupported_error() -> anyhow::Error {
+    match wayclip_common::flatpak_id() {
+        Some(id) => anyhow!(
+            "wlr-data-control is not available inside the {} sandbox. \
+             Run wayclip-daemon on the host; the client finds it through \
+             WAYCLIP_SOCKET",
+            id
+        ),
+        None => anyhow!("Compositor does not support wlr-data-control protocol"),
+    }
 }
 
 /// Copy data to the clipboard using wl-copy.
@@ -49,3 +115,64 @@ pub fn copy_to_clipboard(data: &[u8], mime_type: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// Copy an entry together with its alternative representations.
+///
+/// Plain text is also offered under the legacy text aliases so that X11
+/// and older Wayland clients can paste it. Simple clipboard copies go
+/// through `wl-copy`; everything else uses our own data-control source.
+pub fn copy_with_variants(
+    selection: Selection,
+    mime_type: &str,
+    data: Vec<u8>,
+    variants: Vec<(String, Vec<u8>)>,
+) -> Result<()> {
+    if selection == Selection::Clipboard && variants.is_empty() {
+        return copy_to_clipboard(&data, mime_type);
+    }
+
+    offer_selection(selection, offers(mime_type, data, variants), None)
+}
+
+/// Copy an entry for the paste queue, sending on `pasted` the first time
+/// another application pastes it.
+pub fn copy_for_queue(
+    mime_type: &str,
+    data: Vec<u8>,
+    variants: Vec<(String, Vec<u8>)>,
+    pasted: tokio::sync::mpsc::UnboundedSender<()>,
+) -> Result<()> {
+    let mut offers = offers(mime_type, data, variants);
+    offers.push((QUEUE_MARKER_MIME.to_string(), Vec::new()));
+    offer_selection(Selection::Clipboard, offers, Some(pasted))
+}
+
+/// Content and its variants under every MIME type they are offered as.
+fn offers(
+    mime_type: &str,
+    data: Vec<u8>,
+    variants: Vec<(String, Vec<u8>)>,
+) -> Vec<(String, Vec<u8>)> {
+    let mut offers: Vec<(String, Vec<u8>)> = Vec::new();
+    for (mime_type, data) in std::iter::once((mime_type.to_string(), data)).chain(variants) {
+        let aliases: Vec<String> = if mime_type.starts_with("text/plain") {
+            std::iter::once(mime_type.clone())
+                .chain(
+                    wayclip_common::TEXT_MIME_PRIORITY
+                        .iter()
+                        .filter(|m| !m.starts_with("text/html"))
+                        .map(|m| m.to_string()),
+                )
+                .collect()
+        } else {
+            vec![mime_type]
+        };
+
+        for alias in aliases {
+            if !offers.iter().any(|(m, _)| *m == alias) {
+                offers.push((alias, data.clone()));
+            }
+        }
+    }
+    offers
+}
@@ -1,12 +1,13 @@
 //! Clipboard monitoring using wlr-data-control protocol.
 
-use super::ClipboardEvent;
-use anyhow::{anyhow, Result};
-use std::io::Read;
+use super::toplevel::Toplevels;
+use super::{unsupported_error, CaptureSender, ClipboardEvent, MimeSelection};
+use anyhow::Result;
 use std::os::fd::AsFd;
-use tokio::sync::mpsc;
+use std::sync::OnceLock;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
 use wayland_client::protocol::wl_registry;
-use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::protocol::wl_seat::{self, WlSeat};
 use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, QueueHandle};
 use wayland_protocols_wlr::data_control::v1::client::{
     zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
@@ -14,15 +15,33 @@ use wayland_protocols_wlr::data_control::v1::client::{
     zwlr_data_control_offer_v1::{self, ZwlrDataControlOfferV1},
 };
 
-/// Monitor the clipboard for changes.
-pub fn monitor(tx: mpsc::Sender<ClipboardEvent>) -> Result<()> {
+/// Why monitoring stopped, once it has.
+static STOPPED: OnceLock<String> = OnceLock::new();
+
+/// Monitor the clipboard for changes, reading new selections as the type
+/// `mime_selection` picks. Returns only when monitoring fails, which
+/// `capture_error` then reports.
+pub fn monitor(tx: CaptureSender, mime_selection: MimeSelection) -> Result<()> {
+    let result = watch(tx, mime_selection);
+    if let Err(e) = &result {
+        let _ = STOPPED.set(format!("{:#}", e));
+    }
+    result
+}
+
+/// Why the clipboard is no longer being captured, if it isn't.
+pub fn capture_error() -> Option<String> {
+    STOPPED.get().cloned()
+}
+
+fn watch(tx: CaptureSender, mime_selection: MimeSelection) -> Result<()> {
     let conn = Connection::connect_to_env()?;
     let display = conn.display();
 
     let mut event_queue: EventQueue<ClipboardState> = conn.new_event_queue();
     let qh = event_queue.handle();
 
-    let mut state = ClipboardState::new(tx);
+    let mut state = ClipboardState::new(tx, mime_selection);
 
     display.get_registry(&qh, ());
 
@@ -30,9 +49,7 @@ pub fn monitor(tx: mpsc::Sender<ClipboardEvent>) -> Result<()> {
     event_queue.roundtrip(&mut state)?;
 
     if state.data_control_manager.is_none() {
-        return Err(anyhow!(
-            "Compositor does not support wlr-data-control protocol"
-        ));
+        return Err(unsupported_error());
     }
 
     // Create data device for the seat
@@ -49,22 +66,58 @@ pub fn monitor(tx: mpsc::Sender<ClipboardEvent>) -> Result<()> {
     }
 }
 
-struct ClipboardState {
-    tx: mpsc::Sender<ClipboardEvent>,
+/// Check that the compositor offers wlr-data-control, without watching the
+/// clipboard.
+pub fn check_data_control() -> Result<()> {
+    struct Probe;
+    impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for Probe {
+        fn event(
+            _state: &mut Self,
+            _registry: &wl_registry::WlRegistry,
+            _event: wl_registry::Event,
+            _data: &GlobalListContents,
+            _conn: &Connection,
+            _qh: &QueueHandle<Self>,
+        ) {
+        }
+    }
+
+    let conn = Connection::connect_to_env()?;
+    let (globals, _queue) = registry_queue_init::<Probe>(&conn)?;
+    let supported = globals.contents().with_list(|list| {
+        list.iter()
+            .any(|global| global.interface == "zwlr_data_control_manager_v1")
+    });
+    match supported {
+        true => Ok(()),
+        false => Err(unsupported_error()),
+    }
+}
+
+pub(super) struct ClipboardState {
+    tx: CaptureSender,
     data_control_manager: Option<ZwlrDataControlManagerV1>,
     seat: Option<WlSeat>,
+    seat_name: Option<String>,
     current_offer: Option<ZwlrDataControlOfferV1>,
     offered_mime_types: Vec<String>,
+    /// Which MIME type to read new selections as.
+    mime_selection: MimeSelection,
+    /// Windows and workspaces, for where each capture was copied from.
+    pub(super) toplevels: Toplevels,
 }
 
 impl ClipboardState {
-    fn new(tx: mpsc::Sender<ClipboardEvent>) -> Self {
+    fn new(tx: CaptureSender, mime_selection: MimeSelection) -> Self {
         Self {
             tx,
             data_control_manager: None,
             seat: None,
+            seat_name: None,
             current_offer: None,
             offered_mime_types: Vec::new(),
+            mime_selection,
+            toplevels: Toplevels::default(),
         }
     }
 
@@ -73,8 +126,20 @@ impl ClipboardState {
             return;
         };
 
+        // Queued entries are already in the history, and reading them would
+        // advance the queue as if they had been pasted
+        if self
+            .offered_mime_types
+            .iter()
+            .any(|m| m == super::QUEUE_MARKER_MIME)
+        {
+            offer.destroy();
+            return;
+        }
+
         // Select best MIME type
-        let mime_type = wayclip_common::select_best_mime_type(&self.offered_mime_types);
+        let mime_type = self.mime_selection.select(&self.offered_mime_types);
+        super::mime_stats::record_offer(&self.offered_mime_types, mime_type);
         let Some(mime_type) = mime_type else {
             tracing::debug!("No suitable MIME type offered");
             return;
@@ -100,16 +165,20 @@ impl ClipboardState {
 
         // Read data in a separate thread to not block the wayland event loop
         let mime_type = mime_type.to_string();
+        let seat = self.seat_name.clone();
+        // The window copied from still has focus when the selection changes
+        let context = self.toplevels.context();
         let tx = self.tx.clone();
 
         std::thread::spawn(move || {
-            let mut file = std::fs::File::from(read_fd);
-            let mut content = Vec::new();
-
-            if let Err(e) = file.read_to_end(&mut content) {
-                tracing::error!("Failed to read clipboard data: {}", e);
-                return;
-            }
+            let file = std::fs::File::from(read_fd);
+            let (content, hash) = match crate::hash::read_hashed(file) {
+                Ok(read) => read,
+                Err(e) => {
+                    tracing::error!("Failed to read clipboard data: {}", e);
+                    return;
+                }
+            };
 
             if content.is_empty() {
                 tracing::debug!("Clipboard content is empty, ignoring");
@@ -118,11 +187,16 @@ impl ClipboardState {
 
             let event = ClipboardEvent {
                 content,
+                hash,
                 mime_type,
-                source_app: None,
+                seat,
+                selection: wayclip_common::Selection::Clipboard,
+                source_app: context.app_id,
+                window_title: context.window_title,
+                workspace: context.workspace,
             };
 
-            let _ = tx.blocking_send(event);
+            tx.send(event);
         });
     }
 }
@@ -152,7 +226,9 @@ impl Dispatch<wl_registry::WlRegistry, ()> for ClipboardState {
                     let seat = registry.bind::<WlSeat, _, _>(name, version, qh, ());
                     state.seat = Some(seat);
                 }
-                _ => {}
+                _ => {
+                    Toplevels::bind(registry, name, &interface, version, qh);
+                }
             }
         }
     }
@@ -160,14 +236,17 @@ impl Dispatch<wl_registry::WlRegistry, ()> for ClipboardState {
 
 impl Dispatch<WlSeat, ()> for ClipboardState {
     fn event(
-        _state: &mut Self,
+        state: &mut Self,
         _proxy: &WlSeat,
-        _event: <WlSeat as wayland_client::Proxy>::Event,
+        event: wl_seat::Event,
         _data: &(),
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
     ) {
-        // We don't need to handle seat events
+        // Remember the seat name so entries can be attributed to it
+        if let wl_seat::Event::Name { name } = event {
+            state.seat_name = Some(name);
+        }
     }
 }
 
@@ -199,11 +278,9 @@ impl Dispatch<ZwlrDataControlDeviceV1, ()> for ClipboardState {
                 state.current_offer = Some(id);
                 state.offered_mime_types.clear();
             }
-            zwlr_data_control_device_v1::Event::Selection { id } => {
-                if id.is_some() {
-                    // Selection changed, receive the data
-                    state.receive_clipboard();
-                }
+            zwlr_data_control_device_v1::Event::Selection { id: Some(_) } => {
+                // Selection changed, receive the data
+                state.receive_clipboard();
             }
             zwlr_data_control_device_v1::Event::Finished => {
                 // Device is no longer valid
@@ -0,0 +1,248 @@
+//! Setting the clipboard or primary selection using wlr-data-control.
+//!
+//! Unlike `wl-copy`, a data-control source can offer the same content
+//! under several MIME types at once (e.g. HTML plus a plain-text variant).
+
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use tokio::sync::mpsc::UnboundedSender;
+use wayclip_common::Selection;
+use wayland_client::protocol::wl_registry;
+use wayland_client::protocol::wl_seat::WlSeat;
+use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+/// Offer content to a selection under one or more MIME types.
+///
+/// The selection is served from a background thread with its own Wayland
+/// connection until another client replaces it. `pasted`, if given, is sent
+/// to the first time the content is read.
+pub fn offer_selection(
+    selection: Selection,
+    offers: Vec<(String, Vec<u8>)>,
+    pasted: Option<UnboundedSender<()>>,
+) -> Result<()> {
+    if offers.is_empty() {
+        return Err(anyhow!("Nothing to offer"));
+    }
+
+    let (mut event_queue, mut state, manager, seat) = connect(selection)?;
+    let qh = event_queue.handle();
+    state.offers = offers;
+    state.pasted = pasted;
+
+    let source = manager.create_data_source(&qh, ());
+    for (mime_type, _) in &state.offers {
+        source.offer(mime_type.clone());
+    }
+
+    let device = manager.get_data_device(&seat, &qh, ());
+    match selection {
+        Selection::Clipboard => device.set_selection(Some(&source)),
+        Selection::Primary => device.set_primary_selection(Some(&source)),
+    }
+    event_queue.roundtrip(&mut state)?;
+
+    std::thread::spawn(move || {
+        while !state.cancelled {
+            if let Err(e) = event_queue.blocking_dispatch(&mut state) {
+                tracing::warn!("Clipboard source connection error: {}", e);
+                break;
+            }
+        }
+        source.destroy();
+        device.destroy();
+        tracing::debug!("Clipboard source finished");
+    });
+
+    Ok(())
+}
+
+/// Empty a selection, as password managers do after a timeout.
+pub fn clear_selection(selection: Selection) -> Result<()> {
+    let (mut event_queue, mut state, manager, seat) = connect(selection)?;
+    let qh = event_queue.handle();
+
+    let device = manager.get_data_device(&seat, &qh, ());
+    match selection {
+        Selection::Clipboard => device.set_selection(None),
+        Selection::Primary => device.set_primary_selection(None),
+    }
+    device.destroy();
+    event_queue.roundtrip(&mut state)?;
+
+    Ok(())
+}
+
+/// Connect to the compositor and bind the data-control manager and a seat.
+fn connect(
+    selection: Selection,
+) -> Result<(
+    EventQueue<SourceState>,
+    SourceState,
+    ZwlrDataControlManagerV1,
+    WlSeat,
+)> {
+    let conn = Connection::connect_to_env()?;
+    let mut event_queue = conn.new_event_queue();
+    let qh = event_queue.handle();
+
+    let mut state = SourceState {
+        offers: Vec::new(),
+        pasted: None,
+        manager: None,
+        seat: None,
+        cancelled: false,
+    };
+
+    conn.display().get_registry(&qh, ());
+    event_queue.roundtrip(&mut state)?;
+
+    let (Some(manager), Some(seat)) = (state.manager.clone(), state.seat.clone()) else {
+        return Err(super::unsupported_error());
+    };
+
+    // Primary selection support was added in version 2 of the protocol
+    if selection == Selection::Primary && manager.version() < 2 {
+        return Err(anyhow!(
+            "Compositor does not support setting the primary selection"
+        ));
+    }
+
+    Ok((event_queue, state, manager, seat))
+}
+
+struct SourceState {
+    offers: Vec<(String, Vec<u8>)>,
+    /// Told about the first paste, then dropped.
+    pasted: Option<UnboundedSender<()>>,
+    manager: Option<ZwlrDataControlManagerV1>,
+    seat: Option<WlSeat>,
+    cancelled: bool,
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for SourceState {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            match interface.as_str() {
+                "zwlr_data_control_manager_v1" => {
+                    state.manager = Some(registry.bind::<ZwlrDataControlManagerV1, _, _>(
+                        name,
+                        version.min(2),
+                        qh,
+                        (),
+                    ));
+                }
+                "wl_seat" if state.seat.is_none() => {
+                    state.seat = Some(registry.bind::<WlSeat, _, _>(name, version, qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for SourceState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                let Some((_, data)) = state.offers.iter().find(|(m, _)| *m == mime_type) else {
+                    return;
+                };
+                let mut file = std::fs::File::from(fd);
+                if let Err(e) = file.write_all(data) {
+                    tracing::debug!("Failed to send {} to paste target: {}", mime_type, e);
+                }
+                if mime_type != super::QUEUE_MARKER_MIME {
+                    if let Some(pasted) = state.pasted.take() {
+                        let _ = pasted.send(());
+                    }
+                }
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                state.cancelled = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for SourceState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrDataControlDeviceV1,
+        _event: zwlr_data_control_device_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Selection changes are tracked by the monitor, not here
+    }
+
+    event_created_child!(SourceState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrDataControlOfferV1, ()> for SourceState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrDataControlOfferV1,
+        _event: <ZwlrDataControlOfferV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Offers are only read by the monitor
+    }
+}
+
+impl Dispatch<WlSeat, ()> for SourceState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlSeat,
+        _event: <WlSeat as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events we care about
+    }
+}
+
+impl Dispatch<ZwlrDataControlManagerV1, ()> for SourceState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &ZwlrDataControlManagerV1,
+        _event: <ZwlrDataControlManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events we care about
+    }
+}
@@ -0,0 +1,302 @@
+//! Tracking the focused window and the active workspace, so captures can
+//! record where they were copied from.
+//!
+//! Windows come from wlr-foreign-toplevel-management and workspaces from
+//! ext-workspace; compositors offering neither just leave the context empty.
+
+use std::collections::HashMap;
+use wayland_client::backend::ObjectId;
+use wayland_client::protocol::wl_output::{self, WlOutput};
+use wayland_client::{event_created_child, Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1::{self, ExtWorkspaceGroupHandleV1},
+    ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+    ext_workspace_manager_v1::{self, ExtWorkspaceManagerV1},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use super::monitor::ClipboardState;
+
+/// The focused window and its workspace when something was copied.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CopyContext {
+    /// App ID of the focused window.
+    pub app_id: Option<String>,
+    /// Title of the focused window.
+    pub window_title: Option<String>,
+    /// Name of the active workspace the window is on.
+    pub workspace: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    title: Option<String>,
+    app_id: Option<String>,
+    outputs: Vec<ObjectId>,
+    activated: bool,
+}
+
+#[derive(Debug, Default)]
+struct WorkspaceGroup {
+    outputs: Vec<ObjectId>,
+    workspaces: Vec<ObjectId>,
+}
+
+#[derive(Debug, Default)]
+struct Workspace {
+    name: Option<String>,
+    active: bool,
+}
+
+/// Windows and workspaces as the compositor last described them.
+#[derive(Debug, Default)]
+pub struct Toplevels {
+    windows: HashMap<ObjectId, Window>,
+    groups: HashMap<ObjectId, WorkspaceGroup>,
+    workspaces: HashMap<ObjectId, Workspace>,
+}
+
+impl Toplevels {
+    /// Bind the global `interface` if it is one used here.
+    pub fn bind(
+        registry: &wayland_client::protocol::wl_registry::WlRegistry,
+        name: u32,
+        interface: &str,
+        version: u32,
+        qh: &QueueHandle<ClipboardState>,
+    ) {
+        match interface {
+            "zwlr_foreign_toplevel_manager_v1" => {
+                registry.bind::<ZwlrForeignToplevelManagerV1, _, _>(name, version.min(3), qh, ());
+            }
+            "ext_workspace_manager_v1" => {
+                registry.bind::<ExtWorkspaceManagerV1, _, _>(name, 1, qh, ());
+            }
+            // Windows and workspace groups say which outputs they are on
+            "wl_output" => {
+                registry.bind::<WlOutput, _, _>(name, 1, qh, ());
+            }
+            _ => {}
+        }
+    }
+
+    /// The focused window and its workspace right now.
+    pub fn context(&self) -> CopyContext {
+        let window = self.windows.values().find(|window| window.activated);
+        CopyContext {
+            app_id: window.and_then(|window| window.app_id.clone()),
+            window_title: window.and_then(|window| window.title.clone()),
+            workspace: self.active_workspace(window.map_or(&[], |window| &window.outputs)),
+        }
+    }
+
+    /// Name of the active workspace on one of `outputs`, or of the only
+    /// active workspace when the outputs don't tell.
+    fn active_workspace(&self, outputs: &[ObjectId]) -> Option<String> {
+        let on_outputs = self
+            .groups
+            .values()
+            .filter(|group| group.outputs.iter().any(|output| outputs.contains(output)))
+            .flat_map(|group| &group.workspaces)
+            .filter_map(|id| self.workspaces.get(id))
+            .find(|workspace| workspace.active);
+        if let Some(workspace) = on_outputs {
+            return workspace.name.clone();
+        }
+
+        let mut active = self
+            .workspaces
+            .values()
+            .filter(|workspace| workspace.active);
+        match (active.next(), active.next()) {
+            (Some(workspace), None) => workspace.name.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for ClipboardState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &WlOutput,
+        _event: wl_output::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Only the object's identity is used
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for ClipboardState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                state
+                    .toplevels
+                    .windows
+                    .insert(toplevel.id(), Window::default());
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                tracing::debug!("Foreign toplevel manager finished");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(ClipboardState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for ClipboardState {
+    fn event(
+        state: &mut Self,
+        proxy: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let windows = &mut state.toplevels.windows;
+        if let zwlr_foreign_toplevel_handle_v1::Event::Closed = event {
+            windows.remove(&proxy.id());
+            proxy.destroy();
+            return;
+        }
+        let Some(window) = windows.get_mut(&proxy.id()) else {
+            return;
+        };
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => window.title = Some(title),
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                window.app_id = Some(app_id)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                window.outputs.push(output.id())
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                window.outputs.retain(|id| *id != output.id())
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                // An array of native-endian state values
+                let activated = zwlr_foreign_toplevel_handle_v1::State::Activated as u32;
+                window.activated = state
+                    .chunks_exact(4)
+                    .any(|value| u32::from_ne_bytes(value.try_into().unwrap()) == activated);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtWorkspaceManagerV1, ()> for ClipboardState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ExtWorkspaceManagerV1,
+        event: ext_workspace_manager_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_workspace_manager_v1::Event::WorkspaceGroup { workspace_group } => {
+                let groups = &mut state.toplevels.groups;
+                groups.insert(workspace_group.id(), WorkspaceGroup::default());
+            }
+            ext_workspace_manager_v1::Event::Workspace { workspace } => {
+                let workspaces = &mut state.toplevels.workspaces;
+                workspaces.insert(workspace.id(), Workspace::default());
+            }
+            ext_workspace_manager_v1::Event::Finished => {
+                tracing::debug!("Workspace manager finished");
+            }
+            _ => {}
+        }
+    }
+
+    event_created_child!(ClipboardState, ExtWorkspaceManagerV1, [
+        ext_workspace_manager_v1::EVT_WORKSPACE_GROUP_OPCODE => (ExtWorkspaceGroupHandleV1, ()),
+        ext_workspace_manager_v1::EVT_WORKSPACE_OPCODE => (ExtWorkspaceHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtWorkspaceGroupHandleV1, ()> for ClipboardState {
+    fn event(
+        state: &mut Self,
+        proxy: &ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let groups = &mut state.toplevels.groups;
+        if let ext_workspace_group_handle_v1::Event::Removed = event {
+            groups.remove(&proxy.id());
+            proxy.destroy();
+            return;
+        }
+        let Some(group) = groups.get_mut(&proxy.id()) else {
+            return;
+        };
+
+        match event {
+            ext_workspace_group_handle_v1::Event::OutputEnter { output } => {
+                group.outputs.push(output.id())
+            }
+            ext_workspace_group_handle_v1::Event::OutputLeave { output } => {
+                group.outputs.retain(|id| *id != output.id())
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceEnter { workspace } => {
+                group.workspaces.push(workspace.id())
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceLeave { workspace } => {
+                group.workspaces.retain(|id| *id != workspace.id())
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtWorkspaceHandleV1, ()> for ClipboardState {
+    fn event(
+        state: &mut Self,
+        proxy: &ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let workspaces = &mut state.toplevels.workspaces;
+        if let ext_workspace_handle_v1::Event::Removed = event {
+            workspaces.remove(&proxy.id());
+            proxy.destroy();
+            return;
+        }
+        let Some(workspace) = workspaces.get_mut(&proxy.id()) else {
+            return;
+        };
+
+        match event {
+            ext_workspace_handle_v1::Event::Name { name } => workspace.name = Some(name),
+            ext_workspace_handle_v1::Event::State { state } => {
+                workspace.active = matches!(
+                    state,
+                    WEnum::Value(state) if state.contains(ext_workspace_handle_v1::State::Active)
+                );
+            }
+            _ => {}
+        }
+    }
+}
@@ -2,22 +2,288 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use wayclip_common::IgnoreRules;
+
+use crate::database::EntryLimits;
 
 /// Daemon configuration.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub daemon: DaemonConfig,
     #[serde(default)]
     pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub sources: SourcesConfig,
 }
 
-impl Default for Config {
+/// Policies for clients that aren't local processes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourcesConfig {
+    /// Clients of the HTTP gateway.
+    #[serde(default)]
+    pub http: SourcePolicy,
+    /// Devices paired for LAN sync, on top of each device's own permissions.
+    #[serde(default)]
+    pub sync: SourcePolicy,
+    /// Browser extensions, through `wayclip-native-host`. They act for the
+    /// pages they run in, so by default they may not read content.
+    #[serde(default = "default_browser_policy")]
+    pub browser: SourcePolicy,
+}
+
+impl Default for SourcesConfig {
     fn default() -> Self {
         Self {
-            daemon: DaemonConfig::default(),
-            clipboard: ClipboardConfig::default(),
+            http: SourcePolicy::default(),
+            sync: SourcePolicy::default(),
+            browser: default_browser_policy(),
+        }
+    }
+}
+
+/// What one kind of remote client may do.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourcePolicy {
+    #[serde(default)]
+    pub access: SourceAccess,
+    /// Largest entry it may add, in bytes (0 = `max_entry_size`).
+    #[serde(default)]
+    pub max_entry_size: u64,
+}
+
+/// Which requests a remote client may make.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceAccess {
+    /// Any request.
+    #[default]
+    Full,
+    /// Anything but reading entries' content.
+    NoContent,
+    /// Only adding entries.
+    PushOnly,
+}
+
+/// LAN sync: companion devices, such as a phone, paired with a QR code push
+/// entries to the history and, if allowed, pull them over TLS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// Listen for paired devices at all.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address to listen on.
+    #[serde(default = "default_sync_address")]
+    pub address: String,
+    /// Port to listen on.
+    #[serde(default = "default_sync_port")]
+    pub port: u16,
+    /// Host name or address devices are told to connect to when pairing.
+    /// When empty, this computer's address on the local network is used.
+    #[serde(default)]
+    pub advertise: String,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: default_sync_address(),
+            port: default_sync_port(),
+            advertise: String::new(),
+        }
+    }
+}
+
+/// Local HTTP gateway: the IPC protocol over HTTP on 127.0.0.1, for browser
+/// extensions and tools that can't use a Unix socket.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    /// Serve the gateway at all.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Port to listen on.
+    #[serde(default = "default_http_port")]
+    pub port: u16,
+    /// Token clients must send as `Authorization: Bearer <token>`. When
+    /// empty a new one is made at each start and written to the token file.
+    #[serde(default)]
+    pub token: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: default_http_port(),
+            token: String::new(),
+        }
+    }
+}
+
+// The configuration is logged at startup, so the token is left out
+impl std::fmt::Debug for HttpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpConfig")
+            .field("enabled", &self.enabled)
+            .field("port", &self.port)
+            .field("token", &redacted(&self.token))
+            .finish()
+    }
+}
+
+/// A `[[webhooks]]` entry: new entries matching every pattern given are
+/// POSTed to `url` as JSON. Sensitive captures and captures made while the
+/// history is locked are never sent.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// Where to POST, over http or https.
+    pub url: String,
+    /// Regex matched against text content.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Regex matched against the MIME type.
+    #[serde(default)]
+    pub mime: Option<String>,
+    /// Send the content too, not just metadata and a preview.
+    #[serde(default)]
+    pub include_content: bool,
+    /// Content larger than this many bytes is left out even with
+    /// `include_content`.
+    #[serde(default = "default_webhook_max_content_size")]
+    pub max_content_size: u64,
+    /// Extra request headers, such as an authorization token.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+// Webhook URLs often embed a secret in their path, and header values can
+// be credentials, so only the host and the header names are logged
+impl std::fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let headers: BTreeMap<&str, &str> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), redacted(value)))
+            .collect();
+        f.debug_struct("WebhookConfig")
+            .field("url", &url_host(&self.url))
+            .field("content", &self.content)
+            .field("mime", &self.mime)
+            .field("include_content", &self.include_content)
+            .field("max_content_size", &self.max_content_size)
+            .field("headers", &headers)
+            .finish()
+    }
+}
+
+/// The scheme and host of `url`, without credentials, path or query.
+fn url_host(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return "<redacted>".to_string();
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    format!("{}://{}/…", scheme, host)
+}
+
+/// What `Debug` shows in place of a secret: whether one is set.
+fn redacted(secret: &str) -> &'static str {
+    if secret.is_empty() {
+        ""
+    } else {
+        "<redacted>"
+    }
+}
+
+/// Where the history is kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+}
+
+/// Storage backend for the history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// An SQLite database file.
+    #[default]
+    Sqlite,
+    /// An SQLite `:memory:` database: nothing is written to disk and the
+    /// history is gone when the daemon exits.
+    Memory,
+}
+
+/// How searches match entries.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchConfig {
+    #[serde(default)]
+    pub tokenizer: SearchTokenizer,
+}
+
+/// How the full-text index splits text into the terms searches match.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchTokenizer {
+    /// Words, matched whole or by their beginning.
+    #[default]
+    Words,
+    /// Every three-character sequence, so searches also match inside words
+    /// and in Chinese, Japanese or Korean text written without spaces. The
+    /// index grows about three times larger.
+    Trigram,
+}
+
+/// When heavy maintenance runs: purging entries past `max_age_days`,
+/// archiving long unused ones, tidying cached thumbnails, vacuuming and
+/// backing up the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceConfig {
+    /// Run maintenance at all.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Local time of day to run maintenance, as HH:MM. A run missed while
+    /// the system was off or asleep happens as soon as it is back.
+    #[serde(default = "default_maintenance_at")]
+    pub at: String,
+    /// Put maintenance off while the system runs on battery.
+    #[serde(default)]
+    pub on_ac_power_only: bool,
+    /// Back up the database during maintenance, keeping this many copies
+    /// (0 = no backups).
+    #[serde(default)]
+    pub keep_backups: u32,
+    /// Move entries nobody has copied or used for this many days into the
+    /// archive database, where they can still be searched (0 = never).
+    #[serde(default)]
+    pub archive_after_days: u32,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            at: default_maintenance_at(),
+            on_ac_power_only: false,
+            keep_backups: 0,
+            archive_after_days: 0,
         }
     }
 }
@@ -28,24 +294,97 @@ pub struct DaemonConfig {
     /// Maximum number of entries to keep.
     #[serde(default = "default_max_entries")]
     pub max_entries: u32,
+    /// Maximum number of text entries to keep, within `max_entries`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_text_entries: Option<u32>,
+    /// Maximum number of image entries to keep, within `max_entries`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_image_entries: Option<u32>,
     /// Maximum size of a single entry in bytes.
     #[serde(default = "default_max_entry_size")]
     pub max_entry_size: u64,
     /// Minimum size of an entry in bytes.
     #[serde(default = "default_min_entry_size")]
     pub min_entry_size: u64,
+    /// Count pinned entries and collection members towards `max_entries`
+    /// and the type caps. They are never deleted either way.
+    #[serde(default)]
+    pub count_pinned: bool,
     /// Maximum age of entries in days (0 = no limit).
     #[serde(default)]
     pub max_age_days: u32,
+    /// Show a desktop notification for each new entry, with buttons to pin
+    /// or delete it or open the history.
+    #[serde(default)]
+    pub notify_on_capture: bool,
+    /// Captures at least this many bytes raise a large capture event (0 = never).
+    #[serde(default = "default_large_entry_threshold")]
+    pub large_entry_threshold: u64,
+    /// Bytes of recently read content kept in memory (0 = no cache).
+    #[serde(default = "default_content_cache_size")]
+    pub content_cache_size: u64,
+    /// Record which client read which entry's content, and when.
+    #[serde(default)]
+    pub audit_log: bool,
+    /// Refuse to list entries, not just their content, while locked.
+    #[serde(default)]
+    pub hide_history_when_locked: bool,
+    /// Lock the history after this many minutes without client requests
+    /// (0 = never).
+    #[serde(default)]
+    pub lock_after_idle_minutes: u32,
+    /// Lock the history when the session locks or the system goes to sleep.
+    #[serde(default = "default_true")]
+    pub lock_on_session_lock: bool,
+    /// What happens to the history when the daemon is stopped at logout or
+    /// shutdown.
+    #[serde(default)]
+    pub on_session_end: SessionEndPolicy,
+}
+
+/// What to clear when the session ends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEndPolicy {
+    /// Keep the history for the next session.
+    #[default]
+    Keep,
+    /// Delete everything except pinned entries and collection members.
+    ClearUnpinned,
+    /// Delete every entry, for a history that only lasts one session.
+    ClearAll,
 }
 
 impl Default for DaemonConfig {
     fn default() -> Self {
         Self {
             max_entries: default_max_entries(),
+            max_text_entries: None,
+            max_image_entries: None,
             max_entry_size: default_max_entry_size(),
             min_entry_size: default_min_entry_size(),
+            count_pinned: false,
             max_age_days: 0,
+            notify_on_capture: false,
+            large_entry_threshold: default_large_entry_threshold(),
+            content_cache_size: default_content_cache_size(),
+            audit_log: false,
+            hide_history_when_locked: false,
+            lock_after_idle_minutes: 0,
+            lock_on_session_lock: true,
+            on_session_end: SessionEndPolicy::Keep,
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// The entry caps `Storage::cleanup` enforces.
+    pub fn entry_limits(&self) -> EntryLimits {
+        EntryLimits {
+            total: self.max_entries,
+            text: self.max_text_entries,
+            image: self.max_image_entries,
+            count_kept: self.count_pinned,
         }
     }
 }
@@ -59,6 +398,90 @@ pub struct ClipboardConfig {
     /// Application patterns to ignore (regex).
     #[serde(default)]
     pub ignore_app_patterns: Vec<String>,
+    /// Text content patterns that are never stored (regex).
+    #[serde(default)]
+    pub ignore_content_patterns: Vec<String>,
+    /// Rules applied to captures, first match wins.
+    #[serde(default)]
+    pub rules: Vec<CaptureRule>,
+    /// Seconds after which the clipboard is cleared of a sensitive or
+    /// ephemeral capture (0 = never).
+    #[serde(default = "default_clear_sensitive_after")]
+    pub clear_sensitive_after: u64,
+    /// Keep ephemeral captures in the history after the clipboard is cleared.
+    #[serde(default)]
+    pub keep_ephemeral: bool,
+    /// What to do with an image that looks like one already in the history.
+    #[serde(default)]
+    pub similar_images: SimilarImages,
+    /// Store text with CRLF line endings made LF and trailing whitespace
+    /// removed, so the same text copied from different applications is
+    /// recognized as a duplicate.
+    #[serde(default)]
+    pub normalize_text: bool,
+    /// MIME types to read a new selection as, most preferred first. When
+    /// none is offered, the first type offered that the fallback patterns
+    /// allow is read.
+    #[serde(default = "wayclip_common::default_mime_priority")]
+    pub mime_priority: Vec<String>,
+    /// MIME type patterns a selection offering none of `mime_priority` may
+    /// be read as.
+    #[serde(default = "default_fallback_allow")]
+    pub fallback_allow: Vec<String>,
+    /// MIME type patterns never read as a fallback, such as applications'
+    /// internal formats.
+    #[serde(default = "default_fallback_deny")]
+    pub fallback_deny: Vec<String>,
+}
+
+/// How captured images that look like an earlier one are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimilarImages {
+    /// Store them like any other image.
+    #[default]
+    Keep,
+    /// Store them, marked as similar to the earlier image.
+    Flag,
+    /// Don't store them; the earlier image is marked as just used instead,
+    /// as with an exact duplicate.
+    Coalesce,
+}
+
+/// A `[[clipboard.rules]]` entry. Every condition given must match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRule {
+    /// Regex matched against text content.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// Regex matched against the MIME type.
+    #[serde(default)]
+    pub mime: Option<String>,
+    /// What to do with matching captures.
+    pub action: RuleAction,
+}
+
+/// What a capture rule does with a match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Don't record the capture at all.
+    Ignore,
+    /// Record time, size, source and hash, but not the content.
+    StoreHashOnly,
+    /// Store the capture, then clear the clipboard after a timeout.
+    Ephemeral,
+}
+
+impl ClipboardConfig {
+    /// The `ignore_*_patterns` lists, as shown to clients.
+    pub fn ignore_rules(&self) -> IgnoreRules {
+        IgnoreRules {
+            mime: self.ignore_mime_patterns.clone(),
+            apps: self.ignore_app_patterns.clone(),
+            content: self.ignore_content_patterns.clone(),
+        }
+    }
 }
 
 impl Default for ClipboardConfig {
@@ -69,6 +492,48 @@ impl Default for ClipboardConfig {
                 "x-kde-passwordManagerHint".to_string(),
             ],
             ignore_app_patterns: vec![],
+            ignore_content_patterns: vec![],
+            rules: vec![],
+            clear_sensitive_after: default_clear_sensitive_after(),
+            keep_ephemeral: false,
+            similar_images: SimilarImages::Keep,
+            normalize_text: false,
+            mime_priority: wayclip_common::default_mime_priority(),
+            fallback_allow: default_fallback_allow(),
+            fallback_deny: default_fallback_deny(),
+        }
+    }
+}
+
+/// Logging configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    /// Log filter directive; `RUST_LOG` takes precedence when set.
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    /// Whether to write logs to a file in addition to stderr. Always off
+    /// with the memory storage backend.
+    #[serde(default = "default_true")]
+    pub to_file: bool,
+    /// Log file path (defaults to `$XDG_STATE_HOME/wayclip/daemon.log`).
+    #[serde(default = "wayclip_common::log_path")]
+    pub file: PathBuf,
+    /// Size in bytes at which the log file is rotated.
+    #[serde(default = "default_log_max_size")]
+    pub max_size: u64,
+    /// Number of rotated log files to keep.
+    #[serde(default = "default_log_keep")]
+    pub keep: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_log_level(),
+            to_file: true,
+            file: wayclip_common::log_path(),
+            max_size: default_log_max_size(),
+            keep: default_log_keep(),
         }
     }
 }
@@ -81,10 +546,77 @@ fn default_max_entry_size() -> u64 {
     10 * 1024 * 1024 // 10 MB
 }
 
+fn default_clear_sensitive_after() -> u64 {
+    30
+}
+
+fn default_fallback_allow() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+fn default_fallback_deny() -> Vec<String> {
+    vec![
+        "application/x-libreoffice-*".to_string(),
+        "application/x-openoffice-*".to_string(),
+        "chromium/x-*".to_string(),
+    ]
+}
+
+fn default_large_entry_threshold() -> u64 {
+    4 * 1024 * 1024 // 4 MB
+}
+
+fn default_content_cache_size() -> u64 {
+    32 * 1024 * 1024 // 32 MB
+}
+
+fn default_webhook_max_content_size() -> u64 {
+    64 * 1024 // 64 KB
+}
+
+fn default_http_port() -> u16 {
+    7786
+}
+
+fn default_browser_policy() -> SourcePolicy {
+    SourcePolicy {
+        access: SourceAccess::NoContent,
+        max_entry_size: 0,
+    }
+}
+
+fn default_sync_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_sync_port() -> u16 {
+    7787
+}
+
 fn default_min_entry_size() -> u64 {
     1
 }
 
+fn default_log_level() -> String {
+    "wayclip=info".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_maintenance_at() -> String {
+    "04:00".to_string()
+}
+
+fn default_log_max_size() -> u64 {
+    5 * 1024 * 1024 // 5 MB
+}
+
+fn default_log_keep() -> u32 {
+    3
+}
+
 impl Config {
     /// Load configuration from file, or return defaults if file doesn't exist.
     pub fn load() -> Result<Self> {
@@ -100,19 +632,75 @@ impl Config {
     /// Load configuration from a specific path.
     pub fn load_from(path: &PathBuf) -> Result<Self> {
         let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content).map_err(|e| anyhow::anyhow!("Failed to parse config: {}", e))?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse config: {}", e))?;
         Ok(config)
     }
 }
 
-// Add toml dependency for parsing
-// Note: We're using a simple inline parser for now to avoid adding toml crate
-mod toml {
-    use super::Config;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_partial_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [daemon]
+            max_entries = 50
+
+            [logging]
+            level = "wayclip=debug"
+            to_file = false
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.daemon.max_entries, 50);
+        assert_eq!(config.daemon.max_entry_size, default_max_entry_size());
+        assert_eq!(config.logging.level, "wayclip=debug");
+        assert!(!config.logging.to_file);
+        assert_eq!(config.logging.keep, default_log_keep());
+    }
+
+    #[test]
+    fn test_mime_priority() {
+        let offered = ["text/plain".to_string(), "image/png".to_string()];
+        let config = Config::default();
+        let select = |priority: &[String]| {
+            wayclip_common::select_best_mime_type(&offered, priority, |_| true)
+        };
+        assert_eq!(select(&config.clipboard.mime_priority), Some("image/png"));
+
+        let config: Config = toml::from_str(
+            r#"
+            [clipboard]
+            mime_priority = ["text/plain", "image/png"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(select(&config.clipboard.mime_priority), Some("text/plain"));
+        assert_eq!(select(&["image/jpeg".to_string()]), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_debug_leaves_out_secrets() {
+        let config: Config = toml::from_str(
+            r#"
+            [http]
+            token = "s3cret-token"
+
+            [[webhooks]]
+            url = "https://user:pw@hooks.example.com/services/s3cret-path?key=s3cret-key"
+            headers = { Authorization = "Bearer s3cret-header" }
+            "#,
+        )
+        .unwrap();
 
-    pub fn from_str(_s: &str) -> Result<Config, String> {
-        // For now, just return defaults
-        // TODO: Implement proper TOML parsing
-        Ok(Config::default())
+        let logged = format!("{:?}", config);
+        assert!(!logged.contains("s3cret"), "{}", logged);
+        assert!(!logged.contains("pw@"), "{}", logged);
+        assert!(logged.contains("https://hooks.example.com/"), "{}", logged);
+        assert!(logged.contains("Authorization"), "{}", logged);
     }
 }
@@ -0,0 +1,404 @@
+//! Moving entries nobody has used for a long time into a separate archive
+//! database, where they stay searchable instead of being deleted.
+//!
+//! The archive is a database of its own with the same schema, so entries
+//! keep their IDs, UUIDs and content there.
+
+use anyhow::{bail, Result};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use wayclip_common::{HistoryEntry, PruneCriteria};
+
+use super::operations::DISPOSABLE_ENTRY;
+use super::{Database, HistoryFilter, Storage};
+
+impl Database {
+    /// Keep archived entries in `archive`, which must already be migrated.
+    pub fn set_archive(&mut self, archive: Database) {
+        self.archive = Some(Box::new(archive));
+    }
+
+    /// Move disposable entries not copied or used for `unused_for_secs`
+    /// into the archive, returning how many were moved. Without an archive
+    /// nothing is moved.
+    pub fn archive_entries(&self, unused_for_secs: u64) -> Result<u64> {
+        let Some(archive) = &self.archive else {
+            return Ok(0);
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let cutoff = now.saturating_sub(unused_for_secs) as i64;
+
+        let conn = self.conn.lock().unwrap();
+        let ids: Vec<i64> = conn
+            .prepare(&format!(
+                "SELECT id FROM entries WHERE {DISPOSABLE_ENTRY} AND last_used_at < ?1 ORDER BY id"
+            ))?
+            .query_map(params![cutoff], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        // Copied first and deleted after, so a crash in between leaves an
+        // entry in both rather than in neither
+        {
+            let archive_conn = archive.conn.lock().unwrap();
+            let tx = archive_conn.unchecked_transaction()?;
+            for &id in &ids {
+                copy_entry(&conn, &tx, id)?;
+            }
+            tx.commit()?;
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        for &id in &ids {
+            tx.execute("DELETE FROM entries WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+
+        self.cache.lock().unwrap().clear();
+        self.prune_thumbnails(&conn);
+        Ok(ids.len() as u64)
+    }
+
+    /// Move archived entry `id` back into the history as if it had just been
+    /// copied, returning its ID there, or `None` if it isn't archived. If its
+    /// content was copied again since, that entry is moved up instead.
+    pub fn restore_archived(&self, id: i64) -> Result<Option<i64>> {
+        let Some(archive) = &self.archive else {
+            return Ok(None);
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let conn = self.conn.lock().unwrap();
+        let archive_conn = archive.conn.lock().unwrap();
+        let hash: Option<String> = archive_conn
+            .query_row(
+                "SELECT content_hash FROM entries WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(hash) = hash else {
+            return Ok(None);
+        };
+
+        let live: Option<(i64, String)> = conn
+            .query_row(
+                "SELECT id, content_hash FROM entries WHERE content_hash = ?1 OR id = ?2
+                 ORDER BY content_hash = ?1 DESC",
+                params![hash, id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let tx = conn.unchecked_transaction()?;
+        let restored = match live {
+            Some((live_id, live_hash)) if live_hash == hash => live_id,
+            Some(_) => bail!("Entry {} is both archived and in the history", id),
+            None => {
+                copy_entry(&archive_conn, &tx, id)?;
+                id
+            }
+        };
+        tx.execute(
+            "UPDATE entries SET created_at = ?1, last_used_at = ?1 WHERE id = ?2",
+            params![now, restored],
+        )?;
+        tx.commit()?;
+
+        archive_conn.execute("DELETE FROM entries WHERE id = ?1", params![id])?;
+        Ok(Some(restored))
+    }
+
+    /// Like `get_history`, with archived entries matching `filter` listed
+    /// after all of the live ones and marked `archived`.
+    pub fn get_history_with_archive(
+        &self,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        filter: &HistoryFilter,
+    ) -> Result<(Vec<HistoryEntry>, u64)> {
+        let (mut entries, live_total) = self.get_history(limit, offset, filter)?;
+        let Some(archive) = &self.archive else {
+            return Ok((entries, live_total));
+        };
+
+        // Archived entries are never pinned or in a collection
+        if filter.collection.is_some() || filter.snippets {
+            return Ok((entries, live_total));
+        }
+
+        let limit = limit.unwrap_or(100);
+        let offset = offset.unwrap_or(0);
+        let archive_offset = u64::from(offset).saturating_sub(live_total) as u32;
+        let archive_limit = limit.saturating_sub(entries.len() as u32);
+        let (archived, archived_total) = archive.get_history(
+            Some(archive_limit),
+            Some(archive_offset),
+            &HistoryFilter {
+                similar_to: None,
+                ..*filter
+            },
+        )?;
+        entries.extend(archived.into_iter().map(|entry| HistoryEntry {
+            archived: true,
+            ..entry
+        }));
+        Ok((entries, live_total + archived_total))
+    }
+
+    /// An archived entry's metadata, if it is in the archive.
+    pub(super) fn archived_entry(&self, id: i64) -> Result<Option<HistoryEntry>> {
+        let Some(archive) = &self.archive else {
+            return Ok(None);
+        };
+        Ok(archive.get_entry(id)?.map(|entry| HistoryEntry {
+            archived: true,
+            ..entry
+        }))
+    }
+
+    /// An archived entry's content, if it is in the archive.
+    pub(super) fn archived_content(&self, id: i64) -> Result<Option<(String, Vec<u8>)>> {
+        match &self.archive {
+            Some(archive) => archive.get_content(id),
+            None => Ok(None),
+        }
+    }
+
+    /// An archived entry's variants, if it is in the archive.
+    pub(super) fn archived_variants(&self, id: i64) -> Result<Vec<(String, Vec<u8>)>> {
+        match &self.archive {
+            Some(archive) => archive.get_variants(id),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Delete archived entry `id`, returning `false` if it isn't archived.
+    pub(super) fn delete_archived(&self, id: i64) -> Result<bool> {
+        match &self.archive {
+            Some(archive) => archive.delete_entry(id),
+            None => Ok(false),
+        }
+    }
+
+    /// Delete every archived entry. Archived entries are never pinned or in
+    /// a collection, so this is what clearing the history leaves of them.
+    pub(super) fn clear_archive(&self) -> Result<()> {
+        match &self.archive {
+            Some(archive) => archive.clear_all(),
+            None => Ok(()),
+        }
+    }
+
+    /// Delete the archived entries matching `criteria` and return how many
+    /// there were. A dry run only counts them.
+    pub(super) fn prune_archive(&self, criteria: &PruneCriteria, dry_run: bool) -> Result<u64> {
+        match &self.archive {
+            Some(archive) => archive.prune(criteria, dry_run),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Copy entry `id` with its content and variants from `from` to `to`, both
+/// databases with the same schema. An entry already in `to` with the same
+/// content is replaced.
+pub(super) fn copy_entry(from: &Connection, to: &Connection, id: i64) -> Result<()> {
+    let columns: Vec<String> = from
+        .prepare("SELECT name FROM pragma_table_info('entries')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let column_list = columns.join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+
+    let mut values: Vec<Value> = from.query_row(
+        &format!("SELECT {column_list} FROM entries WHERE id = ?1"),
+        params![id],
+        |row| {
+            (0..columns.len())
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<rusqlite::Result<_>>()
+        },
+    )?;
+    // The entry it looked like may not be in the other database
+    if let Some(i) = columns.iter().position(|column| column == "similar_to") {
+        values[i] = Value::Null;
+    }
+    let hash = columns
+        .iter()
+        .position(|column| column == "content_hash")
+        .map(|i| values[i].clone());
+
+    to.execute(
+        "DELETE FROM entries WHERE id = ?1 OR content_hash = ?2",
+        params_from_iter([Value::Integer(id), hash.unwrap_or(Value::Null)]),
+    )?;
+    to.execute(
+        &format!("INSERT INTO entries ({column_list}) VALUES ({placeholders})"),
+        params_from_iter(&values),
+    )?;
+
+    let content: Option<Vec<u8>> = from
+        .query_row(
+            "SELECT data FROM content WHERE entry_id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(content) = content {
+        to.execute(
+            "INSERT INTO content (entry_id, data) VALUES (?1, ?2)",
+            params![id, content],
+        )?;
+    }
+
+    let variants: Vec<(String, Vec<u8>)> = from
+        .prepare("SELECT mime_type, data FROM content_variants WHERE entry_id = ?1")?
+        .query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (mime_type, data) in variants {
+        to.execute(
+            "INSERT INTO content_variants (entry_id, mime_type, data) VALUES (?1, ?2, ?3)",
+            params![id, mime_type, data],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{text_entry, NewEntry};
+    use wayclip_common::ContentType;
+
+    fn insert(db: &Database, text: &str) -> i64 {
+        db.insert_entry(&NewEntry {
+            variants: &[("text/html".to_string(), b"<b>html</b>".to_vec())],
+            ..text_entry(text)
+        })
+        .unwrap()
+    }
+
+    fn with_archive() -> Database {
+        let mut db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        let archive = Database::open_in_memory().unwrap();
+        archive.migrate().unwrap();
+        db.set_archive(archive);
+        db
+    }
+
+    #[test]
+    fn test_archive_entries() {
+        let db = with_archive();
+        let old = insert(&db, "old note");
+        let pinned = insert(&db, "old pinned note");
+        db.set_pinned(pinned, true).unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE entries SET last_used_at = 0", [])
+            .unwrap();
+        let recent = insert(&db, "recent note");
+
+        assert_eq!(db.archive_entries(24 * 60 * 60).unwrap(), 1);
+        assert_eq!(db.count_entries().unwrap(), 2);
+
+        // Archived entries keep their ID and content
+        let entry = db.archived_entry(old).unwrap().unwrap();
+        assert_eq!(entry.preview, "old note");
+        assert!(entry.archived);
+        assert_eq!(
+            db.archived_content(old).unwrap().unwrap().1,
+            b"old note".to_vec()
+        );
+        assert_eq!(db.archived_variants(old).unwrap().len(), 1);
+
+        let filter = HistoryFilter {
+            search: Some("note"),
+            pinned_first: true,
+            ..Default::default()
+        };
+        let (entries, total) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!((entries.len(), total), (2, 2));
+        let (entries, total) = db.get_history_with_archive(None, None, &filter).unwrap();
+        assert_eq!(total, 3);
+        let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![pinned, recent, old]);
+        assert!(!entries[0].archived && entries[2].archived);
+
+        // Paging carries on into the archive
+        let (entries, _) = db
+            .get_history_with_archive(Some(2), Some(2), &filter)
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, old);
+    }
+
+    #[test]
+    fn test_restore_archived() {
+        let db = with_archive();
+        let old = insert(&db, "old note");
+        let copied_again = insert(&db, "copied again");
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE entries SET created_at = 0, last_used_at = 0", [])
+            .unwrap();
+        assert_eq!(db.archive_entries(60).unwrap(), 2);
+        let recaptured = insert(&db, "copied again");
+
+        assert_eq!(db.restore_archived(old).unwrap(), Some(old));
+        let entry = db.get_entry(old).unwrap().unwrap();
+        assert!(!entry.archived && entry.created_at > 0);
+        assert_eq!(db.get_variants(old).unwrap().len(), 1);
+        assert!(db.archived_entry(old).unwrap().is_none());
+
+        // Content copied again since is the entry that comes back
+        assert_eq!(db.restore_archived(copied_again).unwrap(), Some(recaptured));
+        assert_eq!(db.count_entries().unwrap(), 2);
+
+        assert_eq!(db.restore_archived(old).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_and_clear_archived() {
+        let db = with_archive();
+        let first = insert(&db, "first note");
+        let second = insert(&db, "second note");
+        insert(&db, "third note");
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE entries SET last_used_at = 0", [])
+            .unwrap();
+        assert_eq!(db.archive_entries(60).unwrap(), 3);
+
+        assert!(db.delete_entry(first).unwrap());
+        assert!(db.archived_entry(first).unwrap().is_none());
+        assert!(!db.delete_entry(first).unwrap());
+
+        // Pruning counts archived entries along with live ones
+        let criteria = PruneCriteria {
+            older_than_secs: None,
+            larger_than: None,
+            content_type: Some(ContentType::Text),
+            source_app: None,
+        };
+        assert_eq!(db.prune(&criteria, true).unwrap(), 2);
+
+        db.clear_unpinned().unwrap();
+        assert!(db.archived_entry(second).unwrap().is_none());
+        let (_, total) = db
+            .get_history_with_archive(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(total, 0);
+    }
+}
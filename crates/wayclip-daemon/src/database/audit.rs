@@ -0,0 +1,102 @@
+//! Log of which IPC peers read which entries' content.
+
+use anyhow::Result;
+use rusqlite::params;
+use wayclip_common::AuditRecord;
+
+use super::Database;
+use crate::ipc::Peer;
+
+/// Records kept before the oldest are dropped.
+const MAX_AUDIT_RECORDS: i64 = 10_000;
+
+impl Database {
+    /// Record that `peer` read the content of `entry_ids` through `request`.
+    pub fn record_access(&self, peer: &Peer, request: &str, entry_ids: &[i64]) -> Result<()> {
+        if entry_ids.is_empty() {
+            return Ok(());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO audit_log (timestamp, pid, uid, process, request, entry_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for id in entry_ids {
+                stmt.execute(params![now, peer.pid, peer.uid, peer.process, request, id])?;
+            }
+        }
+        tx.execute(
+            "DELETE FROM audit_log WHERE id <= (SELECT MAX(id) FROM audit_log) - ?1",
+            params![MAX_AUDIT_RECORDS],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Get the most recent audit records, newest first.
+    pub fn audit_log(&self, limit: Option<u32>) -> Result<Vec<AuditRecord>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, pid, uid, process, request, entry_id
+             FROM audit_log
+             ORDER BY id DESC
+             LIMIT ?1",
+        )?;
+        let records = stmt
+            .query_map(params![limit.map_or(-1, i64::from)], |row| {
+                Ok(AuditRecord {
+                    timestamp: row.get(0)?,
+                    pid: row.get(1)?,
+                    uid: row.get(2)?,
+                    process: row.get(3)?,
+                    request: row.get(4)?,
+                    entry_id: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log_is_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let peer = Peer {
+            pid: Some(1234),
+            uid: 1000,
+            process: Some("wayclip".to_string()),
+        };
+        db.record_access(&peer, "GetContents", &[1, 2]).unwrap();
+        db.record_access(&peer, "SetClipboard", &[3]).unwrap();
+
+        let records = db.audit_log(Some(2)).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.request.as_str(), r.entry_id))
+                .collect::<Vec<_>>(),
+            vec![("SetClipboard", 3), ("GetContents", 2)]
+        );
+        assert_eq!(records[0].pid, Some(1234));
+        assert_eq!(records[0].process.as_deref(), Some("wayclip"));
+        assert_eq!(db.audit_log(None).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,154 @@
+//! Size-bounded cache of recently read entry content.
+
+use std::collections::HashMap;
+use wayclip_common::CacheStats;
+
+/// Least-recently-used cache of entry content, keyed by entry ID.
+///
+/// Stored content never changes, so entries only need to be dropped when
+/// their ID is deleted or reused.
+pub(super) struct ContentCache {
+    capacity: u64,
+    bytes: u64,
+    /// Advanced on every access to order entries by recency.
+    clock: u64,
+    entries: HashMap<i64, Cached>,
+    hits: u64,
+    misses: u64,
+}
+
+struct Cached {
+    mime_type: String,
+    data: Vec<u8>,
+    last_used: u64,
+}
+
+impl ContentCache {
+    /// Create a cache holding up to `capacity` bytes of content.
+    pub(super) fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Look up an entry's MIME type and content, counting the hit or miss.
+    pub(super) fn get(&mut self, id: i64) -> Option<(String, Vec<u8>)> {
+        self.clock += 1;
+        match self.entries.get_mut(&id) {
+            Some(cached) => {
+                cached.last_used = self.clock;
+                self.hits += 1;
+                Some((cached.mime_type.clone(), cached.data.clone()))
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Remember an entry's content, evicting the least recently used
+    /// entries to make room. Content larger than the whole cache is skipped.
+    pub(super) fn insert(&mut self, id: i64, mime_type: &str, data: &[u8]) {
+        let size = data.len() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        self.remove(id);
+        while self.bytes + size > self.capacity {
+            self.evict_oldest();
+        }
+
+        self.clock += 1;
+        self.bytes += size;
+        self.entries.insert(
+            id,
+            Cached {
+                mime_type: mime_type.to_string(),
+                data: data.to_vec(),
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Forget an entry.
+    pub(super) fn remove(&mut self, id: i64) {
+        if let Some(cached) = self.entries.remove(&id) {
+            self.bytes -= cached.data.len() as u64;
+        }
+    }
+
+    /// Forget every entry, keeping the hit and miss counts.
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+
+    /// Change the size limit, evicting entries if it shrank.
+    pub(super) fn set_capacity(&mut self, capacity: u64) {
+        self.capacity = capacity;
+        while self.bytes > self.capacity {
+            self.evict_oldest();
+        }
+    }
+
+    pub(super) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len() as u64,
+            bytes: self.bytes,
+            capacity_bytes: self.capacity,
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, cached)| cached.last_used)
+            .map(|(id, _)| *id);
+        if let Some(id) = oldest {
+            self.remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = ContentCache::new(10);
+        cache.insert(1, "text/plain", b"aaaa");
+        cache.insert(2, "text/plain", b"bbbb");
+
+        // Touch 1 so that 2 is evicted to make room for 3
+        assert!(cache.get(1).is_some());
+        cache.insert(3, "text/plain", b"cccc");
+        assert!(cache.get(2).is_none());
+        assert_eq!(
+            cache.get(3),
+            Some(("text/plain".to_string(), b"cccc".to_vec()))
+        );
+
+        // Too big to cache at all
+        cache.insert(4, "text/plain", b"0123456789a");
+        assert!(cache.get(4).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!((stats.entries, stats.bytes), (2, 8));
+
+        cache.set_capacity(4);
+        assert_eq!(cache.stats().entries, 1);
+        assert!(cache.get(3).is_some());
+    }
+}
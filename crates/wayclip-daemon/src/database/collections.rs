@@ -0,0 +1,211 @@
+//! Named, ordered collections of entries.
+
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use wayclip_common::Collection;
+
+use super::Database;
+
+impl Database {
+    /// List all collections in display order.
+    pub fn list_collections(&self) -> Result<Vec<Collection>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.name, COUNT(ce.entry_id)
+             FROM collections c
+             LEFT JOIN collection_entries ce ON ce.collection_id = c.id
+             GROUP BY c.id
+             ORDER BY c.position",
+        )?;
+        let collections = stmt
+            .query_map([], |row| {
+                Ok(Collection {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    entry_count: row.get::<_, i64>(2)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(collections)
+    }
+
+    /// Find a collection by name.
+    pub fn find_collection(&self, name: &str) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let id = conn
+            .query_row(
+                "SELECT id FROM collections WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(id)
+    }
+
+    /// Create an empty collection at the end of the list.
+    pub fn create_collection(&self, name: &str) -> Result<Collection> {
+        let conn = self.conn.lock().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        conn.execute(
+            "INSERT INTO collections (name, position, created_at)
+             VALUES (?1, (SELECT COALESCE(MAX(position), 0) + 1 FROM collections), ?2)",
+            params![name, now],
+        )?;
+
+        Ok(Collection {
+            id: conn.last_insert_rowid(),
+            name: name.to_string(),
+            entry_count: 0,
+        })
+    }
+
+    /// Rename a collection.
+    pub fn rename_collection(&self, id: i64, name: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "UPDATE collections SET name = ?1 WHERE id = ?2",
+            params![name, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Delete a collection. Its entries stay in the history.
+    pub fn delete_collection(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute("DELETE FROM collections WHERE id = ?1", params![id])?;
+        Ok(rows > 0)
+    }
+
+    /// Append an entry to a collection.
+    ///
+    /// Returns `false` if the collection or entry doesn't exist. Adding an
+    /// entry that is already in the collection leaves its position alone.
+    pub fn add_to_collection(&self, collection_id: i64, entry_id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM collections WHERE id = ?1)
+                AND EXISTS(SELECT 1 FROM entries WHERE id = ?2)",
+            params![collection_id, entry_id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(false);
+        }
+
+        conn.execute(
+            "INSERT OR IGNORE INTO collection_entries (collection_id, entry_id, position)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(position), 0) + 1
+                              FROM collection_entries WHERE collection_id = ?1))",
+            params![collection_id, entry_id],
+        )?;
+        Ok(true)
+    }
+
+    /// Remove an entry from a collection. The entry stays in the history.
+    pub fn remove_from_collection(&self, collection_id: i64, entry_id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "DELETE FROM collection_entries WHERE collection_id = ?1 AND entry_id = ?2",
+            params![collection_id, entry_id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Get a collection's name and entry IDs in collection order.
+    pub fn collection_contents(&self, id: i64) -> Result<Option<(String, Vec<i64>)>> {
+        let conn = self.conn.lock().unwrap();
+
+        let name: Option<String> = conn
+            .query_row(
+                "SELECT name FROM collections WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(name) = name else {
+            return Ok(None);
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT entry_id FROM collection_entries
+             WHERE collection_id = ?1
+             ORDER BY position",
+        )?;
+        let ids = stmt
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+        Ok(Some((name, ids)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{insert_text, EntryLimits, HistoryFilter, Storage};
+
+    use super::*;
+
+    #[test]
+    fn test_collections_keep_order_and_survive_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let first = insert_text(&db, "first");
+        let second = insert_text(&db, "second");
+        insert_text(&db, "third");
+
+        let snippets = db.create_collection("Snippets").unwrap();
+        let other = db.create_collection("Other").unwrap();
+        assert!(db.add_to_collection(snippets.id, second).unwrap());
+        assert!(db.add_to_collection(snippets.id, first).unwrap());
+        assert!(db.add_to_collection(other.id, first).unwrap());
+        assert!(!db.add_to_collection(snippets.id, 999).unwrap());
+
+        let names: Vec<_> = db
+            .list_collections()
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.entry_count))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("Snippets".to_string(), 2), ("Other".to_string(), 1)]
+        );
+
+        let filter = HistoryFilter {
+            collection: Some(snippets.id),
+            ..Default::default()
+        };
+        let (entries, total) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(
+            entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![second, first]
+        );
+
+        // Collection members are kept like pinned entries
+        let limits = EntryLimits {
+            total: 0,
+            text: None,
+            image: None,
+            count_kept: false,
+        };
+        db.cleanup(&limits).unwrap();
+        assert_eq!(db.count_entries().unwrap(), 2);
+
+        assert!(db.delete_collection(snippets.id).unwrap());
+        assert_eq!(db.count_entries().unwrap(), 2);
+        assert_eq!(
+            db.collection_contents(other.id).unwrap(),
+            Some(("Other".to_string(), vec![first]))
+        );
+    }
+}
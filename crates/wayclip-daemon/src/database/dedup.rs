@@ -0,0 +1,230 @@
+//! Merging entries that hold the same content once text is normalized, left
+//! over from before captures were compared that way.
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+
+use super::Database;
+use crate::hash::{content_hash, normalize_text};
+
+/// Outcome of merging duplicate entries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Number of entries checked.
+    pub checked: u64,
+    /// Number of entries that duplicates were merged into.
+    pub merged: u64,
+    /// Number of duplicate entries removed.
+    pub removed: u64,
+}
+
+/// The parts of an entry that survive a merge.
+#[derive(Debug)]
+struct Row {
+    id: i64,
+    created_at: i64,
+    last_used_at: i64,
+    use_count: i64,
+    pinned: bool,
+    pin_order: i64,
+    title: Option<String>,
+    note: Option<String>,
+}
+
+impl Database {
+    /// Merge entries whose content is the same once text is normalized,
+    /// keeping the most recently used one of each group. Pins, titles, notes,
+    /// collections and use counts of the others are carried over to it.
+    ///
+    /// With `dry_run` nothing is changed and the report says what would be.
+    pub fn dedup_existing(&self, dry_run: bool) -> Result<DedupReport> {
+        let conn = self.conn.lock().unwrap();
+        let mut report = DedupReport::default();
+
+        let rows: Vec<(Row, bool)> = conn
+            .prepare(
+                "SELECT id, created_at, last_used_at, use_count, pinned, pin_order, title, note,
+                        content_type = 'text'
+                 FROM entries WHERE redacted = 0 ORDER BY id",
+            )?
+            .query_map([], |row| {
+                Ok((
+                    Row {
+                        id: row.get(0)?,
+                        created_at: row.get(1)?,
+                        last_used_at: row.get(2)?,
+                        use_count: row.get::<_, Option<i64>>(3)?.unwrap_or(1),
+                        pinned: row.get(4)?,
+                        pin_order: row.get(5)?,
+                        title: row.get(6)?,
+                        note: row.get(7)?,
+                    },
+                    row.get(8)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+
+        // Content is read one entry at a time to keep memory use flat
+        let mut groups: HashMap<String, Vec<Row>> = HashMap::new();
+        for (row, is_text) in rows {
+            report.checked += 1;
+            let content = conn.query_row(
+                "SELECT data FROM content WHERE entry_id = ?1",
+                params![row.id],
+                |row| row.get::<_, Vec<u8>>(0),
+            );
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    tracing::debug!("Skipping entry {} in dedup: {}", row.id, e);
+                    continue;
+                }
+            };
+            let key = match is_text {
+                true => content_hash(&normalize_text(&content)),
+                false => content_hash(&content),
+            };
+            groups.entry(key).or_default().push(row);
+        }
+
+        let tx = conn.unchecked_transaction()?;
+        for mut group in groups.into_values().filter(|group| group.len() > 1) {
+            report.merged += 1;
+            report.removed += group.len() as u64 - 1;
+            if dry_run {
+                continue;
+            }
+            group.sort_by_key(|row| std::cmp::Reverse((row.last_used_at, row.id)));
+            merge(&tx, &group)?;
+        }
+        tx.commit()?;
+
+        if report.removed > 0 && !dry_run {
+            self.cache.lock().unwrap().clear();
+            self.prune_thumbnails(&conn);
+            tracing::info!(
+                "Merged {} duplicate entries into {}",
+                report.removed,
+                report.merged
+            );
+        }
+        Ok(report)
+    }
+}
+
+/// Fold every row of `group` into the first one and delete the rest.
+fn merge(conn: &Connection, group: &[Row]) -> Result<()> {
+    let (survivor, duplicates) = group.split_first().expect("groups are never empty");
+
+    let mut notes: Vec<&str> = Vec::new();
+    for note in group.iter().filter_map(|row| row.note.as_deref()) {
+        if !note.is_empty() && !notes.contains(&note) {
+            notes.push(note);
+        }
+    }
+    let pinned = group.iter().any(|row| row.pinned);
+    let pin_order = group
+        .iter()
+        .filter(|row| row.pinned)
+        .map(|row| row.pin_order)
+        .min()
+        .unwrap_or(0);
+
+    for duplicate in duplicates {
+        conn.execute(
+            "INSERT OR IGNORE INTO collection_entries (collection_id, entry_id, position)
+             SELECT collection_id, ?1, position FROM collection_entries WHERE entry_id = ?2",
+            params![survivor.id, duplicate.id],
+        )?;
+        conn.execute(
+            "UPDATE entries SET similar_to = ?1 WHERE similar_to = ?2",
+            params![survivor.id, duplicate.id],
+        )?;
+        conn.execute("DELETE FROM entries WHERE id = ?1", params![duplicate.id])?;
+    }
+
+    conn.execute(
+        "UPDATE entries SET
+            created_at = ?1, last_used_at = ?2, use_count = ?3, pinned = ?4, pin_order = ?5,
+            title = ?6, note = NULLIF(?7, '')
+         WHERE id = ?8",
+        params![
+            group.iter().map(|row| row.created_at).min(),
+            survivor.last_used_at,
+            group.iter().map(|row| row.use_count).sum::<i64>(),
+            pinned,
+            pin_order,
+            group.iter().find_map(|row| row.title.as_deref()),
+            notes.join("\n"),
+            survivor.id,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{text_entry, HistoryFilter, NewEntry, Storage};
+
+    fn insert(db: &Database, text: &str) -> i64 {
+        db.insert_entry(&NewEntry {
+            hash: &content_hash(text.as_bytes()),
+            ..text_entry(text)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dedup_existing() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let plain = insert(&db, "hello");
+        let newline = insert(&db, "hello\n");
+        let crlf = insert(&db, "hello\r\n");
+        let other = insert(&db, "goodbye");
+        db.set_pinned(plain, true).unwrap();
+        db.set_entry_meta(newline, None, Some("#work")).unwrap();
+        let collection = db.create_collection("Greetings").unwrap().id;
+        db.add_to_collection(collection, crlf).unwrap();
+        db.touch_by_hash(&content_hash(b"hello\n")).unwrap();
+
+        let dry = db.dedup_existing(true).unwrap();
+        assert_eq!(
+            dry,
+            DedupReport {
+                checked: 4,
+                merged: 1,
+                removed: 2
+            }
+        );
+        assert_eq!(db.count_entries().unwrap(), 4);
+
+        assert_eq!(db.dedup_existing(false).unwrap(), dry);
+        let (entries, _) = db
+            .get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        let merged = entries.iter().find(|entry| entry.id != other).unwrap();
+        assert!(merged.pinned);
+        assert_eq!(merged.note.as_deref(), Some("#work"));
+        let use_count: i64 = db
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT use_count FROM entries WHERE id = ?1",
+                params![merged.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(use_count, 4);
+        let (_, members) = db.collection_contents(collection).unwrap().unwrap();
+        assert_eq!(members, vec![merged.id]);
+
+        // Nothing is left to merge
+        assert_eq!(db.dedup_existing(false).unwrap().removed, 0);
+    }
+}
@@ -0,0 +1,113 @@
+//! Companion devices paired for LAN sync.
+
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use wayclip_common::SyncDevice;
+
+use super::Database;
+
+const DEVICE_COLUMNS: &str = "id, name, can_push, can_pull, paired_at, last_seen_at";
+
+fn row_to_device(row: &rusqlite::Row) -> rusqlite::Result<SyncDevice> {
+    Ok(SyncDevice {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        can_push: row.get(2)?,
+        can_pull: row.get(3)?,
+        paired_at: row.get(4)?,
+        last_seen_at: row.get(5)?,
+    })
+}
+
+impl Database {
+    /// List paired devices in pairing order.
+    pub fn list_devices(&self) -> Result<Vec<SyncDevice>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sync_devices ORDER BY id",
+            DEVICE_COLUMNS
+        ))?;
+        let devices = stmt
+            .query_map([], row_to_device)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(devices)
+    }
+
+    /// Record a newly paired device, allowed to push but not pull.
+    pub fn add_device(&self, name: &str, token_hash: &str) -> Result<SyncDevice> {
+        let conn = self.conn.lock().unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        conn.execute(
+            "INSERT INTO sync_devices (name, token_hash, can_push, can_pull, paired_at)
+             VALUES (?1, ?2, 1, 0, ?3)",
+            params![name, token_hash, now],
+        )?;
+
+        Ok(SyncDevice {
+            id: conn.last_insert_rowid(),
+            name: name.to_string(),
+            can_push: true,
+            can_pull: false,
+            paired_at: now,
+            last_seen_at: None,
+        })
+    }
+
+    /// Find a device by ID and token hash, noting that it was seen.
+    pub fn authenticate_device(&self, id: i64, token_hash: &str) -> Result<Option<SyncDevice>> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let rows = self.conn.lock().unwrap().execute(
+            "UPDATE sync_devices SET last_seen_at = ?1 WHERE id = ?2 AND token_hash = ?3",
+            params![now, id, token_hash],
+        )?;
+        match rows {
+            0 => Ok(None),
+            _ => self.get_device(id),
+        }
+    }
+
+    /// Get a device by ID.
+    pub fn get_device(&self, id: i64) -> Result<Option<SyncDevice>> {
+        let conn = self.conn.lock().unwrap();
+        let device = conn
+            .query_row(
+                &format!("SELECT {} FROM sync_devices WHERE id = ?1", DEVICE_COLUMNS),
+                params![id],
+                row_to_device,
+            )
+            .optional()?;
+        Ok(device)
+    }
+
+    /// Change what a device may do. Permissions given as `None` are kept.
+    pub fn set_device_permissions(
+        &self,
+        id: i64,
+        can_push: Option<bool>,
+        can_pull: Option<bool>,
+    ) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "UPDATE sync_devices
+             SET can_push = COALESCE(?1, can_push), can_pull = COALESCE(?2, can_pull)
+             WHERE id = ?3",
+            params![can_push, can_pull, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Unpair a device.
+    pub fn remove_device(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute("DELETE FROM sync_devices WHERE id = ?1", params![id])?;
+        Ok(rows > 0)
+    }
+}
@@ -0,0 +1,129 @@
+//! Heavy upkeep that the scheduler runs at a quiet time.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use rusqlite::params;
+
+use super::recovery::with_suffix;
+use super::Database;
+
+impl Database {
+    /// Rebuild the database file to give back the space of deleted entries.
+    pub fn vacuum(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Copy the database to a timestamped file beside it, then delete all
+    /// but the newest `keep` copies.
+    ///
+    /// Returns the new copy's path, or `None` for a database that isn't
+    /// kept on disk.
+    pub fn back_up(&self, keep: u32) -> Result<Option<PathBuf>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let backup = with_suffix(path, &format!(".backup-{}", timestamp));
+        let target = backup
+            .to_str()
+            .ok_or_else(|| anyhow!("Backup path {} is not UTF-8", backup.display()))?;
+
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute("VACUUM INTO ?1", params![target])?;
+        }
+
+        let backups = self.backups();
+        let excess = backups.len().saturating_sub(keep as usize);
+        for old in &backups[..excess] {
+            if let Err(e) = std::fs::remove_file(old) {
+                tracing::warn!("Failed to remove old backup {}: {}", old.display(), e);
+            }
+        }
+
+        Ok(Some(backup))
+    }
+
+    /// Backups made by `back_up`, oldest first.
+    fn backups(&self) -> Vec<PathBuf> {
+        let Some(path) = &self.path else {
+            return Vec::new();
+        };
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return Vec::new();
+        };
+        let prefix = format!("{}.backup-", name.to_string_lossy());
+
+        let Ok(files) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut backups: Vec<(u64, PathBuf)> = files
+            .flatten()
+            .map(|e| e.path())
+            .filter_map(|p| {
+                let timestamp = p
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix(&prefix)?
+                    .parse()
+                    .ok()?;
+                Some((timestamp, p))
+            })
+            .collect();
+        backups.sort();
+        backups.into_iter().map(|(_, p)| p).collect()
+    }
+
+    /// Bring the thumbnail directory in line with the history: write any
+    /// missing files and delete those of entries that are gone.
+    pub fn refresh_thumbnails(&self) -> Result<()> {
+        let Some(thumbnails) = &self.thumbnails else {
+            return Ok(());
+        };
+        let conn = self.conn.lock().unwrap();
+        thumbnails.restore(&conn)?;
+        thumbnails.prune(&conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Storage;
+
+    use super::*;
+
+    #[test]
+    fn test_back_up_keeps_newest_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let db = Database::open_at(path.clone()).unwrap();
+        db.migrate().unwrap();
+
+        // Older copies from previous runs
+        for timestamp in ["100", "200"] {
+            std::fs::write(with_suffix(&path, &format!(".backup-{}", timestamp)), "").unwrap();
+        }
+
+        let backup = db.back_up(2).unwrap().unwrap();
+        let copy = Database::open_at(backup.clone()).unwrap();
+        assert_eq!(copy.count_entries().unwrap(), 0);
+
+        assert_eq!(
+            db.backups(),
+            vec![with_suffix(&path, ".backup-200"), backup]
+        );
+
+        assert!(Database::open_in_memory()
+            .unwrap()
+            .back_up(2)
+            .unwrap()
+            .is_none());
+    }
+}
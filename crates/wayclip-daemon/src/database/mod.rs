@@ -1,6 +1,52 @@
-//! SQLite database for storing clipboard history.
+//! Clipboard history storage, backed by SQLite.
 
+mod archive;
+mod audit;
+mod cache;
+mod collections;
+mod dedup;
+mod devices;
+mod maintenance;
 mod operations;
+mod recovery;
+mod report;
 mod schema;
+mod search;
+mod settings;
+mod storage;
+mod thumbnails;
 
-pub use operations::Database;
+pub use operations::{Database, EntryLimits, HistoryFilter, NewEntry};
+pub use storage::Storage;
+
+/// A text entry copied to the clipboard here, with `text` as its content,
+/// preview and hash. Tests change the rest with struct update syntax.
+#[cfg(test)]
+pub(crate) fn text_entry(text: &str) -> NewEntry<'_> {
+    static LOCAL: wayclip_common::Origin = wayclip_common::Origin::Local;
+    NewEntry {
+        hash: text,
+        content_type: wayclip_common::ContentType::Text,
+        mime_type: "text/plain",
+        preview: text,
+        content: text.as_bytes(),
+        thumbnail: None,
+        animated: false,
+        image_hash: None,
+        similar_to: None,
+        variants: &[],
+        seat: None,
+        selection: wayclip_common::Selection::Clipboard,
+        source_app: None,
+        window_title: None,
+        workspace: None,
+        origin: &LOCAL,
+        redacted: None,
+    }
+}
+
+/// Insert `text_entry(text)`, returning its ID.
+#[cfg(test)]
+pub(crate) fn insert_text(db: &Database, text: &str) -> i64 {
+    db.insert_entry(&text_entry(text)).unwrap()
+}
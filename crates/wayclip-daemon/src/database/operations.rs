@@ -1,38 +1,190 @@
 //! Database operations for clipboard history.
 
 use anyhow::Result;
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, DatabaseName, OptionalExtension};
+use std::io::Write;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use wayclip_common::{ContentType, HistoryEntry};
+use wayclip_common::{
+    CacheStats, ContentType, HistoryEntry, HistorySort, Origin, OriginKind, PruneCriteria,
+    Selection,
+};
 
+use super::cache::ContentCache;
 use super::schema;
+use super::search;
+use super::storage::Storage;
+use super::thumbnails::ThumbnailCache;
+
+/// Size of the content cache in bytes until `set_cache_size` is called.
+const DEFAULT_CACHE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Content at least this large is written through incremental blob I/O, so
+/// SQLite doesn't take its own copy of it first.
+const STREAMED_CONTENT_SIZE: usize = 1024 * 1024;
+
+/// Columns selected for a `HistoryEntry`, in the order `row_to_entry` expects.
+const ENTRY_COLUMNS: &str = "e.id, e.content_type, e.mime_type, e.preview, e.byte_size, \
+     e.created_at, e.pinned, e.animated, e.thumbnail, e.seat, e.selection, e.title, e.note, e.redacted, \
+     e.content_hash, e.similar_to, e.uuid, e.origin, e.origin_device, e.origin_name, e.source_app, \
+     e.window_title, e.workspace";
+
+/// Condition matching entries that cleanup may delete: not pinned and not
+/// kept in any collection.
+pub(super) const DISPOSABLE_ENTRY: &str =
+    "pinned = 0 AND id NOT IN (SELECT entry_id FROM collection_entries)";
+
+/// A new clipboard entry to be stored.
+pub struct NewEntry<'a> {
+    /// SHA-256 of the content, hex encoded.
+    pub hash: &'a str,
+    pub content_type: ContentType,
+    pub mime_type: &'a str,
+    pub preview: &'a str,
+    pub content: &'a [u8],
+    /// PNG thumbnail for image entries.
+    pub thumbnail: Option<&'a [u8]>,
+    /// Whether the image has multiple frames.
+    pub animated: bool,
+    /// Perceptual hash of an image, for finding similar images.
+    pub image_hash: Option<u64>,
+    /// Earlier entry this image looks like.
+    pub similar_to: Option<i64>,
+    /// Alternative representations as (MIME type, data) pairs.
+    pub variants: &'a [(String, Vec<u8>)],
+    /// Name of the seat the content was copied on.
+    pub seat: Option<&'a str>,
+    /// Selection the content was captured from.
+    pub selection: Selection,
+    /// Application the content was copied from, if known.
+    pub source_app: Option<&'a str>,
+    /// Title of the window the content was copied from, if known.
+    pub window_title: Option<&'a str>,
+    /// Workspace that window was on, if known.
+    pub workspace: Option<&'a str>,
+    /// Where the content came from.
+    pub origin: &'a Origin,
+    /// Record only the metadata and hash of content this many bytes long;
+    /// `content` is then not stored and may be empty.
+    pub redacted: Option<u64>,
+}
+
+/// Conditions narrowing down `Storage::get_history`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HistoryFilter<'a> {
+    /// Full-text search on the preview, title and note.
+    pub search: Option<&'a str>,
+    /// Only entries copied on this seat.
+    pub seat: Option<&'a str>,
+    /// Only entries captured from this selection.
+    pub selection: Option<Selection>,
+    /// Only entries in this collection, in collection order.
+    pub collection: Option<i64>,
+    /// Only pinned entries and entries in any collection.
+    pub snippets: bool,
+    /// Only images similar to this image entry, itself included.
+    pub similar_to: Option<i64>,
+    /// Only entries of this origin.
+    pub origin: Option<OriginKind>,
+    /// Order of the entries, unless in a collection.
+    pub sort: HistorySort,
+    /// Pinned entries first, in their manual order, unless in a collection.
+    pub pinned_first: bool,
+}
+
+/// How many entries `Storage::cleanup` keeps. Pinned entries and collection
+/// members are never deleted, and only count unless `count_kept` is off.
+#[derive(Debug, Clone, Copy)]
+pub struct EntryLimits {
+    /// Entries of any type.
+    pub total: u32,
+    /// Text entries, if they have a cap of their own.
+    pub text: Option<u32>,
+    /// Image entries, if they have a cap of their own.
+    pub image: Option<u32>,
+    /// Count pinned entries and collection members towards the caps, so
+    /// fewer other entries are kept.
+    pub count_kept: bool,
+}
+
+impl EntryLimits {
+    /// Each cap that applies, with the content type it is for, or `None`
+    /// for the cap on all of them. Type caps come first, so the total cap
+    /// only removes what they left.
+    pub fn caps(&self) -> impl Iterator<Item = (Option<ContentType>, u32)> {
+        [
+            self.text.map(|cap| (Some(ContentType::Text), cap)),
+            self.image.map(|cap| (Some(ContentType::Image), cap)),
+            Some((None, self.total)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
 
 /// Database handle with connection pooling.
 #[derive(Clone)]
 pub struct Database {
-    conn: Arc<Mutex<Connection>>,
-    path: PathBuf,
+    pub(super) conn: Arc<Mutex<Connection>>,
+    /// Database file, or `None` for an in-memory database.
+    pub(super) path: Option<PathBuf>,
+    /// Where a corrupt database found at startup was moved to.
+    pub(super) corrupt_backup: Option<PathBuf>,
+    /// Recently read content, so reopening the picker doesn't hit SQLite.
+    pub(super) cache: Arc<Mutex<ContentCache>>,
+    /// Where thumbnails are cached as files, if anywhere.
+    pub(super) thumbnails: Option<ThumbnailCache>,
+    /// Where long unused entries are moved to, if anywhere.
+    pub(super) archive: Option<Box<Database>>,
+    /// Whether the full-text index exists, so searches can use it.
+    pub(super) fts: Arc<AtomicBool>,
+    /// Whether the full-text index is made of trigrams rather than words.
+    pub(super) trigram: bool,
 }
 
 impl Database {
-    /// Open the database at the default path.
-    pub fn open() -> Result<Self> {
-        let path = wayclip_common::database_path();
-        Self::open_at(path)
-    }
-
     /// Open the database at a specific path.
     pub fn open_at(path: PathBuf) -> Result<Self> {
         let conn = Connection::open(&path)?;
 
         // Enable foreign keys
         conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        search::register_functions(&conn)?;
+
+        Ok(Self::with_connection(conn, Some(path)))
+    }
+
+    /// Open a database that lives in RAM only.
+    pub fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        search::register_functions(&conn)?;
+        Ok(Self::with_connection(conn, None))
+    }
 
-        Ok(Self {
+    fn with_connection(conn: Connection, path: Option<PathBuf>) -> Self {
+        Self {
             conn: Arc::new(Mutex::new(conn)),
             path,
-        })
+            corrupt_backup: None,
+            cache: Arc::new(Mutex::new(ContentCache::new(DEFAULT_CACHE_SIZE))),
+            thumbnails: None,
+            archive: None,
+            fts: Arc::new(AtomicBool::new(false)),
+            trigram: false,
+        }
+    }
+
+    /// Limit the content cache to `bytes` (0 disables it).
+    pub fn set_cache_size(&self, bytes: u64) {
+        self.cache.lock().unwrap().set_capacity(bytes);
+    }
+
+    /// Content cache hit statistics.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats()
     }
 
     /// Run database migrations.
@@ -43,15 +195,116 @@ impl Database {
         conn.execute_batch(schema::CREATE_CONTENT_TABLE)?;
         conn.execute_batch(schema::CREATE_INDEXES)?;
 
-        // FTS table creation might fail on older SQLite versions
-        let _ = conn.execute_batch(schema::CREATE_FTS_TABLE);
-        let _ = conn.execute_batch(schema::CREATE_FTS_TRIGGERS);
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in schema::MIGRATIONS.iter().enumerate().skip(version as usize) {
+            conn.execute_batch(&format!(
+                "BEGIN; {}; PRAGMA user_version = {}; COMMIT;",
+                migration,
+                i + 1
+            ))?;
+            tracing::info!("Applied database migration {}", i + 1);
+        }
+
+        // Search falls back to LIKE where SQLite has no FTS5
+        let fts = search::create_fts(&conn, self.trigram)?;
+        self.fts.store(fts, Ordering::Relaxed);
 
         Ok(())
     }
 
-    /// Find an entry by its content hash.
-    pub fn find_by_hash(&self, hash: &str) -> Result<Option<i64>> {
+    /// Set pinned status. Newly pinned entries go to the end of the pinned list.
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "UPDATE entries SET
+                pin_order = CASE
+                    WHEN ?1 = 0 THEN 0
+                    WHEN pinned = 1 THEN pin_order
+                    ELSE (SELECT COALESCE(MAX(pin_order), -1) + 1 FROM entries WHERE pinned = 1)
+                END,
+                pinned = ?1
+             WHERE id = ?2",
+            params![pinned as i32, id],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Set or clear an entry's title and note. Empty strings clear a field.
+    pub fn set_entry_meta(&self, id: i64, title: Option<&str>, note: Option<&str>) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let rows = conn.execute(
+            "UPDATE entries SET title = NULLIF(?1, ''), note = NULLIF(?2, '') WHERE id = ?3",
+            params![
+                title.map(search::normalize),
+                note.map(search::normalize),
+                id
+            ],
+        )?;
+        Ok(rows > 0)
+    }
+
+    /// Get one entry's metadata, looking in the archive for entries that
+    /// aren't live.
+    pub fn get_entry(&self, id: i64) -> Result<Option<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let entry = conn
+            .query_row(
+                &format!("SELECT {ENTRY_COLUMNS} FROM entries e WHERE e.id = ?1"),
+                params![id],
+                |row| Ok(row_to_entry(row, self.thumbnails.as_ref())),
+            )
+            .optional()?;
+        match entry {
+            Some(entry) => Ok(Some(entry)),
+            None => self.archived_entry(id),
+        }
+    }
+
+    /// Get an entry's title and note.
+    pub fn entry_meta(&self, id: i64) -> Result<Option<(Option<String>, Option<String>)>> {
+        let conn = self.conn.lock().unwrap();
+        let meta = conn
+            .query_row(
+                "SELECT title, note FROM entries WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(meta)
+    }
+
+    /// Move a pinned entry to `position` in the pinned list.
+    ///
+    /// Positions past the end move the entry to the end. Returns `false` if
+    /// the entry doesn't exist or isn't pinned.
+    pub fn move_pinned(&self, id: i64, position: u32) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+
+        let mut ids = tx
+            .prepare("SELECT id FROM entries WHERE pinned = 1 ORDER BY pin_order, created_at DESC")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let Some(current) = ids.iter().position(|&pinned| pinned == id) else {
+            return Ok(false);
+        };
+
+        ids.remove(current);
+        ids.insert((position as usize).min(ids.len()), id);
+        for (order, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE entries SET pin_order = ?1 WHERE id = ?2",
+                params![order as i64, id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+}
+
+impl Storage for Database {
+    fn find_by_hash(&self, hash: &str) -> Result<Option<i64>> {
         let conn = self.conn.lock().unwrap();
         let id: Option<i64> = conn
             .query_row(
@@ -63,8 +316,22 @@ impl Database {
         Ok(id)
     }
 
-    /// Update last_used_at for an entry by hash.
-    pub fn touch_by_hash(&self, hash: &str) -> Result<()> {
+    fn find_by_uuid(&self, uuid: &str) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let id: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM entries WHERE uuid = ?1",
+                params![uuid.to_ascii_lowercase()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match (id, &self.archive) {
+            (None, Some(archive)) => archive.find_by_uuid(uuid),
+            _ => Ok(id),
+        }
+    }
+
+    fn touch_by_hash(&self, hash: &str) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -78,8 +345,7 @@ impl Database {
         Ok(())
     }
 
-    /// Update last_used_at for an entry by ID.
-    pub fn touch_entry(&self, id: i64) -> Result<()> {
+    fn touch_entry(&self, id: i64) -> Result<()> {
         let conn = self.conn.lock().unwrap();
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -93,103 +359,213 @@ impl Database {
         Ok(())
     }
 
-    /// Insert a new clipboard entry.
-    pub fn insert_entry(
-        &self,
-        hash: &str,
-        content_type: ContentType,
-        mime_type: &str,
-        preview: &str,
-        content: &[u8],
-    ) -> Result<i64> {
+    fn insert_entry(&self, entry: &NewEntry) -> Result<i64> {
         let conn = self.conn.lock().unwrap();
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs() as i64;
 
-        let content_type_str = match content_type {
-            ContentType::Text => "text",
-            ContentType::Image => "image",
+        let content_type_str = content_type_name(entry.content_type);
+        let (origin_device, origin_name) = match entry.origin {
+            Origin::Synced { device, name } => (Some(*device), Some(name.as_str())),
+            _ => (None, None),
         };
 
         conn.execute(
-            "INSERT INTO entries (content_hash, content_type, mime_type, preview, byte_size, created_at, last_used_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)",
-            params![hash, content_type_str, mime_type, preview, content.len() as i64, now],
+            "INSERT INTO entries (content_hash, content_type, mime_type, preview, byte_size, created_at, last_used_at, thumbnail, animated, seat, selection, source_app, window_title, workspace, redacted, image_hash, similar_to, uuid, origin, origin_device, origin_name)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            params![
+                entry.hash,
+                content_type_str,
+                entry.mime_type,
+                search::normalize(entry.preview),
+                entry.redacted.unwrap_or(entry.content.len() as u64) as i64,
+                now,
+                entry.thumbnail,
+                entry.animated as i32,
+                entry.seat,
+                entry.selection.name(),
+                entry.source_app,
+                entry.window_title,
+                entry.workspace,
+                entry.redacted.is_some() as i32,
+                // SQLite integers are signed, so the bits are stored as i64
+                entry.image_hash.map(|hash| hash as i64),
+                entry.similar_to,
+                uuid::Uuid::new_v4().to_string(),
+                entry.origin.kind().name(),
+                origin_device,
+                origin_name
+            ],
         )?;
 
         let id = conn.last_insert_rowid();
+        // SQLite may hand out the ID of a deleted entry again
+        self.cache.lock().unwrap().remove(id);
+        if let (Some(thumbnails), Some(png)) = (&self.thumbnails, entry.thumbnail) {
+            thumbnails.ensure(entry.hash, png);
+        }
+        if entry.redacted.is_some() {
+            return Ok(id);
+        }
 
-        conn.execute(
-            "INSERT INTO content (entry_id, data) VALUES (?1, ?2)",
-            params![id, content],
-        )?;
+        if entry.content.len() >= STREAMED_CONTENT_SIZE {
+            conn.execute(
+                "INSERT INTO content (entry_id, data) VALUES (?1, zeroblob(?2))",
+                params![id, entry.content.len() as i64],
+            )?;
+            // `entry_id` is the content table's rowid
+            let mut blob = conn.blob_open(DatabaseName::Main, "content", "data", id, false)?;
+            blob.write_all(entry.content)?;
+        } else {
+            conn.execute(
+                "INSERT INTO content (entry_id, data) VALUES (?1, ?2)",
+                params![id, entry.content],
+            )?;
+        }
+
+        for (mime_type, data) in entry.variants {
+            conn.execute(
+                "INSERT INTO content_variants (entry_id, mime_type, data) VALUES (?1, ?2, ?3)",
+                params![id, mime_type, data],
+            )?;
+        }
 
         Ok(id)
     }
 
-    /// Get clipboard history.
-    pub fn get_history(
+    fn get_history(
         &self,
         limit: Option<u32>,
         offset: Option<u32>,
-        search: Option<&str>,
+        filter: &HistoryFilter,
     ) -> Result<(Vec<HistoryEntry>, u64)> {
         let conn = self.conn.lock().unwrap();
         let limit = limit.unwrap_or(100) as i64;
         let offset = offset.unwrap_or(0) as i64;
 
-        let (entries, total) = if let Some(search) = search {
-            // Use FTS search
-            let search_query = format!("{}*", search.replace('"', "\"\""));
+        let mut from = String::from("entries e");
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
 
-            let total: i64 = conn.query_row(
-                "SELECT COUNT(*) FROM entries_fts WHERE entries_fts MATCH ?1",
-                params![search_query],
-                |row| row.get(0),
-            ).unwrap_or(0);
-
-            let mut stmt = conn.prepare(
-                "SELECT e.id, e.content_type, e.mime_type, e.preview, e.byte_size, e.created_at, e.pinned
-                 FROM entries e
-                 INNER JOIN entries_fts fts ON e.id = fts.rowid
-                 WHERE entries_fts MATCH ?1
-                 ORDER BY e.created_at DESC
-                 LIMIT ?2 OFFSET ?3"
-            )?;
-
-            let entries: Vec<HistoryEntry> = stmt
-                .query_map(params![search_query, limit, offset], |row| {
-                    Ok(row_to_entry(row))
-                })?
-                .filter_map(|r| r.ok())
-                .collect();
+        let search_condition;
+        if let Some(search) = filter.search {
+            let search_values;
+            (search_condition, search_values) = search::condition(search, self.search_backend());
+            conditions.push(&search_condition);
+            values.extend(search_values);
+        }
+        if let Some(seat) = filter.seat {
+            conditions.push("e.seat = ?");
+            values.push(Value::Text(seat.to_string()));
+        }
+        if let Some(selection) = filter.selection {
+            conditions.push("e.selection = ?");
+            values.push(Value::Text(selection.name().to_string()));
+        }
+        if let Some(origin) = filter.origin {
+            conditions.push("e.origin = ?");
+            values.push(Value::Text(origin.name().to_string()));
+        }
+        let snippets = format!("NOT ({DISPOSABLE_ENTRY})");
+        if filter.snippets {
+            conditions.push(&snippets);
+        }
+        let similar = match filter.similar_to {
+            Some(id) => {
+                let hash: Option<i64> = conn
+                    .query_row(
+                        "SELECT image_hash FROM entries WHERE id = ?1",
+                        params![id],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .flatten();
+                let ids: Vec<String> = match hash {
+                    Some(hash) => images_near(&conn, hash as u64)?
+                        .into_iter()
+                        .map(|(id, _)| id.to_string())
+                        .collect(),
+                    None => Vec::new(),
+                };
+                format!("e.id IN ({})", ids.join(", "))
+            }
+            None => String::new(),
+        };
+        if filter.similar_to.is_some() {
+            conditions.push(&similar);
+        }
+        let pinned_order;
+        let order = if let Some(collection) = filter.collection {
+            from.push_str(" INNER JOIN collection_entries ce ON e.id = ce.entry_id");
+            conditions.push("ce.collection_id = ?");
+            values.push(Value::Integer(collection));
+            "ce.position"
+        } else {
+            // Entries added in the same second keep the order they were
+            // added in
+            let sort = match filter.sort {
+                HistorySort::Recent => "e.created_at DESC, e.id DESC",
+                HistorySort::MostUsed => {
+                    "COALESCE(e.use_count, 1) DESC, e.created_at DESC, e.id DESC"
+                }
+                HistorySort::Largest => "e.byte_size DESC, e.created_at DESC, e.id DESC",
+                HistorySort::Oldest => "e.created_at, e.id",
+            };
+            if filter.pinned_first {
+                pinned_order = format!("e.pinned DESC, e.pin_order, {sort}");
+                &pinned_order
+            } else {
+                sort
+            }
+        };
 
-            (entries, total as u64)
+        let where_clause = if conditions.is_empty() {
+            String::new()
         } else {
-            let total: i64 = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
+            format!("WHERE {}", conditions.join(" AND "))
+        };
 
-            let mut stmt = conn.prepare(
-                "SELECT id, content_type, mime_type, preview, byte_size, created_at, pinned
-                 FROM entries
-                 ORDER BY created_at DESC
-                 LIMIT ?1 OFFSET ?2",
-            )?;
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM {from} {where_clause}"),
+            params_from_iter(&values),
+            |row| row.get(0),
+        )?;
 
-            let entries: Vec<HistoryEntry> = stmt
-                .query_map(params![limit, offset], |row| Ok(row_to_entry(row)))?
-                .filter_map(|r| r.ok())
-                .collect();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ENTRY_COLUMNS}
+             FROM {from}
+             {where_clause}
+             ORDER BY {order}
+             LIMIT ? OFFSET ?"
+        ))?;
 
-            (entries, total as u64)
-        };
+        values.push(Value::Integer(limit));
+        values.push(Value::Integer(offset));
+        let entries: Vec<HistoryEntry> = stmt
+            .query_map(params_from_iter(&values), |row| {
+                Ok(row_to_entry(row, self.thumbnails.as_ref()))
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok((entries, total as u64))
+    }
 
-        Ok((entries, total))
+    fn find_similar_image(&self, image_hash: u64) -> Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let closest = images_near(&conn, image_hash)?
+            .into_iter()
+            .min_by_key(|&(_, distance)| distance);
+        Ok(closest.map(|(id, _)| id))
     }
 
-    /// Get the content of an entry.
-    pub fn get_content(&self, id: i64) -> Result<Option<(String, Vec<u8>)>> {
+    fn get_content(&self, id: i64) -> Result<Option<(String, Vec<u8>)>> {
+        if let Some(cached) = self.cache.lock().unwrap().get(id) {
+            return Ok(Some(cached));
+        }
+
         let conn = self.conn.lock().unwrap();
 
         let result: Option<(String, Vec<u8>)> = conn
@@ -203,93 +579,748 @@ impl Database {
             )
             .optional()?;
 
+        match &result {
+            Some((mime_type, data)) => self.cache.lock().unwrap().insert(id, mime_type, data),
+            None => return self.archived_content(id),
+        }
         Ok(result)
     }
 
-    /// Delete an entry.
-    pub fn delete_entry(&self, id: i64) -> Result<bool> {
+    fn get_variants(&self, id: i64) -> Result<Vec<(String, Vec<u8>)>> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt =
+            conn.prepare("SELECT mime_type, data FROM content_variants WHERE entry_id = ?1")?;
+        let variants = stmt
+            .query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        if variants.is_empty() {
+            return self.archived_variants(id);
+        }
+        Ok(variants)
+    }
+
+    fn delete_entry(&self, id: i64) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
 
         // Content is deleted automatically via CASCADE
         let rows = conn.execute("DELETE FROM entries WHERE id = ?1", params![id])?;
-        Ok(rows > 0)
+        self.cache.lock().unwrap().remove(id);
+        if rows == 0 {
+            return self.delete_archived(id);
+        }
+        self.prune_thumbnails(&conn);
+        Ok(true)
     }
 
-    /// Clear all non-pinned entries.
-    pub fn clear_unpinned(&self) -> Result<()> {
+    fn delete_disposable(&self, id: i64) -> Result<bool> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM entries WHERE pinned = 0", [])?;
-        Ok(())
+
+        let rows = conn.execute(
+            &format!("DELETE FROM entries WHERE id = ?1 AND {DISPOSABLE_ENTRY}"),
+            params![id],
+        )?;
+        if rows == 0 {
+            return self.delete_archived(id);
+        }
+        self.cache.lock().unwrap().remove(id);
+        self.prune_thumbnails(&conn);
+        Ok(true)
     }
 
-    /// Set pinned status.
-    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<bool> {
+    fn clear_unpinned(&self) -> Result<()> {
         let conn = self.conn.lock().unwrap();
-        let rows = conn.execute(
-            "UPDATE entries SET pinned = ?1 WHERE id = ?2",
-            params![pinned as i32, id],
+        conn.execute(&format!("DELETE FROM entries WHERE {DISPOSABLE_ENTRY}"), [])?;
+        self.cache.lock().unwrap().clear();
+        self.prune_thumbnails(&conn);
+        self.clear_archive()
+    }
+
+    fn prune(&self, criteria: &PruneCriteria, dry_run: bool) -> Result<u64> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut conditions = vec![DISPOSABLE_ENTRY];
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(secs) = criteria.older_than_secs {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            conditions.push("created_at < ?");
+            values.push(Value::Integer(now.saturating_sub(secs) as i64));
+        }
+        if let Some(bytes) = criteria.larger_than {
+            conditions.push("byte_size > ?");
+            values.push(Value::Integer(bytes.min(i64::MAX as u64) as i64));
+        }
+        if let Some(content_type) = criteria.content_type {
+            conditions.push("content_type = ?");
+            values.push(Value::Text(content_type_name(content_type).to_string()));
+        }
+        if let Some(app) = &criteria.source_app {
+            conditions.push("instr(lower(source_app), lower(?)) > 0");
+            values.push(Value::Text(app.clone()));
+        }
+        let where_clause = conditions.join(" AND ");
+
+        let archived = self.prune_archive(criteria, dry_run)?;
+        if dry_run {
+            let count: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM entries WHERE {where_clause}"),
+                params_from_iter(&values),
+                |row| row.get(0),
+            )?;
+            return Ok(count as u64 + archived);
+        }
+
+        let count = conn.execute(
+            &format!("DELETE FROM entries WHERE {where_clause}"),
+            params_from_iter(&values),
         )?;
-        Ok(rows > 0)
+        if count > 0 {
+            self.cache.lock().unwrap().clear();
+            self.prune_thumbnails(&conn);
+        }
+        Ok(count as u64 + archived)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM entries", [])?;
+        self.cache.lock().unwrap().clear();
+        self.prune_thumbnails(&conn);
+        self.clear_archive()
     }
 
-    /// Count total entries.
-    pub fn count_entries(&self) -> Result<u64> {
+    fn count_entries(&self) -> Result<u64> {
         let conn = self.conn.lock().unwrap();
         let count: i64 = conn.query_row("SELECT COUNT(*) FROM entries", [], |row| row.get(0))?;
         Ok(count as u64)
     }
 
-    /// Get database size in bytes.
-    pub fn database_size(&self) -> Result<u64> {
-        let metadata = std::fs::metadata(&self.path)?;
-        Ok(metadata.len())
+    fn database_size(&self) -> Result<u64> {
+        match &self.path {
+            Some(path) => Ok(std::fs::metadata(path)?.len()),
+            None => {
+                let conn = self.conn.lock().unwrap();
+                let size: i64 = conn.query_row(
+                    "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                    [],
+                    |row| row.get(0),
+                )?;
+                Ok(size as u64)
+            }
+        }
     }
 
-    /// Cleanup old entries to stay within max_entries limit.
-    pub fn cleanup(&self, max_entries: u32) -> Result<()> {
+    fn cleanup(&self, limits: &EntryLimits) -> Result<()> {
         let conn = self.conn.lock().unwrap();
 
-        // Count non-pinned entries
-        let count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM entries WHERE pinned = 0",
-            [],
-            |row| row.get(0),
-        )?;
+        let mut deleted = 0;
+        for (content_type, cap) in limits.caps() {
+            let of_type = match content_type {
+                Some(content_type) => {
+                    format!("content_type = '{}'", content_type_name(content_type))
+                }
+                None => "1".to_string(),
+            };
+            let disposable = format!("{DISPOSABLE_ENTRY} AND {of_type}");
 
-        if count > max_entries as i64 {
-            let to_delete = count - max_entries as i64;
-
-            conn.execute(
-                "DELETE FROM entries WHERE id IN (
-                    SELECT id FROM entries WHERE pinned = 0
-                    ORDER BY last_used_at ASC
-                    LIMIT ?1
-                )",
-                params![to_delete],
+            // Count entries that count towards the cap
+            let counted = match limits.count_kept {
+                true => &of_type,
+                false => &disposable,
+            };
+            let count: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM entries WHERE {counted}"),
+                [],
+                |row| row.get(0),
             )?;
 
-            tracing::debug!("Cleaned up {} old entries", to_delete);
+            if count > cap as i64 {
+                deleted += conn.execute(
+                    &format!(
+                        "DELETE FROM entries WHERE id IN (
+                            SELECT id FROM entries WHERE {disposable}
+                            ORDER BY last_used_at ASC
+                            LIMIT ?1
+                        )"
+                    ),
+                    params![count - cap as i64],
+                )?;
+            }
+        }
+
+        if deleted > 0 {
+            self.cache.lock().unwrap().clear();
+            self.prune_thumbnails(&conn);
+
+            tracing::debug!("Cleaned up {} old entries", deleted);
         }
 
         Ok(())
     }
 }
 
-fn row_to_entry(row: &rusqlite::Row) -> HistoryEntry {
+/// How a content type is stored in the `content_type` column.
+fn content_type_name(content_type: ContentType) -> &'static str {
+    match content_type {
+        ContentType::Text => "text",
+        ContentType::Image => "image",
+        ContentType::Other => "other",
+    }
+}
+
+/// IDs of images within `SIMILAR_IMAGE_DISTANCE` of `image_hash`, with
+/// their distance.
+fn images_near(conn: &Connection, image_hash: u64) -> Result<Vec<(i64, u32)>> {
+    use crate::thumbnail::{hash_distance, SIMILAR_IMAGE_DISTANCE};
+
+    let mut stmt =
+        conn.prepare("SELECT id, image_hash FROM entries WHERE image_hash IS NOT NULL")?;
+    let near = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+        .filter_map(|r| r.ok())
+        .map(|(id, hash)| (id, hash_distance(image_hash, hash as u64)))
+        .filter(|&(_, distance)| distance <= SIMILAR_IMAGE_DISTANCE)
+        .collect();
+    Ok(near)
+}
+
+/// Build a `HistoryEntry` from `ENTRY_COLUMNS`, pointing at the cached
+/// thumbnail file instead of inlining it when there is a cache.
+fn row_to_entry(row: &rusqlite::Row, thumbnails: Option<&ThumbnailCache>) -> HistoryEntry {
+    use base64::Engine;
+
+    let thumbnail: Option<Vec<u8>> = row.get(8).unwrap_or_default();
+    let thumbnail_path = thumbnail.as_deref().and_then(|png| {
+        let hash: String = row.get(14).ok()?;
+        thumbnails?.ensure(&hash, png)
+    });
+
     let content_type_str: String = row.get(1).unwrap_or_default();
     let content_type = match content_type_str.as_str() {
         "image" => ContentType::Image,
+        "other" => ContentType::Other,
         _ => ContentType::Text,
     };
 
+    let selection = row
+        .get::<_, String>(10)
+        .ok()
+        .and_then(|name| Selection::from_name(&name))
+        .unwrap_or_default();
+    let origin = stored_origin(
+        row.get::<_, Option<String>>(17).ok().flatten().as_deref(),
+        row.get(18).unwrap_or_default(),
+        row.get(19).unwrap_or_default(),
+        selection,
+    );
+
     HistoryEntry {
         id: row.get(0).unwrap_or(0),
+        uuid: row.get(16).unwrap_or_default(),
         content_type,
         mime_type: row.get(2).unwrap_or_default(),
         preview: row.get(3).unwrap_or_default(),
         byte_size: row.get::<_, i64>(4).unwrap_or(0) as u64,
         created_at: row.get(5).unwrap_or(0),
         pinned: row.get::<_, i32>(6).unwrap_or(0) != 0,
-        thumbnail: None,
+        animated: row.get::<_, i32>(7).unwrap_or(0) != 0,
+        thumbnail: thumbnail
+            .filter(|_| thumbnail_path.is_none())
+            .map(|png| base64::engine::general_purpose::STANDARD.encode(png)),
+        thumbnail_path: thumbnail_path.map(|path| path.to_string_lossy().into_owned()),
+        seat: row.get(9).unwrap_or_default(),
+        selection,
+        title: row.get(11).unwrap_or_default(),
+        note: row.get(12).unwrap_or_default(),
+        redacted: row.get::<_, i32>(13).unwrap_or(0) != 0,
+        hash: row.get(14).unwrap_or_default(),
+        similar_to: row.get(15).unwrap_or_default(),
+        origin,
+        source_app: row.get(20).unwrap_or_default(),
+        window_title: row.get(21).unwrap_or_default(),
+        workspace: row.get(22).unwrap_or_default(),
+        is_current: false,
+        archived: false,
+    }
+}
+
+/// The origin kept in an entry's `origin`, `origin_device` and `origin_name`
+/// columns. Without a known kind, as in databases from before origins were
+/// recorded, it goes by the selection the entry was captured from.
+pub(super) fn stored_origin(
+    kind: Option<&str>,
+    device: Option<i64>,
+    name: Option<String>,
+    selection: Selection,
+) -> Origin {
+    match kind.and_then(OriginKind::from_name) {
+        Some(OriginKind::Local) => Origin::Local,
+        Some(OriginKind::Primary) => Origin::Primary,
+        Some(OriginKind::Synced) => Origin::Synced {
+            device: device.unwrap_or(0),
+            name: name.unwrap_or_default(),
+        },
+        Some(OriginKind::Api) => Origin::Api,
+        Some(OriginKind::Import) => Origin::Import,
+        None => Origin::captured(selection),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::text_entry;
+
+    fn insert(db: &Database, text: &str, seat: &str, selection: Selection) -> i64 {
+        let hash = format!("{text}-{seat}-{}", selection.name());
+        db.insert_entry(&NewEntry {
+            hash: &hash,
+            seat: Some(seat),
+            selection,
+            origin: &Origin::captured(selection),
+            ..text_entry(text)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_cleanup_caps_each_type() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        for text in ["alpha", "beta", "gamma"] {
+            insert(&db, text, "seat0", Selection::Clipboard);
+        }
+        let images = ["one", "two", "three"].map(|name| {
+            db.insert_entry(&NewEntry {
+                content_type: ContentType::Image,
+                mime_type: "image/png",
+                ..text_entry(name)
+            })
+            .unwrap()
+        });
+        db.set_pinned(images[0], true).unwrap();
+
+        let counts = || {
+            let (entries, _) = db
+                .get_history(None, None, &HistoryFilter::default())
+                .unwrap();
+            let images = entries.iter().filter(|e| e.content_type.is_image()).count();
+            (entries.len() - images, images)
+        };
+
+        // The pinned image doesn't count towards the image cap
+        let mut limits = EntryLimits {
+            total: 10,
+            text: None,
+            image: Some(1),
+            count_kept: false,
+        };
+        db.cleanup(&limits).unwrap();
+        assert_eq!(counts(), (3, 2));
+
+        limits.text = Some(2);
+        db.cleanup(&limits).unwrap();
+        assert_eq!(counts(), (2, 2));
+
+        limits.total = 1;
+        db.cleanup(&limits).unwrap();
+        assert_eq!(db.count_entries().unwrap(), 2);
+        assert!(db.get_entry(images[0]).unwrap().is_some());
+
+        // Counted, the pinned image fills the cap but is still kept
+        limits.count_kept = true;
+        db.cleanup(&limits).unwrap();
+        assert_eq!(db.count_entries().unwrap(), 1);
+        assert!(db.get_entry(images[0]).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_entry_origins() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        insert(&db, "alpha", "seat0", Selection::Clipboard);
+        insert(&db, "beta", "seat0", Selection::Primary);
+        let phone = Origin::Synced {
+            device: 3,
+            name: "Phone".to_string(),
+        };
+        let synced = db
+            .insert_entry(&NewEntry {
+                origin: &phone,
+                ..text_entry("gamma")
+            })
+            .unwrap();
+
+        let origins = |kind| {
+            let filter = HistoryFilter {
+                origin: Some(kind),
+                ..Default::default()
+            };
+            let (entries, _) = db.get_history(None, None, &filter).unwrap();
+            entries
+                .into_iter()
+                .map(|entry| (entry.preview, entry.origin))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            origins(OriginKind::Local),
+            vec![("alpha".to_string(), Origin::Local)]
+        );
+        assert_eq!(
+            origins(OriginKind::Primary),
+            vec![("beta".to_string(), Origin::Primary)]
+        );
+        assert_eq!(
+            origins(OriginKind::Synced),
+            vec![("gamma".to_string(), phone)]
+        );
+        assert!(origins(OriginKind::Import).is_empty());
+        assert_eq!(
+            db.get_entry(synced).unwrap().unwrap().origin.kind(),
+            OriginKind::Synced
+        );
+    }
+
+    #[test]
+    fn test_copy_context() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let plain = insert(&db, "alpha", "seat0", Selection::Clipboard);
+        let id = db
+            .insert_entry(&NewEntry {
+                seat: Some("seat0"),
+                source_app: Some("org.mozilla.firefox"),
+                window_title: Some("Release notes - Mozilla Firefox"),
+                workspace: Some("2"),
+                ..text_entry("beta")
+            })
+            .unwrap();
+
+        let entry = db.get_entry(id).unwrap().unwrap();
+        assert_eq!(entry.source_app.as_deref(), Some("org.mozilla.firefox"));
+        assert_eq!(
+            entry.window_title.as_deref(),
+            Some("Release notes - Mozilla Firefox")
+        );
+        assert_eq!(entry.workspace.as_deref(), Some("2"));
+
+        let entry = db.get_entry(plain).unwrap().unwrap();
+        assert_eq!((entry.window_title, entry.workspace), (None, None));
+    }
+
+    #[test]
+    fn test_large_content_is_streamed() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let content: Vec<u8> = (0..STREAMED_CONTENT_SIZE + 3).map(|i| i as u8).collect();
+        let id = db
+            .insert_entry(&NewEntry {
+                mime_type: "application/octet-stream",
+                content: &content,
+                ..text_entry("large")
+            })
+            .unwrap();
+        let (_, stored) = db.get_content(id).unwrap().unwrap();
+        assert!(stored == content);
+    }
+
+    #[test]
+    fn test_in_memory_database() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let id = insert(&db, "alpha", "seat0", Selection::Clipboard);
+        assert_eq!(
+            db.get_content(id).unwrap(),
+            Some(("text/plain".to_string(), b"alpha".to_vec()))
+        );
+        assert!(db.database_size().unwrap() > 0);
+        assert_eq!(db.default_recovery_source(), None);
+    }
+
+    #[test]
+    fn test_entries_have_uuids() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let first = insert(&db, "alpha", "seat0", Selection::Clipboard);
+        insert(&db, "beta", "seat0", Selection::Clipboard);
+
+        let (entries, _) = db
+            .get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_ne!(entries[0].uuid, entries[1].uuid);
+
+        let uuid = &entries.iter().find(|e| e.id == first).unwrap().uuid;
+        assert!(uuid::Uuid::parse_str(uuid).is_ok());
+        assert_eq!(
+            db.find_by_uuid(&uuid.to_ascii_uppercase()).unwrap(),
+            Some(first)
+        );
+        assert_eq!(db.find_by_uuid("not-a-uuid").unwrap(), None);
+    }
+
+    #[test]
+    fn test_history_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        insert(&db, "alpha", "seat0", Selection::Clipboard);
+        insert(&db, "beta", "seat0", Selection::Primary);
+        insert(&db, "alpha bravo", "seat1", Selection::Clipboard);
+
+        let (_, total) = db
+            .get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(total, 3);
+
+        let filter = HistoryFilter {
+            selection: Some(Selection::Primary),
+            ..Default::default()
+        };
+        let (entries, total) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(entries[0].preview, "beta");
+        assert_eq!(entries[0].seat.as_deref(), Some("seat0"));
+
+        let filter = HistoryFilter {
+            search: Some("alpha"),
+            seat: Some("seat1"),
+            selection: Some(Selection::Clipboard),
+            ..Default::default()
+        };
+        let (entries, total) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(entries[0].preview, "alpha bravo");
+    }
+
+    #[test]
+    fn test_similar_images() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let image = |name: &str, image_hash: u64| {
+            db.insert_entry(&NewEntry {
+                content_type: ContentType::Image,
+                mime_type: "image/png",
+                image_hash: Some(image_hash),
+                ..text_entry(name)
+            })
+            .unwrap()
+        };
+        // The top bit checks hashes survive SQLite's signed integers
+        let original = image("original", 0xF0F0_0000_FFFF_0F0F);
+        let recompressed = image("recompressed", 0xF0F0_0000_FFFF_0F0E);
+        image("different", 0x0F0F_FFFF_0000_F0F0);
+        insert(&db, "text", "seat0", Selection::Clipboard);
+
+        assert_eq!(
+            db.find_similar_image(0xF0F0_0000_FFFF_0F0F).unwrap(),
+            Some(original)
+        );
+        assert_eq!(db.find_similar_image(0).unwrap(), None);
+
+        let filter = HistoryFilter {
+            similar_to: Some(recompressed),
+            ..Default::default()
+        };
+        let (entries, total) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!(total, 2);
+        let mut ids: Vec<i64> = entries.iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![original, recompressed]);
+    }
+
+    #[test]
+    fn test_prune() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let old = insert(&db, "old", "seat0", Selection::Clipboard);
+        let old_pinned = insert(&db, "old and pinned", "seat0", Selection::Clipboard);
+        let old_large = insert(&db, "old and rather large", "seat0", Selection::Clipboard);
+        insert(&db, "recent", "seat0", Selection::Clipboard);
+        db.set_pinned(old_pinned, true).unwrap();
+        db.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE entries SET created_at = created_at - 7200 WHERE id IN (?1, ?2, ?3)",
+                params![old, old_pinned, old_large],
+            )
+            .unwrap();
+
+        let older = PruneCriteria {
+            older_than_secs: Some(3600),
+            ..Default::default()
+        };
+        assert_eq!(db.prune(&older, true).unwrap(), 2);
+        assert_eq!(db.count_entries().unwrap(), 4);
+
+        let older_and_large = PruneCriteria {
+            larger_than: Some(10),
+            ..older.clone()
+        };
+        assert_eq!(db.prune(&older_and_large, false).unwrap(), 1);
+        assert_eq!(db.get_content(old_large).unwrap(), None);
+
+        let text = PruneCriteria {
+            content_type: Some(ContentType::Text),
+            ..Default::default()
+        };
+        assert_eq!(db.prune(&text, false).unwrap(), 2);
+        let (entries, _) = db
+            .get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![old_pinned]
+        );
+    }
+
+    #[test]
+    fn test_move_pinned() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let ids: Vec<i64> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|text| insert(&db, text, "seat0", Selection::Clipboard))
+            .collect();
+        let [a, b, c, d] = ids[..] else {
+            unreachable!()
+        };
+        for id in [a, b, c] {
+            assert!(db.set_pinned(id, true).unwrap());
+        }
+
+        let order = |db: &Database| -> Vec<i64> {
+            let filter = HistoryFilter {
+                pinned_first: true,
+                ..Default::default()
+            };
+            let (entries, _) = db.get_history(None, None, &filter).unwrap();
+            entries.iter().map(|e| e.id).collect()
+        };
+        assert_eq!(order(&db), vec![a, b, c, d]);
+
+        assert!(db.move_pinned(c, 0).unwrap());
+        assert!(db.move_pinned(a, 99).unwrap());
+        assert_eq!(order(&db), vec![c, b, a, d]);
+
+        // Re-pinning appends to the end; pinning twice keeps the position
+        assert!(db.set_pinned(b, false).unwrap());
+        assert!(db.set_pinned(b, true).unwrap());
+        assert!(db.set_pinned(c, true).unwrap());
+        assert_eq!(order(&db), vec![c, a, b, d]);
+
+        assert!(!db.move_pinned(d, 0).unwrap());
+
+        let filter = HistoryFilter {
+            snippets: true,
+            ..Default::default()
+        };
+        assert_eq!(db.get_history(None, None, &filter).unwrap().1, 3);
+    }
+
+    #[test]
+    fn test_history_sort() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let ids: Vec<i64> = ["a", "bbbb", "cc", "ddd"]
+            .iter()
+            .map(|text| insert(&db, text, "seat0", Selection::Clipboard))
+            .collect();
+        let [a, b, c, d] = ids[..] else {
+            unreachable!()
+        };
+        db.touch_entry(c).unwrap();
+        db.touch_entry(c).unwrap();
+        db.touch_entry(a).unwrap();
+        assert!(db.set_pinned(d, true).unwrap());
+
+        let order = |sort: HistorySort, pinned_first: bool| -> Vec<i64> {
+            let filter = HistoryFilter {
+                sort,
+                pinned_first,
+                ..Default::default()
+            };
+            let (entries, _) = db.get_history(None, None, &filter).unwrap();
+            entries.iter().map(|e| e.id).collect()
+        };
+        assert_eq!(order(HistorySort::Recent, false), vec![d, c, b, a]);
+        assert_eq!(order(HistorySort::MostUsed, false), vec![c, a, d, b]);
+        assert_eq!(order(HistorySort::Largest, false), vec![b, d, c, a]);
+        assert_eq!(order(HistorySort::Oldest, false), vec![a, b, c, d]);
+
+        // Asked for, the pinned entry stays first whatever the order
+        assert_eq!(order(HistorySort::Recent, true), vec![d, c, b, a]);
+        assert_eq!(order(HistorySort::MostUsed, true), vec![d, c, a, b]);
+        assert_eq!(order(HistorySort::Largest, true), vec![d, b, c, a]);
+        assert_eq!(order(HistorySort::Oldest, true), vec![d, a, b, c]);
+    }
+
+    #[test]
+    fn test_entry_meta_is_searchable() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let id = insert(&db, "SELECT * FROM users", "seat0", Selection::Clipboard);
+        assert!(db
+            .set_entry_meta(id, Some("All users"), Some("reporting query #infra"))
+            .unwrap());
+
+        let search = |text| {
+            let filter = HistoryFilter {
+                search: Some(text),
+                ..Default::default()
+            };
+            db.get_history(None, None, &filter).unwrap().1
+        };
+        assert_eq!(search("users"), 1);
+        assert_eq!(search("reporting"), 1);
+        assert_eq!(search("infra"), 1);
+
+        let entry = db.get_entry(id).unwrap().unwrap();
+        assert_eq!(entry.display_text(), "All users");
+        assert_eq!(entry.note.as_deref(), Some("reporting query #infra"));
+        assert!(db.get_entry(id + 1).unwrap().is_none());
+
+        // Empty strings clear the fields and drop them from the index
+        assert!(db.set_entry_meta(id, Some(""), None).unwrap());
+        assert_eq!(db.entry_meta(id).unwrap(), Some((None, None)));
+        assert_eq!(search("reporting"), 0);
+    }
+
+    #[test]
+    fn test_delete_disposable_keeps_pinned_and_collected() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        let pinned = insert(&db, "alpha", "seat0", Selection::Clipboard);
+        let collected = insert(&db, "beta", "seat0", Selection::Clipboard);
+        let plain = insert(&db, "gamma", "seat0", Selection::Clipboard);
+        db.set_pinned(pinned, true).unwrap();
+        let collection = db.create_collection("Snippets").unwrap();
+        db.add_to_collection(collection.id, collected).unwrap();
+
+        assert!(!db.delete_disposable(pinned).unwrap());
+        assert!(!db.delete_disposable(collected).unwrap());
+        assert!(db.delete_disposable(plain).unwrap());
+        assert_eq!(db.count_entries().unwrap(), 2);
     }
 }
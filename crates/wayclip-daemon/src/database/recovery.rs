@@ -0,0 +1,489 @@
+//! Corruption detection and salvaging entries from damaged databases.
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use wayclip_common::{ContentType, Origin, Selection};
+
+use super::{Database, NewEntry, Storage};
+use crate::hash::content_hash;
+
+/// Outcome of re-hashing stored content.
+#[derive(Debug, Default, Clone)]
+pub struct IntegrityReport {
+    /// Number of entries checked.
+    pub checked: u64,
+    /// Entries whose content no longer matches `content_hash`.
+    pub mismatched: Vec<i64>,
+    /// Entries whose content could not be read.
+    pub unreadable: Vec<i64>,
+}
+
+/// Outcome of salvaging entries from an old database file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SalvageReport {
+    /// Entries copied into the current database.
+    pub recovered: u64,
+    /// Entries already present in the current database.
+    pub skipped: u64,
+    /// Entries that could not be read.
+    pub failed: u64,
+}
+
+impl Database {
+    /// Open the database at `path`, moving it aside first if SQLite finds
+    /// it corrupt. Any other failure to check it, such as the file being
+    /// locked or unreadable, is returned without touching it.
+    pub fn open_checked(path: PathBuf) -> Result<Self> {
+        let problem = if path.exists() {
+            check_integrity(&path)?
+        } else {
+            None
+        };
+
+        let Some(problem) = problem else {
+            return Self::open_at(path);
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let backup = with_suffix(&path, &format!(".corrupt-{}", timestamp));
+        tracing::error!(
+            "Database failed integrity check ({}), moving it to {:?} and starting fresh",
+            problem,
+            backup
+        );
+
+        std::fs::rename(&path, &backup)?;
+        for journal in ["-wal", "-shm", "-journal"] {
+            let file = with_suffix(&path, journal);
+            if file.exists() {
+                std::fs::rename(&file, with_suffix(&backup, journal))?;
+            }
+        }
+
+        let mut db = Self::open_at(path)?;
+        db.corrupt_backup = Some(backup);
+        Ok(db)
+    }
+
+    /// Check the database file at `path` without changing it, returning the
+    /// problems found.
+    pub fn check_file(path: &Path) -> std::result::Result<(), String> {
+        match check_integrity(path) {
+            Ok(None) => Ok(()),
+            Ok(Some(problem)) => Err(problem),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Warnings about the database to show in the daemon status.
+    pub fn warnings(&self) -> Vec<String> {
+        match &self.corrupt_backup {
+            Some(backup) if backup.exists() => vec![format!(
+                "The database was corrupt and has been reset. Run `wayclip-ctl recover` to salvage entries from {}",
+                backup.display()
+            )],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The corrupt database to recover from when none is given explicitly:
+    /// the one moved aside at startup, or else the newest backup on disk.
+    pub fn default_recovery_source(&self) -> Option<PathBuf> {
+        if let Some(backup) = &self.corrupt_backup {
+            return Some(backup.clone());
+        }
+
+        let path = self.path.as_ref()?;
+        let dir = path.parent()?;
+        let prefix = format!("{}.corrupt-", path.file_name()?.to_string_lossy());
+        std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| n.strip_prefix(&prefix))
+                    .is_some_and(|ts| ts.chars().all(|c| c.is_ascii_digit()))
+            })
+            .max()
+    }
+
+    /// Re-hash the content of every entry and compare it with the stored hash.
+    pub fn verify_content(&self) -> Result<IntegrityReport> {
+        let conn = self.conn.lock().unwrap();
+        let mut report = IntegrityReport::default();
+
+        let entries: Vec<(i64, String)> = conn
+            .prepare("SELECT id, content_hash FROM entries WHERE redacted = 0 ORDER BY id")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        for (id, hash) in entries {
+            report.checked += 1;
+            let content = conn.query_row(
+                "SELECT data FROM content WHERE entry_id = ?1",
+                params![id],
+                |row| row.get::<_, Vec<u8>>(0),
+            );
+            match content {
+                Ok(content) if content_hash(&content) == hash => {}
+                Ok(_) => report.mismatched.push(id),
+                Err(e) => {
+                    tracing::debug!("Could not read content of entry {}: {}", id, e);
+                    report.unreadable.push(id);
+                }
+            }
+        }
+
+        if !report.mismatched.is_empty() || !report.unreadable.is_empty() {
+            tracing::warn!(
+                "Integrity check: {} mismatched and {} unreadable of {} entries",
+                report.mismatched.len(),
+                report.unreadable.len(),
+                report.checked
+            );
+        }
+        Ok(report)
+    }
+
+    /// Copy every readable entry from another database file into this one.
+    ///
+    /// Rows are read one at a time so a damaged page only loses the entries
+    /// stored on it.
+    pub fn salvage_from(&self, path: &Path) -> Result<SalvageReport> {
+        let old = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut report = SalvageReport::default();
+
+        // Collect as many IDs as can be read before hitting damage
+        let mut ids = Vec::new();
+        {
+            let mut stmt = old.prepare("SELECT id FROM entries ORDER BY id")?;
+            let mut rows = stmt.query([])?;
+            loop {
+                match rows.next() {
+                    Ok(Some(row)) => ids.push(row.get::<_, i64>(0)?),
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::warn!("Stopped reading entry list early: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for id in ids {
+            match self.salvage_entry(&old, id) {
+                Ok(true) => report.recovered += 1,
+                Ok(false) => report.skipped += 1,
+                Err(e) => {
+                    tracing::debug!("Could not salvage entry {}: {}", id, e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        tracing::info!(
+            "Salvaged {} entries from {:?} ({} already present, {} unreadable)",
+            report.recovered,
+            path,
+            report.skipped,
+            report.failed
+        );
+        Ok(report)
+    }
+
+    /// Copy one entry, returning `false` if it is already present.
+    fn salvage_entry(&self, old: &Connection, id: i64) -> Result<bool> {
+        // Older databases may lack columns added by later migrations
+        let entry = old.query_row("SELECT * FROM entries WHERE id = ?1", params![id], |row| {
+            Ok(SalvagedEntry {
+                hash: row.get("content_hash")?,
+                content_type: row.get("content_type")?,
+                mime_type: row.get("mime_type")?,
+                preview: row.get::<_, Option<String>>("preview")?.unwrap_or_default(),
+                created_at: row.get("created_at")?,
+                last_used_at: row.get("last_used_at")?,
+                use_count: row.get::<_, Option<i64>>("use_count")?.unwrap_or(1),
+                pinned: row.get::<_, Option<i32>>("pinned")?.unwrap_or(0) != 0,
+                thumbnail: row.get("thumbnail").ok().flatten(),
+                animated: row.get::<_, i32>("animated").unwrap_or(0) != 0,
+                seat: row.get("seat").ok().flatten(),
+                selection: row
+                    .get::<_, String>("selection")
+                    .ok()
+                    .and_then(|name| Selection::from_name(&name))
+                    .unwrap_or_default(),
+                pin_order: row.get("pin_order").unwrap_or(0),
+                title: row.get("title").ok().flatten(),
+                note: row.get("note").ok().flatten(),
+                source_app: row.get("source_app").ok().flatten(),
+                window_title: row.get("window_title").ok().flatten(),
+                workspace: row.get("workspace").ok().flatten(),
+                origin: super::operations::stored_origin(
+                    row.get::<_, Option<String>>("origin")
+                        .ok()
+                        .flatten()
+                        .as_deref(),
+                    row.get("origin_device").ok().flatten(),
+                    row.get("origin_name").ok().flatten(),
+                    row.get::<_, String>("selection")
+                        .ok()
+                        .and_then(|name| Selection::from_name(&name))
+                        .unwrap_or_default(),
+                ),
+                redacted: row.get::<_, i32>("redacted").unwrap_or(0) != 0,
+                uuid: row.get("uuid").ok().flatten(),
+                byte_size: row.get("byte_size")?,
+            })
+        })?;
+
+        if self.find_by_hash(&entry.hash)?.is_some() {
+            return Ok(false);
+        }
+
+        // Hash-only entries never had content to copy
+        let content: Vec<u8> = if entry.redacted {
+            Vec::new()
+        } else {
+            old.query_row(
+                "SELECT data FROM content WHERE entry_id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?
+        };
+
+        let variants: Vec<(String, Vec<u8>)> = old
+            .prepare("SELECT mime_type, data FROM content_variants WHERE entry_id = ?1")
+            .and_then(|mut stmt| {
+                stmt.query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let new_id = self.insert_entry(&NewEntry {
+            hash: &entry.hash,
+            content_type: match entry.content_type.as_str() {
+                "image" => ContentType::Image,
+                "other" => ContentType::Other,
+                _ => ContentType::Text,
+            },
+            mime_type: &entry.mime_type,
+            preview: &entry.preview,
+            content: &content,
+            thumbnail: entry.thumbnail.as_deref(),
+            animated: entry.animated,
+            image_hash: (entry.content_type == "image")
+                .then(|| crate::thumbnail::decode_first_frame(&content, &entry.mime_type).ok())
+                .flatten()
+                .map(|(image, _)| crate::thumbnail::perceptual_hash(&image)),
+            similar_to: None,
+            variants: &variants,
+            seat: entry.seat.as_deref(),
+            selection: entry.selection,
+            source_app: entry.source_app.as_deref(),
+            window_title: entry.window_title.as_deref(),
+            workspace: entry.workspace.as_deref(),
+            origin: &entry.origin,
+            redacted: entry.redacted.then_some(entry.byte_size as u64),
+        })?;
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE entries SET created_at = ?1, last_used_at = ?2, use_count = ?3, pinned = ?4,
+                pin_order = ?5, title = ?6, note = ?7, byte_size = ?8, uuid = COALESCE(?9, uuid)
+             WHERE id = ?10",
+            params![
+                entry.created_at,
+                entry.last_used_at,
+                entry.use_count,
+                entry.pinned as i32,
+                entry.pin_order,
+                entry.title,
+                entry.note,
+                entry.byte_size,
+                entry.uuid,
+                new_id
+            ],
+        )?;
+
+        Ok(true)
+    }
+}
+
+struct SalvagedEntry {
+    hash: String,
+    content_type: String,
+    mime_type: String,
+    preview: String,
+    created_at: i64,
+    last_used_at: i64,
+    use_count: i64,
+    pinned: bool,
+    thumbnail: Option<Vec<u8>>,
+    animated: bool,
+    seat: Option<String>,
+    selection: Selection,
+    pin_order: i64,
+    title: Option<String>,
+    note: Option<String>,
+    source_app: Option<String>,
+    window_title: Option<String>,
+    workspace: Option<String>,
+    origin: Origin,
+    redacted: bool,
+    uuid: Option<String>,
+    byte_size: i64,
+}
+
+/// Run `PRAGMA integrity_check`, returning the reported problems if the
+/// database is corrupt.
+///
+/// Only SQLite saying the file is corrupt or not a database counts as
+/// corruption; errors such as a lock held by another process or a file that
+/// can't be read are returned as errors.
+fn check_integrity(path: &Path) -> rusqlite::Result<Option<String>> {
+    let results: rusqlite::Result<Vec<String>> =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).and_then(|conn| {
+            conn.prepare("PRAGMA integrity_check")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        });
+
+    match results {
+        Ok(results) if results.len() == 1 && results[0] == "ok" => Ok(None),
+        Ok(results) => Ok(Some(results.join("; "))),
+        Err(e) if is_corruption(&e) => Ok(Some(e.to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether SQLite failed because the file is damaged rather than, say,
+/// locked or unreadable.
+fn is_corruption(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+    )
+}
+
+pub(super) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::insert_text;
+    use rusqlite::OptionalExtension;
+
+    fn pinned_by_hash(db: &Database, hash: &str) -> Option<bool> {
+        let conn = db.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT pinned FROM entries WHERE content_hash = ?1",
+            params![hash],
+            |row| row.get::<_, i32>(0),
+        )
+        .optional()
+        .unwrap()
+        .map(|p| p != 0)
+    }
+
+    #[test]
+    fn test_verify_content_reports_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        // `insert` uses the text itself as the hash, which never matches
+        let mismatched = insert_text(&db, "stale hash");
+        let intact = insert_text(&db, &content_hash(b"placeholder"));
+        let unreadable = insert_text(&db, &content_hash(b"also placeholder"));
+
+        let conn = db.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE content SET data = ?1 WHERE entry_id = ?2",
+            params![b"placeholder".to_vec(), intact],
+        )
+        .unwrap();
+        conn.execute(
+            "DELETE FROM content WHERE entry_id = ?1",
+            params![unreadable],
+        )
+        .unwrap();
+        drop(conn);
+
+        let report = db.verify_content().unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.mismatched, vec![mismatched]);
+        assert_eq!(report.unreadable, vec![unreadable]);
+    }
+
+    #[test]
+    fn test_corrupt_database_is_moved_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        std::fs::write(&path, b"definitely not an sqlite database").unwrap();
+
+        let db = Database::open_checked(path.clone()).unwrap();
+        db.migrate().unwrap();
+
+        let backup = db.default_recovery_source().unwrap();
+        assert_ne!(backup, path);
+        assert_eq!(
+            std::fs::read(&backup).unwrap(),
+            b"definitely not an sqlite database"
+        );
+        assert_eq!(db.warnings().len(), 1);
+        assert_eq!(db.count_entries().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unopenable_database_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        // Can't be opened, but isn't corrupt either
+        std::fs::create_dir(&path).unwrap();
+
+        assert!(Database::open_checked(path.clone()).is_err());
+        assert!(path.is_dir());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_salvage_copies_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let old = Database::open_at(dir.path().join("old.db")).unwrap();
+        old.migrate().unwrap();
+        insert_text(&old, "kept");
+        let pinned = insert_text(&old, "pinned");
+        old.set_pinned(pinned, true).unwrap();
+
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+        insert_text(&db, "kept");
+
+        let report = db.salvage_from(&dir.path().join("old.db")).unwrap();
+        assert_eq!((report.recovered, report.skipped, report.failed), (1, 1, 0));
+        assert_eq!(db.count_entries().unwrap(), 2);
+        assert_eq!(pinned_by_hash(&db, "pinned"), Some(true));
+
+        // The UUID comes along, so outside references still work
+        let uuid: String = old
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT uuid FROM entries WHERE id = ?1", [pinned], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(db.find_by_uuid(&uuid).unwrap().is_some());
+    }
+}
@@ -0,0 +1,160 @@
+//! Aggregate queries summarizing what was copied over a period.
+
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use wayclip_common::{AppCount, Report, ReportEntry};
+
+use super::Database;
+
+/// Applications and entries listed in each part of a report, unless asked
+/// otherwise.
+const DEFAULT_LIMIT: u32 = 5;
+
+impl Database {
+    /// Summarize the entries copied or used between `since` and `until`,
+    /// listing at most `limit` applications and entries in each list.
+    pub fn report(&self, since: i64, until: i64, limit: Option<u32>) -> Result<Report> {
+        let conn = self.conn.lock().unwrap();
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+
+        let (captured, captured_bytes) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(byte_size), 0) FROM entries
+             WHERE created_at >= ?1 AND created_at <= ?2",
+            params![since, until],
+            |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT source_app, COUNT(*) AS count FROM entries
+             WHERE created_at >= ?1 AND created_at <= ?2 AND source_app IS NOT NULL
+             GROUP BY source_app
+             ORDER BY count DESC, source_app
+             LIMIT ?3",
+        )?;
+        let top_apps = stmt
+            .query_map(params![since, until, limit], |row| {
+                Ok(AppCount {
+                    app: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let biggest = report_entries(
+            &conn,
+            "created_at >= ?1 AND created_at <= ?2",
+            "byte_size DESC, id DESC",
+            (since, until, limit),
+        )?;
+        let most_reused = report_entries(
+            &conn,
+            "last_used_at >= ?1 AND last_used_at <= ?2 AND use_count > 1",
+            "use_count DESC, last_used_at DESC",
+            (since, until, limit),
+        )?;
+
+        Ok(Report {
+            since,
+            until,
+            captured,
+            captured_bytes,
+            top_apps,
+            biggest,
+            most_reused,
+        })
+    }
+}
+
+/// Entries matching `condition`, in `order`. The condition and the limit
+/// take `since`, `until` and `limit` as `?1`, `?2` and `?3`.
+fn report_entries(
+    conn: &Connection,
+    condition: &str,
+    order: &str,
+    (since, until, limit): (i64, i64, u32),
+) -> Result<Vec<ReportEntry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, preview, byte_size, use_count FROM entries
+         WHERE {condition}
+         ORDER BY {order}
+         LIMIT ?3"
+    ))?;
+    let entries = stmt
+        .query_map(params![since, until, limit], |row| {
+            Ok(ReportEntry {
+                id: row.get(0)?,
+                preview: row.get(1)?,
+                byte_size: row.get::<_, i64>(2)? as u64,
+                use_count: row.get::<_, Option<i64>>(3)?.unwrap_or(1) as u64,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{text_entry, NewEntry, Storage};
+
+    fn insert(db: &Database, text: &str, source_app: Option<&str>) {
+        db.insert_entry(&NewEntry {
+            source_app,
+            ..text_entry(text)
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_report() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        insert(&db, "short", Some("firefox"));
+        insert(&db, "a longer entry", Some("firefox"));
+        insert(&db, "from a terminal", Some("foot"));
+        insert(&db, "unknown source", None);
+        db.touch_by_hash("short").unwrap();
+        db.touch_by_hash("short").unwrap();
+        db.touch_by_hash("from a terminal").unwrap();
+
+        let report = db.report(0, i64::MAX, Some(2)).unwrap();
+        assert_eq!(report.captured, 4);
+        assert_eq!(report.captured_bytes, 5 + 14 + 15 + 14);
+        assert_eq!(
+            report.top_apps,
+            vec![
+                AppCount {
+                    app: "firefox".to_string(),
+                    count: 2
+                },
+                AppCount {
+                    app: "foot".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        let previews = |entries: &[ReportEntry]| {
+            entries
+                .iter()
+                .map(|entry| (entry.preview.clone(), entry.use_count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            previews(&report.biggest),
+            vec![
+                ("from a terminal".to_string(), 2),
+                ("unknown source".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            previews(&report.most_reused),
+            vec![("short".to_string(), 3), ("from a terminal".to_string(), 2)]
+        );
+
+        // A period after every copy has nothing in it
+        let report = db.report(i64::MAX - 1, i64::MAX, None).unwrap();
+        assert_eq!(report.captured, 0);
+        assert!(report.top_apps.is_empty() && report.most_reused.is_empty());
+    }
+}
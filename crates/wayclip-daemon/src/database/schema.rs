@@ -32,27 +32,199 @@ CREATE INDEX IF NOT EXISTS idx_entries_content_hash ON entries(content_hash);
 CREATE INDEX IF NOT EXISTS idx_entries_pinned ON entries(pinned)
 "#;
 
-/// SQL to create FTS table for text search.
-pub const CREATE_FTS_TABLE: &str = r#"
+/// SQL to create the full-text index of previews, titles and notes, and
+/// the triggers keeping it current, with `{tokenizer}` replaced by one of
+/// the tokenizers below. Created after the migrations, and only if SQLite
+/// has FTS5; a migration changing it drops it to have it made anew.
+pub const CREATE_FTS: &str = r#"
 CREATE VIRTUAL TABLE IF NOT EXISTS entries_fts USING fts5(
     preview,
+    title,
+    note,
     content='entries',
-    content_rowid='id'
-)
-"#;
-
-/// SQL to create FTS triggers.
-pub const CREATE_FTS_TRIGGERS: &str = r#"
+    content_rowid='id',
+    tokenize='{tokenizer}'
+);
 CREATE TRIGGER IF NOT EXISTS entries_fts_insert AFTER INSERT ON entries BEGIN
-    INSERT INTO entries_fts(rowid, preview) VALUES (new.id, new.preview);
+    INSERT INTO entries_fts(rowid, preview, title, note)
+    VALUES (new.id, new.preview, new.title, new.note);
 END;
-
 CREATE TRIGGER IF NOT EXISTS entries_fts_delete AFTER DELETE ON entries BEGIN
-    INSERT INTO entries_fts(entries_fts, rowid, preview) VALUES('delete', old.id, old.preview);
+    INSERT INTO entries_fts(entries_fts, rowid, preview, title, note)
+    VALUES ('delete', old.id, old.preview, old.title, old.note);
 END;
-
-CREATE TRIGGER IF NOT EXISTS entries_fts_update AFTER UPDATE ON entries BEGIN
-    INSERT INTO entries_fts(entries_fts, rowid, preview) VALUES('delete', old.id, old.preview);
-    INSERT INTO entries_fts(rowid, preview) VALUES (new.id, new.preview);
+CREATE TRIGGER IF NOT EXISTS entries_fts_update AFTER UPDATE OF preview, title, note ON entries BEGIN
+    INSERT INTO entries_fts(entries_fts, rowid, preview, title, note)
+    VALUES ('delete', old.id, old.preview, old.title, old.note);
+    INSERT INTO entries_fts(rowid, preview, title, note)
+    VALUES (new.id, new.preview, new.title, new.note);
 END
 "#;
+
+/// Tokenizer splitting text into words, matched whatever their case and
+/// diacritics.
+pub const FTS_WORDS: &str = "unicode61 remove_diacritics 2";
+
+/// Tokenizer splitting text into three-character sequences, matched
+/// whatever their case and diacritics.
+pub const FTS_TRIGRAM: &str = "trigram remove_diacritics 1";
+
+/// SQL to drop the full-text index and its triggers.
+pub const DROP_FTS: &str = r#"
+DROP TRIGGER IF EXISTS entries_fts_insert;
+DROP TRIGGER IF EXISTS entries_fts_delete;
+DROP TRIGGER IF EXISTS entries_fts_update;
+DROP TABLE IF EXISTS entries_fts
+"#;
+
+/// Incremental migrations applied on top of the base tables.
+///
+/// Entry `n` upgrades a database from `PRAGMA user_version = n` to `n + 1`.
+/// Never edit an existing migration; append a new one instead.
+pub const MIGRATIONS: &[&str] = &[
+    // 1: image thumbnails and animation flag
+    r#"
+ALTER TABLE entries ADD COLUMN thumbnail BLOB;
+ALTER TABLE entries ADD COLUMN animated INTEGER NOT NULL DEFAULT 0
+"#,
+    // 2: alternative representations offered alongside the main content
+    r#"
+CREATE TABLE IF NOT EXISTS content_variants (
+    entry_id INTEGER NOT NULL,
+    mime_type TEXT NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (entry_id, mime_type),
+    FOREIGN KEY (entry_id) REFERENCES entries(id) ON DELETE CASCADE
+)
+"#,
+    // 3: seat and selection the entry was captured from
+    r#"
+ALTER TABLE entries ADD COLUMN seat TEXT;
+ALTER TABLE entries ADD COLUMN selection TEXT NOT NULL DEFAULT 'clipboard';
+CREATE INDEX IF NOT EXISTS idx_entries_selection ON entries(selection)
+"#,
+    // 4: named, ordered collections of entries
+    r#"
+CREATE TABLE IF NOT EXISTS collections (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    position INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS collection_entries (
+    collection_id INTEGER NOT NULL,
+    entry_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, entry_id),
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
+    FOREIGN KEY (entry_id) REFERENCES entries(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS idx_collection_entries_entry ON collection_entries(entry_id)
+"#,
+    // 5: manual ordering of pinned entries, seeded newest first
+    r#"
+ALTER TABLE entries ADD COLUMN pin_order INTEGER NOT NULL DEFAULT 0;
+UPDATE entries SET pin_order = (
+    SELECT COUNT(*) FROM entries p
+    WHERE p.pinned = 1
+      AND (p.created_at > entries.created_at
+           OR (p.created_at = entries.created_at AND p.id > entries.id))
+) WHERE pinned = 1
+"#,
+    // 6: user titles and notes, searchable alongside the preview once
+    // `CREATE_FTS` indexes them too
+    r#"
+ALTER TABLE entries ADD COLUMN title TEXT;
+ALTER TABLE entries ADD COLUMN note TEXT;
+DROP TRIGGER IF EXISTS entries_fts_insert;
+DROP TRIGGER IF EXISTS entries_fts_delete;
+DROP TRIGGER IF EXISTS entries_fts_update;
+DROP TABLE IF EXISTS entries_fts
+"#,
+    // 7: source application and hash-only entries without stored content
+    r#"
+ALTER TABLE entries ADD COLUMN source_app TEXT;
+ALTER TABLE entries ADD COLUMN redacted INTEGER NOT NULL DEFAULT 0
+"#,
+    // 8: which IPC peer read which entry's content, when audit_log is on
+    r#"
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp INTEGER NOT NULL,
+    pid INTEGER,
+    uid INTEGER NOT NULL,
+    process TEXT,
+    request TEXT NOT NULL,
+    entry_id INTEGER NOT NULL
+)
+"#,
+    // 9: small daemon settings, such as the lock passphrase hash
+    r#"
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+)
+"#,
+    // 10: perceptual hashes of images, and which earlier image one resembles
+    r#"
+ALTER TABLE entries ADD COLUMN image_hash INTEGER;
+ALTER TABLE entries ADD COLUMN similar_to INTEGER REFERENCES entries(id) ON DELETE SET NULL
+"#,
+    // 11: UUIDs that stay with an entry, unlike row IDs; existing entries
+    // get random version 4 UUIDs
+    r#"
+ALTER TABLE entries ADD COLUMN uuid TEXT;
+UPDATE entries SET uuid = lower(
+    hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' ||
+    substr(hex(randomblob(2)), 2) || '-' ||
+    substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-' ||
+    hex(randomblob(6))
+);
+CREATE UNIQUE INDEX idx_entries_uuid ON entries(uuid)
+"#,
+    // 12: companion devices paired for LAN sync, with a hash of their token
+    r#"
+CREATE TABLE IF NOT EXISTS sync_devices (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL,
+    can_push INTEGER NOT NULL DEFAULT 1,
+    can_pull INTEGER NOT NULL DEFAULT 0,
+    paired_at INTEGER NOT NULL,
+    last_seen_at INTEGER
+)
+"#,
+    // 13: remote source that pushed an entry, when that source marks its
+    // entries
+    r#"
+ALTER TABLE entries ADD COLUMN origin TEXT
+"#,
+    // 14: where every entry came from: `origin` becomes its kind, with the
+    // device for synced ones; entries from before go by their selection
+    r#"
+ALTER TABLE entries ADD COLUMN origin_device INTEGER;
+ALTER TABLE entries ADD COLUMN origin_name TEXT;
+UPDATE entries SET
+    origin_name = origin,
+    origin_device = (SELECT id FROM sync_devices d WHERE d.name = entries.origin),
+    origin = 'synced'
+WHERE origin IS NOT NULL AND origin != 'HTTP';
+UPDATE entries SET origin = 'api' WHERE origin = 'HTTP';
+UPDATE entries SET origin = CASE selection WHEN 'primary' THEN 'primary' ELSE 'local' END
+WHERE origin IS NULL
+"#,
+    // 15: the window and workspace content was copied from
+    r#"
+ALTER TABLE entries ADD COLUMN window_title TEXT;
+ALTER TABLE entries ADD COLUMN workspace TEXT
+"#,
+    // 16: previews, titles and notes in NFC, and a full-text index that
+    // ignores all diacritics, rebuilt after the migrations
+    r#"
+DROP TRIGGER IF EXISTS entries_fts_insert;
+DROP TRIGGER IF EXISTS entries_fts_delete;
+DROP TRIGGER IF EXISTS entries_fts_update;
+DROP TABLE IF EXISTS entries_fts;
+UPDATE entries SET preview = nfc(preview), title = nfc(title), note = nfc(note)
+"#,
+];
@@ -0,0 +1,323 @@
+//! Matching search text against entries: through the full-text index when
+//! SQLite has FTS5, and with LIKE when it doesn't.
+
+use std::sync::atomic::Ordering;
+
+use anyhow::Result;
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use wayclip_common::SearchBackend;
+
+use super::{schema, Database};
+
+impl Database {
+    /// Index trigrams rather than words from the next `migrate` on,
+    /// rebuilding the index if it was made the other way.
+    pub fn set_trigram_index(&mut self, trigram: bool) {
+        self.trigram = trigram;
+    }
+
+    /// How search text is matched, as found by `migrate`.
+    pub fn search_backend(&self) -> SearchBackend {
+        match (self.fts.load(Ordering::Relaxed), self.trigram) {
+            (true, false) => SearchBackend::FullText,
+            (true, true) => SearchBackend::Trigram,
+            (false, _) => SearchBackend::Substring,
+        }
+    }
+}
+
+/// Replace SQLite's `lower()`, which only lowers ASCII letters, with one
+/// lowering all of Unicode, add `nfc()` and `fold()` for normalizing and
+/// matching text as [`normalize`] and [`fold`] do, and give `REGEXP` the
+/// `regexp()` it calls.
+pub(super) fn register_functions(conn: &Connection) -> Result<()> {
+    let lower: fn(&str) -> String = str::to_lowercase;
+    for (name, function) in [("lower", lower), ("nfc", normalize), ("fold", fold)] {
+        conn.create_scalar_function(
+            name,
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let text: Option<String> = ctx.get(0)?;
+                Ok(text.map(|text| function(&text)))
+            },
+        )?;
+    }
+
+    // `text REGEXP pattern` calls regexp(pattern, text); the pattern is
+    // compiled once per query
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let regex = ctx.get_or_create_aux(0, |pattern| {
+                Regex::new(pattern.as_str()?).map_err(anyhow::Error::from)
+            })?;
+            let text: Option<String> = ctx.get(1)?;
+            Ok(text.is_some_and(|text| regex.is_match(&text)))
+        },
+    )?;
+    Ok(())
+}
+
+/// `text` in Unicode NFC, so the same characters copied from sources that
+/// compose them differently are stored and searched alike.
+pub(super) fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// `text` lowered and without diacritics, for substring matching that
+/// ignores both, as the full-text index does.
+fn fold(text: &str) -> String {
+    text.to_lowercase()
+        .nfd()
+        .filter(|&c| !is_combining_mark(c))
+        .nfc()
+        .collect()
+}
+
+/// Create the full-text index of words or of trigrams and the triggers
+/// keeping it current, filling the index if it is new. An index made the
+/// other way is made anew. Returns whether SQLite could create it.
+pub(super) fn create_fts(conn: &Connection, trigram: bool) -> Result<bool> {
+    let tokenizer = match trigram {
+        true => schema::FTS_TRIGRAM,
+        false => schema::FTS_WORDS,
+    };
+    let sql: Option<String> = conn
+        .query_row(
+            "SELECT sql FROM sqlite_master WHERE name = 'entries_fts'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let existed = match sql {
+        Some(sql) if !sql.contains(tokenizer) => {
+            conn.execute_batch(schema::DROP_FTS)?;
+            false
+        }
+        Some(_) => true,
+        None => false,
+    };
+    if let Err(e) = conn.execute_batch(&schema::CREATE_FTS.replace("{tokenizer}", tokenizer)) {
+        tracing::warn!(
+            "Full-text search is unavailable, matching substrings instead: {}",
+            e
+        );
+        return Ok(false);
+    }
+    if !existed {
+        conn.execute_batch("INSERT INTO entries_fts(entries_fts) VALUES ('rebuild')")?;
+    }
+    Ok(true)
+}
+
+/// The condition on `entries e` matching `search`, with its values. A
+/// search written as `/pattern/` is a regular expression.
+pub(super) fn condition(search: &str, backend: SearchBackend) -> (String, Vec<Value>) {
+    let search = normalize(search);
+    if let Some(pattern) = regex_pattern(&search) {
+        return (
+            "(e.preview REGEXP ? OR e.title REGEXP ? OR e.note REGEXP ?)".to_string(),
+            vec![Value::Text(pattern.to_string()); 3],
+        );
+    }
+    let words: Vec<&str> = search.split_whitespace().collect();
+    if words.is_empty() {
+        return ("1".to_string(), Vec::new());
+    }
+
+    match backend {
+        SearchBackend::FullText => {
+            let query = format!("{}*", quote(&words));
+            (FTS_MATCH.to_string(), vec![Value::Text(query)])
+        }
+        SearchBackend::Trigram => {
+            // Trigrams can't match words shorter than three characters
+            let (long, short): (Vec<&str>, Vec<&str>) =
+                words.iter().partition(|word| word.chars().count() >= 3);
+            let (mut conditions, mut values) = like(&short);
+            if !long.is_empty() {
+                conditions.push(FTS_MATCH);
+                values.push(Value::Text(quote(&long)));
+            }
+            (conditions.join(" AND "), values)
+        }
+        SearchBackend::Substring => {
+            let (conditions, values) = like(&words);
+            (conditions.join(" AND "), values)
+        }
+    }
+}
+
+/// Condition on `entries e` matching an FTS5 query.
+const FTS_MATCH: &str = "e.id IN (SELECT rowid FROM entries_fts WHERE entries_fts MATCH ?)";
+
+/// The regular expression in a search written as `/pattern/`.
+fn regex_pattern(search: &str) -> Option<&str> {
+    let pattern = search.trim().strip_prefix('/')?.strip_suffix('/')?;
+    (!pattern.is_empty()).then_some(pattern)
+}
+
+/// `words` as an FTS5 query of quoted phrases, so punctuation such as the
+/// dot in "example.com" is part of the text searched for instead of query
+/// syntax.
+fn quote(words: &[&str]) -> String {
+    let quoted: Vec<String> = words
+        .iter()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    quoted.join(" ")
+}
+
+/// Conditions matching each of `words` as a substring, with their values.
+fn like(words: &[&str]) -> (Vec<&'static str>, Vec<Value>) {
+    // LIKE alone ignores the case of ASCII letters only, and no diacritics
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    for word in words {
+        conditions.push(
+            "(fold(e.preview) LIKE ? ESCAPE '\\' OR fold(e.title) LIKE ? ESCAPE '\\' \
+             OR fold(e.note) LIKE ? ESCAPE '\\')",
+        );
+        let pattern = format!("%{}%", escape_like(&fold(word)));
+        values.extend(std::iter::repeat_n(Value::Text(pattern), 3));
+    }
+    (conditions, values)
+}
+
+/// `text` with LIKE's wildcards escaped by backslashes.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{insert_text, HistoryFilter, Storage};
+
+    fn search(db: &Database, text: &str) -> Vec<i64> {
+        let filter = HistoryFilter {
+            search: Some(text),
+            ..Default::default()
+        };
+        let (entries, _) = db.get_history(None, None, &filter).unwrap();
+        entries.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn test_search_backends() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        assert_eq!(db.search_backend(), SearchBackend::FullText);
+
+        let site = insert_text(&db, "see https://example.com/docs");
+        let percent = insert_text(&db, "100% done");
+        insert_text(&db, "something else");
+
+        assert_eq!(search(&db, "example.com"), vec![site]);
+        assert_eq!(search(&db, "exam"), vec![site]);
+        assert_eq!(search(&db, "\"docs"), vec![site]);
+
+        // Without the index, the same searches match substrings
+        db.fts.store(false, Ordering::Relaxed);
+        assert_eq!(db.search_backend(), SearchBackend::Substring);
+        assert_eq!(search(&db, "EXAMPLE.com"), vec![site]);
+        assert_eq!(search(&db, "ample docs"), vec![site]);
+        assert_eq!(search(&db, "100%"), vec![percent]);
+        assert!(search(&db, "0_").is_empty());
+    }
+
+    #[test]
+    fn test_substring_search_ignores_unicode_case() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        db.fts.store(false, Ordering::Relaxed);
+
+        let german = insert_text(&db, "Grüße aus MÜNCHEN");
+        let greek = insert_text(&db, "ΟΔΥΣΣΕΥΣ");
+        let japanese = insert_text(&db, "東京タワーの写真");
+
+        assert_eq!(search(&db, "münchen"), vec![german]);
+        assert_eq!(search(&db, "grüße"), vec![german]);
+        assert_eq!(search(&db, "οδυσ"), vec![greek]);
+        assert_eq!(search(&db, "タワー"), vec![japanese]);
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        let ticket = insert_text(&db, "Fixed in PROJ-1234, see review");
+        let address = insert_text(&db, "ssh admin@192.168.1.20");
+        insert_text(&db, "nothing to see");
+
+        assert_eq!(search(&db, r"/\b[A-Z]+-\d+\b/"), vec![ticket]);
+        assert_eq!(search(&db, r"/(\d{1,3}\.){3}\d{1,3}/"), vec![address]);
+        // Without both slashes it is an ordinary search, ignoring case
+        assert_eq!(search(&db, "/proj"), vec![ticket]);
+
+        let filter = HistoryFilter {
+            search: Some("/[unclosed/"),
+            ..Default::default()
+        };
+        assert!(db.get_history(None, None, &filter).is_err());
+    }
+
+    #[test]
+    fn test_trigram_search() {
+        let mut db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        let japanese = insert_text(&db, "東京タワーの写真");
+        let site = insert_text(&db, "see https://example.com/docs");
+        assert!(search(&db, "ample").is_empty());
+
+        // Switching tokenizers rebuilds the index from the entries
+        db.set_trigram_index(true);
+        db.migrate().unwrap();
+        assert_eq!(db.search_backend(), SearchBackend::Trigram);
+        let korean = insert_text(&db, "서울특별시 지하철");
+
+        assert_eq!(search(&db, "ample"), vec![site]);
+        assert_eq!(search(&db, "タワーの"), vec![japanese]);
+        assert_eq!(search(&db, "특별시"), vec![korean]);
+        // Words too short for trigrams are matched as substrings
+        assert_eq!(search(&db, "東京"), vec![japanese]);
+        assert_eq!(search(&db, "EXAMPLE co"), vec![site]);
+        assert!(search(&db, "東京 docs").is_empty());
+    }
+
+    #[test]
+    fn test_search_ignores_composition_and_diacritics() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+
+        // "café" with the accent as a separate combining character
+        let decomposed = insert_text(&db, "cafe\u{301} au lait");
+        let naive = insert_text(&db, "naïve");
+        assert_eq!(
+            db.get_entry(decomposed).unwrap().unwrap().preview,
+            "caf\u{e9} au lait"
+        );
+
+        for fts in [true, false] {
+            db.fts.store(fts, Ordering::Relaxed);
+            assert_eq!(search(&db, "caf\u{e9}"), vec![decomposed]);
+            assert_eq!(search(&db, "cafe au"), vec![decomposed]);
+            assert_eq!(search(&db, "NAIVE"), vec![naive]);
+        }
+    }
+}
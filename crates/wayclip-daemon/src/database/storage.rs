@@ -0,0 +1,77 @@
+//! The storage interface for capturing, reading and cleaning up the history.
+//!
+//! `Database` implements it on SQLite, either on disk or in memory, and is
+//! the only implementation. Capture, the clipboard ring, the paste queue and
+//! the content helpers are written against the trait. The IPC handler takes
+//! `Database` itself, as most of what it serves (collections, the archive,
+//! the audit log, paired devices, settings and recovery) is outside the
+//! trait; its tests run on an in-memory database instead of a fake.
+
+use anyhow::Result;
+use wayclip_common::{HistoryEntry, PruneCriteria};
+
+use super::{EntryLimits, HistoryFilter, NewEntry};
+
+/// Where clipboard history entries and their content are kept.
+pub trait Storage {
+    /// Insert a new clipboard entry and return its ID.
+    fn insert_entry(&self, entry: &NewEntry) -> Result<i64>;
+
+    /// Find an entry by its content hash.
+    fn find_by_hash(&self, hash: &str) -> Result<Option<i64>>;
+
+    /// Find an entry by its UUID.
+    fn find_by_uuid(&self, uuid: &str) -> Result<Option<i64>>;
+
+    /// Mark the entry with this content hash as just used.
+    fn touch_by_hash(&self, hash: &str) -> Result<()>;
+
+    /// Mark an entry as just used.
+    fn touch_entry(&self, id: i64) -> Result<()>;
+
+    /// Find the image whose perceptual hash is closest to `image_hash`, if
+    /// any is close enough to count as similar.
+    fn find_similar_image(&self, image_hash: u64) -> Result<Option<i64>>;
+
+    /// Get clipboard history, pinned entries first and then newest first,
+    /// along with the total number of matching entries.
+    fn get_history(
+        &self,
+        limit: Option<u32>,
+        offset: Option<u32>,
+        filter: &HistoryFilter,
+    ) -> Result<(Vec<HistoryEntry>, u64)>;
+
+    /// Count total entries.
+    fn count_entries(&self) -> Result<u64>;
+
+    /// Get the MIME type and content of an entry.
+    fn get_content(&self, id: i64) -> Result<Option<(String, Vec<u8>)>>;
+
+    /// Get the alternative representations stored for an entry.
+    fn get_variants(&self, id: i64) -> Result<Vec<(String, Vec<u8>)>>;
+
+    /// Bytes used by the stored history.
+    fn database_size(&self) -> Result<u64>;
+
+    /// Delete an entry, returning `false` if it doesn't exist.
+    fn delete_entry(&self, id: i64) -> Result<bool>;
+
+    /// Delete an entry unless it is pinned or in a collection, returning
+    /// `false` if it was kept or doesn't exist.
+    fn delete_disposable(&self, id: i64) -> Result<bool>;
+
+    /// Delete all entries that are neither pinned nor in a collection.
+    fn clear_unpinned(&self) -> Result<()>;
+
+    /// Delete every entry, pinned or not.
+    fn clear_all(&self) -> Result<()>;
+
+    /// Delete the disposable entries matching `criteria` and return how many
+    /// there were. A dry run only counts them.
+    fn prune(&self, criteria: &PruneCriteria, dry_run: bool) -> Result<u64>;
+
+    /// Delete the least recently used disposable entries beyond each of
+    /// `limits`.
+    fn cleanup(&self, limits: &EntryLimits) -> Result<()>;
+}
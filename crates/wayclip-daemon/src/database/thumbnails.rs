@@ -0,0 +1,158 @@
+//! On-disk cache of image thumbnails, so history responses can point at a
+//! file instead of carrying every thumbnail inline.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use rusqlite::Connection;
+
+use super::Database;
+
+/// Directory of `<hash>.png` thumbnail files.
+#[derive(Debug, Clone)]
+pub(super) struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    /// Make sure the thumbnail for `hash` is on disk and return its path.
+    ///
+    /// Returns `None` if the file can't be written, in which case the
+    /// thumbnail is sent inline as before.
+    pub(super) fn ensure(&self, hash: &str, png: &[u8]) -> Option<PathBuf> {
+        // Hashes are hex, but don't let anything else escape the directory
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+
+        let path = self.dir.join(format!("{}.png", hash));
+        if path.exists() {
+            return Some(path);
+        }
+
+        // Write to a temporary file first so readers never see half a PNG
+        let tmp = self.dir.join(format!("{}.png.tmp", hash));
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(&tmp, png))
+            .and_then(|()| std::fs::rename(&tmp, &path));
+        match written {
+            Ok(()) => Some(path),
+            Err(e) => {
+                tracing::warn!("Failed to cache thumbnail {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Delete cached thumbnails of entries that no longer exist.
+    pub(super) fn prune(&self, conn: &Connection) -> Result<()> {
+        let Ok(files) = std::fs::read_dir(&self.dir) else {
+            return Ok(());
+        };
+
+        let mut stmt =
+            conn.prepare("SELECT content_hash FROM entries WHERE thumbnail IS NOT NULL")?;
+        let keep = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<HashSet<String>>>()?;
+
+        for file in files.flatten() {
+            let path = file.path();
+            let stale = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".png"))
+                .is_some_and(|hash| !keep.contains(hash));
+            if stale {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::debug!("Failed to remove {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write any thumbnails that are missing from the directory, e.g.
+    /// after it was cleaned out.
+    pub(super) fn restore(&self, conn: &Connection) -> Result<()> {
+        let mut stmt = conn
+            .prepare("SELECT content_hash, thumbnail FROM entries WHERE thumbnail IS NOT NULL")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let hash: String = row.get(0)?;
+            let png: Vec<u8> = row.get(1)?;
+            self.ensure(&hash, &png);
+        }
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Cache thumbnails as files in `dir`.
+    pub fn set_thumbnail_dir(&mut self, dir: PathBuf) {
+        self.thumbnails = Some(ThumbnailCache { dir });
+    }
+
+    /// Drop cached thumbnails after entries were deleted.
+    pub(super) fn prune_thumbnails(&self, conn: &Connection) {
+        if let Some(thumbnails) = &self.thumbnails {
+            if let Err(e) = thumbnails.prune(conn) {
+                tracing::warn!("Failed to prune cached thumbnails: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{text_entry, HistoryFilter, NewEntry, Storage};
+    use wayclip_common::ContentType;
+
+    use super::*;
+
+    fn insert_image(db: &Database, hash: &str) -> i64 {
+        db.insert_entry(&NewEntry {
+            content_type: ContentType::Image,
+            mime_type: "image/png",
+            preview: "copied image",
+            thumbnail: Some(b"png"),
+            ..text_entry(hash)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_thumbnails_are_cached_and_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let thumbs = dir.path().join("thumbs");
+        let mut db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.set_thumbnail_dir(thumbs.clone());
+        db.migrate().unwrap();
+
+        let first = insert_image(&db, "aaaa");
+        insert_image(&db, "bbbb");
+        assert!(thumbs.join("aaaa.png").exists());
+
+        // History points at the file instead of sending the PNG inline
+        let (entries, _) = db
+            .get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert!(entries.iter().all(|e| e.thumbnail.is_none()));
+        assert!(entries.iter().all(|e| e
+            .thumbnail_path
+            .as_deref()
+            .is_some_and(|p| p.ends_with(".png"))));
+
+        // A wiped cache is refilled from the database
+        std::fs::remove_file(thumbs.join("bbbb.png")).unwrap();
+        db.get_history(None, None, &HistoryFilter::default())
+            .unwrap();
+        assert!(thumbs.join("bbbb.png").exists());
+
+        db.delete_entry(first).unwrap();
+        assert!(!thumbs.join("aaaa.png").exists());
+        assert!(thumbs.join("bbbb.png").exists());
+    }
+}
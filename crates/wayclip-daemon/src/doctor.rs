@@ -0,0 +1,133 @@
+//! `wayclip-daemon --doctor`: checking what the daemon needs, with advice
+//! for each problem found.
+
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use wayclip_common::SearchBackend;
+
+use crate::config::Config;
+use crate::database::Database;
+use crate::{clipboard, policy, scheduler};
+
+/// Where the daemon would find its configuration, history and socket.
+pub struct Paths {
+    /// Configuration file given on the command line, if any.
+    pub config: Option<PathBuf>,
+    pub db: PathBuf,
+    pub socket: PathBuf,
+}
+
+/// Run every check and print what each found. Returns whether all passed.
+pub fn run(paths: &Paths) -> bool {
+    let checks: [(&str, Result<String>); 6] = [
+        ("Configuration", check_config(paths.config.as_ref())),
+        ("Wayland data-control", check_wayland()),
+        ("Socket", check_socket(&paths.socket)),
+        ("Database", check_database(&paths.db)),
+        ("Full-text search", check_fts()),
+        ("wl-clipboard", check_wl_clipboard()),
+    ];
+
+    let mut ok = true;
+    for (name, result) in checks {
+        match result {
+            Ok(detail) => println!("[ ok ] {}: {}", name, detail),
+            Err(e) => {
+                ok = false;
+                println!("[FAIL] {}: {:#}", name, e);
+            }
+        }
+    }
+    ok
+}
+
+fn check_config(path: Option<&PathBuf>) -> Result<String> {
+    let (config, path) = match path {
+        Some(path) => (Config::load_from(path)?, path.clone()),
+        None => (Config::load()?, wayclip_common::config_path()),
+    };
+    policy::CapturePolicy::from_config(&config.clipboard, &Default::default())?;
+    scheduler::parse_time(&config.maintenance.at)?;
+
+    Ok(match path.exists() {
+        true => format!("{} is valid", path.display()),
+        false => format!("No {}, using the defaults", path.display()),
+    })
+}
+
+fn check_wayland() -> Result<String> {
+    if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err(anyhow!(
+            "WAYLAND_DISPLAY is not set. Start the daemon from inside the Wayland session, \
+             e.g. from the compositor's autostart"
+        ));
+    }
+    clipboard::check_data_control().context(
+        "The clipboard can't be watched. Compositors based on wlroots, KDE Plasma and \
+         others offer wlr-data-control; GNOME does not",
+    )?;
+    Ok("Supported by the compositor".to_string())
+}
+
+fn check_socket(socket: &Path) -> Result<String> {
+    if UnixStream::connect(socket).is_ok() {
+        return Ok(format!("A daemon is listening on {}", socket.display()));
+    }
+
+    let dir = socket.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Can't create {}. Check XDG_RUNTIME_DIR", dir.display()))?;
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .with_context(|| format!("Can't write to {}. Check its owner", dir.display()))?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(format!("{} can be created", socket.display()))
+}
+
+fn check_database(db: &Path) -> Result<String> {
+    if !db.exists() {
+        return Ok(format!(
+            "No history yet; it will be created at {}",
+            db.display()
+        ));
+    }
+    Database::check_file(db).map_err(|problem| {
+        anyhow!(
+            "{} is damaged ({}). The daemon moves it aside and starts a new one; \
+             `wayclip-ctl recover` salvages entries from it",
+            db.display(),
+            problem
+        )
+    })?;
+    Ok(format!("{} passed the integrity check", db.display()))
+}
+
+fn check_fts() -> Result<String> {
+    // Migrating goes on without the index when SQLite can't create it
+    let db = Database::open_in_memory()?;
+    db.migrate()?;
+    match db.search_backend() {
+        SearchBackend::FullText | SearchBackend::Trigram => Ok("Available".to_string()),
+        SearchBackend::Substring => Err(anyhow!(
+            "SQLite has no FTS5 support, so searches match substrings instead, more slowly. \
+             Build wayclip with its bundled SQLite"
+        )),
+    }
+}
+
+fn check_wl_clipboard() -> Result<String> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .map(|dir| dir.join("wl-copy"))
+        .find(|candidate| candidate.is_file())
+        .map(|found| format!("Found {}", found.display()))
+        .ok_or_else(|| {
+            anyhow!(
+                "wl-copy is not on PATH, so entries can't be copied back to the clipboard. \
+                 Install wl-clipboard"
+            )
+        })
+}
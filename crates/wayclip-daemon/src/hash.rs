@@ -0,0 +1,77 @@
+//! Content hashing used for deduplication and integrity checks, and random
+//! tokens for clients that connect over the network.
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+/// How much of a stream `read_hashed` reads at a time.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hex-encoded SHA-256 of entry content, as stored in `content_hash`.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Read `reader` to the end, hashing the content as it arrives, and return
+/// it along with its `content_hash`.
+pub fn read_hashed(mut reader: impl Read) -> io::Result<(Vec<u8>, String)> {
+    let mut hasher = Sha256::new();
+    let mut content = Vec::new();
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&chunk[..read]);
+        content.extend_from_slice(&chunk[..read]);
+    }
+    Ok((content, format!("{:x}", hasher.finalize())))
+}
+
+/// Text with CRLF line endings made LF and trailing whitespace removed, so
+/// the same text copied from different applications compares equal.
+/// Content that isn't UTF-8 is returned as it is.
+pub fn normalize_text(content: &[u8]) -> Cow<'_, [u8]> {
+    let Ok(text) = std::str::from_utf8(content) else {
+        return Cow::Borrowed(content);
+    };
+    let text = text.trim_end();
+    match text.contains("\r\n") {
+        true => Cow::Owned(text.replace("\r\n", "\n").into_bytes()),
+        false => Cow::Borrowed(text.as_bytes()),
+    }
+}
+
+/// A random, URL-safe token with 256 bits of entropy.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hashed() {
+        let content: Vec<u8> = (0..READ_CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let (read, hash) = read_hashed(content.as_slice()).unwrap();
+        assert_eq!(read, content);
+        assert_eq!(hash, content_hash(&content));
+    }
+
+    #[test]
+    fn test_normalize_text() {
+        assert_eq!(&*normalize_text(b"a\r\nb \r\n\n"), b"a\nb");
+        assert_eq!(&*normalize_text(b"  indented\t"), b"  indented");
+        assert_eq!(&*normalize_text(b"\xff\r\n"), b"\xff\r\n");
+    }
+}
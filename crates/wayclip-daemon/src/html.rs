@@ -0,0 +1,152 @@
+//! Plain-text extraction from HTML clipboard content.
+
+/// Elements whose text content is never shown.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style", "head", "title"];
+
+/// Elements that start a new line in the extracted text.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "br",
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "pre",
+    "blockquote",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "table",
+    "ul",
+    "ol",
+];
+
+/// Strip tags from an HTML fragment, keeping line structure.
+///
+/// This is not a full HTML parser; it is meant for the fragments browsers
+/// and office suites put on the clipboard.
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut hidden: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if hidden.is_none() {
+            text.push_str(&decode_entities(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        // Comments may contain '>' so skip them as a whole
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match &hidden {
+            Some(element) if closing && *element == name => {
+                hidden = None;
+            }
+            Some(_) => {}
+            None if !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) => {
+                hidden = Some(name);
+            }
+            None if BLOCK_ELEMENTS.contains(&name.as_str()) => {
+                text.push('\n');
+            }
+            None => {}
+        }
+    }
+
+    if hidden.is_none() {
+        text.push_str(&decode_entities(rest));
+    }
+
+    // Collapse the source formatting whitespace while keeping block breaks
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Decode the character references commonly found in clipboard HTML.
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_tags() {
+        let html = r#"<meta charset="utf-8"><style>p { color: red; }</style>
+            <p>Hello <b>world</b> &amp; friends</p><!-- a > b --><ul><li>one</li><li>two&#x21;</li></ul>"#;
+        assert_eq!(strip_tags(html), "Hello world & friends\none\ntwo!");
+    }
+
+    #[test]
+    fn test_unknown_entities_are_kept() {
+        assert_eq!(strip_tags("fish &chips; &bogus;"), "fish &chips; &bogus;");
+    }
+}
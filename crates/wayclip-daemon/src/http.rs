@@ -0,0 +1,229 @@
+//! Local HTTP gateway: the IPC protocol over HTTP on 127.0.0.1, for browser
+//! extensions and tools that can't use a Unix socket.
+//!
+//! `POST /request` takes a request in the same JSON as the socket and
+//! answers with its response. `GET /events` streams what `subscribe` would,
+//! as server-sent events. Both need `Authorization: Bearer <token>`.
+
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::Router;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{debug, info};
+use wayclip_common::{decode_request, encode_response, DaemonEvent, ErrorCode, Request, Response};
+
+use crate::config::HttpConfig;
+use crate::hash::random_token;
+use crate::ipc::{IpcEvent, Peer, Source};
+
+/// Room for the JSON around base64 content in a request body.
+const BODY_OVERHEAD: u64 = 64 * 1024;
+
+#[derive(Clone)]
+struct Gateway {
+    token: Arc<str>,
+    event_tx: mpsc::Sender<IpcEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+}
+
+impl Gateway {
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| same_token(token.as_bytes(), self.token.as_bytes()))
+    }
+
+    /// Hand a request to the main loop, like the socket does.
+    async fn forward(&self, request: Request) -> Response {
+        let (response_tx, response_rx) = oneshot::channel();
+        let event = IpcEvent {
+            request,
+            response_tx,
+            // Anyone with the token can read it from a file only this user
+            // can read
+            peer: Some(Peer {
+                pid: None,
+                uid: nix::unistd::getuid().as_raw(),
+                process: Some("http".to_string()),
+            }),
+            source: Source::Http,
+        };
+        if self.event_tx.send(event).await.is_err() {
+            return Response::error(ErrorCode::InternalError, "The daemon is shutting down");
+        }
+        response_rx.await.unwrap_or_else(|_| {
+            Response::error(
+                ErrorCode::InternalError,
+                "Internal error: response channel closed",
+            )
+        })
+    }
+}
+
+/// Serve the gateway until it fails. The token, from the config or made up
+/// if there is none, is written to the token file first.
+pub async fn serve(
+    config: HttpConfig,
+    max_entry_size: u64,
+    event_tx: mpsc::Sender<IpcEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+) -> Result<()> {
+    let token = match config.token.is_empty() {
+        true => random_token(),
+        false => config.token,
+    };
+    let token_path = wayclip_common::http_token_path();
+    write_token(&token_path, &token)
+        .with_context(|| format!("Failed to write {}", token_path.display()))?;
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("HTTP gateway listening on http://{}", addr);
+
+    // Big enough for the largest entry AddEntry can store, base64 encoded
+    let body_limit = (max_entry_size / 3 * 4).saturating_add(BODY_OVERHEAD);
+    let gateway = Gateway {
+        token: token.into(),
+        event_tx,
+        daemon_events,
+    };
+    let app = Router::new()
+        .route("/request", post(request))
+        .route("/events", get(events))
+        .layer(DefaultBodyLimit::max(
+            usize::try_from(body_limit).unwrap_or(usize::MAX),
+        ))
+        .with_state(gateway);
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn request(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> axum::response::Response {
+    if !gateway.authorized(&headers) {
+        return unauthorized();
+    }
+
+    let response = match decode_request(&body) {
+        Ok(Request::Subscribe) => Response::error(
+            ErrorCode::InvalidRequest,
+            "Subscribe with GET /events instead",
+        ),
+        Ok(request) => {
+            debug!("Received HTTP request: {:?}", request);
+            gateway.forward(request).await
+        }
+        Err(e) => Response::error(ErrorCode::InvalidRequest, format!("Invalid request: {}", e)),
+    };
+    json(StatusCode::OK, &response)
+}
+
+async fn events(State(gateway): State<Gateway>, headers: HeaderMap) -> axum::response::Response {
+    if !gateway.authorized(&headers) {
+        return unauthorized();
+    }
+
+    let rx = gateway.daemon_events.subscribe();
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((Event::default().json_data(&event), rx)),
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    debug!("HTTP subscriber fell behind, dropped {} events", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn unauthorized() -> axum::response::Response {
+    let response = Response::error(ErrorCode::InvalidRequest, "Missing or wrong token");
+    let mut http = json(StatusCode::UNAUTHORIZED, &response);
+    http.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        header::HeaderValue::from_static("Bearer"),
+    );
+    http
+}
+
+fn json(status: StatusCode, response: &Response) -> axum::response::Response {
+    match encode_response(response) {
+        Ok(body) => (status, [(header::CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+/// Compare tokens without returning early at the first difference.
+fn same_token(given: &[u8], token: &[u8]) -> bool {
+    given.len() == token.len()
+        && given
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Write the token where only this user can read it, replacing any old one.
+fn write_token(path: &Path, token: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorization() {
+        let (event_tx, _event_rx) = mpsc::channel(1);
+        let gateway = Gateway {
+            token: "secret".into(),
+            event_tx,
+            daemon_events: broadcast::channel(1).0,
+        };
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        assert!(gateway.authorized(&headers("Bearer secret")));
+        assert!(!gateway.authorized(&headers("Bearer secre")));
+        assert!(!gateway.authorized(&headers("Bearer secrets")));
+        assert!(!gateway.authorized(&headers("secret")));
+        assert!(!gateway.authorized(&HeaderMap::new()));
+    }
+}
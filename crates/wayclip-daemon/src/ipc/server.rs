@@ -4,18 +4,94 @@ use anyhow::Result;
 use std::path::PathBuf;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tracing::{debug, error, info};
-use wayclip_common::{decode_request, encode_response, Request, Response};
+use wayclip_common::{decode_request, encode_response, DaemonEvent, Origin, Request, Response};
 
 /// Event from IPC client.
 pub struct IpcEvent {
     pub request: Request,
     pub response_tx: oneshot::Sender<Response>,
+    /// Process on the other end of the connection, if its credentials could
+    /// be read.
+    pub peer: Option<Peer>,
+    /// Where the request came from.
+    pub source: Source,
+}
+
+/// Where a request came from, for the policy applied to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The Unix socket, so a local process.
+    Local,
+    /// The Unix socket, from `wayclip-native-host` on behalf of a browser
+    /// extension.
+    Browser,
+    /// The HTTP gateway.
+    Http,
+    /// A device paired for LAN sync.
+    Sync { device: i64, name: String },
+}
+
+impl Source {
+    /// Origin of entries added by requests from this source.
+    pub fn origin(&self) -> Origin {
+        match self {
+            Self::Local | Self::Browser | Self::Http => Origin::Api,
+            Self::Sync { device, name } => Origin::Synced {
+                device: *device,
+                name: name.clone(),
+            },
+        }
+    }
+}
+
+/// Credentials of a connected client, from `SO_PEERCRED`.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub pid: Option<i32>,
+    pub uid: u32,
+    /// Process name from `/proc/<pid>/comm`, read when the client connected.
+    pub process: Option<String>,
+}
+
+impl Peer {
+    fn of(stream: &UnixStream) -> Option<Self> {
+        let cred = stream.peer_cred().ok()?;
+        let process = cred.pid().and_then(|pid| {
+            std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                .ok()
+                .map(|name| name.trim_end().to_string())
+        });
+        Some(Self {
+            pid: cred.pid(),
+            uid: cred.uid(),
+            process,
+        })
+    }
+
+    /// Where requests from this client come from, by its executable: `comm`
+    /// is cut at 15 bytes, too short for the native host's name.
+    fn source(&self) -> Source {
+        let executable = self
+            .pid
+            .and_then(|pid| std::fs::read_link(format!("/proc/{}/exe", pid)).ok());
+        match executable.as_deref().and_then(|path| path.file_name()) {
+            Some(name) if name == "wayclip-native-host" => Source::Browser,
+            _ => Source::Local,
+        }
+    }
 }
 
 /// Start the IPC server.
-pub async fn serve(socket_path: PathBuf, event_tx: mpsc::Sender<IpcEvent>) -> Result<()> {
+///
+/// Connections that send `Request::Subscribe` are also sent everything
+/// published on `daemon_events`.
+pub async fn serve(
+    socket_path: PathBuf,
+    event_tx: mpsc::Sender<IpcEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+) -> Result<()> {
     // Remove existing socket if present
     if socket_path.exists() {
         std::fs::remove_file(&socket_path)?;
@@ -33,8 +109,9 @@ pub async fn serve(socket_path: PathBuf, event_tx: mpsc::Sender<IpcEvent>) -> Re
         match listener.accept().await {
             Ok((stream, _)) => {
                 let tx = event_tx.clone();
+                let daemon_events = daemon_events.clone();
                 tokio::spawn(async move {
-                    if let Err(e) = handle_client(stream, tx).await {
+                    if let Err(e) = handle_client(stream, tx, daemon_events).await {
                         debug!("Client connection ended: {}", e);
                     }
                 });
@@ -46,19 +123,31 @@ pub async fn serve(socket_path: PathBuf, event_tx: mpsc::Sender<IpcEvent>) -> Re
     }
 }
 
-async fn handle_client(stream: UnixStream, event_tx: mpsc::Sender<IpcEvent>) -> Result<()> {
+async fn handle_client(
+    stream: UnixStream,
+    event_tx: mpsc::Sender<IpcEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+) -> Result<()> {
+    let peer = Peer::of(&stream);
+    let source = peer.as_ref().map_or(Source::Local, Peer::source);
     let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let mut line = String::new();
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscription = None;
 
     loop {
-        line.clear();
-        let bytes_read = reader.read_line(&mut line).await?;
-
-        if bytes_read == 0 {
-            // Client disconnected
-            break;
-        }
+        let line = tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => line,
+                // Client disconnected
+                None => break,
+            },
+            event = next_event(&mut subscription) => {
+                let encoded = encode_response(&Response::Event { event })?;
+                writer.write_all(&encoded).await?;
+                writer.flush().await?;
+                continue;
+            }
+        };
 
         let request = match decode_request(line.trim().as_bytes()) {
             Ok(req) => req,
@@ -75,11 +164,20 @@ async fn handle_client(stream: UnixStream, event_tx: mpsc::Sender<IpcEvent>) ->
 
         debug!("Received request: {:?}", request);
 
+        if let Request::Subscribe = request {
+            subscription = Some(daemon_events.subscribe());
+            writer.write_all(&encode_response(&Response::Ok)?).await?;
+            writer.flush().await?;
+            continue;
+        }
+
         // Send request to main loop and wait for response
         let (response_tx, response_rx) = oneshot::channel();
         let event = IpcEvent {
             request,
             response_tx,
+            peer: peer.clone(),
+            source: source.clone(),
         };
 
         if event_tx.send(event).await.is_err() {
@@ -95,10 +193,31 @@ async fn handle_client(stream: UnixStream, event_tx: mpsc::Sender<IpcEvent>) ->
             ),
         };
 
+        // Content is only needed encoded from here on
         let encoded = encode_response(&response)?;
+        drop(response);
         writer.write_all(&encoded).await?;
         writer.flush().await?;
     }
 
     Ok(())
 }
+
+/// Wait for the next daemon event, or forever when not subscribed.
+pub async fn next_event(
+    subscription: &mut Option<broadcast::Receiver<DaemonEvent>>,
+) -> DaemonEvent {
+    let Some(rx) = subscription else {
+        return std::future::pending().await;
+    };
+
+    loop {
+        match rx.recv().await {
+            Ok(event) => return event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                debug!("Subscriber fell behind, dropped {} events", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => return std::future::pending().await,
+        }
+    }
+}
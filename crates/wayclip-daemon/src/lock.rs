@@ -0,0 +1,189 @@
+//! Locking the history behind a passphrase.
+//!
+//! The lock is enforced by the daemon: stored content is not encrypted, so
+//! it only keeps other programs talking to the socket from reading entries.
+
+use anyhow::{anyhow, Result};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use std::time::{Duration, Instant};
+use wayclip_common::{ErrorCode, Request, Response};
+
+use crate::database::Database;
+
+/// Settings key holding the passphrase's Argon2 hash.
+const PASSPHRASE_KEY: &str = "lock_passphrase";
+
+/// Whether the history is locked, and what that hides.
+#[derive(Debug)]
+pub struct HistoryLock {
+    locked: bool,
+    has_passphrase: bool,
+    hide_history: bool,
+    /// Lock after this long without client requests.
+    idle_timeout: Option<Duration>,
+    last_activity: Instant,
+}
+
+impl HistoryLock {
+    /// Start locked if a passphrase has been set.
+    pub fn new(db: &Database, hide_history: bool, idle_timeout: Option<Duration>) -> Result<Self> {
+        let has_passphrase = db.setting(PASSPHRASE_KEY)?.is_some();
+        Ok(Self {
+            locked: has_passphrase,
+            has_passphrase,
+            hide_history,
+            idle_timeout,
+            last_activity: Instant::now(),
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Lock the history. Returns `false` if no passphrase has been set.
+    pub fn lock(&mut self) -> bool {
+        if self.has_passphrase {
+            self.locked = true;
+        }
+        self.has_passphrase
+    }
+
+    /// Note a client request, pushing back the idle lock. Status polls
+    /// don't count, so a status bar can't keep the history unlocked.
+    pub fn record_activity(&mut self, request: &Request) {
+        if !matches!(request, Request::Ping | Request::GetStatus) {
+            self.last_activity = Instant::now();
+        }
+    }
+
+    /// When the history locks itself for being idle, if it will.
+    pub fn idle_deadline(&self) -> Option<Instant> {
+        match self.idle_timeout {
+            Some(timeout) if self.has_passphrase && !self.locked => {
+                Some(self.last_activity + timeout)
+            }
+            _ => None,
+        }
+    }
+
+    /// Unlock the history if `passphrase` is right.
+    pub fn unlock(&mut self, db: &Database, passphrase: &str) -> Result<bool> {
+        let ok = match db.setting(PASSPHRASE_KEY)? {
+            Some(hash) => verify(&hash, passphrase)?,
+            None => true,
+        };
+        if ok {
+            self.locked = false;
+        }
+        Ok(ok)
+    }
+
+    /// Set or remove the passphrase. `current` must match the one set, if
+    /// any. Removing the passphrase also unlocks the history.
+    pub fn set_passphrase(
+        &mut self,
+        db: &Database,
+        current: Option<&str>,
+        new: Option<&str>,
+    ) -> Result<bool> {
+        if let Some(hash) = db.setting(PASSPHRASE_KEY)? {
+            if !current.is_some_and(|current| verify(&hash, current).unwrap_or(false)) {
+                return Ok(false);
+            }
+        }
+
+        match new {
+            Some(new) => {
+                let salt = SaltString::generate(&mut OsRng);
+                let hash = Argon2::default()
+                    .hash_password(new.as_bytes(), &salt)
+                    .map_err(|e| anyhow!("Failed to hash passphrase: {}", e))?;
+                db.set_setting(PASSPHRASE_KEY, Some(&hash.to_string()))?;
+            }
+            None => {
+                db.set_setting(PASSPHRASE_KEY, None)?;
+                self.locked = false;
+            }
+        }
+        self.has_passphrase = new.is_some();
+        Ok(true)
+    }
+
+    /// Whether `request` has to wait until the history is unlocked.
+    pub fn refuses(&self, request: &Request) -> bool {
+        if !self.locked {
+            return false;
+        }
+        match request {
+            Request::GetContent { .. }
+            | Request::GetContents { .. }
+            | Request::GetByHash { .. }
+            | Request::SetClipboard { .. }
+            | Request::SetClipboardByHash { .. }
+            | Request::SetClipboardAs { .. }
+            | Request::CycleClipboard { .. }
+            | Request::SetPrimarySelection { .. }
+            | Request::SplitEntry { .. }
+            | Request::SaveToFile { .. }
+            | Request::QueuePaste { .. }
+            | Request::PopQueue
+            | Request::ExportCollection { .. }
+            | Request::StartPairing
+            | Request::SetDevicePermissions { .. } => true,
+            Request::GetHistory { .. }
+            | Request::SearchAll { .. }
+            | Request::GetEntry { .. }
+            | Request::GetReport { .. }
+            | Request::AddEntry { .. } => self.hide_history,
+            _ => false,
+        }
+    }
+}
+
+/// The response to a request refused while locked.
+pub fn locked() -> Response {
+    Response::error(ErrorCode::Locked, "History is locked")
+}
+
+fn verify(hash: &str, passphrase: &str) -> Result<bool> {
+    let hash =
+        PasswordHash::new(hash).map_err(|e| anyhow!("Stored passphrase is invalid: {}", e))?;
+    Ok(Argon2::default()
+        .verify_password(passphrase.as_bytes(), &hash)
+        .is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_needs_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open_at(dir.path().join("history.db")).unwrap();
+        db.migrate().unwrap();
+
+        let mut lock = HistoryLock::new(&db, false, None).unwrap();
+        assert!(!lock.is_locked());
+        assert!(!lock.lock());
+
+        assert!(lock.set_passphrase(&db, None, Some("hunter2")).unwrap());
+        assert!(lock.lock());
+        assert!(lock.refuses(&Request::GetContent { id: 1.into() }));
+        assert!(!lock.refuses(&Request::GetStatus));
+
+        assert!(!lock.unlock(&db, "wrong").unwrap());
+        assert!(lock.is_locked());
+        assert!(lock.unlock(&db, "hunter2").unwrap());
+        assert!(!lock.refuses(&Request::GetContent { id: 1.into() }));
+
+        // Changing or removing it needs the current one
+        assert!(!lock.set_passphrase(&db, Some("wrong"), None).unwrap());
+        assert!(HistoryLock::new(&db, false, None).unwrap().is_locked());
+        assert!(lock.set_passphrase(&db, Some("hunter2"), None).unwrap());
+        assert!(!HistoryLock::new(&db, false, None).unwrap().is_locked());
+    }
+}
@@ -0,0 +1,198 @@
+//! Logging setup with a filter that can be changed at runtime.
+
+use crate::config::LoggingConfig;
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle for inspecting and replacing the active log filter.
+pub struct LogHandle {
+    filter: reload::Handle<EnvFilter, Registry>,
+    /// Flushes buffered file output when the daemon exits.
+    _file_guard: Option<WorkerGuard>,
+}
+
+impl LogHandle {
+    /// A handle to a filter no subscriber uses, for tests.
+    #[cfg(test)]
+    pub fn unused() -> Self {
+        let (_, filter) = reload::Layer::new(EnvFilter::new("info"));
+        Self {
+            filter,
+            _file_guard: None,
+        }
+    }
+
+    /// The active filter, formatted as an `EnvFilter` directive string.
+    pub fn level(&self) -> String {
+        self.filter
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Replace the active filter, e.g. with `"wayclip=debug"`.
+    pub fn set_level(&self, directive: &str) -> Result<()> {
+        let filter = EnvFilter::try_new(directive)
+            .map_err(|e| anyhow!("Invalid log directive '{}': {}", directive, e))?;
+        self.filter.reload(filter)?;
+        tracing::info!("Log level set to {}", directive);
+        Ok(())
+    }
+}
+
+/// Install the global tracing subscriber.
+///
+/// Logs always go to stderr, and additionally to a rotating file if enabled.
+/// `RUST_LOG` takes precedence over the configured level unless `force_level`
+/// is set, as it is when the level was given on the command line.
+pub fn init(config: &LoggingConfig, force_level: bool) -> Result<LogHandle> {
+    let from_env = if force_level {
+        None
+    } else {
+        EnvFilter::try_from_default_env().ok()
+    };
+    let filter = match from_env {
+        Some(filter) => filter,
+        None => EnvFilter::try_new(&config.level)
+            .map_err(|e| anyhow!("Invalid log level '{}': {}", config.level, e))?,
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let (file_layer, file_guard) = if config.to_file {
+        let file = RotatingFile::open(config.file.clone(), config.max_size, config.keep)?;
+        let (writer, guard) = tracing_appender::non_blocking(file);
+        let layer = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(writer);
+        (Some(layer), Some(guard))
+    } else {
+        (None, None)
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
+        .init();
+
+    Ok(LogHandle {
+        filter: handle,
+        _file_guard: file_guard,
+    })
+}
+
+/// A log file that is rotated once it grows past a size limit.
+///
+/// Rotated files are named `daemon.log.1` (newest) up to `daemon.log.<keep>`.
+/// Only the user can read them, as they can mention what was copied.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: u32,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, keep: u32) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&path)?;
+        // A log written before it was kept private
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep > 0 {
+            for i in (1..self.keep).rev() {
+                let from = self.rotated_path(i);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(i + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_limited_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        for line in ["first line\n", "second line\n", "third line\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            std::fs::read_to_string(file.rotated_path(1)).unwrap(),
+            "third line\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(file.rotated_path(2)).unwrap(),
+            "second line\n"
+        );
+        assert!(!file.rotated_path(3).exists());
+
+        for path in [path, file.rotated_path(1)] {
+            let mode = std::fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
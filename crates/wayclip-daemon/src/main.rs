@@ -1,80 +1,314 @@
 //! Wayclip daemon - clipboard history manager for Wayland.
 
+mod access;
+mod autoclear;
 mod clipboard;
 mod config;
 mod database;
+mod doctor;
+mod hash;
+mod html;
+mod http;
 mod ipc;
+mod lock;
+mod logging;
+mod notify;
+mod policy;
+mod queue;
+mod ring;
+mod scheduler;
+mod session;
+mod spill;
+mod sync;
+mod thumbnail;
+mod transform;
+mod webhook;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::Parser;
+use database::Storage;
+use std::path::{Path, PathBuf};
+use tokio::signal::unix::{signal, SignalKind};
 use tracing::info;
-use tracing_subscriber::EnvFilter;
 
 /// Daemon version from Cargo.toml.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Clipboard history daemon for Wayland.
+///
+/// Every path defaults to its `WAYCLIP_*` environment variable or XDG
+/// location, so several isolated daemons can
+/// run side by side by giving each its own `--profile`, or its own `--db`
+/// and `--socket`.
+#[derive(Parser)]
+#[command(name = "wayclip-daemon", version)]
+struct Args {
+    /// Profile whose history to keep, instead of $WAYCLIP_PROFILE.
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Configuration file; it must exist when given.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// History database file. Spilled captures are kept next to it.
+    #[arg(long, value_name = "FILE")]
+    db: Option<PathBuf>,
+    /// IPC socket to listen on.
+    #[arg(long, value_name = "FILE")]
+    socket: Option<PathBuf>,
+    /// Log filter, e.g. "wayclip=debug". Overrides the config file and RUST_LOG.
+    #[arg(long, value_name = "DIRECTIVE")]
+    log_level: Option<String>,
+    /// Check the compositor, configuration, database and socket, print
+    /// what is wrong and exit.
+    #[arg(long)]
+    doctor: bool,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
+    wayclip_common::init_profile(args.profile.as_deref())?;
+
+    if args.doctor {
+        let ok = doctor::run(&doctor::Paths {
+            config: args.config,
+            db: args.db.unwrap_or_else(wayclip_common::database_path),
+            socket: args.socket.unwrap_or_else(wayclip_common::socket_path),
+        });
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // Load configuration first so it can set up logging
+    let mut config = match &args.config {
+        Some(path) => config::Config::load_from(path)
+            .with_context(|| format!("Failed to load {}", path.display()))?,
+        None => config::Config::load()?,
+    };
+    if let Some(level) = &args.log_level {
+        config.logging.level = level.clone();
+    }
+    // The memory backend keeps nothing on disk, the log file included
+    if config.storage.backend == config::StorageBackend::Memory {
+        config.logging.to_file = false;
+    }
+
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("wayclip=info".parse()?))
-        .init();
+    let log = logging::init(&config.logging, args.log_level.is_some())?;
 
     info!("Starting wayclip daemon v{}", VERSION);
+    if let Some(profile) = wayclip_common::profile() {
+        info!("Using profile {}", profile);
+    }
 
-    // Ensure directories exist
-    let socket_dir = wayclip_common::socket_dir();
-    std::fs::create_dir_all(&socket_dir)?;
+    let socket_path = args.socket.unwrap_or_else(wayclip_common::socket_path);
+    // A database given on the command line keeps its thumbnails beside it
+    let thumbnail_dir = match &args.db {
+        Some(path) => parent_dir(path).join("thumbs"),
+        None => wayclip_common::thumbnail_dir(),
+    };
+    let db_path = args.db.unwrap_or_else(wayclip_common::database_path);
 
-    let db_dir = wayclip_common::database_dir();
-    std::fs::create_dir_all(&db_dir)?;
+    // Ensure directories exist
+    let socket_dir = parent_dir(&socket_path);
+    std::fs::create_dir_all(socket_dir)?;
 
-    // Load configuration
-    let config = config::Config::load()?;
     info!("Loaded configuration: {:?}", config);
 
     // Initialize database
-    let db = database::Database::open()?;
-    db.migrate()?;
-    info!("Database initialized");
+    let (db, mut spill) = open_storage(&config, &db_path, thumbnail_dir)?;
+    db.set_cache_size(config.daemon.content_cache_size);
+    let mut policy = policy::CapturePolicy::load(&config.clipboard, &db)?;
+
+    let lock_after_idle = match config.daemon.lock_after_idle_minutes {
+        0 => None,
+        minutes => Some(std::time::Duration::from_secs(u64::from(minutes) * 60)),
+    };
+    let mut history_lock =
+        lock::HistoryLock::new(&db, config.daemon.hide_history_when_locked, lock_after_idle)?;
+    if history_lock.is_locked() {
+        info!("History is locked until unlocked with its passphrase");
+    }
+
+    let mut autoclear = autoclear::AutoClear::default();
+    let mut maintenance = scheduler::Scheduler::new(&db, &config.maintenance)?;
 
     // Create event channels
-    let (clipboard_tx, mut clipboard_rx) = tokio::sync::mpsc::channel::<clipboard::ClipboardEvent>(100);
+    let (clipboard_tx, mut clipboard_rx) = clipboard::channel(100);
     let (ipc_tx, mut ipc_rx) = tokio::sync::mpsc::channel::<ipc::IpcEvent>(100);
+    let (events_tx, _) = tokio::sync::broadcast::channel::<wayclip_common::DaemonEvent>(16);
 
     // Start clipboard monitor in dedicated thread
     let clipboard_handle = {
         let tx = clipboard_tx;
+        let mime_selection = clipboard::MimeSelection {
+            priority: config.clipboard.mime_priority.clone(),
+            fallback_allow: config.clipboard.fallback_allow.clone(),
+            fallback_deny: config.clipboard.fallback_deny.clone(),
+        };
         std::thread::spawn(move || {
-            if let Err(e) = clipboard::monitor(tx) {
+            if let Err(e) = clipboard::monitor(tx, mime_selection) {
                 tracing::error!("Clipboard monitor error: {}", e);
             }
         })
     };
 
+    // Watch for the session locking so the history can lock with it
+    let (session_tx, mut session_rx) = tokio::sync::mpsc::channel::<&'static str>(4);
+    if config.daemon.lock_on_session_lock {
+        tokio::spawn(async move {
+            if let Err(e) = session::watch(session_tx).await {
+                tracing::debug!("Not watching for session locks: {}", e);
+            }
+        });
+    }
+
+    // Announce new entries, with buttons handled in the main loop
+    let (action_tx, mut action_rx) = tokio::sync::mpsc::channel::<notify::ActionInvoked>(4);
+    let announcers = Announcers {
+        notifier: config
+            .daemon
+            .notify_on_capture
+            .then(|| notify::Notifier::spawn(action_tx)),
+        webhooks: webhook::Webhooks::spawn(&config.webhooks)?,
+    };
+
+    // Queued entries go on the clipboard one paste at a time
+    let (mut paste_queue, mut pasted_rx) = queue::PasteQueue::new();
+    let mut ring = ring::ClipboardRing::default();
+
+    // Serve the same requests over HTTP on localhost when enabled
+    if config.http.enabled {
+        let http = http::serve(
+            config.http.clone(),
+            config.daemon.max_entry_size,
+            ipc_tx.clone(),
+            events_tx.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = http.await {
+                tracing::error!("HTTP gateway stopped: {:#}", e);
+            }
+        });
+    }
+
+    // Let paired devices sync over the local network when enabled
+    let (sync_tx, mut sync_rx) = tokio::sync::mpsc::channel::<sync::SyncEvent>(16);
+    if config.sync.enabled {
+        let sync = sync::serve(
+            config.sync.clone(),
+            sync::pairing::Identity::load_or_create(&db)?,
+            config.daemon.max_entry_size,
+            sync_tx,
+            events_tx.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = sync.await {
+                tracing::error!("LAN sync stopped: {:#}", e);
+            }
+        });
+    }
+
     // Start IPC server
-    let socket_path = wayclip_common::socket_path();
-    let ipc_handle = tokio::spawn(ipc::serve(socket_path, ipc_tx));
+    let ipc_handle = tokio::spawn(ipc::serve(socket_path, ipc_tx, events_tx.clone()));
+
+    // systemd stops user services with SIGTERM at logout and shutdown
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
 
     info!("Daemon started, waiting for events...");
 
+    let mut was_locked = history_lock.is_locked();
+
     // Main event loop
     loop {
         tokio::select! {
-            Some(event) = clipboard_rx.recv() => {
-                if let Err(e) = handle_clipboard_event(&db, &config, event).await {
-                    tracing::error!("Failed to handle clipboard event: {}", e);
+            event = clipboard_rx.recv() => {
+                ring.captured(event.selection, &event.hash);
+                if !spill.is_empty() {
+                    // Keep captures in order behind the ones still waiting
+                    spill_capture(&config, &policy, &mut spill, event);
+                } else if let Err(e) = handle_clipboard_event(&db, &config, &policy, &events_tx, &mut autoclear, Some(&announcers).filter(|_| !history_lock.is_locked()), &event, None).await {
+                    if spill::is_transient(&e) {
+                        tracing::warn!("Failed to store clipboard event, will retry: {}", e);
+                        spill_capture(&config, &policy, &mut spill, event);
+                    } else {
+                        tracing::error!("Failed to store clipboard event: {}", e);
+                    }
+                }
+            }
+            _ = wait_for_retry(&spill) => {
+                retry_spilled(&db, &config, &policy, &events_tx, &mut autoclear, &mut spill).await;
+            }
+            _ = wait_until(autoclear.next_due()) => {
+                run_autoclear(&db, &events_tx, &mut autoclear, &mut ring);
+            }
+            _ = wait_until(history_lock.idle_deadline()) => {
+                if history_lock.lock() {
+                    info!("History locked after {} idle minutes", config.daemon.lock_after_idle_minutes);
+                }
+            }
+            _ = wait_until(maintenance.as_ref().map(|m| m.next_check())) => {
+                if let Some(maintenance) = &mut maintenance {
+                    maintenance.run_if_due(&db, config.daemon.max_age_days).await;
+                }
+            }
+            Some(invoked) = action_rx.recv() => {
+                run_notification_action(&db, invoked);
+            }
+            Some(reason) = session_rx.recv() => {
+                if !history_lock.is_locked() && history_lock.lock() {
+                    info!("History locked: {}", reason);
+                }
+            }
+            Some(()) = pasted_rx.recv() => {
+                // Locking stops the queue where it is
+                if !history_lock.is_locked() {
+                    match paste_queue.pasted(&db) {
+                        Ok(Some(hash)) => ring.copied(hash),
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!("Failed to copy the next queued entry: {}", e),
+                    }
                 }
             }
             Some(event) = ipc_rx.recv() => {
-                handle_ipc_event(&db, event).await;
+                let sets_config = matches!(event.request, wayclip_common::Request::SetConfig { .. });
+                handle_ipc_event(&db, &config, &log, &events_tx, &mut history_lock, &mut paste_queue, &mut ring, event).await;
+                // The capture policy holds the added ignore rules compiled
+                if sets_config {
+                    match policy::CapturePolicy::load(&config.clipboard, &db) {
+                        Ok(reloaded) => policy = reloaded,
+                        Err(e) => tracing::warn!("Failed to reload ignore rules: {}", e),
+                    }
+                }
+            }
+            Some(event) = sync_rx.recv() => {
+                if let Some(event) = sync::admit(&db, event) {
+                    handle_ipc_event(&db, &config, &log, &events_tx, &mut history_lock, &mut paste_queue, &mut ring, event).await;
+                }
             }
             _ = tokio::signal::ctrl_c() => {
                 info!("Received shutdown signal");
                 break;
             }
+            _ = terminate.recv() => {
+                info!("Received shutdown signal");
+                break;
+            }
+            _ = hangup.recv() => {
+                info!("Session ended");
+                break;
+            }
+        }
+
+        // The lock changes from several branches, so announce it in one place
+        if history_lock.is_locked() != was_locked {
+            was_locked = history_lock.is_locked();
+            let _ = events_tx.send(wayclip_common::DaemonEvent::LockChanged { locked: was_locked });
         }
     }
 
+    end_session(&db, &mut spill, config.daemon.on_session_end);
+
     // Cleanup
     drop(ipc_handle);
     drop(clipboard_handle);
@@ -83,57 +317,957 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn handle_clipboard_event(
-    db: &database::Database,
+/// The directory containing `path`, treating a bare file name as relative to
+/// the working directory.
+/// Open the history with the configured backend, along with the queue for
+/// captures that fail to store.
+///
+/// The memory backend is an SQLite `:memory:` database. It keeps nothing on
+/// disk, so there is no archive, no thumbnail cache and no spill directory,
+/// and backups are skipped.
+fn open_storage(
     config: &config::Config,
+    db_path: &Path,
+    thumbnail_dir: PathBuf,
+) -> Result<(database::Database, spill::SpillQueue)> {
+    let trigram = config.search.tokenizer == config::SearchTokenizer::Trigram;
+    let (mut db, spill) = match config.storage.backend {
+        config::StorageBackend::Sqlite => {
+            let db_dir = parent_dir(db_path);
+            std::fs::create_dir_all(db_dir)?;
+
+            let mut db = database::Database::open_checked(db_path.to_path_buf())?;
+            db.set_thumbnail_dir(thumbnail_dir);
+            info!("Database initialized at {}", db_path.display());
+
+            // An archive left from when archiving was on stays searchable
+            let archive_path = db_path.with_extension("archive.db");
+            if config.maintenance.archive_after_days > 0 || archive_path.exists() {
+                let mut archive = database::Database::open_at(archive_path.clone())?;
+                archive.set_trigram_index(trigram);
+                archive.migrate()?;
+                db.set_archive(archive);
+                info!("Archive database at {}", archive_path.display());
+            }
+
+            // Captures that failed to store in a previous run are retried first
+            (db, spill::SpillQueue::open(db_dir.join("spill")))
+        }
+        config::StorageBackend::Memory => {
+            info!("Keeping history in memory only");
+            (
+                database::Database::open_in_memory()?,
+                spill::SpillQueue::in_memory(),
+            )
+        }
+    };
+    db.set_trigram_index(trigram);
+    db.migrate()?;
+    Ok((db, spill))
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Clear the history as `policy` says before the daemon exits.
+fn end_session(db: &dyn Storage, spill: &mut spill::SpillQueue, policy: config::SessionEndPolicy) {
+    let result = match policy {
+        config::SessionEndPolicy::Keep => return,
+        config::SessionEndPolicy::ClearUnpinned => db.clear_unpinned(),
+        config::SessionEndPolicy::ClearAll => db.clear_all(),
+    };
+    // Unsaved captures would otherwise be stored again on the next start
+    spill.discard();
+
+    match result {
+        Ok(()) => info!("Cleared history for the end of the session ({:?})", policy),
+        Err(e) => tracing::error!("Failed to clear history at the end of the session: {}", e),
+    }
+}
+
+/// Sleep until the spill queue's next retry is due, or forever if it is empty.
+async fn wait_for_retry(spill: &spill::SpillQueue) {
+    match spill.retry_delay() {
+        Some(delay) => tokio::time::sleep(delay).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`, or forever if there is none.
+async fn wait_until(deadline: Option<std::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Act on a button clicked on a new entry's notification.
+fn run_notification_action(db: &database::Database, invoked: notify::ActionInvoked) {
+    let notify::ActionInvoked { entry_id, action } = invoked;
+    let result = match action {
+        notify::Action::Pin => db.set_pinned(entry_id, true).map(drop),
+        notify::Action::Delete => db.delete_entry(entry_id).map(drop),
+        notify::Action::Open => {
+            // Tokio reaps the client when it exits
+            let mut client = tokio::process::Command::new("wayclip");
+            if let Some(profile) = wayclip_common::profile() {
+                client.arg("--profile").arg(profile);
+            }
+            client.spawn().map(drop).map_err(Into::into)
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!(
+            "Failed to {:?} entry {} from its notification: {}",
+            action,
+            entry_id,
+            e
+        );
+    }
+}
+
+/// Clear selections whose sensitive content has timed out.
+fn run_autoclear(
+    db: &dyn Storage,
+    events: &tokio::sync::broadcast::Sender<wayclip_common::DaemonEvent>,
+    autoclear: &mut autoclear::AutoClear,
+    ring: &mut ring::ClipboardRing,
+) {
+    for pending in autoclear.take_due() {
+        if pending.still_current {
+            match clipboard::clear_selection(pending.selection) {
+                Ok(()) => {
+                    info!(
+                        "Cleared sensitive content from the {}",
+                        pending.selection.name()
+                    );
+                    if pending.selection == wayclip_common::Selection::Clipboard {
+                        ring.cleared();
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to clear the {}: {}", pending.selection.name(), e),
+            }
+        }
+        // Pinned since, or kept in a collection, it stays
+        if let Some(id) = pending.delete_entry {
+            match db.delete_disposable(id) {
+                Ok(true) => {
+                    let _ = events.send(wayclip_common::DaemonEvent::EntryDeleted { id });
+                }
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to delete ephemeral entry {}: {}", id, e),
+            }
+        }
+    }
+}
+
+/// Store queued captures in order, stopping at the first failure that may
+/// pass. Captures that can't be stored at all are dropped.
+async fn retry_spilled(
+    db: &dyn Storage,
+    config: &config::Config,
+    policy: &policy::CapturePolicy,
+    events: &tokio::sync::broadcast::Sender<wayclip_common::DaemonEvent>,
+    autoclear: &mut autoclear::AutoClear,
+    spill: &mut spill::SpillQueue,
+) {
+    while let Some(capture) = spill.front() {
+        // Captures stored late aren't announced
+        match handle_clipboard_event(
+            db,
+            config,
+            policy,
+            events,
+            autoclear,
+            None,
+            &capture.event,
+            capture.hash_only,
+        )
+        .await
+        {
+            Ok(()) => spill.pop_stored(),
+            Err(e) if spill::is_transient(&e) => {
+                tracing::warn!(
+                    "Still unable to store clipboard event ({} queued): {}",
+                    spill.len(),
+                    e
+                );
+                spill.retry_later();
+                return;
+            }
+            Err(e) => {
+                tracing::error!("Failed to store queued clipboard event: {}", e);
+                spill.drop_front();
+            }
+        }
+    }
+    info!("Stored all queued clipboard captures");
+}
+
+/// Queue `event` to be stored later, as far as the capture policy lets it
+/// be kept: ignored captures are dropped, and ones kept by hash only are
+/// queued without their content.
+fn spill_capture(
+    config: &config::Config,
+    policy: &policy::CapturePolicy,
+    spill: &mut spill::SpillQueue,
     event: clipboard::ClipboardEvent,
-) -> Result<()> {
-    use sha2::{Digest, Sha256};
+) {
+    match screen(config, policy, &event).map(|(_, decision)| decision) {
+        None | Some(policy::Decision::Ignore) => {}
+        Some(policy::Decision::StoreHashOnly) => spill.push_hash_only(event),
+        Some(policy::Decision::Store | policy::Decision::Ephemeral) => spill.push(event),
+    }
+}
+
+/// Where new entries are announced, besides to subscribed clients.
+struct Announcers {
+    notifier: Option<notify::Notifier>,
+    webhooks: Option<webhook::Webhooks>,
+}
 
+/// Store a clipboard capture. `hash_only` is the size of the content of a
+/// queued capture that is kept by hash only, whose content was dropped.
+#[allow(clippy::too_many_arguments)]
+async fn handle_clipboard_event(
+    db: &dyn Storage,
+    config: &config::Config,
+    policy: &policy::CapturePolicy,
+    events: &tokio::sync::broadcast::Sender<wayclip_common::DaemonEvent>,
+    autoclear: &mut autoclear::AutoClear,
+    announcers: Option<&Announcers>,
+    event: &clipboard::ClipboardEvent,
+    hash_only: Option<u64>,
+) -> Result<()> {
     let clipboard::ClipboardEvent {
         content,
+        hash,
         mime_type,
-        ..
+        seat,
+        selection,
+        source_app,
+        window_title,
+        workspace,
     } = event;
 
+    // Anything newly copied means an earlier sensitive copy is gone
+    autoclear.captured(*selection, hash);
+
+    // A queued hash-only capture went through the policy before it lost its
+    // content
+    let (content, decision) = match hash_only {
+        Some(_) => (
+            std::borrow::Cow::Borrowed(content.as_slice()),
+            policy::Decision::StoreHashOnly,
+        ),
+        None => match screen(config, policy, event) {
+            Some(screened) => screened,
+            None => return Ok(()),
+        },
+    };
+    let byte_size = hash_only.unwrap_or(content.len() as u64);
+    // Normalizing only ever removes bytes
+    let stored_hash = match content.len() == event.content.len() {
+        true => std::borrow::Cow::Borrowed(hash.as_str()),
+        false => std::borrow::Cow::Owned(hash::content_hash(&content)),
+    };
+
+    let (id, inserted) = match decision {
+        policy::Decision::Ignore => {
+            tracing::debug!("Ignoring entry: matches an ignore rule");
+            return Ok(());
+        }
+        policy::Decision::StoreHashOnly => {
+            store_hash_only(db, event, hash_only.unwrap_or(event.content.len() as u64))?
+        }
+        policy::Decision::Store | policy::Decision::Ephemeral => store_entry(
+            db,
+            &content,
+            &stored_hash,
+            mime_type,
+            Provenance {
+                seat: seat.as_deref(),
+                selection: *selection,
+                source_app: source_app.as_deref(),
+                window_title: window_title.as_deref(),
+                workspace: workspace.as_deref(),
+                origin: None,
+            },
+            config.clipboard.similar_images,
+        )?,
+    };
+
+    // Sensitive content stays out of notifications and webhooks
+    if let (Some(announcers), policy::Decision::Store) = (announcers, decision) {
+        let preview = capture_preview(&content, mime_type);
+        if let Some(notifier) = &announcers.notifier {
+            notifier.notify(id, &preview);
+        }
+        if let Some(webhooks) = &announcers.webhooks {
+            webhooks.captured(id, event, &preview);
+        }
+    }
+
+    let clear_after = config.clipboard.clear_sensitive_after;
+    if decision.is_sensitive() && clear_after > 0 {
+        // An entry that was already in the history isn't this capture's to
+        // discard
+        let discard =
+            decision == policy::Decision::Ephemeral && !config.clipboard.keep_ephemeral && inserted;
+        autoclear.schedule(
+            *selection,
+            hash,
+            discard.then_some(id),
+            std::time::Duration::from_secs(clear_after),
+        );
+    }
+
+    let threshold = config.daemon.large_entry_threshold;
+    if threshold > 0 && byte_size >= threshold {
+        info!("Captured a large entry ({} bytes)", byte_size);
+        // Sending only fails when nobody is subscribed
+        let _ = events.send(wayclip_common::DaemonEvent::LargeCapture {
+            id,
+            byte_size,
+            mime_type: mime_type.clone(),
+        });
+    }
+
+    // Run cleanup
+    db.cleanup(&config.daemon.entry_limits())?;
+
+    let _ = events.send(wayclip_common::DaemonEvent::EntryAdded { id });
+
+    Ok(())
+}
+
+/// Normalize `event`'s content for storing and run the capture policy on
+/// it, or return `None` if the content is too large, too small or only
+/// whitespace.
+fn screen<'a>(
+    config: &config::Config,
+    policy: &policy::CapturePolicy,
+    event: &'a clipboard::ClipboardEvent,
+) -> Option<(std::borrow::Cow<'a, [u8]>, policy::Decision)> {
+    let content = &event.content;
+
     // Check size limits
     if content.len() as u64 > config.daemon.max_entry_size {
         tracing::debug!("Ignoring entry: too large ({} bytes)", content.len());
-        return Ok(());
+        return None;
     }
 
     if (content.len() as u64) < config.daemon.min_entry_size {
         tracing::debug!("Ignoring entry: too small ({} bytes)", content.len());
-        return Ok(());
+        return None;
     }
 
-    // Compute hash for deduplication
-    let mut hasher = Sha256::new();
-    hasher.update(&content);
-    let hash = format!("{:x}", hasher.finalize());
+    // Only what is stored is normalized; autoclear goes by the hash of the
+    // clipboard's content as copied
+    let content_type = wayclip_common::ContentType::from_mime(&event.mime_type);
+    let normalized = match config.clipboard.normalize_text && content_type.is_text() {
+        true => hash::normalize_text(content),
+        false => std::borrow::Cow::Borrowed(content.as_slice()),
+    };
+    if normalized.is_empty() && !content.is_empty() {
+        tracing::debug!("Ignoring entry: only whitespace");
+        return None;
+    }
+
+    let decision = policy.decide(&normalized, &event.mime_type, event.source_app.as_deref());
+    Some((normalized, decision))
+}
 
+/// Where content being stored came from.
+#[derive(Debug, Clone, Copy, Default)]
+struct Provenance<'a> {
+    /// Name of the seat it was copied on.
+    seat: Option<&'a str>,
+    /// Selection it was captured from.
+    selection: wayclip_common::Selection,
+    /// Application it was copied from.
+    source_app: Option<&'a str>,
+    /// Title of the window it was copied from.
+    window_title: Option<&'a str>,
+    /// Workspace that window was on.
+    workspace: Option<&'a str>,
+    /// Where it came from, if not copied on this machine; without one it
+    /// goes by `selection`.
+    origin: Option<&'a wayclip_common::Origin>,
+}
+
+/// Store content with `content_hash` `hash` as a history entry and return
+/// its ID, along with whether a new entry was inserted for it.
+///
+/// Content already in the history is not stored twice; the existing entry
+/// is marked as used instead. `similar_images` says whether the same goes
+/// for images that only look like one in the history.
+fn store_entry(
+    db: &dyn Storage,
+    content: &[u8],
+    hash: &str,
+    mime_type: &str,
+    provenance: Provenance,
+    similar_images: config::SimilarImages,
+) -> Result<(i64, bool)> {
     // Check for duplicate
-    if db.find_by_hash(&hash)?.is_some() {
+    if let Some(id) = db.find_by_hash(hash)? {
         tracing::debug!("Ignoring duplicate entry");
-        db.touch_by_hash(&hash)?;
-        return Ok(());
+        db.touch_by_hash(hash)?;
+        return Ok((id, false));
     }
 
     // Generate preview
-    let content_type = wayclip_common::ContentType::from_mime(&mime_type);
-    let preview = generate_preview(&content, &mime_type, content_type);
+    let content_type = wayclip_common::ContentType::from_mime(mime_type);
+    let mut image_info = if content_type.is_image() {
+        thumbnail::analyze(content, mime_type)
+    } else {
+        None
+    };
+
+    let image_hash = image_info.as_ref().map(|i| i.perceptual_hash);
+    let similar_to = match (image_hash, similar_images) {
+        (Some(hash), config::SimilarImages::Flag | config::SimilarImages::Coalesce) => {
+            db.find_similar_image(hash)?
+        }
+        _ => None,
+    };
+    if let Some(id) = similar_to {
+        if similar_images == config::SimilarImages::Coalesce {
+            tracing::debug!("Ignoring image similar to entry {}", id);
+            db.touch_entry(id)?;
+            return Ok((id, false));
+        }
+    }
+
+    let plain_text = plain_text(content, mime_type);
+
+    let mut variants: Vec<(String, Vec<u8>)> = Vec::new();
+    if let Some(text) = &plain_text {
+        variants.push((
+            "text/plain;charset=utf-8".to_string(),
+            text.as_bytes().to_vec(),
+        ));
+    }
+    if let Some(png) = image_info.as_mut().and_then(|i| i.rasterized.take()) {
+        variants.push(("image/png".to_string(), png));
+    }
+
+    let preview = match &plain_text {
+        Some(text) => generate_preview(text.as_bytes(), content_type, None),
+        None => generate_preview(content, content_type, image_info.as_ref()),
+    };
 
     // Store entry
-    db.insert_entry(&hash, content_type, &mime_type, &preview, &content)?;
-    tracing::info!("Stored new entry: {} ({} bytes)", preview, content.len());
+    let id = db.insert_entry(&database::NewEntry {
+        hash,
+        content_type,
+        mime_type,
+        preview: &preview,
+        content,
+        thumbnail: image_info.as_ref().and_then(|i| i.thumbnail.as_deref()),
+        animated: image_info.as_ref().is_some_and(|i| i.animated),
+        image_hash,
+        similar_to,
+        variants: &variants,
+        seat: provenance.seat,
+        selection: provenance.selection,
+        source_app: provenance.source_app,
+        window_title: provenance.window_title,
+        workspace: provenance.workspace,
+        origin: &provenance
+            .origin
+            .cloned()
+            .unwrap_or_else(|| wayclip_common::Origin::captured(provenance.selection)),
+        redacted: None,
+    })?;
+    // The log file outlives the history, so content stays out of it
+    tracing::info!("Stored new entry {} ({} bytes)", id, content.len());
 
-    // Run cleanup
-    db.cleanup(config.daemon.max_entries)?;
+    Ok((id, true))
+}
+
+/// Record a capture's time, size, source and hash without its content, and
+/// return the entry's ID along with whether it was newly inserted. The
+/// content was `byte_size` bytes long; `event` need not hold it any more.
+fn store_hash_only(
+    db: &dyn Storage,
+    event: &clipboard::ClipboardEvent,
+    byte_size: u64,
+) -> Result<(i64, bool)> {
+    let hash = &event.hash;
+    if let Some(id) = db.find_by_hash(hash)? {
+        db.touch_by_hash(hash)?;
+        return Ok((id, false));
+    }
+
+    let content_type = wayclip_common::ContentType::from_mime(&event.mime_type);
+    let preview = format!(
+        "Sensitive {} not stored ({} bytes)",
+        if content_type.is_image() {
+            "image"
+        } else {
+            "text"
+        },
+        byte_size
+    );
+
+    let id = db.insert_entry(&database::NewEntry {
+        hash,
+        content_type,
+        mime_type: &event.mime_type,
+        preview: &preview,
+        content: &[],
+        thumbnail: None,
+        animated: false,
+        image_hash: None,
+        similar_to: None,
+        variants: &[],
+        seat: event.seat.as_deref(),
+        selection: event.selection,
+        source_app: event.source_app.as_deref(),
+        window_title: event.window_title.as_deref(),
+        workspace: event.workspace.as_deref(),
+        origin: &wayclip_common::Origin::captured(event.selection),
+        redacted: Some(byte_size),
+    })?;
+    tracing::info!("Stored hash-only entry ({} bytes)", byte_size);
+
+    Ok((id, true))
+}
+
+/// The row ID of the entry `id` refers to, looking up a UUID. `None` is an
+/// unknown UUID; row IDs are returned as they are.
+fn resolve_entry(db: &dyn Storage, id: &wayclip_common::EntryId) -> Result<Option<i64>> {
+    match id {
+        wayclip_common::EntryId::Row(id) => Ok(Some(*id)),
+        wayclip_common::EntryId::Uuid(uuid) => db.find_by_uuid(uuid),
+    }
+}
+
+/// Like `resolve_entry`, answering with an error response for an unknown
+/// UUID.
+fn entry_row(
+    db: &dyn Storage,
+    id: &wayclip_common::EntryId,
+) -> Result<i64, wayclip_common::Response> {
+    use wayclip_common::{ErrorCode, Response};
+
+    match resolve_entry(db, id) {
+        Ok(Some(row)) => Ok(row),
+        Ok(None) => Err(Response::not_found(id)),
+        Err(e) => Err(Response::error(ErrorCode::DatabaseError, e.to_string())),
+    }
+}
+
+/// Answer a request about the entry `id` refers to with `f`, given its row
+/// ID.
+fn with_entry(
+    db: &dyn Storage,
+    id: &wayclip_common::EntryId,
+    f: impl FnOnce(i64) -> wayclip_common::Response,
+) -> wayclip_common::Response {
+    match entry_row(db, id) {
+        Ok(row) => f(row),
+        Err(response) => response,
+    }
+}
+
+/// Look up the entry with a content hash, answering with an error response
+/// if there is none.
+fn find_hash(db: &dyn Storage, hash: &str) -> Result<i64, wayclip_common::Response> {
+    use wayclip_common::{ErrorCode, Response};
+
+    match db.find_by_hash(&hash.to_ascii_lowercase()) {
+        Ok(Some(id)) => Ok(id),
+        Ok(None) => Err(Response::error(
+            ErrorCode::NotFound,
+            format!("No entry with hash {}", hash),
+        )),
+        Err(e) => Err(Response::error(ErrorCode::DatabaseError, e.to_string())),
+    }
+}
+
+/// Answer with an entry's metadata.
+fn entry_response(db: &database::Database, id: i64) -> wayclip_common::Response {
+    use wayclip_common::{ErrorCode, Response};
+
+    match db.get_entry(id) {
+        Ok(Some(entry)) => Response::Entry {
+            entry: Box::new(entry),
+        },
+        Ok(None) => Response::not_found(id),
+        Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+    }
+}
+
+/// Answer a request for an entry's content.
+fn content_response(db: &dyn Storage, id: i64) -> wayclip_common::Response {
+    use wayclip_common::{ErrorCode, Response};
+
+    match db.get_content(id) {
+        Ok(Some((mime_type, data))) => Response::Content {
+            id,
+            mime_type,
+            data: data.into(),
+        },
+        Ok(None) => Response::not_found(id),
+        Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+    }
+}
+
+/// Store content sent by a client from `origin`, answering with an error
+/// response if it can't be.
+fn add_entry(
+    db: &dyn Storage,
+    config: &config::Config,
+    mime_type: &str,
+    data: &str,
+    origin: &wayclip_common::Origin,
+) -> Result<i64, wayclip_common::Response> {
+    use base64::Engine;
+    use wayclip_common::{ErrorCode, Response};
+
+    let content = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| Response::error(ErrorCode::InvalidRequest, format!("Invalid data: {}", e)))?;
+    if content.is_empty() {
+        return Err(Response::error(ErrorCode::InvalidRequest, "Nothing to add"));
+    }
+    if content.len() as u64 > config.daemon.max_entry_size {
+        return Err(Response::error(
+            ErrorCode::InvalidRequest,
+            format!(
+                "Entry is larger than max_entry_size ({} bytes)",
+                content.len()
+            ),
+        ));
+    }
+
+    let stored = store_entry(
+        db,
+        &content,
+        &hash::content_hash(&content),
+        mime_type,
+        Provenance {
+            origin: Some(origin),
+            ..Default::default()
+        },
+        config.clipboard.similar_images,
+    )
+    .and_then(|(id, _)| {
+        db.cleanup(&config.daemon.entry_limits())?;
+        Ok(id)
+    });
+    stored.map_err(|e| Response::error(ErrorCode::DatabaseError, e.to_string()))
+}
 
+/// Store each piece of a text entry as an entry of its own, copied from
+/// where the entry was.
+fn split_entry(
+    db: &database::Database,
+    config: &config::Config,
+    id: i64,
+    delimiter: Option<&str>,
+) -> wayclip_common::Response {
+    use wayclip_common::{ErrorCode, Response};
+
+    let source = match db.get_entry(id) {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Response::not_found(id),
+        Err(e) => return Response::error(ErrorCode::DatabaseError, e.to_string()),
+    };
+    let pieces = match db.get_content(id) {
+        Ok(Some((mime_type, data))) => match transform::split(&mime_type, &data, delimiter) {
+            Ok(pieces) => pieces,
+            Err(e) => return Response::error(ErrorCode::InvalidRequest, e.to_string()),
+        },
+        Ok(None) => return Response::not_found(id),
+        Err(e) => return Response::error(ErrorCode::DatabaseError, e.to_string()),
+    };
+
+    // Stored last to first, so the first piece ends up at the top
+    let mut ids = Vec::with_capacity(pieces.len());
+    for piece in pieces.iter().rev() {
+        let stored = store_entry(
+            db,
+            piece.as_bytes(),
+            &hash::content_hash(piece.as_bytes()),
+            "text/plain;charset=utf-8",
+            Provenance {
+                seat: source.seat.as_deref(),
+                selection: source.selection,
+                source_app: source.source_app.as_deref(),
+                window_title: source.window_title.as_deref(),
+                workspace: source.workspace.as_deref(),
+                origin: Some(&source.origin),
+            },
+            config.clipboard.similar_images,
+        );
+        match stored {
+            Ok((id, _)) => ids.push(id),
+            Err(e) => return Response::error(ErrorCode::DatabaseError, e.to_string()),
+        }
+    }
+    ids.reverse();
+
+    if let Err(e) = db.cleanup(&config.daemon.entry_limits()) {
+        tracing::warn!("Failed to clean up after splitting an entry: {}", e);
+    }
+    Response::Split { ids }
+}
+
+/// Write an entry's content to `path`, adding an extension for its MIME type
+/// if the path has none.
+fn save_to_file(db: &dyn Storage, id: i64, path: &str) -> wayclip_common::Response {
+    use wayclip_common::{ErrorCode, Response};
+
+    // Relative paths would be relative to wherever the daemon was started
+    let mut path = PathBuf::from(path);
+    if !path.is_absolute() || path.file_name().is_none() {
+        return Response::error(
+            ErrorCode::InvalidRequest,
+            format!("Not an absolute file path: {}", path.display()),
+        );
+    }
+
+    let (mime_type, data) = match db.get_content(id) {
+        Ok(Some(content)) => content,
+        Ok(None) => return Response::not_found(id),
+        Err(e) => return Response::error(ErrorCode::DatabaseError, e.to_string()),
+    };
+    if path.extension().is_none() {
+        path.set_extension(wayclip_common::extension_for_mime(&mime_type));
+    }
+
+    match write_synced(&path, &data) {
+        Ok(()) => {
+            info!("Saved entry {} to {}", id, path.display());
+            Response::Saved {
+                path: path.to_string_lossy().into_owned(),
+            }
+        }
+        Err(e) => Response::error(
+            ErrorCode::InternalError,
+            format!("Failed to write {}: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Replace `path` with `data` in one step, so a crash never leaves half a
+/// file behind.
+fn write_synced(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(path.file_name().unwrap_or_default());
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let result = std::fs::File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    // The rename itself only lasts once the directory is synced
+    if let Some(dir) = path.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
     Ok(())
 }
 
-fn generate_preview(content: &[u8], mime_type: &str, content_type: wayclip_common::ContentType) -> String {
+/// Copy an entry back to the clipboard, with its other formats.
+fn set_clipboard(db: &dyn Storage, id: i64) -> wayclip_common::Response {
+    use wayclip_common::{ErrorCode, Response};
+
+    match db.get_content(id) {
+        Ok(Some((mime_type, data))) => {
+            let variants = db.get_variants(id).unwrap_or_default();
+            match clipboard::copy_with_variants(
+                wayclip_common::Selection::Clipboard,
+                &mime_type,
+                data,
+                variants,
+            ) {
+                Ok(()) => {
+                    let _ = db.touch_entry(id);
+                    Response::Ok
+                }
+                Err(e) => Response::error(ErrorCode::ClipboardError, e.to_string()),
+            }
+        }
+        Ok(None) => Response::not_found(id),
+        Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+    }
+}
+
+/// Load the content of several entries, leaving out those that would push
+/// the total past `MAX_CONTENTS_BYTES`.
+fn get_contents(
+    db: &dyn Storage,
+    ids: &[wayclip_common::EntryId],
+) -> Result<(Vec<wayclip_common::EntryContent>, Vec<i64>)> {
+    let mut contents = Vec::with_capacity(ids.len());
+    let mut omitted = Vec::new();
+    let mut total: u64 = 0;
+    for id in ids {
+        let Some(id) = resolve_entry(db, id)? else {
+            continue;
+        };
+        let Some((mime_type, data)) = db.get_content(id)? else {
+            continue;
+        };
+        if total + data.len() as u64 > wayclip_common::MAX_CONTENTS_BYTES {
+            omitted.push(id);
+            continue;
+        }
+        total += data.len() as u64;
+        contents.push(wayclip_common::EntryContent {
+            id,
+            mime_type,
+            data: data.into(),
+        });
+    }
+
+    Ok((contents, omitted))
+}
+
+/// Export a collection with the content of its entries.
+fn export_collection(
+    db: &database::Database,
+    id: i64,
+) -> Result<Option<wayclip_common::CollectionExport>> {
+    use base64::Engine;
+
+    let Some((name, entry_ids)) = db.collection_contents(id)? else {
+        return Ok(None);
+    };
+
+    let mut entries = Vec::with_capacity(entry_ids.len());
+    for entry_id in entry_ids {
+        if let Some((mime_type, data)) = db.get_content(entry_id)? {
+            let (title, note) = db.entry_meta(entry_id)?.unwrap_or_default();
+            entries.push(wayclip_common::ExportedEntry {
+                mime_type,
+                data: base64::engine::general_purpose::STANDARD.encode(data),
+                title,
+                note,
+            });
+        }
+    }
+
+    Ok(Some(wayclip_common::CollectionExport { name, entries }))
+}
+
+/// Import an exported collection, merging into an existing one of the same name.
+fn import_collection(
+    db: &database::Database,
+    export: &wayclip_common::CollectionExport,
+) -> Result<wayclip_common::Collection> {
+    use base64::Engine;
+
+    // Decode everything first so a bad export doesn't leave a partial import
+    let contents = export
+        .entries
+        .iter()
+        .map(|entry| {
+            base64::engine::general_purpose::STANDARD
+                .decode(&entry.data)
+                .map(|data| (entry, data))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let id = match db.find_collection(&export.name)? {
+        Some(id) => id,
+        None => db.create_collection(&export.name)?.id,
+    };
+
+    for (entry, data) in contents {
+        let (entry_id, _) = store_entry(
+            db,
+            &data,
+            &hash::content_hash(&data),
+            &entry.mime_type,
+            Provenance {
+                origin: Some(&wayclip_common::Origin::Import),
+                ..Default::default()
+            },
+            config::SimilarImages::Keep,
+        )?;
+        // Keep titles and notes already set locally
+        if entry.title.is_some() || entry.note.is_some() {
+            let (title, note) = db.entry_meta(entry_id)?.unwrap_or_default();
+            db.set_entry_meta(
+                entry_id,
+                title.as_deref().or(entry.title.as_deref()),
+                note.as_deref().or(entry.note.as_deref()),
+            )?;
+        }
+        db.add_to_collection(id, entry_id)?;
+    }
+
+    db.list_collections()?
+        .into_iter()
+        .find(|c| c.id == id)
+        .ok_or_else(|| anyhow::anyhow!("Imported collection disappeared"))
+}
+
+fn collection_not_found(id: i64) -> wayclip_common::Response {
+    wayclip_common::Response::error(
+        wayclip_common::ErrorCode::NotFound,
+        format!("Collection {} not found", id),
+    )
+}
+
+fn device_not_found(id: i64) -> wayclip_common::Response {
+    wayclip_common::Response::error(
+        wayclip_common::ErrorCode::NotFound,
+        format!("Device {} not found", id),
+    )
+}
+
+fn wrong_passphrase() -> wayclip_common::Response {
+    wayclip_common::Response::error(wayclip_common::ErrorCode::Locked, "Wrong passphrase")
+}
+
+fn collection_exists(name: &str) -> wayclip_common::Response {
+    wayclip_common::Response::error(
+        wayclip_common::ErrorCode::InvalidRequest,
+        format!("A collection named '{}' already exists", name),
+    )
+}
+
+/// Plain-text rendition of HTML, kept for previews, search and pasting, or
+/// `None` for content of any other type.
+fn plain_text(content: &[u8], mime_type: &str) -> Option<String> {
+    (wayclip_common::mime_essence(mime_type) == "text/html")
+        .then(|| html::strip_tags(&String::from_utf8_lossy(content)))
+}
+
+/// Preview of a capture for notifications and webhooks: the one its entry
+/// gets, without the size of an image.
+fn capture_preview(content: &[u8], mime_type: &str) -> String {
+    let content_type = wayclip_common::ContentType::from_mime(mime_type);
+    match plain_text(content, mime_type) {
+        Some(text) => generate_preview(text.as_bytes(), content_type, None),
+        None => generate_preview(content, content_type, None),
+    }
+}
+
+fn generate_preview(
+    content: &[u8],
+    content_type: wayclip_common::ContentType,
+    image_info: Option<&thumbnail::ImageInfo>,
+) -> String {
     match content_type {
         wayclip_common::ContentType::Text => {
             let text = String::from_utf8_lossy(content);
@@ -141,29 +1275,119 @@ fn generate_preview(content: &[u8], mime_type: &str, content_type: wayclip_commo
             // Normalize whitespace for preview
             preview.split_whitespace().collect::<Vec<_>>().join(" ")
         }
-        wayclip_common::ContentType::Image => {
-            // Try to extract dimensions from PNG
-            if mime_type == "image/png" && content.len() >= 24 {
-                let width = u32::from_be_bytes([content[16], content[17], content[18], content[19]]);
-                let height = u32::from_be_bytes([content[20], content[21], content[22], content[23]]);
-                format!("copied image ({}x{})", width, height)
-            } else {
-                "copied image".to_string()
-            }
-        }
+        wayclip_common::ContentType::Image => match image_info {
+            Some(info) => format!("copied image ({}x{})", info.width, info.height),
+            None => "copied image".to_string(),
+        },
+        wayclip_common::ContentType::Other => hexdump_preview(content),
     }
 }
 
-async fn handle_ipc_event(db: &database::Database, event: ipc::IpcEvent) {
-    use wayclip_common::{ErrorCode, Request, Response};
+/// Bytes shown in the preview of content that is neither text nor an image.
+const HEXDUMP_PREVIEW_BYTES: usize = 16;
+
+/// The first bytes of `content` in hex, then as ASCII with dots for the rest,
+/// like a line of `hexdump -C`.
+fn hexdump_preview(content: &[u8]) -> String {
+    let head = &content[..content.len().min(HEXDUMP_PREVIEW_BYTES)];
+    let hex: Vec<String> = head.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let ascii: String = head
+        .iter()
+        .map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        })
+        .collect();
+    let more = if content.len() > head.len() {
+        " …"
+    } else {
+        ""
+    };
+    format!("{}{}  |{}|", hex.join(" "), more, ascii)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_ipc_event(
+    db: &database::Database,
+    config: &config::Config,
+    log: &logging::LogHandle,
+    events: &tokio::sync::broadcast::Sender<wayclip_common::DaemonEvent>,
+    history_lock: &mut lock::HistoryLock,
+    paste_queue: &mut queue::PasteQueue,
+    ring: &mut ring::ClipboardRing,
+    event: ipc::IpcEvent,
+) {
+    use wayclip_common::{DaemonEvent, ErrorCode, Request, Response};
+
+    history_lock.record_activity(&event.request);
+    if history_lock.refuses(&event.request) {
+        let _ = event.response_tx.send(lock::locked());
+        return;
+    }
+    if let Some(refusal) = access::refuse(&config.sources, &event.source, &event.request) {
+        let _ = event.response_tx.send(refusal);
+        return;
+    }
+
+    let reads = match config.daemon.audit_log {
+        true => content_reads(db, &event.request),
+        false => None,
+    };
 
-    let response = match event.request {
+    let mut response = match event.request {
         Request::GetHistory {
             limit,
             offset,
             search,
+            seat,
+            selection,
+            collection,
+            snippets,
+            similar_to,
+            origin,
+            sort,
+            pinned_first,
+            include_archived,
+        } => match similar_to.map(|id| entry_row(db, &id)).transpose() {
+            Ok(similar_to) => {
+                let filter = database::HistoryFilter {
+                    search: search.as_deref(),
+                    seat: seat.as_deref(),
+                    selection,
+                    collection,
+                    snippets,
+                    similar_to,
+                    origin,
+                    sort: sort.unwrap_or_default(),
+                    pinned_first,
+                };
+                let history = match include_archived {
+                    true => db.get_history_with_archive(limit, offset, &filter),
+                    false => db.get_history(limit, offset, &filter),
+                };
+                match history {
+                    Ok((entries, total_count)) => Response::History {
+                        entries,
+                        total_count,
+                    },
+                    Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+                }
+            }
+            Err(response) => response,
+        },
+
+        Request::SearchAll {
+            query,
+            limit,
+            offset,
+            sort,
         } => {
-            match db.get_history(limit, offset, search.as_deref()) {
+            let filter = database::HistoryFilter {
+                search: Some(&query),
+                sort: sort.unwrap_or_default(),
+                ..Default::default()
+            };
+            match db.get_history_with_archive(limit, offset, &filter) {
                 Ok((entries, total_count)) => Response::History {
                     entries,
                     total_count,
@@ -172,43 +1396,148 @@ async fn handle_ipc_event(db: &database::Database, event: ipc::IpcEvent) {
             }
         }
 
-        Request::GetContent { id } => {
-            match db.get_content(id) {
-                Ok(Some((mime_type, data))) => {
-                    use base64::Engine;
-                    Response::Content {
-                        id,
-                        mime_type,
-                        data: base64::engine::general_purpose::STANDARD.encode(&data),
-                    }
+        Request::RestoreArchived { id } => {
+            with_entry(db, &id, |id| match db.restore_archived(id) {
+                Ok(Some(id)) => entry_response(db, id),
+                Ok(None) => {
+                    Response::error(ErrorCode::NotFound, format!("Entry {} is not archived", id))
                 }
-                Ok(None) => Response::not_found(id),
                 Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
-            }
+            })
         }
 
-        Request::SetClipboard { id } => {
+        Request::GetEntry { id } => with_entry(db, &id, |id| entry_response(db, id)),
+
+        Request::GetContent { id } => with_entry(db, &id, |id| content_response(db, id)),
+
+        Request::GetByHash { hash } => match find_hash(db, &hash) {
+            Ok(id) => content_response(db, id),
+            Err(response) => response,
+        },
+
+        Request::GetContents { ids } => match get_contents(db, &ids) {
+            Ok((contents, omitted)) => Response::Contents { contents, omitted },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::SetClipboard { id } => with_entry(db, &id, |id| set_clipboard(db, id)),
+
+        Request::SetClipboardByHash { hash } => match find_hash(db, &hash) {
+            Ok(id) => set_clipboard(db, id),
+            Err(response) => response,
+        },
+
+        Request::SetPrimarySelection { id } => with_entry(db, &id, |id| match db.get_content(id) {
+            Ok(Some((mime_type, data))) => {
+                let variants = db.get_variants(id).unwrap_or_default();
+                match clipboard::copy_with_variants(
+                    wayclip_common::Selection::Primary,
+                    &mime_type,
+                    data,
+                    variants,
+                ) {
+                    Ok(()) => Response::Ok,
+                    Err(e) => Response::error(ErrorCode::ClipboardError, e.to_string()),
+                }
+            }
+            Ok(None) => Response::not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        }),
+
+        Request::SetClipboardAs { id, transform } => with_entry(db, &id, |id| {
             match db.get_content(id) {
-                Ok(Some((mime_type, data))) => {
-                    match clipboard::copy_to_clipboard(&data, &mime_type) {
+                Ok(Some((mime_type, data))) => match transform::apply(transform, &mime_type, &data) {
+                    Ok((mime_type, data)) => match clipboard::copy_to_clipboard(&data, &mime_type) {
                         Ok(()) => {
                             let _ = db.touch_entry(id);
                             Response::Ok
                         }
                         Err(e) => Response::error(ErrorCode::ClipboardError, e.to_string()),
-                    }
-                }
+                    },
+                    Err(e) => Response::error(ErrorCode::InvalidRequest, e.to_string()),
+                },
                 Ok(None) => Response::not_found(id),
                 Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
             }
+        }),
+
+        Request::CycleClipboard { direction } => match ring.next(db, direction) {
+            Ok(Some(entry)) => match set_clipboard(db, entry.id) {
+                Response::Ok => {
+                    ring.moved_to(&entry);
+                    Response::Entry {
+                        entry: Box::new(entry),
+                    }
+                }
+                error => error,
+            },
+            Ok(None) => Response::error(ErrorCode::NotFound, "The history is empty"),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::SaveToFile { id, path } => with_entry(db, &id, |id| save_to_file(db, id, &path)),
+
+        Request::SplitEntry { id, delimiter } => with_entry(db, &id, |id| {
+            split_entry(db, config, id, delimiter.as_deref())
+        }),
+
+        Request::DeleteEntry { id } => with_entry(db, &id, |id| match db.delete_entry(id) {
+            Ok(true) => {
+                let _ = events.send(DaemonEvent::EntryDeleted { id });
+                Response::Ok
+            }
+            Ok(false) => Response::not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        }),
+
+        Request::AddEntry {
+            mime_type,
+            data,
+            set_clipboard: copy,
+        } => match add_entry(db, config, &mime_type, &data, &event.source.origin()) {
+            Ok(id) if copy => match set_clipboard(db, id) {
+                Response::Ok => entry_response(db, id),
+                error => error,
+            },
+            Ok(id) => entry_response(db, id),
+            Err(response) => response,
+        },
+
+        Request::QueuePaste { ids } => {
+            match ids
+                .iter()
+                .map(|id| entry_row(db, id))
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(ids) => match paste_queue.extend(db, ids) {
+                    Ok(copied) => {
+                        if let Some(hash) = copied {
+                            ring.copied(hash);
+                        }
+                        Response::Queue {
+                            waiting: paste_queue.waiting(),
+                        }
+                    }
+                    Err(e) => Response::error(ErrorCode::ClipboardError, e.to_string()),
+                },
+                Err(response) => response,
+            }
         }
 
-        Request::DeleteEntry { id } => {
-            match db.delete_entry(id) {
-                Ok(true) => Response::Ok,
-                Ok(false) => Response::not_found(id),
-                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        Request::PopQueue => match paste_queue.advance(db) {
+            Ok(Some(hash)) => {
+                ring.copied(hash);
+                Response::Queue {
+                    waiting: paste_queue.waiting(),
+                }
             }
+            Ok(None) => Response::error(ErrorCode::NotFound, "The paste queue is empty"),
+            Err(e) => Response::error(ErrorCode::ClipboardError, e.to_string()),
+        },
+
+        Request::ClearQueue => {
+            paste_queue.clear();
+            Response::Ok
         }
 
         Request::ClearHistory => {
@@ -218,27 +1547,863 @@ async fn handle_ipc_event(db: &database::Database, event: ipc::IpcEvent) {
             }
         }
 
+        Request::Prune { criteria, dry_run } => match db.prune(&criteria, dry_run) {
+            Ok(count) => {
+                if !dry_run {
+                    info!("Pruned {} entries matching {:?}", count, criteria);
+                }
+                Response::Pruned { count }
+            }
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::DedupExisting { dry_run } => match db.dedup_existing(dry_run) {
+            Ok(report) => Response::Deduplicated {
+                checked: report.checked,
+                merged: report.merged,
+                removed: report.removed,
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
         Request::SetPinned { id, pinned } => {
-            match db.set_pinned(id, pinned) {
-                Ok(true) => Response::Ok,
+            with_entry(db, &id, |id| match db.set_pinned(id, pinned) {
+                Ok(true) => {
+                    let _ = events.send(DaemonEvent::EntryUpdated { id });
+                    Response::Ok
+                }
+                Ok(false) => Response::not_found(id),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            })
+        }
+
+        Request::SetEntryMeta { id, title, note } => with_entry(db, &id, |id| {
+            match db.set_entry_meta(id, title.as_deref(), note.as_deref()) {
+                Ok(true) => {
+                    let _ = events.send(DaemonEvent::EntryUpdated { id });
+                    Response::Ok
+                }
                 Ok(false) => Response::not_found(id),
                 Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
             }
+        }),
+
+        Request::MovePinned { id, position } => {
+            with_entry(db, &id, |id| match db.move_pinned(id, position) {
+                Ok(true) => {
+                    let _ = events.send(DaemonEvent::EntryUpdated { id });
+                    Response::Ok
+                }
+                Ok(false) => Response::error(
+                    ErrorCode::InvalidRequest,
+                    format!("Entry {} is not pinned", id),
+                ),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            })
+        }
+
+        Request::ListCollections => match db.list_collections() {
+            Ok(collections) => Response::Collections { collections },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::CreateCollection { name } => match db.find_collection(&name) {
+            Ok(Some(_)) => collection_exists(&name),
+            Ok(None) => match db.create_collection(&name) {
+                Ok(collection) => Response::Collection { collection },
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::RenameCollection { id, name } => match db.find_collection(&name) {
+            Ok(Some(other)) if other != id => collection_exists(&name),
+            Ok(_) => match db.rename_collection(id, &name) {
+                Ok(true) => Response::Ok,
+                Ok(false) => collection_not_found(id),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::DeleteCollection { id } => match db.delete_collection(id) {
+            Ok(true) => Response::Ok,
+            Ok(false) => collection_not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::AddToCollection {
+            collection_id,
+            entry_id,
+        } => with_entry(db, &entry_id, |entry_id| {
+            match db.add_to_collection(collection_id, entry_id) {
+                Ok(true) => Response::Ok,
+                Ok(false) => Response::error(
+                    ErrorCode::NotFound,
+                    format!(
+                        "Collection {} or entry {} not found",
+                        collection_id, entry_id
+                    ),
+                ),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            }
+        }),
+
+        Request::RemoveFromCollection {
+            collection_id,
+            entry_id,
+        } => with_entry(db, &entry_id, |entry_id| {
+            match db.remove_from_collection(collection_id, entry_id) {
+                Ok(true) => Response::Ok,
+                Ok(false) => Response::not_found(entry_id),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            }
+        }),
+
+        Request::ExportCollection { id } => match export_collection(db, id) {
+            Ok(Some(export)) => Response::CollectionExport { export },
+            Ok(None) => collection_not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::ImportCollection { export } => match import_collection(db, &export) {
+            Ok(collection) => Response::Collection { collection },
+            Err(e) => Response::error(ErrorCode::InvalidRequest, e.to_string()),
+        },
+
+        Request::GetStatus => match (db.count_entries(), db.database_size()) {
+            (Ok(entry_count), Ok(database_size_bytes)) => Response::Status {
+                version: VERSION.to_string(),
+                entry_count,
+                database_size_bytes,
+                log_level: log.level(),
+                locked: history_lock.is_locked(),
+                warnings: db.warnings(),
+                search_backend: db.search_backend(),
+                capture_error: clipboard::capture_error(),
+            },
+            _ => Response::error(ErrorCode::DatabaseError, "Failed to get status"),
+        },
+
+        Request::GetStats => Response::Stats {
+            cache: db.cache_stats(),
+            dropped_captures: clipboard::dropped_captures(),
+            mime_types: clipboard::mime_type_counts(),
+        },
+
+        Request::GetAuditLog { limit } => match db.audit_log(limit) {
+            Ok(records) => Response::AuditLog {
+                enabled: config.daemon.audit_log,
+                records,
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::GetReport { period_secs, limit } => {
+            let until = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64);
+            let since = until.saturating_sub(period_secs.min(i64::MAX as u64) as i64);
+            match db.report(since, until, limit) {
+                Ok(report) => Response::Report { report },
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            }
         }
 
-        Request::GetStatus => {
-            match (db.count_entries(), db.database_size()) {
-                (Ok(entry_count), Ok(database_size_bytes)) => Response::Status {
-                    version: VERSION.to_string(),
-                    entry_count,
-                    database_size_bytes,
+        Request::Lock => match history_lock.lock() {
+            true => {
+                info!("History locked");
+                Response::Ok
+            }
+            false => Response::error(ErrorCode::InvalidRequest, "No passphrase is set"),
+        },
+
+        Request::Unlock { passphrase } => match history_lock.unlock(db, &passphrase) {
+            Ok(true) => {
+                info!("History unlocked");
+                Response::Ok
+            }
+            Ok(false) => wrong_passphrase(),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::SetPassphrase { new: Some(new), .. } if new.is_empty() => {
+            Response::error(ErrorCode::InvalidRequest, "The passphrase can't be empty")
+        }
+
+        Request::SetPassphrase { current, new } => {
+            match history_lock.set_passphrase(db, current.as_deref(), new.as_deref()) {
+                Ok(true) => Response::Ok,
+                Ok(false) => wrong_passphrase(),
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            }
+        }
+
+        Request::RecoverDatabase { path } => {
+            match path
+                .map(PathBuf::from)
+                .or_else(|| db.default_recovery_source())
+            {
+                Some(path) => match db.salvage_from(&path) {
+                    Ok(report) => Response::Recovered {
+                        recovered: report.recovered,
+                        skipped: report.skipped,
+                        failed: report.failed,
+                    },
+                    Err(e) => Response::error(
+                        ErrorCode::DatabaseError,
+                        format!("Failed to read {}: {}", path.display(), e),
+                    ),
                 },
-                _ => Response::error(ErrorCode::DatabaseError, "Failed to get status"),
+                None => Response::error(
+                    ErrorCode::InvalidRequest,
+                    "No corrupt database backup found to recover from",
+                ),
+            }
+        }
+
+        Request::VerifyIntegrity => match db.verify_content() {
+            Ok(report) => Response::Integrity {
+                checked: report.checked,
+                mismatched: report.mismatched,
+                unreadable: report.unreadable,
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::GetConfig => match policy::added_rules(db) {
+            Ok(added_ignore) => Response::Config {
+                ignore: Box::new(config.clipboard.ignore_rules()),
+                added_ignore: Box::new(added_ignore),
+            },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::SetConfig { ignore } => {
+            if let Err(e) = policy::compile_ignore_rules(&ignore) {
+                Response::error(ErrorCode::InvalidRequest, e.to_string())
+            } else {
+                match policy::set_added_rules(db, &ignore) {
+                    Ok(()) => Response::Config {
+                        ignore: Box::new(config.clipboard.ignore_rules()),
+                        added_ignore: Box::new(ignore),
+                    },
+                    Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+                }
             }
         }
 
+        Request::SetLogLevel { directive } => match log.set_level(&directive) {
+            Ok(()) => Response::Ok,
+            Err(e) => Response::error(ErrorCode::InvalidRequest, e.to_string()),
+        },
+
+        Request::StartPairing if !config.sync.enabled => Response::error(
+            ErrorCode::InvalidRequest,
+            "LAN sync is off; set `enabled = true` under [sync] in the config",
+        ),
+
+        Request::StartPairing => match sync::pairing::start(db, &config.sync) {
+            Ok((uri, expires_at)) => Response::Pairing { uri, expires_at },
+            Err(e) => Response::error(ErrorCode::InternalError, e.to_string()),
+        },
+
+        Request::ListDevices => match db.list_devices() {
+            Ok(devices) => Response::Devices { devices },
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::SetDevicePermissions {
+            id,
+            can_push,
+            can_pull,
+        } => match db.set_device_permissions(id, can_push, can_pull) {
+            Ok(true) => Response::Ok,
+            Ok(false) => device_not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        Request::RemoveDevice { id } => match db.remove_device(id) {
+            Ok(true) => Response::Ok,
+            Ok(false) => device_not_found(id),
+            Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+        },
+
+        // Subscriptions are per connection and handled by the IPC server
+        Request::Subscribe => Response::Ok,
+
         Request::Ping => Response::Pong,
+
+        Request::Unknown { name } => Response::error(
+            ErrorCode::InvalidRequest,
+            format!(
+                "Unknown request type '{}'; the daemon may be older than the client",
+                name
+            ),
+        ),
     };
 
+    match &mut response {
+        Response::History { entries, .. } => {
+            for entry in entries {
+                entry.is_current = ring.current() == Some(entry.hash.as_str());
+            }
+        }
+        Response::Entry { entry } => entry.is_current = ring.current() == Some(entry.hash.as_str()),
+        _ => {}
+    }
+
+    if let (Some((request, mut ids)), Some(peer)) = (reads, &event.peer) {
+        // Only what was actually handed out
+        match &response {
+            Response::Error { .. } => ids.clear(),
+            Response::Contents { contents, .. } => {
+                ids = contents.iter().map(|c| c.id).collect();
+            }
+            // Which entry a cycle copies is only known once it has
+            Response::Entry { entry } => ids = vec![entry.id],
+            _ => {}
+        }
+        if let Err(e) = db.record_access(peer, request, &ids) {
+            tracing::warn!("Failed to write audit log: {}", e);
+        }
+    }
+
+    access::redact(&config.sources, &event.source, &mut response);
     let _ = event.response_tx.send(response);
 }
+
+/// The entries whose content `request` reads, for the audit log.
+fn content_reads(
+    db: &database::Database,
+    request: &wayclip_common::Request,
+) -> Option<(&'static str, Vec<i64>)> {
+    use wayclip_common::Request;
+
+    let row = |id| resolve_entry(db, id).ok().flatten();
+    match request {
+        Request::GetContent { id } => Some(("GetContent", vec![row(id)?])),
+        Request::GetContents { ids } => Some(("GetContents", ids.iter().filter_map(row).collect())),
+        Request::SetClipboard { id } => Some(("SetClipboard", vec![row(id)?])),
+        Request::QueuePaste { ids } if !ids.is_empty() => {
+            Some(("QueuePaste", ids.iter().filter_map(row).collect()))
+        }
+        Request::GetByHash { hash } => {
+            let id = db.find_by_hash(&hash.to_ascii_lowercase()).ok().flatten()?;
+            Some(("GetByHash", vec![id]))
+        }
+        Request::SetClipboardByHash { hash } => {
+            let id = db.find_by_hash(&hash.to_ascii_lowercase()).ok().flatten()?;
+            Some(("SetClipboardByHash", vec![id]))
+        }
+        Request::SetClipboardAs { id, .. } => Some(("SetClipboardAs", vec![row(id)?])),
+        Request::SaveToFile { id, .. } => Some(("SaveToFile", vec![row(id)?])),
+        Request::CycleClipboard { .. } => Some(("CycleClipboard", Vec::new())),
+        Request::SetPrimarySelection { id } => Some(("SetPrimarySelection", vec![row(id)?])),
+        Request::ExportCollection { id } => {
+            let ids = db.collection_contents(*id).ok().flatten()?.1;
+            Some(("ExportCollection", ids))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayclip_common::{Origin, Selection};
+
+    fn capture(text: &str) -> clipboard::ClipboardEvent {
+        clipboard::ClipboardEvent {
+            content: text.as_bytes().to_vec(),
+            hash: hash::content_hash(text.as_bytes()),
+            mime_type: "text/plain".to_string(),
+            seat: None,
+            selection: Selection::Clipboard,
+            source_app: None,
+            window_title: None,
+            workspace: None,
+        }
+    }
+
+    fn memory_db() -> database::Database {
+        let db = database::Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    fn store_text(db: &dyn Storage, text: &str) -> i64 {
+        let similar = config::SimilarImages::Keep;
+        store_entry(
+            db,
+            text.as_bytes(),
+            &hash::content_hash(text.as_bytes()),
+            "text/plain",
+            Provenance::default(),
+            similar,
+        )
+        .unwrap()
+        .0
+    }
+
+    /// What `handle_ipc_event` works with, around an in-memory database.
+    struct Daemon {
+        db: database::Database,
+        config: config::Config,
+        log: logging::LogHandle,
+        events: tokio::sync::broadcast::Sender<wayclip_common::DaemonEvent>,
+        history_lock: lock::HistoryLock,
+        paste_queue: queue::PasteQueue,
+        ring: ring::ClipboardRing,
+    }
+
+    impl Daemon {
+        fn new(config: config::Config) -> Self {
+            let db = memory_db();
+            let history_lock = lock::HistoryLock::new(&db, false, None).unwrap();
+            Self {
+                db,
+                config,
+                log: logging::LogHandle::unused(),
+                events: tokio::sync::broadcast::channel(16).0,
+                history_lock,
+                paste_queue: queue::PasteQueue::new().0,
+                ring: ring::ClipboardRing::default(),
+            }
+        }
+
+        async fn request(
+            &mut self,
+            source: ipc::Source,
+            request: wayclip_common::Request,
+        ) -> wayclip_common::Response {
+            let (response_tx, response_rx) = tokio::sync::oneshot::channel();
+            let event = ipc::IpcEvent {
+                request,
+                response_tx,
+                peer: None,
+                source,
+            };
+            handle_ipc_event(
+                &self.db,
+                &self.config,
+                &self.log,
+                &self.events,
+                &mut self.history_lock,
+                &mut self.paste_queue,
+                &mut self.ring,
+                event,
+            )
+            .await;
+            response_rx.await.unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ipc_requests() {
+        use base64::Engine;
+        use wayclip_common::{EntryId, Request, Response};
+
+        let mut daemon = Daemon::new(config::Config {
+            sources: toml::from_str(r#"http = { access = "no_content" }"#).unwrap(),
+            ..Default::default()
+        });
+
+        let add = Request::AddEntry {
+            mime_type: "text/plain".to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode("hunter2"),
+            set_clipboard: false,
+        };
+        let id = match daemon.request(ipc::Source::Local, add).await {
+            Response::Entry { entry } => {
+                assert_eq!(entry.origin, Origin::Api);
+                entry.id
+            }
+            other => panic!("Unexpected response: {:?}", other),
+        };
+
+        let read = || Request::GetContent {
+            id: EntryId::Row(id),
+        };
+        assert!(matches!(
+            daemon.request(ipc::Source::Local, read()).await,
+            Response::Content { data, .. } if data.0 == b"hunter2"
+        ));
+        assert!(daemon.request(ipc::Source::Http, read()).await.is_error());
+
+        let entry = || Request::GetEntry {
+            id: EntryId::Row(id),
+        };
+        assert!(matches!(
+            daemon.request(ipc::Source::Local, entry()).await,
+            Response::Entry { entry } if entry.preview == "hunter2"
+        ));
+        assert!(matches!(
+            daemon.request(ipc::Source::Http, entry()).await,
+            Response::Entry { entry } if entry.preview.is_empty()
+        ));
+
+        let delete = Request::DeleteEntry {
+            id: EntryId::Row(id),
+        };
+        assert!(matches!(
+            daemon.request(ipc::Source::Local, delete).await,
+            Response::Ok
+        ));
+        assert_eq!(daemon.db.count_entries().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_hexdump_preview() {
+        assert_eq!(hexdump_preview(b"PK\x03\x04"), "50 4b 03 04  |PK..|");
+        let preview = hexdump_preview(&[b'a'; 20]);
+        assert!(preview.ends_with(" …  |aaaaaaaaaaaaaaaa|"), "{}", preview);
+    }
+
+    #[test]
+    fn test_capture_preview_matches_stored_preview() {
+        let db = memory_db();
+        let html = b"<p>Hello <b>world</b></p>";
+        let (id, _) = store_entry(
+            &db,
+            html,
+            &hash::content_hash(html),
+            "text/html",
+            Provenance::default(),
+            config::SimilarImages::Keep,
+        )
+        .unwrap();
+
+        let (entries, _) = db
+            .get_history(None, None, &database::HistoryFilter::default())
+            .unwrap();
+        assert_eq!(entries[0].id, id);
+        assert_eq!(capture_preview(html, "text/html"), entries[0].preview);
+        assert_eq!(capture_preview(html, "text/html"), "Hello world");
+    }
+
+    /// A PNG of a horizontal gradient, with `noise` flipping low bits.
+    fn gradient_png(noise: u8) -> Vec<u8> {
+        let pixels = (0..64 * 48)
+            .flat_map(|i| {
+                let value = ((i % 64) * 4) as u8 ^ (i as u8 % 3 * noise);
+                [value, value, value, 255]
+            })
+            .collect();
+        thumbnail::encode_png(&thumbnail::RgbaImage {
+            width: 64,
+            height: 48,
+            pixels,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_memory_backend_writes_nothing() {
+        let config: config::Config = toml::from_str(
+            r#"
+            [storage]
+            backend = "memory"
+
+            [maintenance]
+            keep_backups = 2
+            archive_after_days = 1
+            "#,
+        )
+        .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let (db, mut spill) = open_storage(
+            &config,
+            &dir.path().join("history.db"),
+            dir.path().join("thumbs"),
+        )
+        .unwrap();
+
+        let png = gradient_png(0);
+        store_entry(
+            &db,
+            &png,
+            &hash::content_hash(&png),
+            "image/png",
+            Provenance::default(),
+            config::SimilarImages::Keep,
+        )
+        .unwrap();
+        spill.push(capture("hello"));
+        db.archive_entries(0).unwrap();
+        db.refresh_thumbnails().unwrap();
+        assert_eq!(db.back_up(2).unwrap(), None);
+
+        assert_eq!(db.count_entries().unwrap(), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_store_entry_skips_duplicates() {
+        let db = memory_db();
+        let first = store_text(&db, "hello");
+        store_text(&db, "other");
+        let again = store_text(&db, "hello");
+
+        assert_eq!(first, again);
+        assert_eq!(db.count_entries().unwrap(), 2);
+        let (_, inserted) = store_entry(
+            &db,
+            b"hello",
+            &hash::content_hash(b"hello"),
+            "text/plain",
+            Provenance::default(),
+            config::SimilarImages::Keep,
+        )
+        .unwrap();
+        assert!(!inserted);
+
+        // The duplicates count as uses
+        let filter = database::HistoryFilter {
+            sort: wayclip_common::HistorySort::MostUsed,
+            ..Default::default()
+        };
+        let (entries, _) = db.get_history(None, None, &filter).unwrap();
+        assert_eq!(entries[0].id, first);
+    }
+
+    #[test]
+    fn test_similar_images_are_flagged_or_coalesced() {
+        let store = |db: &database::Database, png: &[u8], similar| {
+            store_entry(
+                db,
+                png,
+                &hash::content_hash(png),
+                "image/png",
+                Provenance::default(),
+                similar,
+            )
+            .unwrap()
+            .0
+        };
+
+        let db = memory_db();
+        let original = store(&db, &gradient_png(0), config::SimilarImages::Flag);
+        let flagged = store(&db, &gradient_png(1), config::SimilarImages::Flag);
+        assert_ne!(original, flagged);
+        let (entries, _) = db
+            .get_history(None, None, &database::HistoryFilter::default())
+            .unwrap();
+        assert_eq!(entries[0].similar_to, Some(original));
+
+        let coalesced = store(&db, &gradient_png(2), config::SimilarImages::Coalesce);
+        assert!(coalesced == original || coalesced == flagged);
+        assert_eq!(db.count_entries().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_store_hash_only_keeps_no_content() {
+        let db = memory_db();
+        let (id, _) = store_hash_only(&db, &capture("hunter2"), 7).unwrap();
+
+        let (entries, _) = db
+            .get_history(None, None, &database::HistoryFilter::default())
+            .unwrap();
+        assert!(entries[0].redacted);
+        assert_eq!(entries[0].byte_size, 7);
+        assert!(!entries[0].preview.contains("hunter2"));
+        assert!(db.get_content(id).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_policy_runs_before_spilling() {
+        let config = config::Config {
+            clipboard: toml::from_str(
+                r#"
+                ignore_content_patterns = ["^ghp_"]
+                rules = [{ content = "^password:", action = "store_hash_only" }]
+                "#,
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        let policy =
+            policy::CapturePolicy::from_config(&config.clipboard, &Default::default()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut spill = spill::SpillQueue::open(dir.path().to_path_buf());
+
+        spill_capture(&config, &policy, &mut spill, capture("ghp_token"));
+        spill_capture(&config, &policy, &mut spill, capture("password: hunter2"));
+        spill_capture(&config, &policy, &mut spill, capture("hello"));
+        assert_eq!(spill.len(), 2);
+        for file in std::fs::read_dir(dir.path()).unwrap() {
+            let written = std::fs::read(file.unwrap().path()).unwrap();
+            assert!(!String::from_utf8_lossy(&written).contains("ghp_"));
+            assert!(!String::from_utf8_lossy(&written).contains("cGFzc3dvcmQ6IGh1bnRlcjI"));
+        }
+
+        let db = memory_db();
+        let (events, _) = tokio::sync::broadcast::channel(16);
+        let mut autoclear = autoclear::AutoClear::default();
+        retry_spilled(&db, &config, &policy, &events, &mut autoclear, &mut spill).await;
+        assert!(spill.is_empty());
+
+        let (entries, _) = db
+            .get_history(None, None, &database::HistoryFilter::default())
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        let redacted = entries.iter().find(|e| e.redacted).unwrap();
+        assert_eq!(redacted.byte_size, 17);
+        assert!(db.get_content(redacted.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_split_pieces_keep_provenance() {
+        let db = memory_db();
+        let origin = Origin::Synced {
+            device: 3,
+            name: "laptop".to_string(),
+        };
+        let (id, _) = store_entry(
+            &db,
+            b"one\ntwo",
+            &hash::content_hash(b"one\ntwo"),
+            "text/plain",
+            Provenance {
+                seat: Some("seat1"),
+                source_app: Some("kitty"),
+                origin: Some(&origin),
+                ..Default::default()
+            },
+            config::SimilarImages::Keep,
+        )
+        .unwrap();
+
+        let ids = match split_entry(&db, &config::Config::default(), id, None) {
+            wayclip_common::Response::Split { ids } => ids,
+            other => panic!("Unexpected response: {:?}", other),
+        };
+        assert_eq!(ids.len(), 2);
+        for id in ids {
+            let piece = db.get_entry(id).unwrap().unwrap();
+            assert_eq!(piece.seat.as_deref(), Some("seat1"));
+            assert_eq!(piece.source_app.as_deref(), Some("kitty"));
+            assert_eq!(piece.origin, origin);
+        }
+    }
+
+    #[test]
+    fn test_get_contents_skips_missing_entries() {
+        let db = memory_db();
+        let id = store_text(&db, "hello");
+
+        let (contents, omitted) = get_contents(&db, &[id.into(), (id + 1).into()]).unwrap();
+        assert_eq!(contents.len(), 1);
+        assert_eq!(contents[0].id, id);
+        assert!(omitted.is_empty());
+    }
+
+    #[test]
+    fn test_add_entry() {
+        use base64::Engine;
+
+        let db = memory_db();
+        let config = config::Config::default();
+        let encode = |text: &str| base64::engine::general_purpose::STANDARD.encode(text);
+
+        let id = add_entry(
+            &db,
+            &config,
+            "text/plain",
+            &encode("part of it"),
+            &Origin::Api,
+        )
+        .unwrap();
+        assert_eq!(
+            db.get_content(id).unwrap(),
+            Some(("text/plain".to_string(), b"part of it".to_vec()))
+        );
+        // Adding it again finds the same entry
+        assert_eq!(
+            add_entry(
+                &db,
+                &config,
+                "text/plain",
+                &encode("part of it"),
+                &Origin::Api
+            )
+            .ok(),
+            Some(id)
+        );
+
+        assert!(add_entry(&db, &config, "text/plain", "", &Origin::Api).is_err());
+        assert!(add_entry(&db, &config, "text/plain", "not base64!", &Origin::Api).is_err());
+    }
+
+    #[test]
+    fn test_clipboard_ring() {
+        use wayclip_common::CycleDirection::{Next, Previous};
+
+        let db = memory_db();
+        let [a, b, c] = ["a", "b", "c"].map(|text| store_text(&db, text));
+        let mut ring = ring::ClipboardRing::default();
+        let mut cycle = |direction| {
+            let entry = ring.next(&db, direction).unwrap().unwrap();
+            ring.moved_to(&entry);
+            // The monitor then sees the copy
+            ring.captured(Selection::Clipboard, &entry.hash);
+            entry.id
+        };
+
+        assert_eq!(cycle(Previous), b);
+        assert_eq!(cycle(Previous), a);
+        assert_eq!(cycle(Previous), c);
+        assert_eq!(cycle(Next), a);
+
+        // Copying something else starts over from the newest
+        ring.captured(Selection::Primary, &hash::content_hash(b"elsewhere"));
+        assert_eq!(ring.next(&db, Previous).unwrap().map(|e| e.id), Some(c));
+        ring.captured(Selection::Clipboard, &hash::content_hash(b"new"));
+        assert_eq!(ring.next(&db, Previous).unwrap().map(|e| e.id), Some(b));
+        assert_eq!(ring.current(), Some(hash::content_hash(b"new").as_str()));
+
+        ring.cleared();
+        assert_eq!(ring.current(), None);
+    }
+
+    #[test]
+    fn test_save_to_file() {
+        use wayclip_common::Response;
+
+        let db = memory_db();
+        let id = store_text(&db, "hello");
+        let dir = tempfile::tempdir().unwrap();
+
+        // The extension comes from the MIME type unless one is given
+        let path = dir.path().join("greeting");
+        let expected = dir.path().join("greeting.txt");
+        assert!(matches!(
+            save_to_file(&db, id, path.to_str().unwrap()),
+            Response::Saved { path } if path == expected.to_str().unwrap()
+        ));
+        assert_eq!(std::fs::read(&expected).unwrap(), b"hello");
+
+        let path = dir.path().join("greeting.md");
+        assert!(!save_to_file(&db, id, path.to_str().unwrap()).is_error());
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+        assert!(save_to_file(&db, id, "greeting.txt").is_error());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_find_hash_ignores_case() {
+        let db = memory_db();
+        let id = store_text(&db, "hello");
+
+        let hash = hash::content_hash(b"hello").to_ascii_uppercase();
+        assert_eq!(find_hash(&db, &hash).ok(), Some(id));
+        assert!(matches!(
+            find_hash(&db, &hash::content_hash(b"other")),
+            Err(wayclip_common::Response::Error {
+                code: wayclip_common::ErrorCode::NotFound,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+//! Desktop notifications for new entries, with buttons that act on them.
+
+use anyhow::Result;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use zbus::zvariant::Value;
+use zbus::{Connection, Proxy};
+
+/// Action keys and button labels, as `Notify` takes them. "default" is
+/// invoked by clicking the notification itself.
+const ACTIONS: &[&str] = &[
+    "default",
+    "Open in wayclip",
+    "pin",
+    "Pin",
+    "delete",
+    "Delete",
+    "open",
+    "Open in wayclip",
+];
+
+/// Longest preview shown in a notification, in characters.
+const MAX_BODY_CHARS: usize = 100;
+
+/// A notification button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pin,
+    Delete,
+    Open,
+}
+
+impl Action {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "pin" => Some(Self::Pin),
+            "delete" => Some(Self::Delete),
+            "open" | "default" => Some(Self::Open),
+            _ => None,
+        }
+    }
+}
+
+/// A button clicked on the notification for an entry.
+#[derive(Debug)]
+pub struct ActionInvoked {
+    pub entry_id: i64,
+    pub action: Action,
+}
+
+/// A new entry to announce.
+struct Notice {
+    entry_id: i64,
+    preview: String,
+}
+
+/// Handle to the task that talks to the notification server.
+pub struct Notifier {
+    tx: mpsc::Sender<Notice>,
+}
+
+impl Notifier {
+    /// Start showing notifications, sending clicked buttons to `actions`.
+    pub fn spawn(actions: mpsc::Sender<ActionInvoked>) -> Self {
+        let (tx, rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            if let Err(e) = run(rx, actions).await {
+                tracing::warn!("Notifications unavailable: {}", e);
+            }
+        });
+        Self { tx }
+    }
+
+    /// Announce a new entry. Dropped if notifications are backed up.
+    pub fn notify(&self, entry_id: i64, preview: &str) {
+        let _ = self.tx.try_send(Notice {
+            entry_id,
+            preview: preview.to_string(),
+        });
+    }
+}
+
+async fn run(
+    mut notices: mpsc::Receiver<Notice>,
+    actions: mpsc::Sender<ActionInvoked>,
+) -> Result<()> {
+    let conn = Connection::session().await?;
+    let server = Proxy::new(
+        &conn,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .await?;
+    let mut invoked = server.receive_signal("ActionInvoked").await?;
+    let mut closed = server.receive_signal("NotificationClosed").await?;
+
+    // Each new notification replaces the last, so a burst of copies
+    // doesn't pile up on screen
+    let mut shown: Option<(u32, i64)> = None;
+
+    loop {
+        tokio::select! {
+            notice = notices.recv() => {
+                let Some(notice) = notice else { break };
+                let replaces = shown.map_or(0, |(id, _)| id);
+                let hints: HashMap<&str, Value> = HashMap::new();
+                let reply = server
+                    .call(
+                        "Notify",
+                        &(
+                            "Wayclip",
+                            replaces,
+                            "edit-paste",
+                            "Copied",
+                            body(&notice.preview),
+                            ACTIONS,
+                            hints,
+                            -1i32,
+                        ),
+                    )
+                    .await;
+                match reply {
+                    Ok(id) => shown = Some((id, notice.entry_id)),
+                    Err(e) => tracing::debug!("Failed to show notification: {}", e),
+                }
+            }
+            Some(message) = invoked.next() => {
+                let Ok((id, key)) = message.body().deserialize::<(u32, String)>() else {
+                    continue;
+                };
+                let entry_id = match shown {
+                    Some((shown_id, entry_id)) if shown_id == id => entry_id,
+                    _ => continue,
+                };
+                if let Some(action) = Action::from_key(&key) {
+                    if actions.send(ActionInvoked { entry_id, action }).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Some(message) = closed.next() => {
+                if let Ok((id, _reason)) = message.body().deserialize::<(u32, u32)>() {
+                    if shown.is_some_and(|(shown_id, _)| shown_id == id) {
+                        shown = None;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shorten a preview and escape it, since servers may read the body as
+/// markup.
+fn body(preview: &str) -> String {
+    let mut body = String::new();
+    for (i, c) in preview.chars().enumerate() {
+        if i == MAX_BODY_CHARS {
+            body.push('…');
+            break;
+        }
+        match c {
+            '&' => body.push_str("&amp;"),
+            '<' => body.push_str("&lt;"),
+            '>' => body.push_str("&gt;"),
+            c => body.push(c),
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_body_is_short_and_escaped() {
+        assert_eq!(body("a <b> & c"), "a &lt;b&gt; &amp; c");
+        let long = "x".repeat(MAX_BODY_CHARS + 10);
+        assert_eq!(body(&long).chars().count(), MAX_BODY_CHARS + 1);
+        assert_eq!(Action::from_key("default"), Some(Action::Open));
+        assert_eq!(Action::from_key("snooze"), None);
+    }
+}
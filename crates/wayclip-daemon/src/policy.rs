@@ -0,0 +1,295 @@
+//! Rules deciding what happens to a capture before it is stored.
+
+use anyhow::{anyhow, Context, Result};
+use regex::{Regex, RegexSet};
+use wayclip_common::{ContentType, IgnoreRules};
+
+use crate::config::{ClipboardConfig, RuleAction};
+use crate::database::Database;
+
+/// Setting holding the ignore rules added by clients, as JSON.
+const ADDED_RULES_KEY: &str = "added_ignore_rules";
+
+/// What to do with a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Store it normally.
+    Store,
+    /// Drop it without storing anything.
+    Ignore,
+    /// Record metadata and the content hash, but not the content.
+    StoreHashOnly,
+    /// Store it, and clear it from the clipboard after a timeout.
+    Ephemeral,
+}
+
+impl Decision {
+    /// Whether the clipboard should be cleared of this capture after a while.
+    pub fn is_sensitive(self) -> bool {
+        matches!(self, Decision::StoreHashOnly | Decision::Ephemeral)
+    }
+}
+
+impl From<RuleAction> for Decision {
+    fn from(action: RuleAction) -> Self {
+        match action {
+            RuleAction::Ignore => Decision::Ignore,
+            RuleAction::StoreHashOnly => Decision::StoreHashOnly,
+            RuleAction::Ephemeral => Decision::Ephemeral,
+        }
+    }
+}
+
+/// Capture rules compiled from the `[clipboard]` config section and the
+/// ignore rules added by clients.
+#[derive(Debug)]
+pub struct CapturePolicy {
+    ignore_mime: RegexSet,
+    ignore_apps: RegexSet,
+    ignore_content: RegexSet,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    content: Option<Regex>,
+    mime: Option<Regex>,
+    decision: Decision,
+}
+
+impl CapturePolicy {
+    /// Compile the configured patterns and the ignore rules added by
+    /// clients in `db`.
+    pub fn load(config: &ClipboardConfig, db: &Database) -> Result<Self> {
+        Self::from_config(config, &added_rules(db)?)
+    }
+
+    /// Compile the configured patterns along with `added` ignore rules,
+    /// failing on the first invalid one.
+    pub fn from_config(config: &ClipboardConfig, added: &IgnoreRules) -> Result<Self> {
+        let fields = [
+            ("ignore_mime_patterns", &config.ignore_mime_patterns),
+            ("ignore_app_patterns", &config.ignore_app_patterns),
+            ("ignore_content_patterns", &config.ignore_content_patterns),
+        ];
+        for (field, patterns) in fields {
+            for pattern in patterns {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid {} entry {:?}", field, pattern))?;
+            }
+        }
+        compile_ignore_rules(added).context("Invalid ignore rule added by a client")?;
+
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| {
+                if rule.content.is_none() && rule.mime.is_none() {
+                    return Err(anyhow!(
+                        "Capture rule {} needs a content or mime pattern",
+                        i + 1
+                    ));
+                }
+                let compile = |pattern: &Option<String>| {
+                    pattern
+                        .as_deref()
+                        .map(Regex::new)
+                        .transpose()
+                        .with_context(|| format!("Invalid pattern in capture rule {}", i + 1))
+                };
+                Ok(Rule {
+                    content: compile(&rule.content)?,
+                    mime: compile(&rule.mime)?,
+                    decision: rule.action.into(),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        let set = |config: &[String], added: &[String]| RegexSet::new(config.iter().chain(added));
+        Ok(Self {
+            ignore_mime: set(&config.ignore_mime_patterns, &added.mime)?,
+            ignore_apps: set(&config.ignore_app_patterns, &added.apps)?,
+            ignore_content: set(&config.ignore_content_patterns, &added.content)?,
+            rules,
+        })
+    }
+
+    /// Decide what to do with a capture copied from `source_app`, if it is
+    /// known.
+    pub fn decide(&self, content: &[u8], mime_type: &str, source_app: Option<&str>) -> Decision {
+        // Images are never matched against text patterns
+        let text = (!ContentType::from_mime(mime_type).is_image())
+            .then(|| String::from_utf8_lossy(content));
+
+        if self.ignore_mime.is_match(mime_type)
+            || source_app.is_some_and(|app| self.ignore_apps.is_match(app))
+            || text
+                .as_deref()
+                .is_some_and(|text| self.ignore_content.is_match(text))
+        {
+            return Decision::Ignore;
+        }
+
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.mime.as_ref().is_none_or(|re| re.is_match(mime_type))
+                    && rule
+                        .content
+                        .as_ref()
+                        .is_none_or(|re| text.as_deref().is_some_and(|text| re.is_match(text)))
+            })
+            .map_or(Decision::Store, |rule| rule.decision)
+    }
+}
+
+/// Check that every pattern in `rules` compiles, naming the first that
+/// doesn't.
+pub fn compile_ignore_rules(rules: &IgnoreRules) -> Result<()> {
+    let lists = [
+        ("mime", &rules.mime),
+        ("app", &rules.apps),
+        ("content", &rules.content),
+    ];
+    for (kind, patterns) in lists {
+        for pattern in patterns {
+            Regex::new(pattern)
+                .with_context(|| format!("Invalid ignore {} pattern {:?}", kind, pattern))?;
+        }
+    }
+    Ok(())
+}
+
+/// The ignore rules added by clients.
+pub fn added_rules(db: &Database) -> Result<IgnoreRules> {
+    match db.setting(ADDED_RULES_KEY)? {
+        Some(json) => Ok(serde_json::from_str(&json)?),
+        None => Ok(IgnoreRules::default()),
+    }
+}
+
+/// Replace the ignore rules added by clients.
+pub fn set_added_rules(db: &Database, rules: &IgnoreRules) -> Result<()> {
+    let json = (rules != &IgnoreRules::default())
+        .then(|| serde_json::to_string(rules))
+        .transpose()?;
+    db.set_setting(ADDED_RULES_KEY, json.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CaptureRule;
+
+    #[test]
+    fn test_ignore_content_patterns() {
+        let config = ClipboardConfig {
+            ignore_content_patterns: vec![
+                r"\b4\d{3}( ?\d{4}){3}\b".to_string(),
+                "^ghp_".to_string(),
+            ],
+            ..Default::default()
+        };
+        let policy = CapturePolicy::from_config(&config, &IgnoreRules::default()).unwrap();
+
+        assert_eq!(
+            policy.decide(b"card: 4111 1111 1111 1111", "text/plain", None),
+            Decision::Ignore
+        );
+        assert_eq!(
+            policy.decide(b"ghp_abc123", "text/plain;charset=utf-8", None),
+            Decision::Ignore
+        );
+        assert_eq!(policy.decide(b"hello", "text/plain", None), Decision::Store);
+        assert_eq!(
+            policy.decide(b"ghp_abc123", "image/png", None),
+            Decision::Store
+        );
+
+        let config = ClipboardConfig {
+            ignore_content_patterns: vec!["(unclosed".to_string()],
+            ..Default::default()
+        };
+        assert!(CapturePolicy::from_config(&config, &IgnoreRules::default()).is_err());
+    }
+
+    #[test]
+    fn test_added_ignore_rules() {
+        let config = ClipboardConfig {
+            ignore_app_patterns: vec!["^keepassxc$".to_string()],
+            ..Default::default()
+        };
+        let added = IgnoreRules {
+            mime: vec!["^image/".to_string()],
+            apps: vec!["^org\\.gnome\\.Terminal$".to_string()],
+            ..Default::default()
+        };
+        let policy = CapturePolicy::from_config(&config, &added).unwrap();
+
+        let text = |app| policy.decide(b"hello", "text/plain", app);
+        assert_eq!(text(Some("keepassxc")), Decision::Ignore);
+        assert_eq!(text(Some("org.gnome.Terminal")), Decision::Ignore);
+        assert_eq!(text(Some("firefox")), Decision::Store);
+        assert_eq!(text(None), Decision::Store);
+        assert_eq!(policy.decide(b"", "image/png", None), Decision::Ignore);
+
+        let added = IgnoreRules {
+            apps: vec!["(unclosed".to_string()],
+            ..Default::default()
+        };
+        assert!(CapturePolicy::from_config(&config, &added).is_err());
+    }
+
+    #[test]
+    fn test_rules_first_match_wins() {
+        let config = ClipboardConfig {
+            rules: vec![
+                CaptureRule {
+                    content: Some("^password:".to_string()),
+                    mime: None,
+                    action: RuleAction::StoreHashOnly,
+                },
+                CaptureRule {
+                    content: None,
+                    mime: Some("^image/".to_string()),
+                    action: RuleAction::Ignore,
+                },
+                CaptureRule {
+                    content: Some("secret".to_string()),
+                    mime: Some("^text/plain".to_string()),
+                    action: RuleAction::Ignore,
+                },
+            ],
+            ..Default::default()
+        };
+        let policy = CapturePolicy::from_config(&config, &IgnoreRules::default()).unwrap();
+
+        assert_eq!(
+            policy.decide(b"password: secret", "text/plain", None),
+            Decision::StoreHashOnly
+        );
+        assert_eq!(
+            policy.decide(b"anything", "image/png", None),
+            Decision::Ignore
+        );
+        assert_eq!(
+            policy.decide(b"a secret", "text/plain", None),
+            Decision::Ignore
+        );
+        assert_eq!(
+            policy.decide(b"a secret", "text/html", None),
+            Decision::Store
+        );
+
+        let config = ClipboardConfig {
+            rules: vec![CaptureRule {
+                content: None,
+                mime: None,
+                action: RuleAction::Ignore,
+            }],
+            ..Default::default()
+        };
+        assert!(CapturePolicy::from_config(&config, &IgnoreRules::default()).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Paste queue: entries put on the clipboard one after another, each as soon
+//! as the one before it has been pasted, for filling in forms.
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+use crate::clipboard;
+use crate::database::Storage;
+use crate::hash::content_hash;
+
+/// Entries waiting to be put on the clipboard.
+pub struct PasteQueue {
+    waiting: VecDeque<i64>,
+    /// Whether the clipboard holds a queued entry that hasn't been pasted.
+    active: bool,
+    pasted_tx: mpsc::UnboundedSender<()>,
+}
+
+impl PasteQueue {
+    /// Create an empty queue, and the receiver that hears when the queued
+    /// entry on the clipboard is pasted.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<()>) {
+        let (pasted_tx, pasted_rx) = mpsc::unbounded_channel();
+        let queue = Self {
+            waiting: VecDeque::new(),
+            active: false,
+            pasted_tx,
+        };
+        (queue, pasted_rx)
+    }
+
+    /// Add entries to the end of the queue. The first is copied right away
+    /// unless a queued entry is still waiting to be pasted; its content hash
+    /// is returned if so.
+    pub fn extend(
+        &mut self,
+        db: &dyn Storage,
+        ids: impl IntoIterator<Item = i64>,
+    ) -> Result<Option<String>> {
+        self.waiting.extend(ids);
+        match self.active {
+            true => Ok(None),
+            false => self.advance(db),
+        }
+    }
+
+    /// The queued entry on the clipboard was pasted, so copy the next one.
+    pub fn pasted(&mut self, db: &dyn Storage) -> Result<Option<String>> {
+        self.active = false;
+        self.advance(db)
+    }
+
+    /// Copy the next waiting entry, returning its content hash, or `None`
+    /// if there was none.
+    pub fn advance(&mut self, db: &dyn Storage) -> Result<Option<String>> {
+        // Entries deleted since they were queued are skipped
+        while let Some(id) = self.waiting.pop_front() {
+            let Some((mime_type, data)) = db.get_content(id)? else {
+                continue;
+            };
+            let hash = content_hash(&data);
+            let variants = db.get_variants(id).unwrap_or_default();
+            clipboard::copy_for_queue(&mime_type, data, variants, self.pasted_tx.clone())?;
+            self.active = true;
+            return Ok(Some(hash));
+        }
+        Ok(None)
+    }
+
+    /// Empty the queue, leaving the clipboard as it is.
+    pub fn clear(&mut self) {
+        self.waiting.clear();
+        self.active = false;
+    }
+
+    /// Entries still waiting, next first.
+    pub fn waiting(&self) -> Vec<i64> {
+        self.waiting.iter().copied().collect()
+    }
+}
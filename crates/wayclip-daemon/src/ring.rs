@@ -0,0 +1,91 @@
+//! Keeping track of what is on the clipboard, and cycling it through recent
+//! copies from a hotkey without opening the picker.
+
+use anyhow::Result;
+use wayclip_common::{CycleDirection, HistoryEntry, Selection};
+
+use crate::database::{HistoryFilter, Storage};
+
+/// How many of the newest copies to cycle through.
+const RING_SIZE: u32 = 50;
+
+/// What is on the clipboard, and where the last cycle left it.
+#[derive(Debug, Default)]
+pub struct ClipboardRing {
+    /// Content hash of what is on the clipboard, if it is known.
+    current: Option<String>,
+    /// Entry put on the clipboard by the last cycle, and its content hash.
+    position: Option<(i64, String)>,
+}
+
+impl ClipboardRing {
+    /// The entry to cycle to from the current position, if the history has
+    /// any clipboard copies.
+    pub fn next(
+        &self,
+        db: &dyn Storage,
+        direction: CycleDirection,
+    ) -> Result<Option<HistoryEntry>> {
+        let filter = HistoryFilter {
+            selection: Some(Selection::Clipboard),
+            ..Default::default()
+        };
+        let (mut entries, _) = db.get_history(Some(RING_SIZE), None, &filter)?;
+
+        // Hash-only entries have nothing to copy back
+        entries.retain(|entry| !entry.redacted);
+        if entries.is_empty() {
+            return Ok(None);
+        }
+
+        // Without a position the newest entry is taken to be on the clipboard
+        let current = self
+            .position
+            .as_ref()
+            .and_then(|(id, _)| entries.iter().position(|entry| entry.id == *id))
+            .unwrap_or(0);
+        let next = match direction {
+            CycleDirection::Previous => (current + 1) % entries.len(),
+            CycleDirection::Next => (current + entries.len() - 1) % entries.len(),
+        };
+        Ok(Some(entries.swap_remove(next)))
+    }
+
+    /// Note that a cycle put `entry` on the clipboard.
+    pub fn moved_to(&mut self, entry: &HistoryEntry) {
+        self.current = Some(entry.hash.clone());
+        self.position = Some((entry.id, entry.hash.clone()));
+    }
+
+    /// Note a new capture of content with `hash`. Copying anything but the
+    /// entry cycled to starts the next cycle from the newest entry again.
+    pub fn captured(&mut self, selection: Selection, hash: &str) {
+        if selection == Selection::Clipboard {
+            self.copied(hash.to_string());
+        }
+    }
+
+    /// Note content put on the clipboard without being captured, such as by
+    /// the paste queue.
+    pub fn copied(&mut self, hash: String) {
+        if self
+            .position
+            .as_ref()
+            .is_some_and(|(_, cycled)| *cycled != hash)
+        {
+            self.position = None;
+        }
+        self.current = Some(hash);
+    }
+
+    /// Note that the clipboard was emptied.
+    pub fn cleared(&mut self) {
+        self.current = None;
+        self.position = None;
+    }
+
+    /// Content hash of what is on the clipboard, if it is known.
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+}
@@ -0,0 +1,222 @@
+//! Running heavy maintenance once a day at a quiet time.
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::time::{Duration, Instant};
+use tracing::info;
+use wayclip_common::PruneCriteria;
+use zbus::{Connection, Proxy};
+
+use crate::config::MaintenanceConfig;
+use crate::database::{Database, Storage};
+
+/// How often to look again when maintenance isn't due yet, so a clock
+/// change or a resume from sleep is noticed without waiting all day.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Setting holding the date of the last run.
+const LAST_RUN_KEY: &str = "maintenance_last_run";
+
+/// Decides when maintenance is due and runs it.
+#[derive(Debug)]
+pub struct Scheduler {
+    at: NaiveTime,
+    ac_power_only: bool,
+    keep_backups: u32,
+    archive_after_days: u32,
+    last_run: NaiveDate,
+    /// Set while maintenance is due but waiting for AC power.
+    retry_at: Option<Instant>,
+}
+
+impl Scheduler {
+    /// Create a scheduler for `config`, or `None` if maintenance is off.
+    pub fn new(db: &Database, config: &MaintenanceConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let at = parse_time(&config.at)?;
+
+        // Without a record of earlier runs, start counting from today
+        // rather than vacuuming straight away on the first start
+        let last_run = db
+            .setting(LAST_RUN_KEY)?
+            .and_then(|date| date.parse().ok())
+            .unwrap_or_else(|| Local::now().date_naive());
+
+        Ok(Some(Self {
+            at,
+            ac_power_only: config.on_ac_power_only,
+            keep_backups: config.keep_backups,
+            archive_after_days: config.archive_after_days,
+            last_run,
+            retry_at: None,
+        }))
+    }
+
+    /// When to next check whether maintenance is due.
+    pub fn next_check(&self) -> Instant {
+        match self.retry_at {
+            Some(retry_at) => retry_at,
+            None => Instant::now() + self.wait(Local::now().naive_local()),
+        }
+    }
+
+    /// How long after `now` maintenance is due, capped at `CHECK_INTERVAL`.
+    fn wait(&self, now: NaiveDateTime) -> Duration {
+        if self.is_due(now) {
+            return Duration::ZERO;
+        }
+        let mut next = now.date().and_time(self.at);
+        if next <= now {
+            next += chrono::Duration::days(1);
+        }
+        (next - now)
+            .to_std()
+            .unwrap_or_default()
+            .min(CHECK_INTERVAL)
+    }
+
+    /// Maintenance is due once a day, from `at` onwards. A day that was
+    /// missed entirely is made up on the next one.
+    fn is_due(&self, now: NaiveDateTime) -> bool {
+        self.last_run < now.date() && now.time() >= self.at
+    }
+
+    /// Run maintenance if it is due and the power source allows.
+    pub async fn run_if_due(&mut self, db: &Database, max_age_days: u32) {
+        let now = Local::now().naive_local();
+        if !self.is_due(now) {
+            self.retry_at = None;
+            return;
+        }
+        if self.ac_power_only && !on_ac_power().await {
+            tracing::debug!("Maintenance is due, waiting for AC power");
+            self.retry_at = Some(Instant::now() + CHECK_INTERVAL);
+            return;
+        }
+
+        self.retry_at = None;
+        self.last_run = now.date();
+        if let Err(e) = db.set_setting(LAST_RUN_KEY, Some(&self.last_run.to_string())) {
+            tracing::warn!("Failed to record the maintenance run: {}", e);
+        }
+        run(db, max_age_days, self.archive_after_days, self.keep_backups);
+    }
+}
+
+/// Parse the time of day maintenance runs at, given as HH:MM.
+pub fn parse_time(at: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(at, "%H:%M")
+        .map_err(|_| anyhow!("Invalid maintenance time '{}', expected HH:MM", at))
+}
+
+/// Archive and purge old entries, tidy thumbnails, vacuum and back up.
+/// Each step is tried even if an earlier one fails.
+fn run(db: &Database, max_age_days: u32, archive_after_days: u32, keep_backups: u32) {
+    info!("Running maintenance");
+
+    // Archived before purging, so entries old enough for both are kept
+    if archive_after_days > 0 {
+        match db.archive_entries(u64::from(archive_after_days) * 24 * 60 * 60) {
+            Ok(0) => {}
+            Ok(count) => info!(
+                "Archived {} entries unused for {} days",
+                count, archive_after_days
+            ),
+            Err(e) => tracing::warn!("Failed to archive old entries: {}", e),
+        }
+    }
+
+    if max_age_days > 0 {
+        let criteria = PruneCriteria {
+            older_than_secs: Some(u64::from(max_age_days) * 24 * 60 * 60),
+            ..Default::default()
+        };
+        match db.prune(&criteria, false) {
+            Ok(0) => {}
+            Ok(count) => info!("Deleted {} entries older than {} days", count, max_age_days),
+            Err(e) => tracing::warn!("Failed to delete old entries: {}", e),
+        }
+    }
+
+    if let Err(e) = db.refresh_thumbnails() {
+        tracing::warn!("Failed to refresh cached thumbnails: {}", e);
+    }
+
+    if let Err(e) = db.vacuum() {
+        tracing::warn!("Failed to vacuum the database: {}", e);
+    }
+
+    if keep_backups > 0 {
+        match db.back_up(keep_backups) {
+            Ok(Some(path)) => info!("Backed up the database to {}", path.display()),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to back up the database: {}", e),
+        }
+    }
+}
+
+/// Whether the system is on AC power, according to UPower. Without UPower
+/// there is no battery to save, so this is assumed.
+async fn on_ac_power() -> bool {
+    async fn on_battery() -> zbus::Result<bool> {
+        let conn = Connection::system().await?;
+        let upower = Proxy::new(
+            &conn,
+            "org.freedesktop.UPower",
+            "/org/freedesktop/UPower",
+            "org.freedesktop.UPower",
+        )
+        .await?;
+        upower.get_property("OnBattery").await
+    }
+
+    match on_battery().await {
+        Ok(on_battery) => !on_battery,
+        Err(e) => {
+            tracing::debug!("Couldn't ask UPower about the power source: {}", e);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(at: &str, last_run: &str) -> Scheduler {
+        Scheduler {
+            at: at.parse().unwrap(),
+            ac_power_only: false,
+            keep_backups: 0,
+            archive_after_days: 0,
+            last_run: last_run.parse().unwrap(),
+            retry_at: None,
+        }
+    }
+
+    fn time(text: &str) -> NaiveDateTime {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_maintenance_is_due_once_a_day() {
+        let scheduler = scheduler("04:00:00", "2024-03-01");
+
+        // Already ran today
+        assert!(!scheduler.is_due(time("2024-03-01T23:00:00")));
+        // Next day, before and after the time
+        assert!(!scheduler.is_due(time("2024-03-02T03:59:00")));
+        assert!(scheduler.is_due(time("2024-03-02T04:00:00")));
+        // A missed day is made up for
+        assert!(scheduler.is_due(time("2024-03-05T12:00:00")));
+
+        assert_eq!(scheduler.wait(time("2024-03-02T04:30:00")), Duration::ZERO);
+        assert_eq!(
+            scheduler.wait(time("2024-03-02T03:50:00")),
+            Duration::from_secs(10 * 60)
+        );
+        assert_eq!(scheduler.wait(time("2024-03-01T12:00:00")), CHECK_INTERVAL);
+    }
+}
@@ -0,0 +1,54 @@
+//! Session lock and sleep notifications from logind.
+
+use anyhow::Result;
+use futures_util::{stream, StreamExt};
+use tokio::sync::mpsc;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+const LOGIND: &str = "org.freedesktop.login1";
+
+/// Send a reason on `tx` whenever the user's session is locked or the
+/// system is about to sleep.
+pub async fn watch(tx: mpsc::Sender<&'static str>) -> Result<()> {
+    let conn = Connection::system().await?;
+    let manager = Proxy::new(
+        &conn,
+        LOGIND,
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await?;
+
+    // "auto" is the caller's session, or the user's graphical session for
+    // a daemon started outside one, e.g. by systemd
+    let session_path: OwnedObjectPath = manager.call("GetSession", &("auto",)).await?;
+    let session = Proxy::new(
+        &conn,
+        LOGIND,
+        session_path,
+        "org.freedesktop.login1.Session",
+    )
+    .await?;
+
+    let locks = session
+        .receive_signal("Lock")
+        .await?
+        .map(|_| "session locked");
+    let sleeps =
+        manager
+            .receive_signal("PrepareForSleep")
+            .await?
+            .filter_map(|message| async move {
+                let starting: bool = message.body().deserialize().ok()?;
+                starting.then_some("going to sleep")
+            });
+    let mut events = std::pin::pin!(stream::select(locks, sleeps));
+
+    while let Some(reason) = events.next().await {
+        if tx.send(reason).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,450 @@
+//! Queue for clipboard captures that could not be written to the database.
+//!
+//! Captures that fail to store for a reason that may pass (e.g. because the
+//! database is locked or the disk is full) are kept in memory and mirrored
+//! to disk, then retried with exponential backoff. Anything still queued
+//! when the daemon exits is picked up again on the next start. Captures the
+//! capture policy keeps only the hash of are queued without their content.
+
+use crate::clipboard::ClipboardEvent;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wayclip_common::Selection;
+
+/// Maximum number of captures held; the oldest are dropped beyond this.
+const MAX_PENDING: usize = 100;
+
+/// Delay before the first retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound for the retry delay.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Failed attempts after which a capture is dropped, so one that can never
+/// be stored doesn't hold up the rest: about seven minutes of retrying.
+const MAX_ATTEMPTS: u32 = 20;
+
+/// On-disk form of a queued capture.
+#[derive(Serialize, Deserialize)]
+struct SpilledEvent {
+    mime_type: String,
+    #[serde(default)]
+    seat: Option<String>,
+    #[serde(default)]
+    selection: Selection,
+    #[serde(default)]
+    source_app: Option<String>,
+    #[serde(default)]
+    window_title: Option<String>,
+    #[serde(default)]
+    workspace: Option<String>,
+    /// Base64-encoded content, empty for a hash-only capture.
+    content: String,
+    /// `content_hash` and size of the content of a hash-only capture.
+    #[serde(default)]
+    hash_only: Option<(String, u64)>,
+}
+
+/// A capture waiting to be stored.
+pub struct Capture {
+    pub event: ClipboardEvent,
+    /// Size of the content of a capture that is stored by hash only. Its
+    /// content is dropped before it is queued.
+    pub hash_only: Option<u64>,
+}
+
+struct Pending {
+    capture: Capture,
+    /// Where the capture is mirrored on disk, if writing it succeeded.
+    file: Option<PathBuf>,
+    /// Retries that failed so far in this run.
+    attempts: u32,
+}
+
+/// Captures waiting to be stored, oldest first.
+pub struct SpillQueue {
+    /// Where captures are mirrored, or `None` to keep them in memory only.
+    dir: Option<PathBuf>,
+    pending: VecDeque<Pending>,
+    backoff: Duration,
+    next_retry: Instant,
+    sequence: u64,
+}
+
+impl SpillQueue {
+    /// Open the queue, loading captures spilled by a previous run.
+    pub fn open(dir: PathBuf) -> Self {
+        let mut queue = Self::in_memory();
+
+        let mut files: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        // File names start with a timestamp, so this restores capture order
+        files.sort();
+
+        for path in files {
+            match load_capture(&path) {
+                Ok(capture) => queue.pending.push_back(Pending {
+                    capture,
+                    file: Some(path),
+                    attempts: 0,
+                }),
+                Err(e) => {
+                    tracing::warn!("Discarding unreadable spill file {:?}: {}", path, e);
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+
+        if !queue.pending.is_empty() {
+            tracing::info!(
+                "Recovered {} unsaved clipboard captures",
+                queue.pending.len()
+            );
+        }
+
+        queue.dir = Some(dir);
+        queue
+    }
+
+    /// A queue that never writes captures to disk.
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            pending: VecDeque::new(),
+            backoff: INITIAL_BACKOFF,
+            next_retry: Instant::now(),
+            sequence: 0,
+        }
+    }
+
+    /// Whether there are captures waiting to be stored.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Number of captures waiting to be stored.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queue a capture for a later retry.
+    pub fn push(&mut self, event: ClipboardEvent) {
+        self.enqueue(Capture {
+            event,
+            hash_only: None,
+        });
+    }
+
+    /// Queue a capture to be stored by hash only, without its content.
+    pub fn push_hash_only(&mut self, mut event: ClipboardEvent) {
+        let byte_size = event.content.len() as u64;
+        event.content = Vec::new();
+        self.enqueue(Capture {
+            event,
+            hash_only: Some(byte_size),
+        });
+    }
+
+    fn enqueue(&mut self, capture: Capture) {
+        if self.pending.len() >= MAX_PENDING {
+            if let Some(dropped) = self.pending.pop_front() {
+                tracing::warn!("Spill queue full, dropping oldest unsaved capture");
+                if let Some(file) = dropped.file {
+                    let _ = std::fs::remove_file(file);
+                }
+            }
+        }
+
+        let file = match self.write_capture(&capture) {
+            Ok(path) => path,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to write spill file, keeping capture in memory: {}",
+                    e
+                );
+                None
+            }
+        };
+
+        if self.pending.is_empty() {
+            self.backoff = INITIAL_BACKOFF;
+            self.next_retry = Instant::now() + self.backoff;
+        }
+        self.pending.push_back(Pending {
+            capture,
+            file,
+            attempts: 0,
+        });
+    }
+
+    /// Time until the next retry is due, or `None` if the queue is empty.
+    pub fn retry_delay(&self) -> Option<Duration> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(self.next_retry.saturating_duration_since(Instant::now()))
+        }
+    }
+
+    /// The oldest queued capture.
+    pub fn front(&self) -> Option<&Capture> {
+        self.pending.front().map(|p| &p.capture)
+    }
+
+    /// Remove the oldest capture after it has been stored.
+    pub fn pop_stored(&mut self) {
+        self.remove_front();
+        self.backoff = INITIAL_BACKOFF;
+    }
+
+    /// Drop the oldest capture, which can't be stored, so the ones behind it
+    /// get their turn.
+    pub fn drop_front(&mut self) {
+        tracing::warn!("Dropping an unsaved clipboard capture that can't be stored");
+        self.remove_front();
+        self.backoff = INITIAL_BACKOFF;
+        self.next_retry = Instant::now();
+    }
+
+    fn remove_front(&mut self) {
+        if let Some(removed) = self.pending.pop_front() {
+            if let Some(file) = removed.file {
+                let _ = std::fs::remove_file(file);
+            }
+        }
+    }
+
+    /// Drop every queued capture, including its file on disk.
+    pub fn discard(&mut self) {
+        for pending in self.pending.drain(..) {
+            if let Some(file) = pending.file {
+                let _ = std::fs::remove_file(file);
+            }
+        }
+    }
+
+    /// Schedule the next retry after a failed attempt, or drop the oldest
+    /// capture once it has failed `MAX_ATTEMPTS` times.
+    pub fn retry_later(&mut self) {
+        let Some(front) = self.pending.front_mut() else {
+            return;
+        };
+        front.attempts += 1;
+        if front.attempts >= MAX_ATTEMPTS {
+            self.drop_front();
+            return;
+        }
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+        self.next_retry = Instant::now() + self.backoff;
+    }
+
+    /// Mirror `capture` to disk, returning the file written, if any.
+    fn write_capture(&mut self, capture: &Capture) -> Result<Option<PathBuf>> {
+        use base64::Engine;
+
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+        std::fs::create_dir_all(dir)?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        self.sequence += 1;
+        let path = dir.join(format!("{:020}-{:06}.json", timestamp, self.sequence));
+        let tmp_path = path.with_extension("tmp");
+
+        let event = &capture.event;
+        let spilled = SpilledEvent {
+            mime_type: event.mime_type.clone(),
+            seat: event.seat.clone(),
+            selection: event.selection,
+            source_app: event.source_app.clone(),
+            window_title: event.window_title.clone(),
+            workspace: event.workspace.clone(),
+            content: base64::engine::general_purpose::STANDARD.encode(&event.content),
+            hash_only: capture
+                .hash_only
+                .map(|byte_size| (event.hash.clone(), byte_size)),
+        };
+
+        // Clipboard content may be sensitive, keep it private to the user
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        file.write_all(&serde_json::to_vec(&spilled)?)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        Ok(Some(path))
+    }
+}
+
+/// Whether storing a capture failed for a reason that may pass, such as
+/// another process holding a lock or the disk being full, so it is worth
+/// queueing for a retry.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    use rusqlite::ErrorCode;
+
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<rusqlite::Error>())
+        .any(|e| {
+            matches!(
+                e.sqlite_error_code(),
+                Some(
+                    ErrorCode::DatabaseBusy
+                        | ErrorCode::DatabaseLocked
+                        | ErrorCode::DiskFull
+                        | ErrorCode::SystemIoFailure
+                )
+            )
+        })
+}
+
+fn load_capture(path: &Path) -> Result<Capture> {
+    use base64::Engine;
+
+    let spilled: SpilledEvent = serde_json::from_slice(&std::fs::read(path)?)?;
+    let content = base64::engine::general_purpose::STANDARD.decode(spilled.content)?;
+    let (hash, hash_only) = match spilled.hash_only {
+        Some((hash, byte_size)) => (hash, Some(byte_size)),
+        None => (crate::hash::content_hash(&content), None),
+    };
+    Ok(Capture {
+        event: ClipboardEvent {
+            content,
+            hash,
+            mime_type: spilled.mime_type,
+            seat: spilled.seat,
+            selection: spilled.selection,
+            source_app: spilled.source_app,
+            window_title: spilled.window_title,
+            workspace: spilled.workspace,
+        },
+        hash_only,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(text: &str) -> ClipboardEvent {
+        ClipboardEvent {
+            content: text.as_bytes().to_vec(),
+            hash: crate::hash::content_hash(text.as_bytes()),
+            mime_type: "text/plain".to_string(),
+            seat: Some("seat0".to_string()),
+            selection: Selection::Clipboard,
+            source_app: None,
+            window_title: None,
+            workspace: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut queue = SpillQueue::open(dir.path().to_path_buf());
+        queue.push(event("first"));
+        queue.push(event("second"));
+        queue.push(event("third"));
+        queue.pop_stored();
+        drop(queue);
+
+        let mut queue = SpillQueue::open(dir.path().to_path_buf());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.front().unwrap().event.content, b"second");
+        assert_eq!(queue.front().unwrap().event.seat.as_deref(), Some("seat0"));
+
+        queue.pop_stored();
+        queue.pop_stored();
+        assert!(queue.is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_hash_only_capture_is_queued_without_content() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut queue = SpillQueue::open(dir.path().to_path_buf());
+        queue.push_hash_only(event("hunter2"));
+        assert!(queue.front().unwrap().event.content.is_empty());
+        drop(queue);
+
+        for file in std::fs::read_dir(dir.path()).unwrap() {
+            let written = std::fs::read_to_string(file.unwrap().path()).unwrap();
+            assert!(!written.contains("hunter2"));
+            assert!(!written.contains("aHVudGVyMg"));
+        }
+
+        let queue = SpillQueue::open(dir.path().to_path_buf());
+        let capture = queue.front().unwrap();
+        assert!(capture.event.content.is_empty());
+        assert_eq!(capture.event.hash, crate::hash::content_hash(b"hunter2"));
+        assert_eq!(capture.hash_only, Some(7));
+    }
+
+    #[test]
+    fn test_backoff_grows_until_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = SpillQueue::open(dir.path().to_path_buf());
+        assert_eq!(queue.retry_delay(), None);
+
+        queue.push(event("pending"));
+        for _ in 0..10 {
+            queue.retry_later();
+        }
+        let delay = queue.retry_delay().unwrap();
+        assert!(delay <= MAX_BACKOFF && delay > MAX_BACKOFF / 2);
+    }
+
+    #[test]
+    fn test_capture_failing_too_often_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = SpillQueue::open(dir.path().to_path_buf());
+        queue.push(event("stuck"));
+        queue.push(event("next"));
+
+        for _ in 1..MAX_ATTEMPTS {
+            queue.retry_later();
+        }
+        assert_eq!(queue.front().unwrap().event.content, b"stuck");
+        queue.retry_later();
+        assert_eq!(queue.front().unwrap().event.content, b"next");
+        assert_eq!(queue.retry_delay(), Some(Duration::ZERO));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_only_lock_and_disk_errors_are_transient() {
+        let error = |code| {
+            anyhow::Error::from(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(code),
+                None,
+            ))
+        };
+        assert!(is_transient(&error(rusqlite::ffi::SQLITE_BUSY)));
+        assert!(is_transient(&error(rusqlite::ffi::SQLITE_FULL)));
+        assert!(!is_transient(&error(rusqlite::ffi::SQLITE_READONLY)));
+        assert!(!is_transient(&error(rusqlite::ffi::SQLITE_CONSTRAINT)));
+        assert!(!is_transient(&error(rusqlite::ffi::SQLITE_TOOBIG)));
+        assert!(!is_transient(&anyhow::anyhow!("not a database error")));
+    }
+}
@@ -0,0 +1,380 @@
+//! LAN sync: companion devices, such as a phone app, connect over TLS to
+//! push entries to the history and, if allowed, pull them.
+//!
+//! A connection starts with a hello, either pairing with the code from the
+//! QR code, `{"type": "pair", "code": ..., "name": ...}`, or authenticating
+//! as a device paired before, `{"type": "auth", "device": ..., "token": ...}`.
+//! The daemon answers `{"type": "welcome", "device": ...}`, with the token
+//! to keep after pairing. From then on the connection speaks the IPC
+//! protocol, limited to what the device is allowed.
+
+pub mod pairing;
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+use wayclip_common::{
+    decode_request, encode_response, DaemonEvent, ErrorCode, Request, Response, SyncDevice,
+};
+
+use crate::config::SyncConfig;
+use crate::database::Database;
+use crate::ipc::{next_event, IpcEvent, Peer, Source};
+use pairing::Identity;
+
+/// How long a device has to finish the TLS handshake and say hello.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest hello accepted.
+const HELLO_LIMIT: u64 = 4096;
+
+/// Room for the JSON around base64 content in a request.
+const REQUEST_OVERHEAD: u64 = 64 * 1024;
+
+/// First message on a connection.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Hello {
+    /// Pair with the code from the QR code.
+    Pair { code: String, name: String },
+    /// Connect as a paired device.
+    Auth { device: i64, token: String },
+}
+
+/// Something a device connection needs the main loop for.
+pub enum SyncEvent {
+    /// Pair a new device, answered with it and its token.
+    Pair {
+        code: String,
+        name: String,
+        reply: oneshot::Sender<Option<(SyncDevice, String)>>,
+    },
+    /// Check a paired device's token, answered with the device.
+    Authenticate {
+        device: i64,
+        token: String,
+        reply: oneshot::Sender<Option<SyncDevice>>,
+    },
+    /// A request from a paired device, handled if it is allowed.
+    Request { device: i64, event: IpcEvent },
+    /// Check that a subscribed device may still pull, before passing it an
+    /// event.
+    MayPull {
+        device: i64,
+        reply: oneshot::Sender<bool>,
+    },
+}
+
+/// Handle a sync event in the main loop. A request the device is allowed to
+/// make is returned, to be handled like any IPC request.
+pub fn admit(db: &Database, event: SyncEvent) -> Option<IpcEvent> {
+    match event {
+        SyncEvent::Pair { code, name, reply } => {
+            let paired = pairing::complete(db, &code, &name).unwrap_or_else(|e| {
+                warn!("Failed to pair device: {}", e);
+                None
+            });
+            if let Some((device, _)) = &paired {
+                info!("Paired device {} ({})", device.id, device.name);
+            }
+            let _ = reply.send(paired);
+            None
+        }
+        SyncEvent::Authenticate {
+            device,
+            token,
+            reply,
+        } => {
+            let device = pairing::authenticate(db, device, &token).unwrap_or_else(|e| {
+                warn!("Failed to check device token: {}", e);
+                None
+            });
+            let _ = reply.send(device);
+            None
+        }
+        SyncEvent::Request { device, event } => {
+            // Looked up every time, so changed permissions apply at once
+            let refusal = match db.get_device(device) {
+                Ok(Some(device)) if allows(&device, &event.request) => return Some(event),
+                Ok(Some(device)) => Response::error(
+                    ErrorCode::InvalidRequest,
+                    format!("{} isn't allowed to make this request", device.name),
+                ),
+                Ok(None) => {
+                    Response::error(ErrorCode::InvalidRequest, "Device is no longer paired")
+                }
+                Err(e) => Response::error(ErrorCode::DatabaseError, e.to_string()),
+            };
+            let _ = event.response_tx.send(refusal);
+            None
+        }
+        SyncEvent::MayPull { device, reply } => {
+            let allowed = db
+                .get_device(device)
+                .is_ok_and(|device| device.is_some_and(|device| device.can_pull));
+            let _ = reply.send(allowed);
+            None
+        }
+    }
+}
+
+/// Whether `device` may make `request`.
+fn allows(device: &SyncDevice, request: &Request) -> bool {
+    match request {
+        Request::AddEntry { .. } => device.can_push,
+        Request::GetHistory { .. }
+        | Request::GetEntry { .. }
+        | Request::GetContent { .. }
+        | Request::GetContents { .. }
+        | Request::Subscribe => device.can_pull,
+        Request::Ping => true,
+        _ => false,
+    }
+}
+
+/// Listen for devices until it fails.
+pub async fn serve(
+    config: SyncConfig,
+    identity: Identity,
+    max_entry_size: u64,
+    sync_tx: mpsc::Sender<SyncEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+) -> Result<()> {
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(identity.private_key));
+    let tls = tokio_rustls::rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![CertificateDer::from(identity.certificate)], key)?;
+    let acceptor = TlsAcceptor::from(Arc::new(tls));
+
+    let addr = format!("{}:{}", config.address, config.port);
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    info!("LAN sync listening on {}", addr);
+
+    // Big enough for the largest entry AddEntry can store, base64 encoded
+    let request_limit = (max_entry_size / 3 * 4).saturating_add(REQUEST_OVERHEAD);
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Failed to accept sync connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let sync_tx = sync_tx.clone();
+        let daemon_events = daemon_events.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_device(
+                acceptor,
+                stream,
+                addr,
+                request_limit,
+                sync_tx,
+                daemon_events,
+            )
+            .await
+            {
+                debug!("Sync connection from {} ended: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_device(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    addr: SocketAddr,
+    request_limit: u64,
+    sync_tx: mpsc::Sender<SyncEvent>,
+    daemon_events: broadcast::Sender<DaemonEvent>,
+) -> Result<()> {
+    let (stream, hello) = tokio::time::timeout(HELLO_TIMEOUT, async {
+        let stream = acceptor.accept(stream).await?;
+        let (reader, writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let hello = read_line(&mut reader, HELLO_LIMIT).await?;
+        anyhow::Ok(((reader, writer), hello))
+    })
+    .await
+    .context("Timed out waiting for hello")??;
+    let (mut reader, mut writer) = stream;
+
+    let Some(device) = welcome(&mut writer, &sync_tx, hello.as_deref()).await? else {
+        return Ok(());
+    };
+    info!(
+        "Device {} ({}) connected from {}",
+        device.id, device.name, addr
+    );
+
+    // The token was checked, and only this user's daemon could hand it out
+    let peer = Peer {
+        pid: None,
+        uid: nix::unistd::getuid().as_raw(),
+        process: Some(format!("sync: {}", device.name)),
+    };
+    let mut subscription = None;
+    loop {
+        let line = tokio::select! {
+            line = read_line(&mut reader, request_limit) => match line? {
+                Some(line) => line,
+                None => break,
+            },
+            event = next_event(&mut subscription) => {
+                // Permission may have been taken away since subscribing
+                let (reply, allowed) = oneshot::channel();
+                sync_tx.send(SyncEvent::MayPull { device: device.id, reply }).await?;
+                match allowed.await? {
+                    true => write_response(&mut writer, &Response::Event { event }).await?,
+                    false => subscription = None,
+                }
+                continue;
+            }
+        };
+
+        let request = match decode_request(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let response =
+                    Response::error(ErrorCode::InvalidRequest, format!("Invalid request: {}", e));
+                write_response(&mut writer, &response).await?;
+                continue;
+            }
+        };
+        let subscribing = matches!(request, Request::Subscribe);
+
+        let (response_tx, response_rx) = oneshot::channel();
+        let event = IpcEvent {
+            request,
+            response_tx,
+            peer: Some(peer.clone()),
+            source: Source::Sync {
+                device: device.id,
+                name: device.name.clone(),
+            },
+        };
+        if sync_tx
+            .send(SyncEvent::Request {
+                device: device.id,
+                event,
+            })
+            .await
+            .is_err()
+        {
+            break;
+        }
+        let response = response_rx.await.unwrap_or_else(|_| {
+            Response::error(
+                ErrorCode::InternalError,
+                "Internal error: response channel closed",
+            )
+        });
+
+        // Only once the main loop has allowed it
+        if subscribing && matches!(response, Response::Ok) {
+            subscription = Some(daemon_events.subscribe());
+        }
+        write_response(&mut writer, &response).await?;
+    }
+
+    info!("Device {} ({}) disconnected", device.id, device.name);
+    Ok(())
+}
+
+/// Pair or authenticate the device from its hello, telling it the outcome.
+async fn welcome(
+    writer: &mut (impl AsyncWrite + Unpin),
+    sync_tx: &mpsc::Sender<SyncEvent>,
+    hello: Option<&[u8]>,
+) -> Result<Option<SyncDevice>> {
+    let Some(hello) = hello else {
+        return Ok(None);
+    };
+    let hello = match serde_json::from_slice::<Hello>(hello) {
+        Ok(hello) => hello,
+        Err(e) => {
+            let response =
+                Response::error(ErrorCode::InvalidRequest, format!("Invalid hello: {}", e));
+            write_response(writer, &response).await?;
+            return Ok(None);
+        }
+    };
+
+    let (device, token) = match hello {
+        Hello::Pair { code, name } => {
+            let (reply, paired) = oneshot::channel();
+            sync_tx.send(SyncEvent::Pair { code, name, reply }).await?;
+            match paired.await? {
+                Some((device, token)) => (Some(device), Some(token)),
+                None => (None, None),
+            }
+        }
+        Hello::Auth { device, token } => {
+            let (reply, authenticated) = oneshot::channel();
+            sync_tx
+                .send(SyncEvent::Authenticate {
+                    device,
+                    token,
+                    reply,
+                })
+                .await?;
+            (authenticated.await?, None)
+        }
+    };
+
+    let Some(device) = device else {
+        let response = Response::error(
+            ErrorCode::InvalidRequest,
+            "Unknown device, or wrong or expired pairing code",
+        );
+        write_response(writer, &response).await?;
+        return Ok(None);
+    };
+    let mut welcome = json!({ "type": "welcome", "device": device });
+    if let Some(token) = token {
+        welcome["token"] = json!(token);
+    }
+    let mut line = serde_json::to_vec(&welcome)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(Some(device))
+}
+
+/// Read a line of at most `limit` bytes, without its newline, or `None` at
+/// the end of the stream.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    limit: u64,
+) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader.take(limit + 1).read_until(b'\n', &mut line).await?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(anyhow!("Message too large or cut off"));
+    }
+    line.pop();
+    Ok(Some(line))
+}
+
+async fn write_response(writer: &mut (impl AsyncWrite + Unpin), response: &Response) -> Result<()> {
+    writer.write_all(&encode_response(response)?).await?;
+    writer.flush().await?;
+    Ok(())
+}
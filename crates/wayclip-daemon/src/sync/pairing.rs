@@ -0,0 +1,178 @@
+//! Pairing manager: one-time codes shown to a device as a QR code, the
+//! certificate it pins, and the tokens paired devices connect with.
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use std::net::UdpSocket;
+use wayclip_common::SyncDevice;
+
+use crate::config::SyncConfig;
+use crate::database::Database;
+use crate::hash::{content_hash, random_token};
+
+/// Seconds a pairing code works for.
+const PAIRING_LIFETIME: i64 = 5 * 60;
+
+/// Setting holding the hash of the current pairing code and its expiry.
+const PAIRING_SETTING: &str = "sync_pairing";
+
+/// Settings holding the daemon's certificate and private key, base64 DER.
+const CERTIFICATE_SETTING: &str = "sync_certificate";
+const PRIVATE_KEY_SETTING: &str = "sync_private_key";
+
+/// The daemon's self-signed certificate and its PKCS #8 private key.
+#[derive(Clone)]
+pub struct Identity {
+    pub certificate: Vec<u8>,
+    pub private_key: Vec<u8>,
+}
+
+impl Identity {
+    /// The daemon's identity, made the first time it is needed and kept
+    /// with the history so paired devices keep recognizing it.
+    pub fn load_or_create(db: &Database) -> Result<Self> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        if let (Some(certificate), Some(private_key)) = (
+            db.setting(CERTIFICATE_SETTING)?,
+            db.setting(PRIVATE_KEY_SETTING)?,
+        ) {
+            return Ok(Self {
+                certificate: base64.decode(certificate)?,
+                private_key: base64.decode(private_key)?,
+            });
+        }
+
+        let generated = rcgen::generate_simple_self_signed(vec!["wayclip".to_string()])?;
+        let identity = Self {
+            certificate: generated.cert.der().to_vec(),
+            private_key: generated.key_pair.serialize_der(),
+        };
+        db.set_setting(
+            CERTIFICATE_SETTING,
+            Some(&base64.encode(&identity.certificate)),
+        )?;
+        db.set_setting(
+            PRIVATE_KEY_SETTING,
+            Some(&base64.encode(&identity.private_key)),
+        )?;
+        Ok(identity)
+    }
+
+    /// Hex SHA-256 of the certificate, which devices check it against.
+    pub fn fingerprint(&self) -> String {
+        content_hash(&self.certificate)
+    }
+}
+
+/// Start pairing, replacing any code given out before. Returns the URI for
+/// the device and when its code expires.
+pub fn start(db: &Database, config: &SyncConfig) -> Result<(String, i64)> {
+    let identity = Identity::load_or_create(db)?;
+    let host = match config.advertise.is_empty() {
+        true => local_address().ok_or_else(|| {
+            anyhow!("Couldn't find this computer's address on the local network; set `advertise` under [sync]")
+        })?,
+        false => config.advertise.clone(),
+    };
+
+    let code = random_token();
+    let expires_at = unix_now() + PAIRING_LIFETIME;
+    db.set_setting(
+        PAIRING_SETTING,
+        Some(&format!("{} {}", content_hash(code.as_bytes()), expires_at)),
+    )?;
+
+    let uri = format!(
+        "wayclip://pair?host={}&port={}&fingerprint={}&code={}",
+        host,
+        config.port,
+        identity.fingerprint(),
+        code
+    );
+    Ok((uri, expires_at))
+}
+
+/// Pair a device that presented `code`, returning it and the token it
+/// connects with from now on. `None` if the code is wrong or expired; a
+/// code works only once.
+pub fn complete(db: &Database, code: &str, name: &str) -> Result<Option<(SyncDevice, String)>> {
+    let Some(pairing) = db.setting(PAIRING_SETTING)? else {
+        return Ok(None);
+    };
+    let Some((hash, expires_at)) = pairing.split_once(' ') else {
+        return Ok(None);
+    };
+    if expires_at.parse::<i64>().map_or(true, |at| at < unix_now()) {
+        db.set_setting(PAIRING_SETTING, None)?;
+        return Ok(None);
+    }
+    if content_hash(code.as_bytes()) != hash {
+        return Ok(None);
+    }
+
+    db.set_setting(PAIRING_SETTING, None)?;
+    let token = random_token();
+    let name: String = name.trim().chars().take(64).collect();
+    let name = match name.is_empty() {
+        true => "Unnamed device".to_string(),
+        false => name,
+    };
+    let device = db.add_device(&name, &content_hash(token.as_bytes()))?;
+    Ok(Some((device, token)))
+}
+
+/// The paired device with this ID and token, if there is one.
+pub fn authenticate(db: &Database, id: i64, token: &str) -> Result<Option<SyncDevice>> {
+    db.authenticate_device(id, &content_hash(token.as_bytes()))
+}
+
+/// This computer's address on the local network, as the interface that
+/// would be used to reach beyond it. Connecting a UDP socket sends nothing.
+fn local_address() -> Option<String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_loopback() && !ip.is_unspecified()).then(|| ip.to_string())
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairing() {
+        let db = Database::open_in_memory().unwrap();
+        db.migrate().unwrap();
+        let config = SyncConfig {
+            advertise: "desktop.local".to_string(),
+            ..Default::default()
+        };
+
+        let (uri, _) = start(&db, &config).unwrap();
+        let code = uri.rsplit_once("code=").unwrap().1;
+        let fingerprint = Identity::load_or_create(&db).unwrap().fingerprint();
+        assert!(uri.starts_with("wayclip://pair?host=desktop.local&port=7787&"));
+        assert!(uri.contains(&format!("fingerprint={}", fingerprint)));
+
+        assert!(complete(&db, "wrong", "Phone").unwrap().is_none());
+        let (device, token) = complete(&db, code, " Phone ").unwrap().unwrap();
+        assert_eq!(device.name, "Phone");
+        assert!(device.can_push && !device.can_pull);
+
+        // Codes work once; tokens keep working
+        assert!(complete(&db, code, "Phone").unwrap().is_none());
+        let seen = authenticate(&db, device.id, &token).unwrap().unwrap();
+        assert!(seen.last_seen_at.is_some());
+        assert!(authenticate(&db, device.id, "wrong").unwrap().is_none());
+
+        assert!(db.remove_device(device.id).unwrap());
+        assert!(authenticate(&db, device.id, &token).unwrap().is_none());
+    }
+}
@@ -0,0 +1,387 @@
+//! Image decoding for thumbnails and image transforms.
+
+use anyhow::{anyhow, Result};
+use resvg::{tiny_skia, usvg};
+use std::sync::{Arc, OnceLock};
+
+/// Maximum width or height of a generated thumbnail in pixels.
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+/// Maximum width or height an SVG is rasterized at.
+const MAX_SVG_SIZE: f32 = 2048.0;
+
+/// Most pixels a GIF's logical screen may have, 64 MiB as RGBA. Its header
+/// can claim up to 65535x65535, far more than any real one.
+const MAX_GIF_PIXELS: usize = 16 * 1024 * 1024;
+
+/// Images whose perceptual hashes differ in at most this many bits count as
+/// similar, e.g. the same screenshot saved at a different compression level.
+pub const SIMILAR_IMAGE_DISTANCE: u32 = 5;
+
+/// A decoded 8-bit RGBA image.
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Metadata extracted from an image entry at capture time.
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Whether the image has more than one frame.
+    pub animated: bool,
+    /// PNG-encoded thumbnail, if one could be generated.
+    pub thumbnail: Option<Vec<u8>>,
+    /// Full-size PNG rendition of vector images, for apps that can't paste SVG.
+    pub rasterized: Option<Vec<u8>>,
+    /// Perceptual hash of the first frame, see `perceptual_hash`.
+    pub perceptual_hash: u64,
+}
+
+/// Decode an image entry and build its thumbnail.
+///
+/// Returns `None` for formats we can't decode.
+pub fn analyze(content: &[u8], mime_type: &str) -> Option<ImageInfo> {
+    let (image, animated) = match decode_first_frame(content, mime_type) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            tracing::debug!("Could not decode {} image: {}", mime_type, e);
+            return None;
+        }
+    };
+
+    let thumbnail = match encode_png(&downscale(&image, THUMBNAIL_SIZE)) {
+        Ok(png) => Some(png),
+        Err(e) => {
+            tracing::warn!("Failed to encode thumbnail: {}", e);
+            None
+        }
+    };
+
+    let rasterized = if wayclip_common::mime_essence(mime_type) == "image/svg+xml" {
+        encode_png(&image).ok()
+    } else {
+        None
+    };
+
+    Some(ImageInfo {
+        width: image.width,
+        height: image.height,
+        animated,
+        thumbnail,
+        rasterized,
+        perceptual_hash: perceptual_hash(&image),
+    })
+}
+
+/// Difference hash of an image: one bit per neighbouring pair of pixels in a
+/// 9x8 grayscale version, set where brightness increases to the right.
+///
+/// Recompression, rescaling and small edits change only a few bits, so
+/// similar images have hashes a small `hash_distance` apart.
+pub fn perceptual_hash(image: &RgbaImage) -> u64 {
+    let small = resize(image, 9, 8);
+    // Transparent pixels count as white, like on most backgrounds
+    let luma: Vec<u32> = small
+        .pixels
+        .chunks_exact(4)
+        .map(|p| {
+            let gray = (p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000;
+            (gray * p[3] as u32 + 255 * (255 - p[3] as u32)) / 255
+        })
+        .collect();
+
+    let mut hash = 0u64;
+    for row in luma.chunks_exact(9) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | (pair[0] < pair[1]) as u64;
+        }
+    }
+    hash
+}
+
+/// Number of bits in which two perceptual hashes differ.
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Decode the first frame of an image, reporting whether more frames follow.
+pub fn decode_first_frame(content: &[u8], mime_type: &str) -> Result<(RgbaImage, bool)> {
+    match wayclip_common::mime_essence(mime_type).as_str() {
+        "image/png" => Ok((decode_png(content)?, false)),
+        "image/gif" => decode_gif(content),
+        "image/svg+xml" => Ok((decode_svg(content)?, false)),
+        _ => Err(anyhow!("Unsupported image type: {}", mime_type)),
+    }
+}
+
+/// Encode an RGBA image as PNG.
+pub fn encode_png(image: &RgbaImage) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+fn decode_png(content: &[u8]) -> Result<RgbaImage> {
+    let mut decoder = png::Decoder::new(content);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buf)?;
+    buf.truncate(frame.buffer_size());
+
+    let pixels = match frame.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return Err(anyhow!("Unexpanded indexed PNG")),
+    };
+
+    Ok(RgbaImage {
+        width: frame.width,
+        height: frame.height,
+        pixels,
+    })
+}
+
+fn decode_gif(content: &[u8]) -> Result<(RgbaImage, bool)> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options.read_info(content)?;
+
+    let width = decoder.width() as usize;
+    let height = decoder.height() as usize;
+    let size = width
+        .checked_mul(height)
+        .filter(|&size| size > 0 && size <= MAX_GIF_PIXELS)
+        .ok_or_else(|| anyhow!("Unsupported GIF size {}x{}", width, height))?;
+    let mut pixels = vec![0u8; size * 4];
+
+    let frame = decoder
+        .read_next_frame()?
+        .ok_or_else(|| anyhow!("GIF has no frames"))?;
+
+    // The first frame may only cover part of the logical screen
+    let (left, top) = (frame.left as usize, frame.top as usize);
+    let frame_width = frame.width as usize;
+    let visible = frame_width.min(width.saturating_sub(left));
+    if visible > 0 {
+        for (row, line) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+            let y = top + row;
+            if y >= height {
+                break;
+            }
+            let start = (y * width + left) * 4;
+            pixels[start..start + visible * 4].copy_from_slice(&line[..visible * 4]);
+        }
+    }
+
+    let animated = decoder.next_frame_info()?.is_some();
+
+    Ok((
+        RgbaImage {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        },
+        animated,
+    ))
+}
+
+fn decode_svg(content: &[u8]) -> Result<RgbaImage> {
+    // Loading system fonts is slow, so do it once for all SVGs with text
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    let fonts = FONTS.get_or_init(|| {
+        let mut db = usvg::fontdb::Database::new();
+        db.load_system_fonts();
+        Arc::new(db)
+    });
+
+    let options = usvg::Options {
+        fontdb: fonts.clone(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_data(content, &options)?;
+
+    let size = tree.size();
+    let scale = (MAX_SVG_SIZE / size.width().max(size.height())).min(1.0);
+    let width = ((size.width() * scale).ceil() as u32).max(1);
+    let height = ((size.height() * scale).ceil() as u32).max(1);
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(width, height).ok_or_else(|| anyhow!("Invalid SVG size"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    Ok(RgbaImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Shrink an image so neither side exceeds `max_size`, averaging source pixels.
+fn downscale(image: &RgbaImage, max_size: u32) -> RgbaImage {
+    let scale = (max_size as f64 / image.width.max(image.height).max(1) as f64).min(1.0);
+    let width = ((image.width as f64 * scale).round() as u32).max(1);
+    let height = ((image.height as f64 * scale).round() as u32).max(1);
+    resize(image, width, height)
+}
+
+/// Scale an image to exactly `width` by `height`, averaging source pixels
+/// when shrinking and repeating them when growing.
+///
+/// Sizes are used as `usize`, so large images can't overflow the index
+/// arithmetic; `width` and `height` must be at least 1.
+fn resize(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let (source_width, source_height) = (image.width as usize, image.height as usize);
+    let (target_width, target_height) = (width as usize, height as usize);
+
+    let mut pixels = Vec::with_capacity(target_width * target_height * 4);
+    for y in 0..target_height {
+        let y0 = y * source_height / target_height;
+        let y1 = ((y + 1) * source_height / target_height).max(y0 + 1);
+        for x in 0..target_width {
+            let x0 = x * source_width / target_width;
+            let x1 = ((x + 1) * source_width / target_width).max(x0 + 1);
+
+            let mut sum = [0u64; 4];
+            for sy in y0..y1 {
+                for sx in x0..x1 {
+                    let i = (sy * source_width + sx) * 4;
+                    for (total, &channel) in sum.iter_mut().zip(&image.pixels[i..i + 4]) {
+                        *total += channel as u64;
+                    }
+                }
+            }
+            let count = ((x1 - x0) * (y1 - y0)) as u64;
+            pixels.extend(sum.iter().map(|s| (s / count) as u8));
+        }
+    }
+
+    RgbaImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_gif(frames: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let palette = [0, 0, 0, 255, 0, 0];
+            let mut encoder = gif::Encoder::new(&mut out, 4, 4, &palette).unwrap();
+            for i in 0..frames {
+                let frame = gif::Frame {
+                    width: 4,
+                    height: 4,
+                    buffer: std::borrow::Cow::Owned(vec![(i % 2) as u8; 16]),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_gif_animation_detection() {
+        let info = analyze(&make_gif(1), "image/gif").unwrap();
+        assert!(!info.animated);
+        assert_eq!((info.width, info.height), (4, 4));
+
+        let info = analyze(&make_gif(3), "image/gif").unwrap();
+        assert!(info.animated);
+        assert!(info.thumbnail.is_some());
+    }
+
+    #[test]
+    fn test_oversized_gif_screen_is_rejected() {
+        // The logical screen size follows the signature, little-endian
+        let mut gif = make_gif(1);
+        gif[6..10].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+        assert!(analyze(&gif, "image/gif").is_none());
+
+        gif[6..10].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(analyze(&gif, "image/gif").is_none());
+    }
+
+    #[test]
+    fn test_svg_rasterization() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+            <rect width="40" height="20" fill="red"/></svg>"#;
+        let info = analyze(svg, "image/svg+xml").unwrap();
+        assert_eq!((info.width, info.height), (40, 20));
+        assert!(info.rasterized.is_some());
+    }
+
+    #[test]
+    fn test_perceptual_hash_tolerates_small_changes() {
+        let gradient = |noise: u8| RgbaImage {
+            width: 64,
+            height: 48,
+            pixels: (0..64 * 48)
+                .flat_map(|i| {
+                    let value = ((i % 64) * 4) as u8 ^ (i as u8 % 3 * noise);
+                    [value, value, value, 255]
+                })
+                .collect(),
+        };
+        let mirrored = RgbaImage {
+            pixels: gradient(0)
+                .pixels
+                .chunks_exact(64 * 4)
+                .flat_map(|row| {
+                    row.chunks_exact(4)
+                        .rev()
+                        .flatten()
+                        .copied()
+                        .collect::<Vec<_>>()
+                })
+                .collect(),
+            ..gradient(0)
+        };
+
+        let original = perceptual_hash(&gradient(0));
+        assert!(hash_distance(original, perceptual_hash(&gradient(1))) <= SIMILAR_IMAGE_DISTANCE);
+        assert!(hash_distance(original, perceptual_hash(&mirrored)) > SIMILAR_IMAGE_DISTANCE);
+    }
+
+    #[test]
+    fn test_still_png_round_trip() {
+        let (frame, _) = decode_first_frame(&make_gif(2), "image/gif").unwrap();
+        let png = encode_png(&frame).unwrap();
+        let (decoded, animated) = decode_first_frame(&png, "image/png").unwrap();
+        assert!(!animated);
+        assert_eq!(decoded.pixels, frame.pixels);
+    }
+}
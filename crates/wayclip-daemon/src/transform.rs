@@ -0,0 +1,71 @@
+//! Content transforms applied when copying entries back to the clipboard.
+
+use anyhow::{anyhow, Result};
+use wayclip_common::Transform;
+
+use crate::{html, thumbnail};
+
+/// Apply a transform to entry content, returning the new MIME type and data.
+pub fn apply(transform: Transform, mime_type: &str, data: &[u8]) -> Result<(String, Vec<u8>)> {
+    if !transform.applies_to(mime_type) {
+        return Err(anyhow!(
+            "Transform '{}' does not apply to {} content",
+            transform.name(),
+            mime_type
+        ));
+    }
+
+    match transform {
+        Transform::StillPng => {
+            let (frame, _) = thumbnail::decode_first_frame(data, mime_type)?;
+            Ok(("image/png".to_string(), thumbnail::encode_png(&frame)?))
+        }
+        Transform::PlainText => {
+            let text = html::strip_tags(&String::from_utf8_lossy(data));
+            Ok(("text/plain;charset=utf-8".to_string(), text.into_bytes()))
+        }
+    }
+}
+
+/// Split text content into pieces at each line break, or at each
+/// `delimiter`, trimming them and leaving out blank ones.
+pub fn split(mime_type: &str, data: &[u8], delimiter: Option<&str>) -> Result<Vec<String>> {
+    let essence = wayclip_common::mime_essence(mime_type);
+    if !essence.starts_with("text/") || essence == "text/html" {
+        return Err(anyhow!("Only plain text can be split, not {}", mime_type));
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let pieces: Box<dyn Iterator<Item = &str>> = match delimiter.filter(|d| !d.is_empty()) {
+        Some(delimiter) => Box::new(text.split(delimiter)),
+        None => Box::new(text.lines()),
+    };
+    let pieces: Vec<String> = pieces
+        .map(str::trim)
+        .filter(|piece| !piece.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if pieces.len() < 2 {
+        return Err(anyhow!("Nothing to split: the entry is a single piece"));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let pieces = split("text/plain", b"one\r\n\n  two \nthree\n", None).unwrap();
+        assert_eq!(pieces, ["one", "two", "three"]);
+
+        let pieces = split("text/plain;charset=utf-8", b"a, b,,c", Some(",")).unwrap();
+        assert_eq!(pieces, ["a", "b", "c"]);
+
+        assert!(split("text/plain", b"just one line\n", None).is_err());
+        assert!(split("text/html", b"<p>a</p>\n<p>b</p>", None).is_err());
+        assert!(split("image/png", b"a\nb", None).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Webhooks: new entries POSTed as JSON to configured URLs, such as copied
+//! links sent on to a read-it-later service.
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use regex::Regex;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::clipboard::ClipboardEvent;
+use crate::config::WebhookConfig;
+
+/// How long one request may take before it is given up on.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A `[[webhooks]]` entry with its patterns compiled.
+#[derive(Debug)]
+struct Hook {
+    url: reqwest::Url,
+    content: Option<Regex>,
+    mime: Option<Regex>,
+    include_content: bool,
+    max_content_size: u64,
+    headers: Vec<(String, String)>,
+}
+
+impl Hook {
+    fn from_config(index: usize, config: &WebhookConfig) -> Result<Self> {
+        let url = reqwest::Url::parse(&config.url)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+            .ok_or_else(|| anyhow!("Webhook {} needs an http or https url", index + 1))?;
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .with_context(|| format!("Invalid pattern in webhook {}", index + 1))
+        };
+
+        Ok(Self {
+            url,
+            content: compile(&config.content)?,
+            mime: compile(&config.mime)?,
+            include_content: config.include_content,
+            max_content_size: config.max_content_size,
+            headers: config
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        })
+    }
+
+    /// Whether every pattern given matches. Content patterns only match
+    /// text.
+    fn matches(&self, event: &ClipboardEvent) -> bool {
+        let mime_matches = self
+            .mime
+            .as_ref()
+            .is_none_or(|mime| mime.is_match(&event.mime_type));
+        let content_matches = self.content.as_ref().is_none_or(|content| {
+            std::str::from_utf8(&event.content).is_ok_and(|text| content.is_match(text))
+        });
+        mime_matches && content_matches
+    }
+}
+
+/// A request waiting to be sent.
+struct Delivery {
+    url: reqwest::Url,
+    headers: Vec<(String, String)>,
+    payload: Value,
+}
+
+/// Handle to the task that sends webhook requests.
+pub struct Webhooks {
+    hooks: Vec<Hook>,
+    tx: mpsc::Sender<Delivery>,
+}
+
+impl Webhooks {
+    /// Start sending to the configured webhooks, or `None` if there are
+    /// none. Fails on the first invalid one.
+    pub fn spawn(configs: &[WebhookConfig]) -> Result<Option<Self>> {
+        if configs.is_empty() {
+            return Ok(None);
+        }
+        let hooks = configs
+            .iter()
+            .enumerate()
+            .map(|(i, config)| Hook::from_config(i, config))
+            .collect::<Result<Vec<_>>>()?;
+        let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+
+        // One at a time, so a slow endpoint can't pile up connections
+        let (tx, mut rx) = mpsc::channel::<Delivery>(16);
+        tokio::spawn(async move {
+            while let Some(delivery) = rx.recv().await {
+                let url = delivery.url.clone();
+                let mut request = client.post(delivery.url).json(&delivery.payload);
+                for (name, value) in delivery.headers {
+                    request = request.header(name, value);
+                }
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => tracing::debug!("Sent entry to webhook {}", url),
+                    Err(e) => tracing::warn!("Webhook {} failed: {}", url, e),
+                }
+            }
+        });
+
+        Ok(Some(Self { hooks, tx }))
+    }
+
+    /// Send a new entry to every webhook whose patterns match it. Dropped if
+    /// requests are backed up.
+    pub fn captured(&self, entry_id: i64, event: &ClipboardEvent, preview: &str) {
+        for hook in &self.hooks {
+            if !hook.matches(event) {
+                continue;
+            }
+            let mut payload = json!({
+                "event": "entry_added",
+                "id": entry_id,
+                "hash": event.hash,
+                "mime_type": event.mime_type,
+                "byte_size": event.content.len(),
+                "preview": preview,
+                "selection": event.selection.name(),
+                "seat": event.seat,
+                "source_app": event.source_app,
+            });
+            if hook.include_content && event.content.len() as u64 <= hook.max_content_size {
+                let (content, encoding) = match std::str::from_utf8(&event.content) {
+                    Ok(text) => (text.to_string(), "utf-8"),
+                    Err(_) => (
+                        base64::engine::general_purpose::STANDARD.encode(&event.content),
+                        "base64",
+                    ),
+                };
+                payload["content"] = json!(content);
+                payload["content_encoding"] = json!(encoding);
+            }
+
+            let delivery = Delivery {
+                url: hook.url.clone(),
+                headers: hook.headers.clone(),
+                payload,
+            };
+            if self.tx.try_send(delivery).is_err() {
+                tracing::warn!(
+                    "Webhook {} is backed up, skipping entry {}",
+                    hook.url,
+                    entry_id
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wayclip_common::Selection;
+
+    fn capture(mime_type: &str, content: &[u8]) -> ClipboardEvent {
+        ClipboardEvent {
+            content: content.to_vec(),
+            hash: crate::hash::content_hash(content),
+            mime_type: mime_type.to_string(),
+            seat: None,
+            selection: Selection::Clipboard,
+            source_app: None,
+            window_title: None,
+            workspace: None,
+        }
+    }
+
+    #[test]
+    fn test_hook_filters() {
+        let config: WebhookConfig = toml::from_str(
+            r#"
+            url = "https://example.com/save"
+            content = "^https?://"
+            mime = "^text/"
+            "#,
+        )
+        .unwrap();
+        let hook = Hook::from_config(0, &config).unwrap();
+
+        assert!(hook.matches(&capture("text/plain", b"https://example.com")));
+        assert!(!hook.matches(&capture("text/plain", b"just text")));
+        assert!(!hook.matches(&capture("image/png", b"https://example.com")));
+
+        let config = WebhookConfig {
+            url: "ftp://example.com".to_string(),
+            ..config
+        };
+        assert!(Hook::from_config(0, &config).is_err());
+    }
+}
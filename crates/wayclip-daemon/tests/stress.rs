@@ -0,0 +1,32 @@
+//! Many clients at once against a real daemon: concurrent history queries
+//! and content fetches while new entries stream in.
+
+mod support;
+
+use std::time::Duration;
+
+use support::{run_load, Daemon, Load};
+
+#[test]
+fn test_concurrent_clients() {
+    let daemon = Daemon::start(10_000);
+    let load = Load {
+        writers: 4,
+        captures: 50,
+        readers: 24,
+        queries: 20,
+    };
+    let report = run_load(&daemon, &load);
+
+    // Every capture was stored exactly once
+    assert_eq!(report.total_count, (load.writers * load.captures) as u64);
+
+    // Generous enough for a loaded CI machine, but a stall or a request
+    // queued behind a lock for the whole run would go over
+    let slowest = report.percentile(1.0);
+    assert!(
+        slowest < Duration::from_secs(5),
+        "slowest request took {:?}",
+        slowest
+    );
+}
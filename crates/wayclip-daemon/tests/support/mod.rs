@@ -0,0 +1,231 @@
+//! Running a real daemon for tests and benchmarks that talk to it over its
+//! socket, isolated in a temporary directory.
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use wayclip_common::{decode_response, encode_request, Request, Response};
+
+/// How long a single request may take before the daemon is taken to be stuck.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A daemon with its own socket, database and configuration, killed when
+/// dropped.
+pub struct Daemon {
+    child: Child,
+    socket: PathBuf,
+    _dir: tempfile::TempDir,
+}
+
+impl Daemon {
+    /// Start a daemon keeping up to `max_entries`, and wait for its socket.
+    pub fn start(max_entries: u32) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("wayclip.sock");
+        let config = dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            format!("[daemon]\nmax_entries = {max_entries}\n\n[logging]\nlevel = \"error\"\n"),
+        )
+        .unwrap();
+
+        // No compositor to watch, so captures only come in over the socket
+        let child = Command::new(env!("CARGO_BIN_EXE_wayclip-daemon"))
+            .arg("--config")
+            .arg(&config)
+            .arg("--db")
+            .arg(dir.path().join("history.db"))
+            .arg("--socket")
+            .arg(&socket)
+            .env_remove("WAYLAND_DISPLAY")
+            .env("XDG_RUNTIME_DIR", dir.path())
+            .env("XDG_DATA_HOME", dir.path())
+            .env("XDG_CACHE_HOME", dir.path())
+            .env("XDG_STATE_HOME", dir.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let daemon = Self {
+            child,
+            socket,
+            _dir: dir,
+        };
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        while UnixStream::connect(&daemon.socket).is_err() {
+            assert!(Instant::now() < deadline, "daemon didn't start listening");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        daemon
+    }
+
+    /// Open a new connection.
+    pub fn connect(&self) -> Client {
+        let stream = UnixStream::connect(&self.socket).unwrap();
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).unwrap();
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT)).unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// One connection to a `Daemon`.
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Send a request and wait for its response, failing if that takes
+    /// longer than `REQUEST_TIMEOUT`.
+    pub fn request(&mut self, request: &Request) -> std::io::Result<Response> {
+        self.writer.write_all(&encode_request(request)?)?;
+        let mut line = Vec::new();
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(decode_response(&line)?)
+    }
+
+    /// Add a text entry, as a capture would, returning its ID.
+    pub fn add_text(&mut self, text: &str) -> i64 {
+        let request = Request::AddEntry {
+            mime_type: "text/plain".to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(text),
+            set_clipboard: false,
+        };
+        match self.request(&request).unwrap() {
+            Response::Entry { entry } => entry.id,
+            other => panic!("Unexpected response to add_entry: {:?}", other),
+        }
+    }
+}
+
+/// The newest `limit` entries of the history.
+pub fn history(limit: u32) -> Request {
+    Request::GetHistory {
+        limit: Some(limit),
+        offset: None,
+        search: None,
+        seat: None,
+        selection: None,
+        collection: None,
+        snippets: false,
+        similar_to: None,
+        origin: None,
+        sort: None,
+        pinned_first: false,
+        include_archived: false,
+    }
+}
+
+/// Shape of a load run.
+pub struct Load {
+    /// Threads adding entries, standing in for clipboard captures.
+    pub writers: usize,
+    /// Entries each writer adds.
+    pub captures: usize,
+    /// Threads listing the history and fetching content.
+    pub readers: usize,
+    /// History queries each reader makes, each followed by content fetches.
+    pub queries: usize,
+}
+
+/// What a load run saw.
+pub struct LoadReport {
+    /// How long each request took, in no particular order.
+    pub latencies: Vec<Duration>,
+    /// Entries in the history once every thread had finished.
+    pub total_count: u64,
+}
+
+impl LoadReport {
+    /// The latency that `fraction` of requests stayed within.
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let index = ((sorted.len() as f64 * fraction) as usize).min(sorted.len() - 1);
+        sorted[index]
+    }
+}
+
+/// Have every thread of `load` hammer `daemon` at once, panicking on any
+/// failed or unexpected response.
+pub fn run_load(daemon: &Daemon, load: &Load) -> LoadReport {
+    let latencies = std::thread::scope(|scope| {
+        let writers = (0..load.writers).map(|writer| {
+            scope.spawn(move || {
+                let mut client = daemon.connect();
+                (0..load.captures)
+                    .map(|n| {
+                        let started = Instant::now();
+                        client.add_text(&format!("capture {writer}-{n}"));
+                        started.elapsed()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+        let readers = (0..load.readers).map(|_| {
+            scope.spawn(move || {
+                let mut client = daemon.connect();
+                let mut latencies = Vec::new();
+                for _ in 0..load.queries {
+                    let started = Instant::now();
+                    let entries = match client.request(&history(20)).unwrap() {
+                        Response::History { entries, .. } => entries,
+                        other => panic!("Unexpected response to get_history: {:?}", other),
+                    };
+                    latencies.push(started.elapsed());
+
+                    // Entries are never removed during the run, so every one
+                    // listed has its content
+                    for entry in entries.iter().take(5) {
+                        let started = Instant::now();
+                        let request = Request::GetContent {
+                            id: entry.id.into(),
+                        };
+                        match client.request(&request).unwrap() {
+                            Response::Content { id, data, .. } => {
+                                assert_eq!(id, entry.id);
+                                assert_eq!(data.0, entry.preview.as_bytes());
+                            }
+                            other => panic!("Unexpected response to get_content: {:?}", other),
+                        }
+                        latencies.push(started.elapsed());
+                    }
+                }
+                latencies
+            })
+        });
+
+        writers
+            .chain(readers)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect()
+    });
+
+    let total_count = match daemon.connect().request(&history(1)).unwrap() {
+        Response::History { total_count, .. } => total_count,
+        other => panic!("Unexpected response to get_history: {:?}", other),
+    };
+    LoadReport {
+        latencies,
+        total_count,
+    }
+}
@@ -0,0 +1,191 @@
+//! Native messaging host for browser extensions.
+//!
+//! Started by Firefox or Chromium for an extension that connects to it, and
+//! relays between the extension and the daemon's socket: each message from
+//! the extension is a request, in the same JSON as the socket, and each
+//! message back is a response or, after `subscribe`, an event. Messages are
+//! framed as the browser expects, with a 4-byte length in native byte order.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use wayclip_common::{decode_request, encode_response, ErrorCode, Response};
+
+/// Largest message a browser accepts from a native messaging host.
+const MAX_OUTGOING: usize = 1024 * 1024;
+
+/// Largest message taken from the browser, well above any entry the daemon
+/// would store.
+const MAX_INCOMING: usize = 256 * 1024 * 1024;
+
+fn main() -> Result<()> {
+    // The browser passes the extension's origin or ID, and nothing we use
+    wayclip_common::init_profile(None)?;
+
+    let path = wayclip_common::socket_path();
+    let socket = UnixStream::connect(&path).map_err(|e| {
+        anyhow!(
+            "Failed to connect to daemon at {:?}: {}. Is wayclip-daemon running?",
+            path,
+            e
+        )
+    })?;
+
+    // Events can arrive while the extension is quiet, so responses are
+    // passed back on a thread of their own
+    let responses = socket.try_clone()?;
+    let relay = std::thread::spawn(move || {
+        let code = match relay_responses(responses) {
+            Ok(()) => 0,
+            Err(e) => {
+                // Shows up in the browser console
+                eprintln!("wayclip-native-host: {:#}", e);
+                1
+            }
+        };
+        std::process::exit(code)
+    });
+
+    relay_requests(&socket)?;
+
+    // The relay exits once the daemon has answered everything sent
+    socket.shutdown(Shutdown::Write)?;
+    let _ = relay.join();
+    Ok(())
+}
+
+/// Pass the extension's messages to the daemon until the browser closes
+/// stdin.
+fn relay_requests(mut socket: &UnixStream) -> Result<()> {
+    let mut stdin = std::io::stdin().lock();
+    while let Some(message) = read_message(&mut stdin)? {
+        let line = match request_line(&message) {
+            Ok(line) => line,
+            Err(e) => {
+                let response =
+                    Response::error(ErrorCode::InvalidRequest, format!("Invalid request: {}", e));
+                // Out of order with responses still on their way, but the
+                // extension gets to know
+                write_message(&mut std::io::stdout().lock(), &encode_response(&response)?)?;
+                continue;
+            }
+        };
+        socket.write_all(&line)?;
+        socket.flush()?;
+    }
+    Ok(())
+}
+
+/// The socket line for the extension's `message`, if it is a request.
+///
+/// The message is checked but not encoded again from the parsed `Request`,
+/// which would lose requests this version doesn't know and fields it
+/// ignores. Only whitespace is taken out, so it fits on a single line; the
+/// daemon answers whatever it doesn't know itself, in order with the rest.
+fn request_line(message: &[u8]) -> serde_json::Result<Vec<u8>> {
+    decode_request(message)?;
+    let request: serde_json::Value = serde_json::from_slice(message)?;
+    let mut line = serde_json::to_vec(&request)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Pass the daemon's responses and events to the extension until the daemon
+/// closes the connection.
+fn relay_responses(socket: UnixStream) -> Result<()> {
+    let mut lines = BufReader::new(socket);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if lines.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let message = line.trim_ascii_end();
+        let mut stdout = std::io::stdout().lock();
+        if message.len() > MAX_OUTGOING {
+            // The browser would drop the connection
+            let response = Response::error(
+                ErrorCode::InvalidRequest,
+                format!(
+                    "Response of {} bytes is too large for native messaging; use the HTTP gateway",
+                    message.len()
+                ),
+            );
+            write_message(&mut stdout, &encode_response(&response)?)?;
+        } else {
+            write_message(&mut stdout, message)?;
+        }
+    }
+}
+
+/// Read one message, or `None` once the browser has closed the stream.
+fn read_message(input: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut length = [0u8; 4];
+    match input.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let length = u32::from_ne_bytes(length) as usize;
+    if length > MAX_INCOMING {
+        return Err(anyhow!("Message of {} bytes is too large", length));
+    }
+
+    let mut message = vec![0u8; length];
+    input
+        .read_exact(&mut message)
+        .context("Browser closed the stream mid-message")?;
+    Ok(Some(message))
+}
+
+/// Write one message, without any trailing newline.
+fn write_message(output: &mut impl Write, message: &[u8]) -> Result<()> {
+    let message = message.trim_ascii_end();
+    output.write_all(&(message.len() as u32).to_ne_bytes())?;
+    output.write_all(message)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_framing() {
+        let mut framed = Vec::new();
+        write_message(&mut framed, b"{\"type\":\"get_status\"}\n").unwrap();
+        write_message(&mut framed, b"{}").unwrap();
+        assert_eq!(&framed[..4], &21u32.to_ne_bytes());
+
+        let mut input = framed.as_slice();
+        assert_eq!(
+            read_message(&mut input).unwrap().unwrap(),
+            b"{\"type\":\"get_status\"}"
+        );
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), b"{}");
+        assert!(read_message(&mut input).unwrap().is_none());
+
+        // A length with nothing after it is an error, not the end
+        let mut truncated: &[u8] = &[5, 0, 0, 0, b'{'];
+        assert!(read_message(&mut truncated).is_err());
+    }
+
+    #[test]
+    fn test_unknown_requests_are_passed_on() {
+        let line = request_line(b"{\"type\": \"summon_entry\",\n \"id\": 3}").unwrap();
+        assert_eq!(line.iter().filter(|&&b| b == b'\n').count(), 1);
+        let forwarded: serde_json::Value = serde_json::from_slice(&line).unwrap();
+        assert_eq!(
+            forwarded,
+            serde_json::json!({ "type": "summon_entry", "id": 3 })
+        );
+        assert_eq!(
+            request_line(b"{\"type\":\"ping\"}").unwrap(),
+            b"{\"type\":\"ping\"}\n"
+        );
+        assert!(request_line(b"{\"id\": 3}").is_err());
+        assert!(request_line(b"not json").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+//! Picker state, drawing and key handling.
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use wayclip_common::{ContentType, EntryId, HistoryEntry, Request, Response};
+
+use crate::client::Client;
+
+/// Entries fetched per search, more than any terminal shows at once.
+const PAGE_SIZE: u32 = 500;
+
+/// Most bytes of text shown in the preview pane.
+const MAX_PREVIEW_BYTES: usize = 64 * 1024;
+
+/// Key help shown in the status line when there is no message.
+const HELP: &str = "Enter copy · Ctrl+P pin · Del delete · Esc quit";
+
+pub struct App {
+    client: Client,
+    search: String,
+    entries: Vec<HistoryEntry>,
+    total: u64,
+    list: ListState,
+    /// Preview text of entries already shown, by content hash, so an ID the
+    /// daemon hands out again never shows another entry's content.
+    previews: HashMap<String, String>,
+    /// Result of the last action, shown in the status line until the next key.
+    message: Option<String>,
+}
+
+impl App {
+    pub fn new(client: Client) -> Self {
+        let mut app = Self {
+            client,
+            search: String::new(),
+            entries: Vec::new(),
+            total: 0,
+            list: ListState::default(),
+            previews: HashMap::new(),
+            message: None,
+        };
+        app.reload();
+        app
+    }
+
+    /// Draw and handle keys until the user copies an entry or quits.
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Fetch the entries matching the search, keeping the selection in range.
+    /// A failed search, e.g. on a stray quote, shows an empty list.
+    fn reload(&mut self) {
+        let request = Request::GetHistory {
+            limit: Some(PAGE_SIZE),
+            offset: None,
+            search: (!self.search.is_empty()).then(|| self.search.clone()),
+            seat: None,
+            selection: None,
+            collection: None,
+            snippets: false,
+            similar_to: None,
+            origin: None,
+            sort: None,
+            pinned_first: false,
+            include_archived: false,
+        };
+        (self.entries, self.total) = match self.client.request(&request) {
+            Ok(Response::History {
+                entries,
+                total_count,
+            }) => (entries, total_count),
+            Ok(other) => {
+                self.message = Some(format!("Unexpected response: {:?}", other));
+                (Vec::new(), 0)
+            }
+            Err(e) => {
+                self.message = Some(e.to_string());
+                (Vec::new(), 0)
+            }
+        };
+
+        let selected = match self.entries.len() {
+            0 => None,
+            len => Some(self.list.selected().unwrap_or(0).min(len - 1)),
+        };
+        self.list.select(selected);
+    }
+
+    fn selected(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.list.selected()?)
+    }
+
+    /// Handle a key press, returning `false` to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Char('c') if ctrl => return false,
+            KeyCode::Esc if self.search.is_empty() => return false,
+            KeyCode::Esc => {
+                self.search.clear();
+                self.reload();
+            }
+            KeyCode::Enter => {
+                if let Some(id) = self.selected().map(|e| e.id) {
+                    match self
+                        .client
+                        .request(&Request::SetClipboard { id: id.into() })
+                    {
+                        Ok(_) => return false,
+                        Err(e) => self.message = Some(e.to_string()),
+                    }
+                }
+            }
+            KeyCode::Char('p') if ctrl => {
+                if let Some(entry) = self.selected() {
+                    let request = Request::SetPinned {
+                        id: entry.id.into(),
+                        pinned: !entry.pinned,
+                    };
+                    self.act(&request);
+                }
+            }
+            KeyCode::Delete => {
+                if let Some(entry) = self.selected() {
+                    let request = Request::DeleteEntry {
+                        id: entry.id.into(),
+                    };
+                    self.act(&request);
+                }
+            }
+            KeyCode::Up => self.list.select_previous(),
+            KeyCode::Down => self.list.select_next(),
+            KeyCode::PageUp => self.list.scroll_up_by(10),
+            KeyCode::PageDown => self.list.scroll_down_by(10),
+            KeyCode::Home => self.list.select_first(),
+            KeyCode::End => self.list.select_last(),
+            KeyCode::Backspace if !self.search.is_empty() => {
+                self.search.pop();
+                self.reload();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.search.push(c);
+                self.list.select_first();
+                self.reload();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Send a request that changes the history, then show the result.
+    fn act(&mut self, request: &Request) {
+        if let Request::DeleteEntry {
+            id: EntryId::Row(id),
+        } = request
+        {
+            if let Some(entry) = self.entries.iter().find(|entry| entry.id == *id) {
+                self.previews.remove(&entry.hash);
+            }
+        }
+        if let Err(e) = self.client.request(request) {
+            self.message = Some(e.to_string());
+        }
+        self.reload();
+    }
+
+    /// Text for the preview pane, fetching an entry's content the first
+    /// time it is shown.
+    fn preview(&mut self) -> String {
+        let Some(entry) = self.selected() else {
+            return String::new();
+        };
+        if entry.redacted {
+            return "Only a hash of this entry was kept.".to_string();
+        }
+        if entry.content_type != ContentType::Text {
+            return format!(
+                "{}\n{}, {} bytes",
+                entry.display_text(),
+                entry.mime_type,
+                entry.byte_size
+            );
+        }
+
+        let (id, hash) = (entry.id, entry.hash.clone());
+        if let Some(text) = self.previews.get(&hash) {
+            return text.clone();
+        }
+        let text = match self.client.request(&Request::GetContent { id: id.into() }) {
+            Ok(Response::Content { data, .. }) => {
+                let data = data.0;
+                let end = data.len().min(MAX_PREVIEW_BYTES);
+                String::from_utf8_lossy(&data[..end]).into_owned()
+            }
+            Ok(_) => String::new(),
+            Err(e) => return e.to_string(),
+        };
+        self.previews.insert(hash, text.clone());
+        text
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main_area);
+
+        frame.render_widget(
+            Paragraph::new(self.search.as_str()).block(Block::bordered().title("Search")),
+            search_area,
+        );
+        frame.set_cursor_position(Position::new(
+            search_area.x + 1 + self.search.chars().count() as u16,
+            search_area.y + 1,
+        ));
+
+        let items: Vec<ListItem> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let marker = if entry.pinned { "* " } else { "  " };
+                let origin = entry
+                    .origin
+                    .label()
+                    .map(|label| format!("[{}] ", label))
+                    .unwrap_or_default();
+                let item = ListItem::new(format!("{}{}{}", marker, origin, entry.display_text()));
+                // What the clipboard holds now stands out
+                match entry.is_current {
+                    true => item.style(Style::new().add_modifier(Modifier::BOLD)),
+                    false => item,
+                }
+            })
+            .collect();
+        let title = format!("{} of {}", self.entries.len(), self.total);
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let preview = Paragraph::new(self.preview())
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Preview"));
+        frame.render_widget(preview, preview_area);
+
+        frame.render_widget(
+            Paragraph::new(self.message.as_deref().unwrap_or(HELP)),
+            status_area,
+        );
+    }
+}
@@ -0,0 +1,30 @@
+//! Terminal picker for the wayclip clipboard history.
+
+mod app;
+mod client;
+
+use anyhow::Result;
+use clap::Parser;
+use client::Client;
+
+/// Pick an entry from the wayclip clipboard history in the terminal.
+#[derive(Parser)]
+#[command(name = "wayclip-tui", version)]
+struct Cli {
+    /// Talk to the daemon for this profile instead of $WAYCLIP_PROFILE.
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    wayclip_common::init_profile(cli.profile.as_deref())?;
+
+    // Connect before taking over the terminal, so errors print normally
+    let mut app = app::App::new(Client::connect()?);
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}